@@ -25,7 +25,8 @@ use zeronsd::{
     server::Server,
     traits::{ToHostname, ToPointerSOA},
     utils::{
-        authtoken_path, domain_or_default, get_listen_ips, parse_ip_from_cidr, ZEROTIER_LOCAL_URL,
+        authtoken_path, domain_or_default, get_listen_ips, parse_ip_from_cidr, update_member_retry,
+        DEFAULT_RECORD_TTL, ZEROTIER_LOCAL_URL,
     },
 };
 
@@ -191,6 +192,7 @@ impl Service {
                 let ptr_authority = RecordAuthority::new(
                     cidr.to_ptr_soa_name().unwrap(),
                     cidr.to_ptr_soa_name().unwrap(),
+                    DEFAULT_RECORD_TTL,
                 )
                 .await
                 .unwrap();
@@ -205,6 +207,7 @@ impl Service {
                     let ptr_authority = RecordAuthority::new(
                         cidr.to_ptr_soa_name().unwrap(),
                         cidr.to_ptr_soa_name().unwrap(),
+                        DEFAULT_RECORD_TTL,
                     )
                     .await
                     .unwrap();
@@ -219,19 +222,21 @@ impl Service {
                 .to_fqdn(domain_or_default(None).unwrap().into())
                 .unwrap()
                 .into(),
+            DEFAULT_RECORD_TTL,
         )
         .await
         .unwrap();
 
         let ztauthority = ZTAuthority {
-            network_id: tn.network.clone().id.unwrap(),
-            client: tn.central(),
             hosts_file: format_hosts_file(hosts),
-            reverse_authority_map: authority_map,
             update_interval,
-            forward_authority: authority.clone(),
             wildcard: wildcard_everything,
-            hosts: None,
+            ..ZTAuthority::new(
+                tn.network.clone().id.unwrap(),
+                tn.central(),
+                authority.clone(),
+                authority_map,
+            )
         };
 
         tokio::spawn(find_members(ztauthority.clone()));
@@ -240,7 +245,7 @@ impl Service {
         for ip in listen_ips.clone() {
             let server = Server::new(ztauthority.to_owned());
             info!("Serving {}", ip.clone());
-            tokio::spawn(server.listen(ip.ip(), Duration::new(1, 0), None, None, None));
+            tokio::spawn(server.listen(ip.ip(), Duration::new(1, 0), None));
         }
 
         listen_ips
@@ -289,16 +294,16 @@ impl Service {
 
         member.name = Some(name.to_string());
 
-        let _ = &self
-            .network()
-            .central()
-            .update_network_member(
-                &self.network().network.clone().id.unwrap(),
-                &self.network().identity(),
-                &member.to_owned(),
-            )
-            .await
-            .unwrap();
+        update_member_retry(
+            &self.network().central(),
+            &self.network().network.clone().id.unwrap(),
+            &self.network().identity(),
+            &member,
+            5,
+            Duration::new(1, 0),
+        )
+        .await
+        .unwrap();
 
         tokio::time::sleep(self.update_interval).await; // wait for it to update
     }
@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use tracing::warn;
-use zeronsd::utils::{authtoken_path, get_listen_ips, ZEROTIER_LOCAL_URL};
+use zeronsd::utils::{authtoken_path, get_listen_ips, update_member_retry, ZEROTIER_LOCAL_URL};
 
 use zerotier_api::{
     central_api::{
@@ -45,10 +45,16 @@ impl TestNetwork {
 
         let member = tc.get_member(network.clone().id.unwrap());
 
-        tc.central
-            .update_network_member(&network.clone().id.unwrap(), &tc.identity, &member)
-            .await
-            .unwrap();
+        update_member_retry(
+            &tc.central,
+            &network.clone().id.unwrap(),
+            &tc.identity,
+            &member,
+            5,
+            Duration::new(1, 0),
+        )
+        .await
+        .unwrap();
 
         let s = Self {
             network: network.to_owned(),
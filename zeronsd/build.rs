@@ -0,0 +1,26 @@
+use std::process::Command;
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-changed=../.git/HEAD");
+    println!("cargo::rerun-if-changed=../.git/refs");
+    println!("cargo::rerun-if-changed=../.git/packed-refs");
+
+    // builds outside of a git checkout (release tarballs, nix) have no commit to report.
+    let sha = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo::rustc-env=ZERONSD_GIT_SHA={}", sha);
+    println!(
+        "cargo::rustc-env=ZERONSD_LONG_VERSION={} (git {})",
+        std::env::var("CARGO_PKG_VERSION").unwrap(),
+        sha
+    );
+}
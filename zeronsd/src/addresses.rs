@@ -1,10 +1,18 @@
 /// various IP calculation systems and some encode/decode functions
-use std::net::{IpAddr, Ipv6Addr};
+use std::{
+    net::{IpAddr, Ipv6Addr},
+    str::FromStr,
+};
 
+use anyhow::anyhow;
 use hex::FromHexError;
 use ipnetwork::IpNetwork;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use zerotier_api::central_api::types::{Member, Network};
 
+use crate::traits::node_id_from_hostname;
+
 fn digest_hex(code: String) -> Result<u64, FromHexError> {
     Ok(hex::decode(code)?
         .into_iter()
@@ -13,11 +21,61 @@ fn digest_hex(code: String) -> Result<u64, FromHexError> {
 
 fn get_parts(member: Member) -> Result<(u64, u64), anyhow::Error> {
     Ok((
-        digest_hex(member.network_id.clone().unwrap_or(String::new()))?,
-        digest_hex(member.node_id.unwrap_or(String::new()))?,
+        digest_hex(member.network_id.clone().unwrap_or_default())?,
+        digest_hex(member.node_id.unwrap_or_default())?,
     ))
 }
 
+// IpFamilies selects which address families (A, AAAA or both) a kind of record is published for.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum IpFamilies {
+    #[serde(rename = "a")]
+    A,
+    #[serde(rename = "aaaa")]
+    AAAA,
+    #[default]
+    #[serde(rename = "both")]
+    Both,
+}
+
+impl IpFamilies {
+    pub fn includes(&self, ip: &IpAddr) -> bool {
+        matches!(
+            (self, ip),
+            (IpFamilies::Both, _)
+                | (IpFamilies::A, IpAddr::V4(_))
+                | (IpFamilies::AAAA, IpAddr::V6(_))
+        )
+    }
+
+    pub fn filter(&self, ips: &[IpAddr]) -> Vec<IpAddr> {
+        ips.iter().copied().filter(|ip| self.includes(ip)).collect()
+    }
+}
+
+impl std::fmt::Display for IpFamilies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            IpFamilies::A => "a",
+            IpFamilies::AAAA => "aaaa",
+            IpFamilies::Both => "both",
+        })
+    }
+}
+
+impl FromStr for IpFamilies {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "a" | "A" => Ok(IpFamilies::A),
+            "aaaa" | "AAAA" => Ok(IpFamilies::AAAA),
+            "both" => Ok(IpFamilies::Both),
+            _ => Err(anyhow!("invalid format: allowed values: [a, aaaa, both]")),
+        }
+    }
+}
+
 pub trait Calculator {
     fn sixplane(self) -> Result<IpNetwork, anyhow::Error>;
     fn rfc4193(self) -> Result<IpNetwork, anyhow::Error>;
@@ -25,7 +83,7 @@ pub trait Calculator {
 
 impl Calculator for Network {
     fn sixplane(self) -> Result<IpNetwork, anyhow::Error> {
-        let mut net_parts = digest_hex(self.id.unwrap_or(String::new()))?;
+        let mut net_parts = digest_hex(self.id.unwrap_or_default())?;
 
         net_parts ^= net_parts >> 32;
 
@@ -45,7 +103,7 @@ impl Calculator for Network {
     }
 
     fn rfc4193(self) -> Result<IpNetwork, anyhow::Error> {
-        let net_parts = digest_hex(self.id.unwrap_or(String::new()))?;
+        let net_parts = digest_hex(self.id.unwrap_or_default())?;
         Ok(IpNetwork::new(
             IpAddr::V6(Ipv6Addr::new(
                 0xfd00 | (net_parts >> 56 & 0xff) as u16,
@@ -101,3 +159,30 @@ impl Calculator for Member {
         )?)
     }
 }
+
+// decode_member describes the member behind a zt-<node id> name: its node id and, given the
+// network, its 6plane and RFC 4193 addresses. Whether the network assigns them is up to its
+// settings in Central.
+pub fn decode_member(name: &str, network_id: Option<&str>) -> Result<String, anyhow::Error> {
+    let node_id = node_id_from_hostname(name)?;
+    let mut out = format!("node id: {}\n", node_id);
+
+    if let Some(network_id) = network_id {
+        if network_id.len() != 16 || !network_id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!(
+                "{} is not a ZeroTier network id; network ids are 16 hex digits",
+                network_id
+            ));
+        }
+
+        let member: Member = serde_json::from_value(serde_json::json!({
+            "nodeId": node_id,
+            "networkId": network_id.to_ascii_lowercase(),
+        }))?;
+
+        out += &format!("6plane:  {}\n", member.clone().sixplane()?.ip());
+        out += &format!("rfc4193: {}\n", member.rfc4193()?.ip());
+    }
+
+    Ok(out)
+}
@@ -0,0 +1,394 @@
+/// membership auditing: compares the members seen by each sync with the previous one and appends
+/// the differences to a JSON Lines file, or posts them to a webhook.
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    net::IpAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEvent {
+    pub action: AuditAction,
+    pub member: String,
+    pub name: Option<String>,
+    pub ips: Vec<IpAddr>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberState {
+    pub name: Option<String>,
+    pub ips: Vec<IpAddr>,
+}
+
+// node id -> member state, as computed by a single sync.
+pub type MemberSet = BTreeMap<String, MemberState>;
+
+// diff computes the added and removed members between two syncs. Without a prior sync there is
+// nothing to compare to, so every member is only reported as added if `initial` is set.
+pub fn diff(prior: Option<&MemberSet>, current: &MemberSet, initial: bool) -> Vec<AuditEvent> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let empty = MemberSet::new();
+    let prior = match prior {
+        Some(prior) => prior,
+        None if initial => &empty,
+        None => return Vec::new(),
+    };
+
+    let mut events = Vec::new();
+
+    for (member, state) in current {
+        if !prior.contains_key(member) {
+            events.push(AuditEvent {
+                action: AuditAction::Added,
+                member: member.clone(),
+                name: state.name.clone(),
+                ips: state.ips.clone(),
+                timestamp,
+            });
+        }
+    }
+
+    for (member, state) in prior {
+        if !current.contains_key(member) {
+            events.push(AuditEvent {
+                action: AuditAction::Removed,
+                member: member.clone(),
+                name: state.name.clone(),
+                ips: state.ips.clone(),
+                timestamp,
+            });
+        }
+    }
+
+    events
+}
+
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    initial: bool,
+    prior: Arc<Mutex<Option<MemberSet>>>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf, initial: bool) -> Self {
+        Self {
+            path,
+            initial,
+            prior: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // record diffs the members against the last sync and appends any changes to the audit file.
+    pub async fn record(&self, current: MemberSet) -> Result<Vec<AuditEvent>, anyhow::Error> {
+        let mut prior = self.prior.lock().await;
+        let events = diff(prior.as_ref(), &current, self.initial);
+
+        if !events.is_empty() {
+            let mut f = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+
+            for event in &events {
+                writeln!(f, "{}", serde_json::to_string(event)?)?;
+            }
+        }
+
+        *prior = Some(current);
+        Ok(events)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WebhookPayload {
+    pub network_id: String,
+    pub events: Vec<AuditEvent>,
+}
+
+// Webhook posts the member changes of each sync to a URL as a JSON WebhookPayload. Deliveries run in
+// the background and are retried with backoff, so a slow or failing endpoint never holds up a sync.
+#[derive(Clone)]
+pub struct Webhook {
+    url: String,
+    network_id: String,
+    client: reqwest::Client,
+    attempts: usize,
+    backoff: Duration,
+    prior: Arc<Mutex<Option<MemberSet>>>,
+}
+
+impl Webhook {
+    pub fn new(
+        url: String,
+        network_id: String,
+        attempts: usize,
+        backoff: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            url,
+            network_id,
+            client: reqwest::Client::builder()
+                .user_agent(format!("zeronsd {}", crate::utils::VERSION_STRING))
+                .timeout(Duration::new(10, 0))
+                .build()?,
+            attempts: attempts.max(1),
+            backoff,
+            prior: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    // record diffs the members against the last sync and, if anything changed, starts delivering the
+    // changes. Members present at the first sync are not reported.
+    pub async fn record(&self, current: MemberSet) -> Vec<AuditEvent> {
+        let mut prior = self.prior.lock().await;
+        let events = diff(prior.as_ref(), &current, false);
+        *prior = Some(current);
+
+        if !events.is_empty() {
+            let (webhook, payload) = (
+                self.clone(),
+                WebhookPayload {
+                    network_id: self.network_id.clone(),
+                    events: events.clone(),
+                },
+            );
+            tokio::spawn(async move { webhook.deliver(payload).await });
+        }
+
+        events
+    }
+
+    async fn deliver(&self, payload: WebhookPayload) {
+        let body = match serde_json::to_string(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("error encoding webhook payload: {}", e);
+                return;
+            }
+        };
+
+        let mut backoff = self.backoff;
+        for attempt in 1..=self.attempts {
+            let res = self
+                .client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|res| res.error_for_status());
+
+            match res {
+                Ok(_) => return,
+                Err(e) if attempt < self.attempts => {
+                    tracing::warn!(
+                        "error posting to webhook (attempt {} of {}), retrying in {:?}: {}",
+                        attempt,
+                        self.attempts,
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => tracing::error!(
+                    "error posting to webhook, dropping {} member changes: {}",
+                    payload.events.len(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, str::FromStr};
+
+    use super::{
+        AuditAction, AuditEvent, AuditLog, MemberSet, MemberState, Webhook, WebhookPayload,
+    };
+
+    fn member_set(members: &[(&str, &str)]) -> MemberSet {
+        members
+            .iter()
+            .map(|(id, ip)| {
+                (
+                    id.to_string(),
+                    MemberState {
+                        name: Some(format!("zt-{}.home.arpa.", id)),
+                        ips: vec![IpAddr::from_str(ip).unwrap()],
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let path = std::env::temp_dir().join(format!("zeronsd-audit-{}", rand::random::<u32>()));
+        let audit = AuditLog::new(path.clone(), false);
+
+        // the initial sync does not report every existing member
+        let events = audit
+            .record(member_set(&[("abcdef0123", "10.0.0.1")]))
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+
+        audit
+            .record(member_set(&[
+                ("abcdef0123", "10.0.0.1"),
+                ("0123abcdef", "10.0.0.2"),
+            ]))
+            .await
+            .unwrap();
+        audit
+            .record(member_set(&[("abcdef0123", "10.0.0.1")]))
+            .await
+            .unwrap();
+
+        let events: Vec<AuditEvent> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, AuditAction::Added);
+        assert_eq!(events[1].action, AuditAction::Removed);
+        for event in events {
+            assert_eq!(event.member, "0123abcdef");
+            assert_eq!(event.ips, vec![IpAddr::from_str("10.0.0.2").unwrap()]);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_initial() {
+        let path = std::env::temp_dir().join(format!("zeronsd-audit-{}", rand::random::<u32>()));
+        let audit = AuditLog::new(path.clone(), true);
+
+        let events = audit
+            .record(member_set(&[
+                ("abcdef0123", "10.0.0.1"),
+                ("0123abcdef", "10.0.0.2"),
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.action == AuditAction::Added));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            time::Duration,
+        };
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // an endpoint that fails the first delivery and passes on the body of the next one.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let requests = std::sync::Arc::new(AtomicUsize::new(0));
+
+        let r = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = vec![0; 65535];
+                let body = loop {
+                    let len = conn.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..len]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length: usize = head
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length: ")
+                                    .map(|v| v.parse().unwrap())
+                            })
+                            .unwrap_or_default();
+                        if body.len() >= length {
+                            break body.to_string();
+                        }
+                    }
+                };
+
+                let status = if r.fetch_add(1, Ordering::SeqCst) == 0 {
+                    "500 Internal Server Error"
+                } else {
+                    tx.send(body).unwrap();
+                    "204 No Content"
+                };
+                let _ = conn
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            status
+                        )
+                        .as_bytes(),
+                    )
+                    .await;
+            }
+        });
+
+        let webhook = Webhook::new(
+            url,
+            "1234567891011121".to_string(),
+            3,
+            Duration::from_millis(10),
+        )
+        .unwrap();
+
+        // the first sync only sets the baseline
+        assert!(webhook
+            .record(member_set(&[("abcdef0123", "10.0.0.1")]))
+            .await
+            .is_empty());
+
+        let events = webhook
+            .record(member_set(&[
+                ("abcdef0123", "10.0.0.1"),
+                ("0123abcdef", "10.0.0.2"),
+            ]))
+            .await;
+        assert_eq!(events.len(), 1);
+
+        let body = tokio::time::timeout(Duration::new(5, 0), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let payload: WebhookPayload = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload.network_id, "1234567891011121");
+        assert_eq!(payload.events, events);
+        assert_eq!(payload.events[0].action, AuditAction::Added);
+        assert_eq!(payload.events[0].member, "0123abcdef");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
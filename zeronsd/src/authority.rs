@@ -1,28 +1,52 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     net::IpAddr,
     path::PathBuf,
     str::FromStr,
-    sync::Arc,
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
-    addresses::Calculator,
+    addresses::{Calculator, IpFamilies},
+    audit::{AuditLog, MemberSet, MemberState, Webhook},
+    blocklist::Blocklist,
+    forwarder::{StaleCache, TtlClamp, ZTForwarder},
     hosts::{parse_hosts, HostsFile},
+    metrics::{ForwardMetrics, StatsdSink},
+    overrides::{parse_overrides, Overrides},
+    server::{RootQueries, SocketOptions, Truncation},
+    tls::DoqConfig,
     traits::{ToHostname, ToPointerSOA, ToWildcard},
     utils::parse_member_name,
 };
 
+use anyhow::anyhow;
 use async_trait::async_trait;
 use ipnetwork::IpNetwork;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 use trust_dns_resolver::{
     config::NameServerConfigGroup,
-    proto::rr::{dnssec::SupportedAlgorithms, rdata::SOA, RData, Record, RecordSet, RecordType},
+    proto::{
+        op::ResponseCode,
+        rr::{
+            dnssec::SupportedAlgorithms,
+            rdata::{HINFO, MX, SOA, SRV, TXT},
+            RData, Record, RecordSet, RecordType,
+        },
+    },
     IntoName, Name,
 };
 use trust_dns_server::{
-    authority::{AuthorityObject, Catalog},
+    authority::{
+        AuthLookup, AuthorityObject, Catalog, LookupError, LookupObject, LookupOptions,
+        LookupRecords,
+    },
     client::rr::{LowerName, RrKey},
     store::{
         forwarder::{ForwardAuthority, ForwardConfig},
@@ -32,6 +56,48 @@ use trust_dns_server::{
 
 use zerotier_api::central_api;
 
+// label under the domain where the network's managed routes are published.
+pub const ROUTES_TXT_LABEL: &str = "_routes";
+// label under the domain where members are published by their ZeroTier address.
+pub const ADDRESS_LABEL: &str = "addr";
+// the most NS, MX or SRV targets whose addresses are added to a single answer.
+pub const MAX_GLUE_TARGETS: usize = 8;
+
+// SyncSchedule selects how member syncs are spaced out.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum SyncSchedule {
+    // syncs start on a fixed cadence; after a slow sync the missed ones run back to back.
+    #[default]
+    #[serde(rename = "interval")]
+    Interval,
+    // each sync starts a full interval after the previous one finished.
+    #[serde(rename = "fixed-delay")]
+    FixedDelay,
+}
+
+impl std::fmt::Display for SyncSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SyncSchedule::Interval => "interval",
+            SyncSchedule::FixedDelay => "fixed-delay",
+        })
+    }
+}
+
+impl FromStr for SyncSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interval" => Ok(SyncSchedule::Interval),
+            "fixed-delay" => Ok(SyncSchedule::FixedDelay),
+            _ => Err(anyhow!(
+                "invalid format: allowed values: [interval, fixed-delay]"
+            )),
+        }
+    }
+}
+
 pub async fn find_members(mut zt: ZTAuthority) {
     let mut timer = tokio::time::interval(zt.update_interval);
 
@@ -41,24 +107,74 @@ pub async fn find_members(mut zt: ZTAuthority) {
             Err(e) => tracing::error!("error refreshing hosts file: {}", e),
         }
 
-        match zt.get_members().await {
-            Ok((network, members)) => match zt.configure_members(network, members).await {
-                Ok(_) => {}
-                Err(e) => {
-                    tracing::error!("error configuring authority: {}", e)
-                }
-            },
-            Err(e) => {
-                tracing::error!("error syncing members: {}", e)
+        let synced = zt.sync_members().await;
+        if let Err(e) = &synced {
+            tracing::error!("{}", e)
+        }
+
+        zt.forward_metrics.log();
+
+        if let Some(statsd) = &zt.statsd {
+            statsd.report(
+                &zt.forward_metrics,
+                synced.is_err(),
+                zt.record_count().await,
+            );
+        }
+
+        // the interval's first tick completes immediately, so that schedule syncs twice at
+        // startup; fixed-delay always waits.
+        match zt.sync_schedule {
+            SyncSchedule::Interval => {
+                timer.tick().await;
             }
+            SyncSchedule::FixedDelay => tokio::time::sleep(zt.update_interval).await,
         }
+    }
+}
 
-        timer.tick().await;
+// spawn_sync starts find_members. With threads set, it runs on a runtime of its own with that many
+// workers, so a large sync does not take them from the DNS listeners; the records are shared with
+// the listeners either way, and syncs still run one after another.
+pub fn spawn_sync(zt: ZTAuthority, threads: Option<usize>) -> Result<(), anyhow::Error> {
+    match threads {
+        Some(threads) => spawn_dedicated(threads, find_members(zt)),
+        None => {
+            tokio::spawn(find_members(zt));
+            Ok(())
+        }
     }
 }
 
-pub async fn init_catalog(zt: ZTAuthority) -> Result<Catalog, anyhow::Error> {
+// spawn_dedicated runs a future to completion on a new runtime with its own worker threads.
+pub fn spawn_dedicated<F>(threads: usize, future: F) -> Result<(), anyhow::Error>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(threads.max(1))
+        .thread_name("zeronsd-sync")
+        .enable_all()
+        .build()?;
+
+    std::thread::Builder::new()
+        .name("zeronsd-sync".to_string())
+        .spawn(move || runtime.block_on(future))?;
+
+    Ok(())
+}
+
+// init_catalog builds the catalog served on listen_ip, or for local clients if there is none. Zones
+// that are limited to other listen IPs are still present, but refuse every query, so they are not
+// passed on to the forwarder either.
+pub async fn init_catalog(
+    zt: ZTAuthority,
+    listen_ip: Option<IpAddr>,
+) -> Result<Catalog, anyhow::Error> {
     let mut catalog = Catalog::default();
+    let serves = |ips: &[IpAddr]| {
+        ips.is_empty() || listen_ip.is_none_or(|listen_ip| ips.contains(&listen_ip))
+    };
 
     let resolv = trust_dns_resolver::system_conf::read_system_conf()?;
     let mut nsconfig = NameServerConfigGroup::new();
@@ -67,7 +183,17 @@ pub async fn init_catalog(zt: ZTAuthority) -> Result<Catalog, anyhow::Error> {
         nsconfig.push(server.clone());
     }
 
-    let options = Some(resolv.1);
+    // the resolver's own cache keeps answers for as long as clients are told to.
+    let mut options = resolv.1;
+    options.positive_min_ttl = zt
+        .forward_ttl
+        .min
+        .map(|ttl| Duration::from_secs(ttl.into()));
+    options.positive_max_ttl = zt
+        .forward_ttl
+        .max
+        .map(|ttl| Duration::from_secs(ttl.into()));
+    let options = Some(options);
     let config = &ForwardConfig {
         name_servers: nsconfig.clone(),
         options,
@@ -80,20 +206,90 @@ pub async fn init_catalog(zt: ZTAuthority) -> Result<Catalog, anyhow::Error> {
     )
     .expect("Could not initialize forwarder");
 
-    catalog.upsert(Name::root().into(), Box::new(Arc::new(forwarder)));
+    catalog.upsert(
+        Name::root().into(),
+        Box::new(Arc::new(ZTForwarder::new(
+            forwarder,
+            zt.forward_metrics.clone(),
+            zt.blocklist.clone(),
+            zt.stale_cache.clone(),
+            zt.forward_ttl,
+        ))),
+    );
 
+    let mut forward_authority = zt.forward_authority.clone();
+    forward_authority.any_response = zt.any_response;
+    forward_authority.glue = zt.glue;
+    forward_authority.catch_all = zt.catch_all_ip.map(|ip| (ip, zt.ready.clone()));
+    forward_authority.online = zt.online.clone();
+    forward_authority.refuse = !serves(&zt.forward_ips);
+    forward_authority.set_soa_timers(zt.soa_timers).await;
     catalog.upsert(
-        zt.forward_authority.domain_name.clone(),
-        zt.forward_authority.box_clone(),
+        forward_authority.domain_name.clone(),
+        forward_authority.box_clone(),
     );
 
-    for (network, authority) in zt.reverse_authority_map {
+    for mut authority in zt.alias_authorities {
+        authority.any_response = zt.any_response;
+        authority.glue = zt.glue;
+        authority.catch_all = zt.catch_all_ip.map(|ip| (ip, zt.ready.clone()));
+        authority.online = zt.online.clone();
+        authority.refuse = !serves(&zt.forward_ips);
+        authority.set_soa_timers(zt.soa_timers).await;
+        catalog.upsert(authority.domain_name.clone(), authority.box_clone());
+    }
+
+    let managed_reverse = zt
+        .reverse_authority_map
+        .values()
+        .map(|authority| Name::from(authority.domain_name.clone()))
+        .collect::<Vec<Name>>();
+
+    for (network, mut authority) in zt.reverse_authority_map {
+        authority.any_response = zt.any_response;
+        authority.glue = zt.glue;
+        authority.refuse = !serves(&zt.reverse_ips);
+        authority.set_soa_timers(zt.reverse_soa_timers).await;
         catalog.upsert(network.to_ptr_soa_name()?, authority.box_clone())
     }
 
+    // the private ranges are served as empty zones, so their names do not exist rather than time
+    // out upstream. The catalog picks the most specific zone, so the reverse zones within them
+    // still answer; a private zone that is one of them or inside one is left out.
+    if zt.unmanaged_reverse == UnmanagedReverse::NXDomain {
+        for zone in private_reverse_zones()? {
+            if managed_reverse.iter().any(|managed| managed.zone_of(&zone)) {
+                continue;
+            }
+
+            let mut authority = RecordAuthority::new(
+                zone.clone().into(),
+                zone.clone().into(),
+                zt.forward_authority.ttl,
+            )
+            .await?;
+            authority.refuse = !serves(&zt.reverse_ips);
+            authority.set_soa_timers(zt.reverse_soa_timers).await;
+            catalog.upsert(zone.into(), authority.box_clone());
+        }
+    }
+
     Ok(catalog)
 }
 
+// private_reverse_zones are the reverse zones of the RFC 1918 and unique local ranges, as RFC 6303
+// lists them.
+fn private_reverse_zones() -> Result<Vec<Name>, anyhow::Error> {
+    let mut zones = vec![Name::from_str("10.in-addr.arpa.")?];
+    for octet in 16..=31 {
+        zones.push(Name::from_str(&format!("{}.172.in-addr.arpa.", octet))?);
+    }
+    zones.push(Name::from_str("168.192.in-addr.arpa.")?);
+    zones.push(Name::from_str("d.f.ip6.arpa.")?);
+
+    Ok(zones)
+}
+
 #[derive(Clone)]
 pub struct ZTAuthority {
     pub network_id: String,
@@ -104,548 +300,4762 @@ pub struct ZTAuthority {
     pub wildcard: bool,
     pub update_interval: Duration,
     pub hosts: Option<Box<HostsFile>>,
+    pub audit: Option<AuditLog>,
+    pub routes_txt: bool,
+    pub hosts_ttl: Option<u32>,
+    pub wildcard_families: IpFamilies,
+    pub overrides_file: Option<PathBuf>,
+    // the overrides as of the last time the file parsed.
+    pub overrides: Arc<Mutex<Overrides>>,
+    pub forward_metrics: ForwardMetrics,
+    pub blocklist: Option<Blocklist>,
+    pub max_udp_size: Option<u16>,
+    pub truncation: Truncation,
+    // when set, the hosts file is only re-read this often (or when it changes) instead of on every
+    // sync.
+    pub hosts_interval: Option<Duration>,
+    pub hosts_loaded: Option<(Instant, Vec<Option<SystemTime>>)>,
+    pub sync_timeout: Option<Duration>,
+    pub ptr_format: PtrFormat,
+    pub controller_name: Option<Name>,
+    pub ptr_families: IpFamilies,
+    // publish only the lowest assigned address of each family of a member.
+    pub primary_ip: bool,
+    pub min_name_length: usize,
+    pub host_name: Option<Name>,
+    pub host_ips: Vec<IpAddr>,
+    pub any_response: AnyResponse,
+    pub sync_schedule: SyncSchedule,
+    pub forward_ips: Vec<IpAddr>,
+    pub reverse_ips: Vec<IpAddr>,
+    pub ns_hosts: Vec<(Name, Vec<IpAddr>)>,
+    pub min_members: usize,
+    pub authorized_txt: Option<TimeFormat>,
+    // publish the ZeroTier version and protocol each member runs in TXT records at its names.
+    pub version_txt: bool,
+    pub tcp_keepalive: bool,
+    pub presync_response: PresyncResponse,
+    // set once the first sync has configured the members.
+    pub ready: Arc<AtomicBool>,
+    // additional hosts files, each with the domain its names are placed in.
+    pub hosts_sources: Vec<(PathBuf, Name)>,
+    pub shared_ip: SharedIpPolicy,
+    pub statsd: Option<StatsdSink>,
+    pub truncate_names: bool,
+    pub chaos_version: bool,
+    pub chaos_info: bool,
+    // when this instance started, for the uptime in the CHAOS info answer.
+    pub started: Instant,
+    pub stale_cache: Option<StaleCache>,
+    pub webhook: Option<Webhook>,
+    pub max_tcp_connections: Option<usize>,
+    pub socket_options: SocketOptions,
+    // when set, the reverse zones are only rebuilt this often, or when their PTR records change,
+    // instead of on every sync.
+    pub reverse_interval: Option<Duration>,
+    pub reverse_refreshed: Arc<Mutex<Option<(Instant, ReverseInputs)>>>,
+    pub glue: bool,
+    pub refuse_client_subnet: bool,
+    // further domains serving a copy of the forward zone, rebuilt from it after every sync.
+    pub alias_authorities: Vec<RecordAuthority>,
+    // a fixed name for the gateway's addresses, published whatever the members are.
+    pub gateway: Option<(Name, Vec<IpAddr>)>,
+    pub missing_hosts: MissingHosts,
+    // when set, DNS-over-QUIC is also served on every listen IP.
+    pub doq: Option<DoqConfig>,
+    // the answer to A or AAAA queries for names in the forward zones that do not exist.
+    pub catch_all_ip: Option<IpAddr>,
+    // when set, members that are not authorized are only published under this domain.
+    pub pending_domain: Option<Name>,
+    // when set, a hosts file name that a member also claims answers with the addresses of both.
+    pub merge_names: bool,
+    // a name answered with the loopback addresses on every listener, whatever the sync state.
+    pub health_name: Option<Name>,
+    // publish each member as <node id>.addr.<domain> too.
+    pub address_names: bool,
+    pub soa_timers: SoaTimers,
+    // the timers of the reverse zones, which may cache negative answers for longer.
+    pub reverse_soa_timers: SoaTimers,
+    // whether root and NS referral queries are forwarded or refused.
+    pub root_queries: RootQueries,
+    // bounds for the TTLs of forwarded answers.
+    pub forward_ttl: TtlClamp,
+    // when set, members that have not been online lately answer without their addresses.
+    pub online: Option<Liveness>,
+    // tags whose values name groups of members, each answering with the addresses of all of them.
+    pub group_tags: Vec<String>,
+    // whether reverse queries for private ranges outside the reverse zones are forwarded.
+    pub unmanaged_reverse: UnmanagedReverse,
+    // which custom member names are dropped for looking like another member's zt-<member id>.
+    pub id_name_collision: IdNameCollision,
+    // the name every listener answers with this instance's node id in a TXT record, for the
+    // primary election.
+    pub instance_txt: Option<(Name, String)>,
 }
 
-impl ZTAuthority {
-    pub async fn configure_hosts(&mut self) -> Result<(), anyhow::Error> {
-        self.hosts = Some(Box::new(parse_hosts(
-            self.hosts_file.clone(),
-            self.forward_authority.domain_name.clone().into(),
-        )?));
-
-        for (ip, hostnames) in self.hosts.clone().unwrap().iter() {
-            for hostname in hostnames {
-                self.forward_authority
-                    .match_or_insert(hostname.clone(), &[*ip])
-                    .await;
+// ReverseInputs is the PTR records a sync builds the reverse zones from: the names each address
+// points at, from members, shared ips, the gateway and this host alike.
+pub type ReverseInputs = BTreeMap<IpAddr, Vec<Name>>;
+
+// without_soa copies a zone's records except for the SOA, which changes with every serial bump.
+fn without_soa(records: &BTreeMap<RrKey, Arc<RecordSet>>) -> BTreeMap<RrKey, Arc<RecordSet>> {
+    records
+        .iter()
+        .filter(|(key, _)| key.record_type != RecordType::SOA)
+        .map(|(key, rset)| (key.clone(), rset.clone()))
+        .collect()
+}
+
+// hosts_by_name inverts the hosts file into name -> ips, so each name's records can be computed in
+// full before they are written.
+fn hosts_by_name(hosts: &HostsFile) -> HashMap<Name, Vec<IpAddr>> {
+    let mut hosts_map: HashMap<Name, Vec<IpAddr>> = HashMap::new();
+
+    for (ip, hosts) in hosts.iter() {
+        for host in hosts {
+            let ips = hosts_map.entry(host.clone()).or_default();
+            if !ips.contains(ip) {
+                ips.push(*ip);
             }
         }
-
-        Ok(())
     }
 
-    pub async fn configure_members(
-        &self,
-        network: central_api::types::Network,
-        members: Vec<central_api::types::Member>,
-    ) -> Result<(), anyhow::Error> {
-        let mut forward_records = vec![self.forward_authority.domain_name.clone()];
-        let mut reverse_records = HashMap::new();
+    hosts_map
+}
 
-        self.reverse_authority_map
-            .iter()
-            .for_each(|(network, authority)| {
-                reverse_records.insert(network, vec![authority.domain_name.clone()]);
-            });
+// primary_ips keeps the lowest of ips in each family, IPv4 first.
+fn primary_ips(ips: &[IpAddr]) -> Vec<IpAddr> {
+    let v4 = ips.iter().filter(|ip| ip.is_ipv4()).min();
+    let v6 = ips.iter().filter(|ip| ip.is_ipv6()).min();
+    v4.into_iter().chain(v6).copied().collect()
+}
 
-        if let Some(hosts) = self.hosts.clone() {
-            self.forward_authority.prune_hosts(hosts.clone()).await?;
-            forward_records.append(&mut hosts.values().flatten().map(|v| v.into()).collect());
+// merge_ips appends the addresses in more that ips does not already have.
+fn merge_ips(ips: &[IpAddr], more: &[IpAddr]) -> Vec<IpAddr> {
+    let mut ips = ips.to_vec();
+    for ip in more {
+        if !ips.contains(ip) {
+            ips.push(*ip);
         }
+    }
 
-        let (mut sixplane, mut rfc4193) = (None, None);
+    ips
+}
 
-        let v6assign = network.config.clone().unwrap().v6_assign_mode;
-        if let Some(v6assign) = v6assign {
-            if v6assign._6plane.unwrap_or(false) {
-                let s = network.clone().sixplane()?;
-                sixplane = Some(s);
-            }
+// tag_groups maps the ids of the tags members are grouped by to the names of their values, as the
+// network's rules define them.
+fn tag_groups(
+    network: &central_api::types::Network,
+    tags: &[String],
+) -> HashMap<i64, HashMap<i64, String>> {
+    let mut groups = HashMap::new();
 
-            if v6assign.rfc4193.unwrap_or(false) {
-                let s = network.clone().rfc4193()?;
-                rfc4193 = Some(s);
-                reverse_records
-                    .get_mut(&s)
-                    .unwrap()
-                    .push(s.to_ptr_soa_name()?)
+    for tag in tags {
+        let definition = match network.tags_by_name.as_ref().and_then(|t| t.get(tag)) {
+            Some(definition) => definition,
+            None => {
+                tracing::warn!("Tag {} is not defined in the network's rules", tag);
+                continue;
             }
-        }
+        };
 
-        for member in members {
-            let record = ZTRecord::new(
-                &member,
-                sixplane,
-                rfc4193,
-                self.forward_authority.domain_name.clone().into(),
-                self.wildcard,
-            )?;
+        let id = match definition.get("id").and_then(serde_json::Value::as_i64) {
+            Some(id) => id,
+            None => continue,
+        };
 
-            self.forward_authority
-                .insert_member(&mut forward_records, record.clone())
-                .await?;
+        let values = definition
+            .get("enums")
+            .and_then(serde_json::Value::as_object)
+            .map(|enums| {
+                enums
+                    .iter()
+                    .filter_map(|(name, value)| Some((value.as_i64()?, name.clone())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        groups.insert(id, values);
+    }
 
-            if let Some(ips) = member.clone().config.and_then(|c| {
-                c.ip_assignments.map(|v| {
-                    v.iter()
-                        .filter_map(|ip| IpAddr::from_str(ip).ok())
-                        .collect::<Vec<IpAddr>>()
+    groups
+}
+
+// member_groups names the groups a member is in, one for each tag members are grouped by. Values
+// the rules give no name are named by their number.
+fn member_groups(
+    member: &central_api::types::Member,
+    groups: &HashMap<i64, HashMap<i64, String>>,
+) -> Vec<String> {
+    use central_api::types::MemberConfigTagsItemItem::Variant0;
+
+    member
+        .config
+        .as_ref()
+        .and_then(|c| c.tags.as_ref())
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|tag| match tag.as_slice() {
+                    [Variant0(id), Variant0(value)] => Some(
+                        groups
+                            .get(id)?
+                            .get(value)
+                            .cloned()
+                            .unwrap_or_else(|| value.to_string()),
+                    ),
+                    _ => None,
                 })
-            }) {
-                for (network, authority) in self.reverse_authority_map.clone() {
-                    for ip in ips.clone() {
-                        if network.contains(ip) {
-                            authority
-                                .insert_member_ptr(
-                                    reverse_records.get_mut(&network).unwrap(),
-                                    record.clone(),
-                                )
-                                .await?;
-                        }
-                    }
-                }
-            }
+                .collect()
+        })
+        .unwrap_or_default()
+}
 
-            if let Some(ptr) = rfc4193 {
-                if let Some(authority) = self.reverse_authority_map.get(&ptr) {
-                    if let Some(records) = reverse_records.get_mut(&ptr) {
-                        let ptr = member.rfc4193()?.ip().into_name()?;
-                        authority
-                            .configure_ptr(ptr.clone(), record.ptr_name.clone())
-                            .await?;
-                        records.push(ptr.into());
-                    }
-                }
-            }
-        }
+// record_sets groups records by name and type, in the order they come in.
+fn record_sets<'a>(records: impl Iterator<Item = &'a Record>) -> Vec<Arc<RecordSet>> {
+    let mut sets: Vec<RecordSet> = Vec::new();
 
-        self.forward_authority
-            .prune_records(forward_records.clone())
-            .await?;
+    for record in records {
+        let rdata = match record.data() {
+            Some(rdata) => rdata.clone(),
+            None => continue,
+        };
 
-        for (network, authority) in self.reverse_authority_map.clone() {
-            authority
-                .prune_records(reverse_records.get(&network).unwrap().clone())
-                .await?;
+        match sets
+            .iter_mut()
+            .find(|set| set.name() == record.name() && set.record_type() == record.record_type())
+        {
+            Some(set) => {
+                set.add_rdata(rdata);
+            }
+            None => {
+                let mut set =
+                    RecordSet::with_ttl(record.name().clone(), record.record_type(), record.ttl());
+                set.add_rdata(rdata);
+                sets.push(set);
+            }
         }
-
-        Ok(())
     }
 
-    pub async fn get_members(
-        &self,
-    ) -> Result<(central_api::types::Network, Vec<central_api::types::Member>), anyhow::Error> {
-        let client = self.client.clone();
-        let network_id = self.network_id.clone();
+    sets.into_iter().map(Arc::new).collect()
+}
 
-        let members = client.get_network_member_list(&network_id).await?;
-        let network = client.get_network_by_id(&network_id).await?;
+// rdatas_match compares the contents of a record set against a list of rdata, ignoring order.
+fn rdatas_match(rset: &RecordSet, rdatas: &[RData]) -> bool {
+    let current: Vec<&RData> = rset
+        .records_without_rrsigs()
+        .filter_map(|r| r.data())
+        .collect();
 
-        Ok((network.to_owned(), members.to_owned()))
-    }
+    current.len() == rdatas.len() && rdatas.iter().all(|rd| current.contains(&rd))
 }
 
+// Liveness holds when each member name was last online, as Central reported it on the last sync.
+// Names are kept relative to the zone, so the alias zones share it. A member that goes offline
+// stops answering once `within` has passed since it was last online, without waiting for a sync;
+// one that comes back only answers again after the next sync has seen it.
 #[derive(Clone)]
-pub struct RecordAuthority {
-    domain_name: LowerName,
-    authority: Arc<InMemoryAuthority>,
+pub struct Liveness {
+    within: Duration,
+    last_online: Arc<std::sync::Mutex<HashMap<Name, i64>>>,
 }
 
-impl RecordAuthority {
-    pub async fn new(
-        domain_name: LowerName,
-        member_name: LowerName,
-    ) -> Result<Self, anyhow::Error> {
-        Ok(Self {
-            authority: Arc::new(
-                Self::configure_authority(domain_name.clone().into(), member_name.into()).await?,
-            ),
-            domain_name,
-        })
+impl Liveness {
+    pub fn new(within: Duration) -> Self {
+        Self {
+            within,
+            last_online: Default::default(),
+        }
     }
 
-    async fn configure_authority(
-        domain_name: Name,
-        member_name: Name,
-    ) -> Result<InMemoryAuthority, anyhow::Error> {
-        let mut map = BTreeMap::new();
-        let mut soa = Record::with(domain_name.clone(), RecordType::SOA, 30);
+    fn relative(zone: &LowerName, name: &Name) -> Option<Name> {
+        let zone: Name = zone.clone().into();
+        if !zone.zone_of(name) {
+            return None;
+        }
 
-        soa.set_data(Some(RData::SOA(SOA::new(
-            domain_name.clone(),
-            Name::from_str("administrator")?.append_domain(&domain_name)?,
-            1,
-            30,
-            0,
-            -1,
-            0,
-        ))));
+        let labels = name.num_labels() - zone.num_labels();
+        Name::from_labels(name.iter().take(labels as usize)).ok()
+    }
 
-        let mut soa_rs = RecordSet::new(&domain_name, RecordType::SOA, 1);
-        soa_rs.insert(soa, 1);
-        map.insert(
-            RrKey::new(domain_name.clone().into(), RecordType::SOA),
-            soa_rs,
-        );
+    // update replaces the last online times with those of a sync, given in milliseconds since the
+    // epoch for names in zone.
+    fn update(&self, zone: &LowerName, last_online: HashMap<LowerName, i64>) {
+        let last_online = last_online
+            .into_iter()
+            .filter_map(|(name, time)| Some((Self::relative(zone, &name.into())?, time)))
+            .collect();
 
-        let mut ns = Record::with(domain_name.clone(), RecordType::NS, 30);
-        ns.set_data(Some(RData::NS(member_name)));
-        let mut ns_rs = RecordSet::new(&domain_name, RecordType::NS, 1);
-        ns_rs.insert(ns, 1);
+        *self.last_online.lock().unwrap() = last_online;
+    }
 
-        map.insert(
-            RrKey::new(domain_name.clone().into(), RecordType::NS),
-            ns_rs,
-        );
+    // offline reports whether the name belongs to a member that has not been online within the
+    // last `within`. Names Central gave no time for are never offline.
+    fn offline(&self, zone: &LowerName, name: &Name, now: SystemTime) -> bool {
+        let name = match Self::relative(zone, name) {
+            Some(name) => name,
+            None => return false,
+        };
 
-        let authority = InMemoryAuthority::new(
-            domain_name,
-            map,
-            trust_dns_server::authority::ZoneType::Primary,
-            false,
-        )
-        .expect("Could not initialize authority");
+        let last_online = match self.last_online.lock().unwrap().get(&name) {
+            Some(time) => SystemTime::UNIX_EPOCH + Duration::from_millis(*time as u64),
+            None => return false,
+        };
 
-        Ok(authority)
+        now.duration_since(last_online)
+            .map(|since| since > self.within)
+            .unwrap_or(false)
     }
+}
 
-    async fn replace_ip_record(&self, fqdn: Name, rdatas: Vec<RData>) {
-        let serial = self.authority.serial().await;
-        for rdata in rdatas {
-            let mut address = Record::with(fqdn.clone(), rdata.to_record_type(), 60);
-            address.set_data(Some(rdata.clone()));
-            tracing::info!("Adding new record {}: ({})", fqdn.clone(), rdata);
-            self.authority.upsert(address, serial).await;
+impl ZTAuthority {
+    /// Builds an authority serving the given zones with every optional feature turned off. Nothing
+    /// is contacted here; the client is only used by `get_members`, so members can also be fed in
+    /// directly with `configure_members` when embedding or testing without Central.
+    pub fn new(
+        network_id: String,
+        client: central_api::Client,
+        forward_authority: RecordAuthority,
+        reverse_authority_map: HashMap<IpNetwork, RecordAuthority>,
+    ) -> Self {
+        Self {
+            network_id,
+            client,
+            forward_authority,
+            reverse_authority_map,
+            hosts_file: None,
+            wildcard: false,
+            update_interval: Duration::new(30, 0),
+            hosts: None,
+            audit: None,
+            routes_txt: false,
+            hosts_ttl: None,
+            wildcard_families: Default::default(),
+            overrides_file: None,
+            overrides: Default::default(),
+            forward_metrics: Default::default(),
+            hosts_interval: None,
+            hosts_loaded: None,
+            blocklist: None,
+            max_udp_size: None,
+            truncation: Default::default(),
+            sync_timeout: None,
+            ptr_format: Default::default(),
+            controller_name: None,
+            ptr_families: IpFamilies::Both,
+            min_name_length: 0,
+            primary_ip: false,
+            host_name: None,
+            host_ips: Vec::new(),
+            any_response: AnyResponse::Records,
+            sync_schedule: SyncSchedule::Interval,
+            forward_ips: Vec::new(),
+            reverse_ips: Vec::new(),
+            ns_hosts: Vec::new(),
+            min_members: 0,
+            authorized_txt: None,
+            version_txt: false,
+            tcp_keepalive: false,
+            presync_response: PresyncResponse::NXDomain,
+            ready: Default::default(),
+            unmanaged_reverse: UnmanagedReverse::Forward,
+            id_name_collision: IdNameCollision::Allow,
+            group_tags: Vec::new(),
+            online: None,
+            forward_ttl: TtlClamp::default(),
+            root_queries: RootQueries::Forward,
+            soa_timers: SoaTimers::default(),
+            reverse_soa_timers: SoaTimers::default(),
+            address_names: false,
+            health_name: None,
+            merge_names: false,
+            pending_domain: None,
+            catch_all_ip: None,
+            doq: None,
+            missing_hosts: MissingHosts::Keep,
+            gateway: None,
+            alias_authorities: Vec::new(),
+            refuse_client_subnet: false,
+            glue: false,
+            reverse_refreshed: Default::default(),
+            reverse_interval: None,
+            socket_options: SocketOptions::default(),
+            max_tcp_connections: None,
+            webhook: None,
+            stale_cache: None,
+            chaos_version: false,
+            chaos_info: false,
+            started: Instant::now(),
+            truncate_names: false,
+            statsd: None,
+            hosts_sources: Vec::new(),
+            shared_ip: SharedIpPolicy::Warn,
+            instance_txt: None,
         }
     }
 
-    async fn prune_hosts(&self, hosts: Box<HostsFile>) -> Result<(), anyhow::Error> {
-        let serial = self.authority.serial().await;
-        let mut rr = self.authority.records_mut().await;
+    // record_count is the number of record sets across the forward, alias and reverse zones.
+    pub async fn record_count(&self) -> usize {
+        let mut count = self.forward_authority.authority.records().await.len();
+        for authority in &self.alias_authorities {
+            count += authority.authority.records().await.len();
+        }
+        for authority in self.reverse_authority_map.values() {
+            count += authority.authority.records().await.len();
+        }
+        count
+    }
 
-        let mut hosts_map = HashMap::new();
+    // ptr_ips lists the member's assigned IPs that get PTRs; PTRs can be limited to one family,
+    // while the forward records still carry every ip.
+    fn ptr_ips(&self, member: &central_api::types::Member) -> Vec<IpAddr> {
+        let ips: Vec<IpAddr> = member
+            .config
+            .as_ref()
+            .and_then(|c| c.ip_assignments.as_ref())
+            .map(|v| {
+                v.iter()
+                    .filter_map(|ip| IpAddr::from_str(ip).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
 
-        for (ip, hosts) in hosts.into_iter() {
-            for host in hosts {
-                if !hosts_map.contains_key(&host) {
-                    hosts_map.insert(host.clone(), vec![]);
-                }
+        // an address that is not published gets no PTR either.
+        let ips = if self.primary_ip {
+            primary_ips(&ips)
+        } else {
+            ips
+        };
 
-                hosts_map.get_mut(&host).unwrap().push(ip);
-            }
-        }
+        ips.into_iter()
+            .filter(|ip| self.ptr_families.includes(ip))
+            .collect()
+    }
 
-        for (host, ips) in hosts_map.into_iter() {
-            for (rrkey, rset) in rr.clone() {
-                let key = &rrkey.name().into_name().expect("could not parse name");
-                let records = rset.records(false, SupportedAlgorithms::all());
+    // reverse_served tells whether any reverse zone covers ip.
+    fn reverse_served(&self, ip: IpAddr) -> bool {
+        self.reverse_authority_map
+            .keys()
+            .any(|network| network.contains(ip))
+    }
 
-                let rt = rset.record_type();
-                let rdatas: Vec<RData> = ips
-                    .clone()
-                    .into_iter()
-                    .filter_map(|i| match i {
-                        IpAddr::V4(ip) => {
-                            if rt == RecordType::A {
-                                Some(RData::A(ip))
-                            } else {
-                                None
-                            }
-                        }
-                        IpAddr::V6(ip) => {
-                            if rt == RecordType::AAAA {
-                                Some(RData::AAAA(ip))
-                            } else {
-                                None
-                            }
-                        }
-                    })
-                    .collect();
+    // reverse_due decides whether this sync rebuilds the reverse zones. Without a reverse interval
+    // it always does. With one, it does once the interval has passed, or right away when the PTR
+    // records it would write changed, whatever changed them.
+    async fn reverse_due(&self, inputs: &ReverseInputs) -> bool {
+        let interval = match self.reverse_interval {
+            Some(interval) => interval,
+            None => return true,
+        };
 
-                if key.eq(&host)
-                    && (records.is_empty()
-                        || !records
-                            .map(|r| r.data().unwrap())
-                            .all(|rd| rdatas.contains(rd)))
-                {
-                    let mut new_rset = RecordSet::new(key, rt, serial);
-                    for rdata in rdatas.clone() {
-                        new_rset.add_rdata(rdata);
-                    }
+        match &*self.reverse_refreshed.lock().await {
+            Some((refreshed, prior)) => prior != inputs || refreshed.elapsed() >= interval,
+            None => true,
+        }
+    }
 
-                    tracing::warn!("Replacing host record for {} with {:?}", key, ips);
-                    rr.remove(&rrkey);
-                    rr.insert(rrkey.clone(), Arc::new(new_rset));
-                }
+    // zones lists the names of the forward, alias and reverse zones.
+    pub fn zones(&self) -> Vec<LowerName> {
+        let mut zones = vec![self.forward_authority.domain_name.clone()];
+        zones.extend(
+            self.alias_authorities
+                .iter()
+                .map(|authority| authority.domain_name.clone()),
+        );
+        zones.extend(
+            self.reverse_authority_map
+                .values()
+                .map(|authority| authority.domain_name.clone()),
+        );
+        zones
+    }
+
+    fn hosts_modified(&self) -> Vec<Option<SystemTime>> {
+        self.hosts_file
+            .iter()
+            .chain(self.hosts_sources.iter().map(|(path, _)| path))
+            .map(|f| std::fs::metadata(f).ok().and_then(|m| m.modified().ok()))
+            .collect()
+    }
+
+    // hosts_due reports whether the hosts files should be re-read on this sync.
+    fn hosts_due(&self) -> bool {
+        match (self.hosts_interval, &self.hosts_loaded) {
+            (Some(interval), Some((at, modified))) => {
+                at.elapsed() >= interval || self.hosts_modified() != *modified
             }
+            _ => true,
         }
-
-        Ok(())
     }
 
-    async fn prune_records(&self, written: Vec<LowerName>) -> Result<(), anyhow::Error> {
-        let mut rrkey_list = Vec::new();
+    // configure_hosts re-reads the hosts files if they are due and returns whether it did. The last
+    // parsed copy is kept either way, so members are always pruned against it.
+    pub async fn configure_hosts(&mut self) -> Result<bool, anyhow::Error> {
+        if !self.hosts_due() {
+            return Ok(false);
+        }
 
-        let mut rr = self.authority.records_mut().await;
+        let modified = self.hosts_modified();
+        let read = |path: &PathBuf, domain: Name| match parse_hosts(Some(path.clone()), domain) {
+            Err(e)
+                if e.kind() == std::io::ErrorKind::NotFound
+                    && self.missing_hosts == MissingHosts::Clear =>
+            {
+                tracing::warn!(
+                    "hosts file {} is missing; removing its records",
+                    path.display()
+                );
+                Ok(HostsFile::new())
+            }
+            Err(e) => Err(anyhow!(
+                "{}: {}; keeping its last records",
+                path.display(),
+                e
+            )),
+            Ok(hosts) => Ok(hosts),
+        };
 
-        for (rrkey, rs) in rr.clone() {
-            let key = &rrkey.name().into_name()?.into();
-            if !written.contains(key) && rs.record_type() != RecordType::SOA {
-                rrkey_list.push(rrkey);
+        let mut hosts = match &self.hosts_file {
+            Some(path) => read(path, self.forward_authority.domain_name.clone().into())?,
+            None => HostsFile::new(),
+        };
+
+        for (path, domain) in &self.hosts_sources {
+            for (ip, mut names) in read(path, domain.clone())? {
+                hosts.entry(ip).or_default().append(&mut names);
             }
         }
 
-        for rrkey in rrkey_list {
-            tracing::warn!("Removing expired record {}", rrkey.name());
-            rr.remove(&rrkey);
+        // fully qualified names may point anywhere, but only those in the zone are ever asked for.
+        let zone = &self.forward_authority.domain_name;
+        for names in hosts.values_mut() {
+            names.retain(|name| {
+                let covered = zone.zone_of(&name.into());
+                if !covered {
+                    tracing::warn!("hosts name {} is not in {}; skipping it", name, zone);
+                }
+                covered
+            });
         }
+        hosts.retain(|_, names| !names.is_empty());
 
-        Ok(())
-    }
+        self.hosts = Some(Box::new(hosts));
+        self.hosts_loaded = Some((Instant::now(), modified));
+
+        let ttl = self.hosts_ttl.unwrap_or(self.forward_authority.ttl);
+
+        for (hostname, ips) in hosts_by_name(self.hosts.as_ref().unwrap()) {
+            // a member's addresses on the name stay until the next members sync recomputes it.
+            let ips = if self.merge_names {
+                merge_ips(&self.forward_authority.address_ips(&hostname).await, &ips)
+            } else {
+                ips
+            };
+
+            self.forward_authority
+                .match_or_insert_ttl(hostname, &ips, ttl)
+                .await;
+        }
+
+        Ok(true)
+    }
+
+    // sync_members fetches the members from central and applies them, giving up once the sync
+    // timeout passes. Every write is idempotent and pruning only happens at the end, so an abandoned
+    // sync leaves the previous records in place for the next one to converge.
+    pub async fn sync_members(&self) -> Result<(), anyhow::Error> {
+        let sync = async {
+            let (network, members) = self
+                .get_members()
+                .await
+                .map_err(|e| anyhow!("error syncing members: {}", e))?;
+
+            self.configure_members(network, members)
+                .await
+                .map_err(|e| anyhow!("error configuring authority: {}", e))
+        };
+
+        match self.sync_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, sync)
+                .await
+                .map_err(|_| anyhow!("member sync timed out after {:?}; skipping", timeout))?,
+            None => sync.await,
+        }
+    }
+
+    pub async fn configure_members(
+        &self,
+        network: central_api::types::Network,
+        members: Vec<central_api::types::Member>,
+    ) -> Result<(), anyhow::Error> {
+        // members without a node id, such as pending invites, have nothing to be named after.
+        let members: Vec<_> = members
+            .into_iter()
+            .filter(|member| {
+                if member.node_id.is_none() {
+                    tracing::debug!(
+                        "Skipping member {} of network {}: it has no node id",
+                        member.id.as_deref().unwrap_or("<unknown>"),
+                        self.network_id
+                    );
+                }
+                member.node_id.is_some()
+            })
+            .collect();
+
+        // a short member list is more likely a Central glitch than a network that lost its members
+        // at once; pruning against it would take the names down.
+        if members.len() < self.min_members {
+            tracing::warn!(
+                "Central returned {} members, fewer than the minimum of {}; keeping the current records",
+                members.len(),
+                self.min_members
+            );
+            return Ok(());
+        }
+
+        // pending members are kept out of everything but their names in the pending domain.
+        let (members, pending): (Vec<_>, Vec<_>) = members.into_iter().partition(|member| {
+            self.pending_domain.is_none()
+                || member
+                    .config
+                    .as_ref()
+                    .and_then(|c| c.authorized)
+                    .unwrap_or(true)
+        });
+
+        let mut forward_records = vec![self.forward_authority.domain_name.clone()];
+        // the PTR records are collected first and only written if the reverse zones are due.
+        let mut ptrs = ReverseInputs::new();
+
+        let hosts_ttl = self.hosts_ttl.unwrap_or(self.forward_authority.ttl);
+
+        // when merging, the hosts file's addresses are added to the members' as they are written,
+        // and pruning waits until it is known which names no member claims.
+        let hosts_ips = match (&self.hosts, self.merge_names) {
+            (Some(hosts), true) => hosts_by_name(hosts),
+            _ => HashMap::new(),
+        };
+
+        if let Some(hosts) = self.hosts.clone() {
+            if !self.merge_names {
+                self.forward_authority
+                    .prune_hosts(hosts.clone(), hosts_ttl)
+                    .await?;
+            }
+            forward_records.append(&mut hosts.values().flatten().map(|v| v.into()).collect());
+        }
+
+        if self.routes_txt {
+            let name = Name::from_str(ROUTES_TXT_LABEL)?
+                .append_domain(&self.forward_authority.domain_name.clone().into())?;
+
+            let routes: Vec<String> = network
+                .config
+                .clone()
+                .and_then(|c| c.routes)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|r| match (r.target, r.via) {
+                    (Some(target), Some(via)) => Some(format!("{} via {}", target, via)),
+                    (Some(target), None) => Some(target),
+                    _ => None,
+                })
+                .collect();
+
+            if !routes.is_empty() {
+                self.forward_authority
+                    .replace_txt_records(name.clone(), routes)
+                    .await;
+                forward_records.push(name.into());
+            }
+        }
+
+        let (mut sixplane, mut rfc4193) = (None, None);
+
+        let v6assign = network.config.clone().unwrap().v6_assign_mode;
+        if let Some(v6assign) = v6assign {
+            if v6assign._6plane.unwrap_or(false) {
+                let s = network.clone().sixplane()?;
+                sixplane = Some(s);
+            }
+
+            if v6assign.rfc4193.unwrap_or(false) {
+                let s = network.clone().rfc4193()?;
+                rfc4193 = Some(s);
+            }
+        }
+
+        // a file that no longer parses keeps the last overrides that did, as the hosts files do.
+        let overrides = match parse_overrides(self.overrides_file.clone()) {
+            Ok(overrides) => {
+                *self.overrides.lock().await = overrides.clone();
+                overrides
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "{}: {}; keeping the last overrides",
+                    self.overrides_file
+                        .as_ref()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_default(),
+                    e
+                );
+                self.overrides.lock().await.clone()
+            }
+        };
+        let mut member_set = MemberSet::new();
+
+        // an IP assigned to several members would get a PTR for each; their names are collected
+        // instead and the shared IP policy applied once every member has been seen.
+        let mut ip_members: HashMap<IpAddr, usize> = HashMap::new();
+        for member in &members {
+            let mut ips = self.ptr_ips(member);
+            ips.sort();
+            ips.dedup();
+            for ip in ips {
+                *ip_members.entry(ip).or_default() += 1;
+            }
+        }
+        let mut shared_ptrs: BTreeMap<IpAddr, Vec<Name>> = BTreeMap::new();
+
+        // the controller's node id is the first ten hex digits of the network id; Central also
+        // reports it on each member.
+        let controller_id = self.network_id.get(..10).map(ToString::to_string);
+
+        let groups = tag_groups(&network, &self.group_tags);
+        let mut group_ips: BTreeMap<String, Vec<IpAddr>> = BTreeMap::new();
+
+        // the zt-<member id> names every member gets, which a custom name must not take over.
+        let id_names = members
+            .iter()
+            .filter_map(|member| member.node_id.as_ref())
+            .map(|node_id| {
+                format!("zt-{}", node_id).to_fqdn(self.forward_authority.domain_name.clone().into())
+            })
+            .collect::<Result<HashSet<Name>, anyhow::Error>>()?;
+
+        let hosts_written = forward_records.len();
+        let mut last_online = HashMap::new();
+        for member in members {
+            let member_written = forward_records.len();
+            let mut record = ZTRecord::new(
+                &member,
+                sixplane,
+                rfc4193,
+                self,
+                self.forward_authority.domain_name.clone().into(),
+            )?;
+
+            if let Some(name) = record.custom_name.clone().filter(|name| {
+                self.id_name_collision
+                    .rejects(name, &record.fqdn, &id_names)
+            }) {
+                tracing::warn!(
+                    "Member {} is named {}, which is kept for member ids; it is only published as {}",
+                    record.node_id,
+                    name,
+                    record.fqdn
+                );
+                record.custom_name = None;
+                record.ptr_name = self.ptr_format.apply(record.fqdn.clone())?;
+            }
+
+            if let Some(node_id) = member.node_id.clone() {
+                member_set.insert(
+                    node_id,
+                    MemberState {
+                        name: record.custom_name.as_ref().map(ToString::to_string),
+                        ips: record.ips.clone(),
+                    },
+                );
+            }
+
+            for group in member_groups(&member, &groups) {
+                let ips = group_ips.entry(group).or_default();
+                *ips = merge_ips(ips, &record.ips);
+            }
+
+            let controller_name = self.controller_name.as_ref().filter(|_| {
+                member.node_id.is_some()
+                    && member.node_id == member.controller_id.clone().or(controller_id.clone())
+            });
+
+            // overrides only change the forward records; PTRs still follow the member's own ips.
+            let member_override = member.node_id.as_ref().and_then(|id| overrides.get(id));
+            match member_override.and_then(|o| o.cname.clone()) {
+                Some(target) => {
+                    let target = Name::from_str(&target)?;
+                    let target = if target.is_fqdn() {
+                        target
+                    } else {
+                        target.append_domain(&self.forward_authority.domain_name.clone().into())?
+                    };
+
+                    if let Some(name) = controller_name {
+                        self.forward_authority
+                            .set_cname_record(name.clone(), target.clone())
+                            .await;
+                        forward_records.push(name.clone().into());
+                    }
+
+                    self.forward_authority
+                        .insert_member_cname(&mut forward_records, record.clone(), target)
+                        .await?;
+                }
+                None => {
+                    let mut forward = record.clone();
+                    if let Some(member_override) = member_override {
+                        for ip in &member_override.ips {
+                            if !forward.ips.contains(ip) {
+                                forward.ips.push(*ip);
+                            }
+                        }
+                    }
+
+                    if let Some(name) = controller_name {
+                        let ips = merge_ips(
+                            &forward.ips,
+                            hosts_ips.get(name).map(Vec::as_slice).unwrap_or_default(),
+                        );
+                        self.forward_authority
+                            .match_or_insert(name.clone(), &ips)
+                            .await;
+                        forward_records.push(name.clone().into());
+                    }
+
+                    self.forward_authority
+                        .insert_member(&mut forward_records, forward, &hosts_ips)
+                        .await?;
+                }
+            }
+
+            // Central reports 0 when it has lost track, which is not taken as offline.
+            if let Some(time) = member.last_online.filter(|time| *time > 0) {
+                for name in &forward_records[member_written..] {
+                    last_online.insert(name.clone(), time);
+                }
+            }
+
+            for ip in self.ptr_ips(&member) {
+                if !self.reverse_served(ip) {
+                    continue;
+                }
+
+                if ip_members.get(&ip).copied().unwrap_or_default() > 1 {
+                    let names = shared_ptrs.entry(ip).or_default();
+                    if !names.contains(&record.ptr_name) {
+                        names.push(record.ptr_name.clone());
+                    }
+                } else {
+                    ptrs.insert(ip, vec![record.ptr_name.clone()]);
+                }
+            }
+
+            if let Some(ptr) = rfc4193.filter(|_| self.ptr_families != IpFamilies::A) {
+                let ip = member.rfc4193()?.ip();
+                if self.reverse_authority_map.contains_key(&ptr) && record.ips.contains(&ip) {
+                    ptrs.insert(ip, vec![record.ptr_name.clone()]);
+                }
+            }
+        }
+
+        if let Some(online) = &self.online {
+            online.update(&self.forward_authority.domain_name, last_online);
+        }
+
+        // hosts names no member claims any more go back to the hosts file's addresses alone.
+        let member_names: HashSet<LowerName> =
+            forward_records[hosts_written..].iter().cloned().collect();
+        for (name, ips) in &hosts_ips {
+            if !member_names.contains(&LowerName::from(name)) {
+                self.forward_authority
+                    .match_or_insert_ttl(name.clone(), ips, hosts_ttl)
+                    .await;
+            }
+        }
+
+        // a group only gets its name if no member or hosts entry has it already.
+        for (group, ips) in group_ips {
+            let name = match Name::from_str(&group).and_then(|name| {
+                name.append_domain(&self.forward_authority.domain_name.clone().into())
+            }) {
+                Ok(name) => name,
+                Err(e) => {
+                    tracing::warn!("Group {} is not a valid name: {}", group, e);
+                    continue;
+                }
+            };
+
+            if forward_records.contains(&LowerName::from(&name)) {
+                tracing::warn!(
+                    "Group {} has the name of a member or host; skipping it",
+                    name
+                );
+                continue;
+            }
+
+            self.forward_authority
+                .match_or_insert(name.clone(), &ips)
+                .await;
+            forward_records.push(name.into());
+        }
+
+        if let Some(domain) = &self.pending_domain {
+            for member in pending {
+                let record = ZTRecord::new(&member, sixplane, rfc4193, self, domain.clone())?;
+                self.forward_authority
+                    .insert_pending_member(&mut forward_records, record)
+                    .await;
+            }
+        }
+
+        for (ip, names) in shared_ptrs {
+            let names = match self.shared_ip {
+                SharedIpPolicy::Warn => {
+                    tracing::warn!(
+                        "{} is assigned to several members ({}); its PTR points at {}",
+                        ip,
+                        names
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<String>>()
+                            .join(", "),
+                        names.last().unwrap()
+                    );
+                    vec![names.last().unwrap().clone()]
+                }
+                SharedIpPolicy::Merge => names,
+                SharedIpPolicy::Skip => continue,
+            };
+
+            ptrs.insert(ip, names);
+        }
+
+        // the gateway's name is published for its addresses. A member assigned one of them keeps
+        // the PTR for it.
+        if let Some((name, ips)) = &self.gateway {
+            self.forward_authority
+                .match_or_insert(name.clone(), ips)
+                .await;
+            forward_records.push(name.clone().into());
+
+            for ip in self.ptr_families.filter(ips) {
+                if self.reverse_served(ip) {
+                    ptrs.entry(ip).or_insert_with(|| vec![name.clone()]);
+                }
+            }
+        }
+
+        // the member running zeronsd answers for its own listen IPs even when Central does not
+        // list it, e.g. before it is authorized. If it is listed, its member record above wins.
+        if let Some(host_name) = &self.host_name {
+            for ip in self.ptr_families.filter(&self.host_ips) {
+                if self.reverse_served(ip) {
+                    ptrs.entry(ip).or_insert_with(|| vec![host_name.clone()]);
+                }
+            }
+        }
+
+        // the reverse zones are left as they are when they are not due.
+        let refresh_reverse = self.reverse_due(&ptrs).await;
+        let reverse_authority_map = if refresh_reverse {
+            self.reverse_authority_map.clone()
+        } else {
+            HashMap::new()
+        };
+
+        let mut reverse_records = HashMap::new();
+        for (network, authority) in &reverse_authority_map {
+            let mut records = vec![authority.domain_name.clone()];
+            if rfc4193 == Some(*network) {
+                records.push(network.to_ptr_soa_name()?);
+            }
+
+            for (ip, names) in &ptrs {
+                if network.contains(*ip) {
+                    authority
+                        .insert_member_ptrs(&mut records, *ip, names.clone())
+                        .await?;
+                }
+            }
+
+            reverse_records.insert(network, records);
+        }
+
+        if !self.ns_hosts.is_empty() {
+            let hosts: Vec<Name> = self.ns_hosts.iter().map(|(name, _)| name.clone()).collect();
+            self.forward_authority
+                .replace_ns_records(hosts.clone())
+                .await;
+
+            // the reverse zones are served by the same hosts; their glue is in the forward zone.
+            for authority in reverse_authority_map.values() {
+                authority.replace_ns_records(hosts.clone()).await;
+            }
+
+            for (name, ips) in &self.ns_hosts {
+                self.forward_authority
+                    .match_or_insert(name.clone(), ips)
+                    .await;
+                forward_records.push(name.clone().into());
+            }
+        }
+
+        self.forward_authority
+            .prune_records(forward_records.clone())
+            .await?;
+
+        for (network, authority) in &reverse_authority_map {
+            authority
+                .prune_records(reverse_records.get(network).unwrap().clone())
+                .await?;
+        }
+
+        self.forward_authority.publish().await;
+        for authority in &self.alias_authorities {
+            authority.mirror(&self.forward_authority).await?;
+            authority.publish().await;
+        }
+        for authority in reverse_authority_map.values() {
+            authority.publish().await;
+        }
+        self.ready.store(true, Ordering::Relaxed);
+
+        if refresh_reverse {
+            *self.reverse_refreshed.lock().await = Some((Instant::now(), ptrs));
+        }
+
+        if let Some(webhook) = &self.webhook {
+            webhook.record(member_set.clone()).await;
+        }
+
+        if let Some(audit) = &self.audit {
+            if let Err(e) = audit.record(member_set).await {
+                tracing::error!("error writing audit log: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_members(
+        &self,
+    ) -> Result<(central_api::types::Network, Vec<central_api::types::Member>), anyhow::Error> {
+        let client = self.client.clone();
+        let network_id = self.network_id.clone();
+
+        // the central API returns the whole member list in a single response; there are no
+        // paging parameters on this endpoint (see zerotier-api/specs/centralv1.json), so there is
+        // nothing to page through or bound here.
+        let members = client.get_network_member_list(&network_id).await?;
+        let network = client.get_network_by_id(&network_id).await?;
+
+        Ok((network.to_owned(), members.to_owned()))
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordAuthority {
+    domain_name: LowerName,
+    authority: Arc<InMemoryAuthority>,
+    // the records, besides the SOA, as of the last serial bump.
+    published: Arc<Mutex<BTreeMap<RrKey, Arc<RecordSet>>>>,
+    ttl: u32,
+    any_response: AnyResponse,
+    // add the addresses of every in-zone NS, MX and SRV target to the additional section.
+    glue: bool,
+    // set on the copies in catalogs for listen IPs that do not serve this zone.
+    refuse: bool,
+    // answers names that do not exist with this ip, once the flag says the first sync is done.
+    catch_all: Option<(IpAddr, Arc<AtomicBool>)>,
+    // leaves members that have not been online lately out of A and AAAA answers.
+    online: Option<Liveness>,
+}
+
+impl RecordAuthority {
+    pub async fn new(
+        domain_name: LowerName,
+        member_name: LowerName,
+        ttl: u32,
+    ) -> Result<Self, anyhow::Error> {
+        let authority =
+            Self::configure_authority(domain_name.clone().into(), member_name.into()).await?;
+
+        Ok(Self {
+            published: Arc::new(Mutex::new(without_soa(&authority.records().await))),
+            authority: Arc::new(authority),
+            domain_name,
+            ttl,
+            any_response: AnyResponse::default(),
+            glue: false,
+            refuse: false,
+            catch_all: None,
+            online: None,
+        })
+    }
+
+    async fn configure_authority(
+        domain_name: Name,
+        member_name: Name,
+    ) -> Result<InMemoryAuthority, anyhow::Error> {
+        let mut map = BTreeMap::new();
+        let mut soa = Record::with(domain_name.clone(), RecordType::SOA, 30);
+
+        soa.set_data(Some(RData::SOA(SoaTimers::default().soa(
+            domain_name.clone(),
+            Name::from_str("administrator")?.append_domain(&domain_name)?,
+            1,
+        ))));
+
+        let mut soa_rs = RecordSet::new(&domain_name, RecordType::SOA, 1);
+        soa_rs.insert(soa, 1);
+        map.insert(
+            RrKey::new(domain_name.clone().into(), RecordType::SOA),
+            soa_rs,
+        );
+
+        let mut ns = Record::with(domain_name.clone(), RecordType::NS, 30);
+        ns.set_data(Some(RData::NS(member_name)));
+        let mut ns_rs = RecordSet::new(&domain_name, RecordType::NS, 1);
+        ns_rs.insert(ns, 1);
+
+        map.insert(
+            RrKey::new(domain_name.clone().into(), RecordType::NS),
+            ns_rs,
+        );
+
+        let authority = InMemoryAuthority::new(
+            domain_name,
+            map,
+            trust_dns_server::authority::ZoneType::Primary,
+            false,
+        )
+        .expect("Could not initialize authority");
+
+        Ok(authority)
+    }
+
+    // set_soa_timers replaces the timers in the zone's SOA, keeping its serial.
+    pub async fn set_soa_timers(&self, timers: SoaTimers) {
+        let mut rr = self.authority.records_mut().await;
+
+        let key = RrKey::new(self.domain_name.clone(), RecordType::SOA);
+        if let Some(mut soa) = rr
+            .get(&key)
+            .and_then(|rset| rset.records_without_rrsigs().next().cloned())
+        {
+            if let Some(RData::SOA(data)) = soa.data().cloned() {
+                let data = timers.soa(data.mname().clone(), data.rname().clone(), data.serial());
+                soa.set_data(Some(RData::SOA(data)));
+                let mut rset = RecordSet::new(soa.name(), RecordType::SOA, 0);
+                rset.insert(soa, 0);
+                rr.insert(key, Arc::new(rset));
+            }
+        }
+    }
+
+    // publish bumps the SOA serial if the records changed since the last bump, so secondaries only
+    // transfer the zone again after a sync that changed something.
+    async fn publish(&self) {
+        let mut published = self.published.lock().await;
+        let mut rr = self.authority.records_mut().await;
+
+        let current = without_soa(&rr);
+        if current == *published {
+            return;
+        }
+
+        let key = RrKey::new(self.domain_name.clone(), RecordType::SOA);
+        if let Some(mut soa) = rr
+            .get(&key)
+            .and_then(|rset| rset.records_without_rrsigs().next().cloned())
+        {
+            if let Some(RData::SOA(mut data)) = soa.data().cloned() {
+                data.increment_serial();
+                tracing::debug!(
+                    "{} changed; SOA serial is now {}",
+                    self.domain_name,
+                    data.serial()
+                );
+
+                soa.set_data(Some(RData::SOA(data)));
+                let mut rset = RecordSet::new(soa.name(), RecordType::SOA, 0);
+                rset.insert(soa, 0);
+                rr.insert(key, Arc::new(rset));
+            }
+        }
+
+        *published = current;
+    }
+
+    // mirror replaces this zone's records with those of `primary`, moved from its domain to this
+    // one. Names in the records' data, such as CNAME and MX targets, move along when they are in the
+    // primary zone; records for names outside it are left out.
+    async fn mirror(&self, primary: &RecordAuthority) -> Result<(), anyhow::Error> {
+        let from: Name = primary.domain_name.clone().into();
+        let to: Name = self.domain_name.clone().into();
+        let rebase = |name: &Name| -> Result<Name, anyhow::Error> {
+            if !from.zone_of(name) {
+                return Ok(name.clone());
+            }
+
+            let labels = name.num_labels() - from.num_labels();
+            Ok(Name::from_labels(name.iter().take(labels as usize))?.append_domain(&to)?)
+        };
+
+        let mut records = BTreeMap::new();
+        for (key, rset) in primary.authority.records().await.iter() {
+            let name: Name = key.name().into();
+            if rset.record_type() == RecordType::SOA || !from.zone_of(&name) {
+                continue;
+            }
+
+            let name = rebase(&name)?;
+            let mut mirrored = RecordSet::with_ttl(name.clone(), rset.record_type(), rset.ttl());
+            for rdata in rset.records_without_rrsigs().filter_map(|r| r.data()) {
+                mirrored.add_rdata(match rdata {
+                    RData::CNAME(target) => RData::CNAME(rebase(target)?),
+                    RData::NS(target) => RData::NS(rebase(target)?),
+                    RData::MX(mx) => RData::MX(MX::new(mx.preference(), rebase(mx.exchange())?)),
+                    RData::SRV(srv) => RData::SRV(SRV::new(
+                        srv.priority(),
+                        srv.weight(),
+                        srv.port(),
+                        rebase(srv.target())?,
+                    )),
+                    rdata => rdata.clone(),
+                });
+            }
+
+            records.insert(
+                RrKey::new(name.into(), rset.record_type()),
+                Arc::new(mirrored),
+            );
+        }
+
+        let mut rr = self.authority.records_mut().await;
+        rr.retain(|key, _| key.record_type == RecordType::SOA);
+        rr.append(&mut records);
+
+        Ok(())
+    }
+
+    // replace_txt_records swaps the TXT set for the name in one step, each string becoming its own
+    // record; no strings removes the set. Nothing is written if the records are already current.
+    async fn replace_txt_records(&self, name: Name, txts: Vec<String>) {
+        let rdatas: Vec<RData> = txts
+            .into_iter()
+            .map(|txt| RData::TXT(TXT::new(vec![txt])))
+            .collect();
+
+        let mut rr = self.authority.records_mut().await;
+        let key = RrKey::new(name.clone().into(), RecordType::TXT);
+
+        if rdatas.is_empty() {
+            if rr.remove(&key).is_some() {
+                tracing::info!("Removing TXT records for {}", name);
+            }
+            return;
+        }
+
+        if let Some(rset) = rr.get(&key) {
+            if rdatas_match(rset, &rdatas) {
+                return;
+            }
+        }
+
+        let mut rset = RecordSet::with_ttl(name.clone(), RecordType::TXT, self.ttl);
+        for rdata in rdatas {
+            rset.add_rdata(rdata);
+        }
+
+        tracing::info!("Replacing TXT records for {}", name);
+        rr.insert(key, Arc::new(rset));
+    }
+
+    // replace_ns_records swaps the zone's NS set for the given hosts. Nothing is written if the
+    // records are already current.
+    async fn replace_ns_records(&self, hosts: Vec<Name>) {
+        let rdatas: Vec<RData> = hosts.into_iter().map(RData::NS).collect();
+        let name: Name = self.domain_name.clone().into();
+
+        let mut rr = self.authority.records_mut().await;
+        let key = RrKey::new(self.domain_name.clone(), RecordType::NS);
+
+        if let Some(rset) = rr.get(&key) {
+            if rdatas_match(rset, &rdatas) {
+                return;
+            }
+        }
+
+        let mut rset = RecordSet::with_ttl(name.clone(), RecordType::NS, self.ttl);
+        for rdata in rdatas {
+            rset.add_rdata(rdata);
+        }
+
+        tracing::info!("Replacing NS records for {}", name);
+        rr.insert(key, Arc::new(rset));
+    }
+
+    async fn prune_hosts(&self, hosts: Box<HostsFile>, ttl: u32) -> Result<(), anyhow::Error> {
+        let mut rr = self.authority.records_mut().await;
+
+        for (host, ips) in hosts_by_name(&hosts).into_iter() {
+            for (rrkey, rset) in rr.clone() {
+                let key = &rrkey.name().into_name().expect("could not parse name");
+                let records = rset.records(false, SupportedAlgorithms::all());
+
+                let rt = rset.record_type();
+                let rdatas: Vec<RData> = ips
+                    .clone()
+                    .into_iter()
+                    .filter_map(|i| match i {
+                        IpAddr::V4(ip) => {
+                            if rt == RecordType::A {
+                                Some(RData::A(ip))
+                            } else {
+                                None
+                            }
+                        }
+                        IpAddr::V6(ip) => {
+                            if rt == RecordType::AAAA {
+                                Some(RData::AAAA(ip))
+                            } else {
+                                None
+                            }
+                        }
+                    })
+                    .collect();
+
+                if key.eq(&host)
+                    && (records.is_empty()
+                        || !records
+                            .map(|r| r.data().unwrap())
+                            .all(|rd| rdatas.contains(rd)))
+                {
+                    let mut new_rset = RecordSet::with_ttl(key.clone(), rt, ttl);
+                    for rdata in rdatas.clone() {
+                        new_rset.add_rdata(rdata);
+                    }
+
+                    // insert replaces the set in place; the name is never absent in between.
+                    tracing::warn!("Replacing host record for {} with {:?}", key, ips);
+                    rr.insert(rrkey.clone(), Arc::new(new_rset));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn prune_records(&self, written: Vec<LowerName>) -> Result<(), anyhow::Error> {
+        // queries wait on the lock held here, so large zones need the set lookup.
+        let written: HashSet<LowerName> = written.into_iter().collect();
+
+        let mut rr = self.authority.records_mut().await;
+
+        let rrkey_list: Vec<RrKey> = rr
+            .iter()
+            .filter(|(rrkey, rs)| {
+                !written.contains(rrkey.name()) && rs.record_type() != RecordType::SOA
+            })
+            .map(|(rrkey, _)| rrkey.clone())
+            .collect();
+
+        for rrkey in rrkey_list {
+            tracing::warn!("Removing expired record {}", rrkey.name());
+            rr.remove(&rrkey);
+        }
+
+        Ok(())
+    }
+
+    // address_ips returns the addresses the name's A and AAAA records currently hold.
+    async fn address_ips(&self, name: &Name) -> Vec<IpAddr> {
+        let rr = self.authority.records().await;
+
+        [RecordType::A, RecordType::AAAA]
+            .into_iter()
+            .filter_map(|rt| rr.get(&RrKey::new(name.clone().into(), rt)))
+            .flat_map(|rset| rset.records_without_rrsigs())
+            .filter_map(|r| match r.data() {
+                Some(RData::A(ip)) => Some(IpAddr::V4(*ip)),
+                Some(RData::AAAA(ip)) => Some(IpAddr::V6(*ip)),
+                _ => None,
+            })
+            .collect()
+    }
 
     pub async fn match_or_insert(&self, name: Name, ips: &[IpAddr]) {
-        let rdatas: Vec<RData> = ips
+        self.match_or_insert_ttl(name, ips, self.ttl).await
+    }
+
+    // match_or_insert_ttl ensures the A and AAAA sets for the name hold exactly the given ips. Each
+    // set is computed in full and swapped in under a single write lock, so lookups never observe the
+    // name missing mid-update.
+    pub async fn match_or_insert_ttl(&self, name: Name, ips: &[IpAddr], ttl: u32) {
+        let mut rr = self.authority.records_mut().await;
+
+        // a CNAME left over from a removed override would shadow the addresses.
+        if !ips.is_empty() {
+            rr.remove(&RrKey::new(name.clone().into(), RecordType::CNAME));
+        }
+
+        for rt in [RecordType::A, RecordType::AAAA] {
+            let rdatas: Vec<RData> = ips
+                .iter()
+                .filter_map(|&ip| match (ip, rt) {
+                    (IpAddr::V4(ip), RecordType::A) => Some(RData::A(ip)),
+                    (IpAddr::V6(ip), RecordType::AAAA) => Some(RData::AAAA(ip)),
+                    _ => None,
+                })
+                .collect();
+
+            if rdatas.is_empty() {
+                continue;
+            }
+
+            let key = RrKey::new(name.clone().into(), rt);
+            if let Some(rset) = rr.get(&key) {
+                if rset.ttl() == ttl && rdatas_match(rset, &rdatas) {
+                    continue;
+                }
+            }
+
+            let mut rset = RecordSet::with_ttl(name.clone(), rt, ttl);
+            for rdata in rdatas {
+                tracing::info!("Adding new record {}: ({})", name, rdata);
+                rset.add_rdata(rdata);
+            }
+
+            rr.insert(key, Arc::new(rset));
+        }
+    }
+
+    // member_wildcard is the wildcard covering a member's name. A name that is the zone apex has
+    // none: *.<domain> would answer for every name in the zone that does not exist.
+    fn member_wildcard(&self, name: &Name) -> Option<Name> {
+        Some(name)
+            .filter(|name| LowerName::from(*name) != self.domain_name)
+            .map(ToWildcard::to_wildcard)
+    }
+
+    async fn insert_member(
+        &self,
+        records: &mut Vec<LowerName>,
+        record: ZTRecord,
+        hosts_ips: &HashMap<Name, Vec<IpAddr>>,
+    ) -> Result<(), anyhow::Error> {
+        let ips = |name: &Name| {
+            merge_ips(
+                &record.ips,
+                hosts_ips.get(name).map(Vec::as_slice).unwrap_or_default(),
+            )
+        };
+
+        self.match_or_insert(record.fqdn.clone(), &ips(&record.fqdn))
+            .await;
+        records.push(record.fqdn.clone().into());
+
+        // wildcards only carry the configured families; if that leaves nothing, no wildcard is
+        // written and any existing one is pruned.
+        let wildcard_ips = record.wildcard_families.filter(&record.ips);
+        let wildcard = record.wildcard && !wildcard_ips.is_empty();
+
+        if let Some(name) = self.member_wildcard(&record.fqdn).filter(|_| wildcard) {
+            self.match_or_insert(name.clone(), &wildcard_ips).await;
+            records.push(name.into());
+        }
+
+        // a rewritten PTR target gets the member's addresses too, so it still resolves.
+        if record.ptr_name != record.fqdn && Some(&record.ptr_name) != record.custom_name.as_ref() {
+            self.match_or_insert(record.ptr_name.clone(), &ips(&record.ptr_name))
+                .await;
+            records.push(record.ptr_name.clone().into());
+        }
+
+        if let Some(name) = &record.address_name {
+            self.match_or_insert(name.clone(), &ips(name)).await;
+            records.push(name.clone().into());
+        }
+
+        let txts: Vec<String> = record
+            .authorized
+            .clone()
+            .into_iter()
+            .chain(record.version.clone())
+            .collect();
+        self.replace_txt_records(record.fqdn.clone(), txts.clone())
+            .await;
+
+        if let Some(name) = &record.custom_name {
+            self.match_or_insert(name.clone(), &ips(name)).await;
+            self.replace_txt_records(name.clone(), txts).await;
+            records.push(name.clone().into());
+
+            if let Some(name) = self.member_wildcard(name).filter(|_| wildcard) {
+                self.match_or_insert(name.clone(), &wildcard_ips).await;
+                records.push(name.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    // insert_pending_member publishes a member that is not authorized yet: its addresses, if
+    // Central assigned any, and a TXT record with its node id, so it shows up without them.
+    async fn insert_pending_member(&self, records: &mut Vec<LowerName>, record: ZTRecord) {
+        let txts = vec![format!("pending={}", record.node_id)];
+
+        for name in std::iter::once(record.fqdn).chain(record.custom_name) {
+            self.match_or_insert(name.clone(), &record.ips).await;
+            self.replace_txt_records(name.clone(), txts.clone()).await;
+            records.push(name.into());
+        }
+    }
+
+    // insert_member_cname points the member's names at target instead of its addresses.
+    async fn insert_member_cname(
+        &self,
+        records: &mut Vec<LowerName>,
+        record: ZTRecord,
+        target: Name,
+    ) -> Result<(), anyhow::Error> {
+        let mut names = vec![record.fqdn.clone()];
+        names.extend(record.custom_name);
+        names.extend(record.address_name);
+
+        for name in names {
+            self.set_cname_record(name.clone(), target.clone()).await;
+            records.push(name.into());
+        }
+
+        Ok(())
+    }
+
+    // set_cname_record replaces any address records for the name with a CNAME, as the two cannot
+    // coexist.
+    async fn set_cname_record(&self, name: Name, target: Name) {
+        let mut rr = self.authority.records_mut().await;
+        let key = RrKey::new(name.clone().into(), RecordType::CNAME);
+        let rdatas = [RData::CNAME(target.clone())];
+
+        if let Some(rset) = rr.get(&key) {
+            if rset.ttl() == self.ttl && rdatas_match(rset, &rdatas) {
+                return;
+            }
+        }
+
+        let mut rset = RecordSet::with_ttl(name.clone(), RecordType::CNAME, self.ttl);
+        rset.add_rdata(RData::CNAME(target.clone()));
+
+        tracing::info!("Adding CNAME record {}: ({})", name, target);
+        rr.remove(&RrKey::new(name.clone().into(), RecordType::A));
+        rr.remove(&RrKey::new(name.clone().into(), RecordType::AAAA));
+        rr.insert(key, Arc::new(rset));
+    }
+
+    // insert_member_ptr is a lot like insert_authority, but for PTRs.
+    async fn insert_member_ptrs(
+        &self,
+        records: &mut Vec<LowerName>,
+        ip: IpAddr,
+        ptr_names: Vec<Name>,
+    ) -> Result<(), anyhow::Error> {
+        let ip = ip.into_name()?;
+        self.configure_ptrs(ip.clone(), ptr_names).await?;
+        records.push(ip.into());
+
+        Ok(())
+    }
+
+    // configure_ptrs points ptr at each of fqdns, leaving the record set alone if it already holds
+    // exactly those names.
+    async fn configure_ptrs(&self, ptr: Name, fqdns: Vec<Name>) -> Result<(), anyhow::Error> {
+        let wanted: Vec<RData> = fqdns.iter().cloned().map(RData::PTR).collect();
+
+        let current = self
+            .authority
+            .records()
+            .await
+            .get(&RrKey::new(ptr.clone().into(), RecordType::PTR))
+            .map(|records| {
+                records
+                    .records_without_rrsigs()
+                    .filter_map(|rec| rec.data().cloned())
+                    .collect::<Vec<RData>>()
+            });
+
+        match current {
+            Some(current)
+                if current.len() == wanted.len()
+                    && wanted.iter().all(|rdata| current.contains(rdata)) => {}
+            _ => self.set_ptr_records(ptr, fqdns).await,
+        }
+
+        Ok(())
+    }
+
+    // answer_any applies the ANY policy to a lookup: with `minimal`, an ANY query for a name that
+    // exists is answered with the single HINFO record RFC 8482 suggests instead of everything at it.
+    fn answer_any(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+        res: Result<Box<dyn LookupObject>, LookupError>,
+    ) -> Result<Box<dyn LookupObject>, LookupError> {
+        match res {
+            Ok(lookup)
+                if rtype == RecordType::ANY
+                    && self.any_response == AnyResponse::Minimal
+                    && !lookup.is_empty() =>
+            {
+                let mut rset = RecordSet::with_ttl(name.into(), RecordType::HINFO, self.ttl);
+                rset.add_rdata(RData::HINFO(HINFO::new(
+                    "RFC8482".to_string(),
+                    String::new(),
+                )));
+
+                Ok(Box::new(AuthLookup::answers(
+                    LookupRecords::new(lookup_options, Arc::new(rset)),
+                    None,
+                )))
+            }
+            res => res,
+        }
+    }
+
+    // wildcard_rules applies what RFC 4592 asks of wildcards where the store does not: they only
+    // answer for names that do not exist. A name with records of other types, such as a hosts
+    // entry under a member's wildcard, answers NODATA rather than with the wildcard's records. A
+    // name the wildcard covers, but not with the queried type, answers NODATA rather than NXDOMAIN,
+    // which DO=1 clients would take at its word.
+    async fn wildcard_rules(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+        res: Result<Box<dyn LookupObject>, LookupError>,
+    ) -> Result<Box<dyn LookupObject>, LookupError> {
+        if name.is_wildcard() || name.is_root() {
+            return res;
+        }
+
+        match res {
+            Ok(lookup) if rtype != RecordType::ANY && !lookup.is_empty() => {
+                // most names are not under a wildcard with this type, so the answer is their own.
+                let wildcard = name.clone().into_wildcard();
+                match self
+                    .authority
+                    .lookup(&wildcard, rtype, lookup_options)
+                    .await
+                {
+                    Ok(covered) if !covered.is_empty() => {}
+                    _ => return Ok(lookup),
+                }
+
+                let records = self.authority.records().await;
+                let exact = [rtype, RecordType::CNAME]
+                    .iter()
+                    .any(|rtype| records.contains_key(&RrKey::new(name.clone(), *rtype)));
+                // the keys sort by name first, so the name's records, if any, start here.
+                let exists = records
+                    .range(RrKey::new(name.clone(), RecordType::ZERO)..)
+                    .next()
+                    .is_some_and(|(key, _)| key.name() == name);
+
+                // without an exact match, the answer came from the wildcard.
+                if exact || !exists {
+                    Ok(lookup)
+                } else {
+                    Err(LookupError::NameExists)
+                }
+            }
+            Err(e) if e.is_nx_domain() => {
+                let wildcard = name.clone().into_wildcard();
+                match self
+                    .authority
+                    .lookup(&wildcard, rtype, lookup_options)
+                    .await
+                {
+                    Err(LookupError::NameExists) => Err(LookupError::NameExists),
+                    _ => Err(e),
+                }
+            }
+            res => res,
+        }
+    }
+
+    // catch_all answers a name that does not exist in the zone with the catch-all ip. It runs after
+    // wildcard_rules, so records and wildcards are never shadowed. Other types, and the other
+    // address family, answer NODATA, as the name now exists.
+    fn catch_all(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+        res: Result<Box<dyn LookupObject>, LookupError>,
+    ) -> Result<Box<dyn LookupObject>, LookupError> {
+        let ip = match (&self.catch_all, res) {
+            (Some((ip, ready)), Err(e)) if e.is_nx_domain() && ready.load(Ordering::Relaxed) => *ip,
+            (_, res) => return res,
+        };
+
+        let rdata = match (ip, rtype) {
+            (IpAddr::V4(ip), RecordType::A | RecordType::ANY) => RData::A(ip),
+            (IpAddr::V6(ip), RecordType::AAAA | RecordType::ANY) => RData::AAAA(ip),
+            _ => return Err(LookupError::NameExists),
+        };
+
+        let mut rset = RecordSet::with_ttl(name.into(), rdata.to_record_type(), self.ttl);
+        rset.add_rdata(rdata);
+
+        Ok(Box::new(AuthLookup::answers(
+            LookupRecords::new(lookup_options, Arc::new(rset)),
+            None,
+        )))
+    }
+
+    // drop_offline leaves the A and AAAA records of offline members out of an answer, glue
+    // included. A name left without records answers NODATA.
+    fn drop_offline(
+        &self,
+        lookup_options: LookupOptions,
+        res: Result<Box<dyn LookupObject>, LookupError>,
+    ) -> Result<Box<dyn LookupObject>, LookupError> {
+        let (online, mut lookup) = match (&self.online, res) {
+            (Some(online), Ok(lookup)) => (online, lookup),
+            (_, res) => return res,
+        };
+
+        if lookup.is_empty() {
+            return Ok(lookup);
+        }
+
+        let now = SystemTime::now();
+        let offline = |record: &&Record| {
+            matches!(record.record_type(), RecordType::A | RecordType::AAAA)
+                && online.offline(&self.domain_name, record.name(), now)
+        };
+
+        let additionals = lookup.take_additionals();
+        let answers = record_sets(lookup.iter().filter(|r| !offline(r)));
+        if answers.is_empty() {
+            return Err(LookupError::NameExists);
+        }
+
+        let additionals = additionals
+            .map(|additionals| record_sets(additionals.iter().filter(|r| !offline(r))))
+            .unwrap_or_default();
+        Ok(Box::new(AuthLookup::answers(
+            LookupRecords::many(lookup_options, answers),
+            (!additionals.is_empty()).then(|| LookupRecords::many(lookup_options, additionals)),
+        )))
+    }
+
+    // add_glue puts the A and AAAA records of the in-zone NS, MX and SRV targets of an answer in
+    // its additional section; the store only does so for the first target. Targets are not
+    // followed any further, so this cannot loop, and there are at most MAX_GLUE_TARGETS of them.
+    // Responses cut down to fit the UDP size limit lose the additional section first.
+    async fn add_glue(
+        &self,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+        res: Result<Box<dyn LookupObject>, LookupError>,
+    ) -> Result<Box<dyn LookupObject>, LookupError> {
+        let mut lookup = match res {
+            Ok(lookup)
+                if self.glue
+                    && matches!(rtype, RecordType::NS | RecordType::MX | RecordType::SRV) =>
+            {
+                lookup
+            }
+            res => return res,
+        };
+
+        let mut targets: Vec<LowerName> = Vec::new();
+        for record in lookup.iter() {
+            let target = match record.data() {
+                Some(RData::NS(name)) => name.clone(),
+                Some(RData::MX(mx)) => mx.exchange().clone(),
+                Some(RData::SRV(srv)) => srv.target().clone(),
+                _ => continue,
+            };
+
+            let target = LowerName::from(target);
+            if self.domain_name.zone_of(&target) && !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        targets.truncate(MAX_GLUE_TARGETS);
+
+        let mut additionals = lookup
+            .take_additionals()
+            .map(|additionals| record_sets(additionals.iter()))
+            .unwrap_or_default();
+
+        for target in targets {
+            for rtype in [RecordType::A, RecordType::AAAA] {
+                let present = additionals
+                    .iter()
+                    .any(|set| LowerName::from(set.name()) == target && set.record_type() == rtype);
+                if present {
+                    continue;
+                }
+
+                if let Ok(glue) = self.authority.lookup(&target, rtype, lookup_options).await {
+                    additionals.extend(record_sets(glue.iter()));
+                }
+            }
+        }
+
+        let answers = record_sets(lookup.iter());
+        Ok(Box::new(AuthLookup::answers(
+            LookupRecords::many(lookup_options, answers),
+            (!additionals.is_empty()).then(|| LookupRecords::many(lookup_options, additionals)),
+        )))
+    }
+
+    async fn set_ptr_records(&self, ptr: Name, fqdns: Vec<Name>) {
+        tracing::info!(
+            "Adding/Replacing record {}: ({})",
+            ptr,
+            fqdns
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+
+        let mut rset = RecordSet::with_ttl(ptr.clone(), RecordType::PTR, self.ttl);
+        for fqdn in fqdns {
+            rset.add_rdata(RData::PTR(fqdn));
+        }
+
+        self.authority
+            .records_mut()
+            .await
+            .insert(RrKey::new(ptr.into(), RecordType::PTR), Arc::new(rset));
+    }
+}
+
+#[async_trait]
+impl AuthorityObject for RecordAuthority {
+    fn box_clone(&self) -> Box<dyn AuthorityObject> {
+        Box::new(self.clone())
+    }
+
+    fn zone_type(&self) -> trust_dns_server::authority::ZoneType {
+        trust_dns_server::authority::ZoneType::Primary
+    }
+
+    fn is_axfr_allowed(&self) -> bool {
+        false
+    }
+
+    async fn update(
+        &self,
+        update: &trust_dns_server::authority::MessageRequest,
+    ) -> trust_dns_server::authority::UpdateResult<bool> {
+        self.authority.update(update).await
+    }
+
+    fn origin(&self) -> &trust_dns_server::client::rr::LowerName {
+        &self.domain_name
+    }
+
+    async fn lookup(
+        &self,
+        name: &trust_dns_server::client::rr::LowerName,
+        rtype: RecordType,
+        lookup_options: trust_dns_server::authority::LookupOptions,
+    ) -> Result<
+        Box<dyn trust_dns_server::authority::LookupObject>,
+        trust_dns_server::authority::LookupError,
+    > {
+        if self.refuse {
+            return Err(LookupError::from(ResponseCode::Refused));
+        }
+
+        let res = self.authority.lookup(name, rtype, lookup_options).await;
+        let res = self.wildcard_rules(name, rtype, lookup_options, res).await;
+        let res = self.catch_all(name, rtype, lookup_options, res);
+        let res = self.add_glue(rtype, lookup_options, res).await;
+        let res = self.drop_offline(lookup_options, res);
+        self.answer_any(name, rtype, lookup_options, res)
+    }
+
+    async fn search(
+        &self,
+        request_info: trust_dns_server::server::RequestInfo<'_>,
+        lookup_options: trust_dns_server::authority::LookupOptions,
+    ) -> Result<
+        Box<dyn trust_dns_server::authority::LookupObject>,
+        trust_dns_server::authority::LookupError,
+    > {
+        if self.refuse {
+            return Err(LookupError::from(ResponseCode::Refused));
+        }
+
+        let name = request_info.query.name().clone();
+        let rtype = request_info.query.query_type();
+        let res = self.authority.search(request_info, lookup_options).await;
+        let res = self.wildcard_rules(&name, rtype, lookup_options, res).await;
+        let res = self.catch_all(&name, rtype, lookup_options, res);
+        let res = self.add_glue(rtype, lookup_options, res).await;
+        let res = self.drop_offline(lookup_options, res);
+        self.answer_any(&name, rtype, lookup_options, res)
+    }
+
+    async fn get_nsec_records(
+        &self,
+        name: &trust_dns_server::client::rr::LowerName,
+        lookup_options: trust_dns_server::authority::LookupOptions,
+    ) -> Result<
+        Box<dyn trust_dns_server::authority::LookupObject>,
+        trust_dns_server::authority::LookupError,
+    > {
+        if self.refuse {
+            return Err(LookupError::from(ResponseCode::Refused));
+        }
+
+        self.authority.get_nsec_records(name, lookup_options).await
+    }
+}
+
+// AnyResponse selects how ANY queries for names in the managed zones are answered. Forwarded
+// names are not affected; those are up to the upstream resolvers.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum AnyResponse {
+    // every record at the name, e.g. a member's A and AAAA records.
+    #[default]
+    #[serde(rename = "records")]
+    Records,
+    // a single HINFO record, per RFC 8482.
+    #[serde(rename = "minimal")]
+    Minimal,
+}
+
+impl std::fmt::Display for AnyResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AnyResponse::Records => "records",
+            AnyResponse::Minimal => "minimal",
+        })
+    }
+}
+
+impl FromStr for AnyResponse {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "records" => Ok(AnyResponse::Records),
+            "minimal" => Ok(AnyResponse::Minimal),
+            _ => Err(anyhow!(
+                "invalid format: allowed values: [records, minimal]"
+            )),
+        }
+    }
+}
+
+// TimeFormat selects how times published in TXT records are written.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum TimeFormat {
+    // seconds since the unix epoch.
+    #[serde(rename = "epoch")]
+    Epoch,
+    // RFC 3339 / ISO 8601 in UTC, to the second.
+    #[serde(rename = "iso")]
+    Iso,
+}
+
+impl TimeFormat {
+    // format takes milliseconds since the epoch, as Central reports times.
+    pub fn format(&self, millis: i64) -> Option<String> {
+        match self {
+            TimeFormat::Epoch => Some((millis / 1000).to_string()),
+            TimeFormat::Iso => chrono::DateTime::from_timestamp_millis(millis)
+                .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+        }
+    }
+}
+
+impl std::fmt::Display for TimeFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TimeFormat::Epoch => "epoch",
+            TimeFormat::Iso => "iso",
+        })
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "epoch" => Ok(TimeFormat::Epoch),
+            "iso" => Ok(TimeFormat::Iso),
+            _ => Err(anyhow!("invalid format: allowed values: [epoch, iso]")),
+        }
+    }
+}
+
+// UnmanagedReverse selects what happens to reverse queries for the private ranges that none of
+// the reverse zones cover. Upstream resolvers usually have no answer for them and time out.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum UnmanagedReverse {
+    // pass them to the forwarder, like any other query.
+    #[default]
+    #[serde(rename = "forward")]
+    Forward,
+    // answer them authoritatively with NXDOMAIN.
+    #[serde(rename = "nxdomain")]
+    NXDomain,
+}
+
+impl std::fmt::Display for UnmanagedReverse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            UnmanagedReverse::Forward => "forward",
+            UnmanagedReverse::NXDomain => "nxdomain",
+        })
+    }
+}
+
+impl FromStr for UnmanagedReverse {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forward" => Ok(UnmanagedReverse::Forward),
+            "nxdomain" => Ok(UnmanagedReverse::NXDomain),
+            _ => Err(anyhow!(
+                "invalid format: allowed values: [forward, nxdomain]"
+            )),
+        }
+    }
+}
+
+// PresyncResponse selects how names in the managed zones that do not exist are answered before
+// the first sync has loaded the members.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum PresyncResponse {
+    #[default]
+    #[serde(rename = "nxdomain")]
+    NXDomain,
+    // so resolvers retry rather than cache the name as missing.
+    #[serde(rename = "servfail")]
+    ServFail,
+}
+
+impl std::fmt::Display for PresyncResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PresyncResponse::NXDomain => "nxdomain",
+            PresyncResponse::ServFail => "servfail",
+        })
+    }
+}
+
+impl FromStr for PresyncResponse {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nxdomain" => Ok(PresyncResponse::NXDomain),
+            "servfail" => Ok(PresyncResponse::ServFail),
+            _ => Err(anyhow!(
+                "invalid format: allowed values: [nxdomain, servfail]"
+            )),
+        }
+    }
+}
+
+// SharedIpPolicy selects the PTR record of an IP that several members are assigned, e.g. through
+// bridging. The members' forward records are not affected.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum SharedIpPolicy {
+    // the PTR points at the last of the members, and a warning is logged.
+    #[default]
+    #[serde(rename = "warn")]
+    Warn,
+    // the PTR points at every one of the members.
+    #[serde(rename = "merge")]
+    Merge,
+    // the IP gets no PTR.
+    #[serde(rename = "skip")]
+    Skip,
+}
+
+impl std::fmt::Display for SharedIpPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SharedIpPolicy::Warn => "warn",
+            SharedIpPolicy::Merge => "merge",
+            SharedIpPolicy::Skip => "skip",
+        })
+    }
+}
+
+impl FromStr for SharedIpPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(SharedIpPolicy::Warn),
+            "merge" => Ok(SharedIpPolicy::Merge),
+            "skip" => Ok(SharedIpPolicy::Skip),
+            _ => Err(anyhow!(
+                "invalid format: allowed values: [warn, merge, skip]"
+            )),
+        }
+    }
+}
+
+// IdNameCollision selects what happens to a member whose custom name is, or could become, the
+// zt-<member id> name of another member. A dropped name leaves the member with its own zt-<member id>
+// name, which its PTR records then point at.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum IdNameCollision {
+    // the custom name is published anyway, over the other member's records.
+    #[default]
+    #[serde(rename = "allow")]
+    Allow,
+    // the custom name is dropped if it is the zt-<member id> name of a member on the network.
+    #[serde(rename = "skip")]
+    Skip,
+    // every custom name starting with zt- is dropped, so members that join later are safe too.
+    #[serde(rename = "reserve")]
+    Reserve,
+}
+
+impl IdNameCollision {
+    // rejects tells whether name is kept from the member whose own zt-<member id> name is own.
+    fn rejects(&self, name: &Name, own: &Name, id_names: &HashSet<Name>) -> bool {
+        if name == own {
+            return false;
+        }
+
+        match self {
+            IdNameCollision::Allow => false,
+            IdNameCollision::Skip => id_names.contains(name),
+            IdNameCollision::Reserve => name
+                .iter()
+                .next()
+                .is_some_and(|label| label.to_ascii_lowercase().starts_with(b"zt-")),
+        }
+    }
+}
+
+impl std::fmt::Display for IdNameCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            IdNameCollision::Allow => "allow",
+            IdNameCollision::Skip => "skip",
+            IdNameCollision::Reserve => "reserve",
+        })
+    }
+}
+
+impl FromStr for IdNameCollision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(IdNameCollision::Allow),
+            "skip" => Ok(IdNameCollision::Skip),
+            "reserve" => Ok(IdNameCollision::Reserve),
+            _ => Err(anyhow!(
+                "invalid format: allowed values: [allow, skip, reserve]"
+            )),
+        }
+    }
+}
+
+// MissingHosts selects what happens to the records of a hosts file that disappears while running.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum MissingHosts {
+    // the records last read from the file are kept, and an error is logged.
+    #[default]
+    #[serde(rename = "keep")]
+    Keep,
+    // the file is read as empty, so its records are pruned.
+    #[serde(rename = "clear")]
+    Clear,
+}
+
+impl std::fmt::Display for MissingHosts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MissingHosts::Keep => "keep",
+            MissingHosts::Clear => "clear",
+        })
+    }
+}
+
+impl FromStr for MissingHosts {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(MissingHosts::Keep),
+            "clear" => Ok(MissingHosts::Clear),
+            _ => Err(anyhow!("invalid format: allowed values: [keep, clear]")),
+        }
+    }
+}
+
+// PtrFormat rewrites the hostname label of PTR targets, e.g. `islay.home.arpa.` becomes
+// `rev-islay.home.arpa.` with a prefix of `rev-`. The forward names are left alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PtrFormat {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+}
+
+impl PtrFormat {
+    pub fn apply(&self, name: Name) -> Result<Name, anyhow::Error> {
+        if self.prefix.is_none() && self.suffix.is_none() {
+            return Ok(name);
+        }
+
+        let host = match name.iter().next() {
+            Some(label) => String::from_utf8_lossy(label).to_string(),
+            None => return Ok(name),
+        };
+
+        format!(
+            "{}{}{}",
+            self.prefix.as_deref().unwrap_or_default(),
+            host,
+            self.suffix.as_deref().unwrap_or_default()
+        )
+        .to_fqdn(name.base_name())
+    }
+}
+
+// SoaTimers are the SOA fields that tell secondaries how often to check for a new serial, how
+// soon to try again after a failed check and when to stop answering for the zone, plus the
+// negative caching TTL (RFC 2308). All are in seconds; the defaults are those of RFC 1912.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoaTimers {
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+impl Default for SoaTimers {
+    fn default() -> Self {
+        Self {
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        }
+    }
+}
+
+impl SoaTimers {
+    // new fills in the defaults for anything unset and checks that the timers make sense together.
+    pub fn new(
+        refresh: Option<u32>,
+        retry: Option<u32>,
+        expire: Option<u32>,
+        minimum: Option<u32>,
+    ) -> Result<Self, anyhow::Error> {
+        let default = Self::default();
+        let timers = Self {
+            refresh: refresh.unwrap_or(default.refresh),
+            retry: retry.unwrap_or(default.retry),
+            expire: expire.unwrap_or(default.expire),
+            minimum: minimum.unwrap_or(default.minimum),
+        };
+
+        if timers.refresh == 0 || timers.retry == 0 {
+            return Err(anyhow!("soa_refresh and soa_retry must be greater than 0"));
+        }
+
+        if timers.retry > timers.refresh {
+            return Err(anyhow!(
+                "soa_retry ({}) must not be longer than soa_refresh ({})",
+                timers.retry,
+                timers.refresh
+            ));
+        }
+
+        if timers.expire <= timers.refresh.saturating_add(timers.retry) {
+            return Err(anyhow!(
+                "soa_expire ({}) must be longer than soa_refresh and soa_retry together ({})",
+                timers.expire,
+                timers.refresh.saturating_add(timers.retry)
+            ));
+        }
+
+        // the SOA holds refresh, retry and expire as signed 32-bit numbers.
+        if timers.expire > i32::MAX as u32 {
+            return Err(anyhow!(
+                "soa_expire ({}) must be at most {}",
+                timers.expire,
+                i32::MAX
+            ));
+        }
+
+        // RFC 2308 recommends no more than a day of negative caching.
+        if timers.minimum > 86400 {
+            return Err(anyhow!(
+                "soa_minimum ({}) must be at most 86400",
+                timers.minimum
+            ));
+        }
+
+        Ok(timers)
+    }
+
+    fn soa(&self, mname: Name, rname: Name, serial: u32) -> SOA {
+        SOA::new(
+            mname,
+            rname,
+            serial,
+            self.refresh as i32,
+            self.retry as i32,
+            self.expire as i32,
+            self.minimum,
+        )
+    }
+}
+
+// version_text keeps the letters, digits, dots, dashes, underscores and plus signs of a client
+// version, up to 64 of them. Nothing is left of a version with none of those.
+fn version_text(version: &str) -> Option<String> {
+    let version: String = version
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+'))
+        .take(64)
+        .collect();
+
+    Some(version).filter(|version| !version.is_empty())
+}
+
+#[derive(Debug, Clone)]
+struct ZTRecord {
+    node_id: String,
+    fqdn: Name,
+    custom_name: Option<Name>,
+    // <node id>.addr.<domain>, if those names are published.
+    address_name: Option<Name>,
+    ptr_name: Name,
+    ips: Vec<IpAddr>,
+    wildcard: bool,
+    wildcard_families: IpFamilies,
+    // the TXT record with the member's last authorization time, if it is published.
+    authorized: Option<String>,
+    // the TXT records with the member's ZeroTier version and protocol, if they are published.
+    version: Vec<String>,
+}
+
+impl ZTRecord {
+    pub fn new(
+        member: &central_api::types::Member,
+        sixplane: Option<IpNetwork>,
+        rfc4193: Option<IpNetwork>,
+        zt: &ZTAuthority,
+        domain_name: Name,
+    ) -> Result<Self, anyhow::Error> {
+        let member_name = format!(
+            "zt-{}",
+            member
+                .clone()
+                .node_id
+                .expect("Node ID for member does not exist")
+        );
+
+        let fqdn = member_name.to_fqdn(domain_name.clone())?;
+        let address_name = match (zt.address_names, &member.node_id) {
+            (true, Some(node_id)) => Some(
+                Name::from_str(node_id)?
+                    .append_domain(&Name::from_str(ADDRESS_LABEL)?.append_domain(&domain_name)?)?,
+            ),
+            _ => None,
+        };
+
+        // this is default the zt-<member id> but can switch to a named name if
+        // tweaked in central. see below.
+        let mut custom_name = None;
+        let mut ptr_name = fqdn.clone();
+
+        if let Some(name) = parse_member_name(
+            member.name.clone(),
+            domain_name,
+            zt.min_name_length,
+            zt.truncate_names,
+        ) {
+            custom_name = Some(name.clone());
+            ptr_name = name;
+        }
+
+        let ptr_name = zt.ptr_format.apply(ptr_name)?;
+
+        // a member that was added but never configured has no managed ips; its computed 6plane
+        // and rfc4193 addresses only need the node and network ids.
+        let mut ips = member
+            .config
+            .as_ref()
+            .and_then(|c| c.ip_assignments.as_ref())
+            .map_or(Vec::new(), |v| {
+                v.iter()
+                    .map(|s| IpAddr::from_str(s).expect("Could not parse IP address"))
+                    .collect()
+            });
+
+        // the computed addresses only stand in for a primary address of a family with none assigned.
+        if zt.primary_ip {
+            ips = primary_ips(&ips);
+        }
+        let computed = |ips: &[IpAddr]| !zt.primary_ip || !ips.iter().any(IpAddr::is_ipv6);
+
+        if sixplane.is_some() && computed(&ips) {
+            ips.push(member.clone().sixplane()?.ip());
+        }
+
+        if rfc4193.is_some() && computed(&ips) {
+            ips.push(member.clone().rfc4193()?.ip());
+        }
+
+        // Central reports 0 for members that were never authorized.
+        let authorized = zt.authorized_txt.and_then(|format| {
+            member
+                .config
+                .as_ref()
+                .and_then(|c| c.last_authorized_time)
+                .filter(|t| *t > 0)
+                .and_then(|t| format.format(t))
+                .map(|t| format!("authorized={}", t))
+        });
+
+        // Central passes on whatever the client reported, so only the characters of a version
+        // number are kept.
+        let version = if zt.version_txt {
+            member
+                .client_version
+                .as_deref()
+                .and_then(version_text)
+                .map(|version| format!("version={}", version))
+                .into_iter()
+                .chain(
+                    member
+                        .protocol_version
+                        .map(|protocol| format!("protocol={}", protocol)),
+                )
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            node_id: member.node_id.clone().unwrap_or_default(),
+            version,
+            wildcard: zt.wildcard,
+            wildcard_families: zt.wildcard_families,
+            authorized,
+            fqdn,
+            custom_name,
+            address_name,
+            ptr_name,
+            ips,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        net::IpAddr,
+        str::FromStr,
+        time::{Duration, SystemTime},
+    };
+
+    use ipnetwork::IpNetwork;
+    use trust_dns_resolver::{
+        proto::rr::{RData, RecordType},
+        IntoName, Name,
+    };
+    use trust_dns_server::{
+        authority::{Authority, LookupError, LookupOptions},
+        client::rr::LowerName,
+    };
+
+    use super::{
+        find_members, init_catalog, IdNameCollision, Liveness, MissingHosts, PtrFormat,
+        RecordAuthority, SharedIpPolicy, SoaTimers, SyncSchedule, TimeFormat, UnmanagedReverse,
+        ZTAuthority, ZTRecord,
+    };
+    use crate::{addresses::IpFamilies, tests::fake_central, traits::ToPointerSOA};
+    use zerotier_api::central_api::{
+        self,
+        types::{Member, Network},
+    };
+
+    // test_authority builds a forward authority for home.arpa. and a reverse authority for
+    // 10.0.0.0/24.
+    async fn test_authority() -> ZTAuthority {
+        let domain = LowerName::from_str("home.arpa.").unwrap();
+        let member = LowerName::from_str("zt-foo.home.arpa.").unwrap();
+        let cidr = IpNetwork::from_str("10.0.0.0/24").unwrap();
+
+        let mut reverse_authority_map = std::collections::HashMap::new();
+        reverse_authority_map.insert(
+            cidr,
+            RecordAuthority::new(cidr.to_ptr_soa_name().unwrap(), member.clone(), 60)
+                .await
+                .unwrap(),
+        );
+
+        ZTAuthority::new(
+            "1234567891011121".to_string(),
+            central_api::Client::new("http://127.0.0.1:9"),
+            RecordAuthority::new(domain, member, 60).await.unwrap(),
+            reverse_authority_map,
+        )
+    }
+
+    fn test_network(value: serde_json::Value) -> Network {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn test_member(node_id: &str, name: Option<&str>, ips: &[&str]) -> Member {
+        serde_json::from_value(serde_json::json!({
+            "nodeId": node_id,
+            "networkId": "1234567891011121",
+            "name": name,
+            "config": { "ipAssignments": ips },
+        }))
+        .unwrap()
+    }
+
+    // lookup_data is the data of the records the zone holds at name, or none if the lookup fails.
+    fn lookup_data(
+        authority: &RecordAuthority,
+        name: &str,
+        rtype: RecordType,
+    ) -> impl Future<Output = Vec<String>> {
+        let authority = authority.clone();
+        let name = LowerName::from_str(name).unwrap();
+        async move {
+            authority
+                .authority
+                .lookup(&name, rtype, LookupOptions::default())
+                .await
+                .map(|l| l.iter().map(|r| r.data().unwrap().to_string()).collect())
+                .unwrap_or_default()
+        }
+    }
+
+    // lookup_answer is the data of the answer to a query for name, with the zone's own rules, such
+    // as wildcards and the catch-all, applied.
+    fn lookup_answer(
+        authority: &RecordAuthority,
+        name: &str,
+        rtype: RecordType,
+    ) -> impl Future<Output = Result<Vec<String>, LookupError>> {
+        use trust_dns_server::authority::AuthorityObject;
+
+        let authority = authority.clone();
+        let name = LowerName::from_str(name).unwrap();
+        async move {
+            authority
+                .lookup(&name, rtype, LookupOptions::default())
+                .await
+                .map(|l| l.iter().map(|r| r.data().unwrap().to_string()).collect())
+        }
+    }
+
+    async fn lookup_ttls(authority: &RecordAuthority, name: Name, rtype: RecordType) -> Vec<u32> {
+        authority
+            .authority
+            .lookup(&name.into(), rtype, LookupOptions::default())
+            .await
+            .unwrap()
             .iter()
-            .map(|&ip| match ip {
-                IpAddr::V4(ip) => RData::A(ip),
-                IpAddr::V6(ip) => RData::AAAA(ip),
-            })
+            .map(|r| r.ttl())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_record_ttls() {
+        let domain = LowerName::from_str("home.arpa.").unwrap();
+        let member = LowerName::from_str("zt-foo.home.arpa.").unwrap();
+        let cidr = IpNetwork::from_str("10.0.0.0/24").unwrap();
+
+        let forward = RecordAuthority::new(domain, member.clone(), 30)
+            .await
+            .unwrap();
+        let reverse = RecordAuthority::new(cidr.to_ptr_soa_name().unwrap(), member, 3600)
+            .await
+            .unwrap();
+
+        let ip = IpAddr::from_str("10.0.0.1").unwrap();
+        let fqdn = Name::from_str("islay.home.arpa.").unwrap();
+
+        forward.match_or_insert(fqdn.clone(), &[ip]).await;
+        reverse
+            .configure_ptrs(ip.into_name().unwrap(), vec![fqdn.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!(lookup_ttls(&forward, fqdn, RecordType::A).await, vec![30]);
+        assert_eq!(
+            lookup_ttls(&reverse, ip.into_name().unwrap(), RecordType::PTR).await,
+            vec![3600]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_txt() {
+        let mut zt = test_authority().await;
+        zt.routes_txt = true;
+
+        let network = test_network(serde_json::json!({
+            "id": "1234567891011121",
+            "config": {
+                "routes": [
+                    { "target": "10.0.0.0/24" },
+                    { "target": "192.168.1.0/24", "via": "10.0.0.1" },
+                ],
+            },
+        }));
+
+        zt.configure_members(
+            network,
+            vec![test_member("abcdef0123", None, &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+
+        let name = Name::from_str("_routes.home.arpa.").unwrap();
+        let mut txts: Vec<String> = zt
+            .forward_authority
+            .authority
+            .lookup(
+                &name.clone().into(),
+                RecordType::TXT,
+                LookupOptions::default(),
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| r.data().unwrap().to_string())
             .collect();
+        txts.sort();
+        assert_eq!(txts, vec!["10.0.0.0/24", "192.168.1.0/24 via 10.0.0.1"]);
+
+        // routes removed from the network are pruned on the next sync
+        let network = test_network(serde_json::json!({
+            "id": "1234567891011121",
+            "config": { "routes": [] },
+        }));
+        zt.configure_members(network, vec![]).await.unwrap();
+
+        assert!(zt
+            .forward_authority
+            .authority
+            .lookup(&name.into(), RecordType::TXT, LookupOptions::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_hosts_reload_atomic() {
+        let path = std::env::temp_dir().join(format!("zeronsd-hosts-{}", rand::random::<u32>()));
+        std::fs::write(&path, "10.0.0.5 web\n").unwrap();
+
+        let mut zt = test_authority().await;
+        zt.hosts_file = Some(path.clone());
+        zt.hosts_ttl = Some(300);
+        zt.configure_hosts().await.unwrap();
+
+        let name = Name::from_str("web.home.arpa.").unwrap();
+        let forward = zt.forward_authority.clone();
+        let lookups = tokio::spawn(async move {
+            for _ in 0..2000 {
+                let records: Vec<u32> = forward
+                    .authority
+                    .lookup(
+                        &name.clone().into(),
+                        RecordType::A,
+                        LookupOptions::default(),
+                    )
+                    .await
+                    .expect("host record went missing during reload")
+                    .iter()
+                    .map(|r| r.ttl())
+                    .collect();
+                assert_eq!(records, vec![300]);
+                tokio::task::yield_now().await;
+            }
+        });
+
+        for i in 0..200 {
+            std::fs::write(&path, format!("10.0.0.{} web\n", 5 + i % 2)).unwrap();
+            zt.configure_hosts().await.unwrap();
+            zt.configure_members(test_network(serde_json::json!({"config": {}})), vec![])
+                .await
+                .unwrap();
+        }
+
+        lookups.await.unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hosts_interval() {
+        let path = std::env::temp_dir().join(format!("zeronsd-hosts-{}", rand::random::<u32>()));
+        std::fs::write(&path, "10.0.0.5 web\n").unwrap();
+
+        let mut zt = test_authority().await;
+        zt.hosts_file = Some(path.clone());
+        zt.hosts_interval = Some(std::time::Duration::new(3600, 0));
+
+        let network = test_network(serde_json::json!({"config": {}}));
+        let member = test_member("abcdef0123", Some("islay"), &["10.0.0.2"]);
+
+        assert!(zt.configure_hosts().await.unwrap());
+        zt.configure_members(network.clone(), vec![member.clone()])
+            .await
+            .unwrap();
+
+        // a members-only tick keeps the parsed hosts and still prunes against them
+        assert!(!zt.configure_hosts().await.unwrap());
+        zt.configure_members(network.clone(), vec![member.clone()])
+            .await
+            .unwrap();
+
+        let name = LowerName::from_str("web.home.arpa.").unwrap();
+        let lookup = zt
+            .forward_authority
+            .authority
+            .lookup(&name, RecordType::A, LookupOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(lookup.iter().count(), 1);
+
+        // a change to the file is picked up before the interval passes
+        let f = std::fs::File::options().write(true).open(&path).unwrap();
+        f.set_modified(std::time::SystemTime::now() + std::time::Duration::new(10, 0))
+            .unwrap();
+        assert!(zt.configure_hosts().await.unwrap());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_hosts() {
+        let network = test_network(serde_json::json!({"config": {}}));
+        let member = test_member("abcdef0123", Some("islay"), &["10.0.0.2"]);
+        let name = LowerName::from_str("web.home.arpa.").unwrap();
+
+        for (policy, kept) in [(MissingHosts::Keep, true), (MissingHosts::Clear, false)] {
+            let path =
+                std::env::temp_dir().join(format!("zeronsd-hosts-{}", rand::random::<u32>()));
+            std::fs::write(&path, "10.0.0.5 web\n").unwrap();
+
+            let mut zt = test_authority().await;
+            zt.hosts_file = Some(path.clone());
+            zt.missing_hosts = policy;
+
+            zt.configure_hosts().await.unwrap();
+            zt.configure_members(network.clone(), vec![member.clone()])
+                .await
+                .unwrap();
+
+            std::fs::remove_file(path).unwrap();
+            assert_eq!(zt.configure_hosts().await.is_ok(), !kept, "{}", policy);
+            zt.configure_members(network.clone(), vec![member.clone()])
+                .await
+                .unwrap();
+
+            let lookup = zt
+                .forward_authority
+                .authority
+                .lookup(&name, RecordType::A, LookupOptions::default())
+                .await;
+            assert_eq!(lookup.is_ok(), kept, "{}", policy);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hosts_sources() {
+        let dir = std::env::temp_dir();
+        let lab = dir.join(format!("zeronsd-hosts-{}", rand::random::<u32>()));
+        let office = dir.join(format!("zeronsd-hosts-{}", rand::random::<u32>()));
+        std::fs::write(
+            &lab,
+            "10.0.0.5 printer\n10.0.0.7 gw.home.arpa.\n10.0.0.8 router.example.com.\n",
+        )
+        .unwrap();
+        std::fs::write(&office, "10.0.0.6 printer\n").unwrap();
+
+        let mut zt = test_authority().await;
+        zt.hosts_sources = vec![
+            (lab.clone(), Name::from_str("lab.home.arpa.").unwrap()),
+            (office.clone(), Name::from_str("office.home.arpa.").unwrap()),
+        ];
+        zt.configure_hosts().await.unwrap();
+        zt.configure_members(test_network(serde_json::json!({"config": {}})), vec![])
+            .await
+            .unwrap();
+
+        for (name, ip) in [
+            ("printer.lab.home.arpa.", "10.0.0.5"),
+            ("printer.office.home.arpa.", "10.0.0.6"),
+            // already fully qualified, so not suffixed
+            ("gw.home.arpa.", "10.0.0.7"),
+        ] {
+            let lookup = zt
+                .forward_authority
+                .authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .unwrap();
+            let ips: Vec<String> = lookup
+                .iter()
+                .map(|r| r.data().unwrap().to_string())
+                .collect();
+            assert_eq!(ips, vec![ip.to_string()], "{}", name);
+        }
+
+        assert!(zt
+            .forward_authority
+            .authority
+            .lookup(
+                &LowerName::from_str("printer.home.arpa.").unwrap(),
+                RecordType::A,
+                LookupOptions::default(),
+            )
+            .await
+            .is_err());
+
+        // a name outside of the zone would never be asked for, so it is left out altogether
+        let outside = Name::from_str("router.example.com.").unwrap();
+        assert!(zt
+            .hosts
+            .as_ref()
+            .unwrap()
+            .values()
+            .all(|names| !names.contains(&outside)));
+        assert!(
+            lookup_data(&zt.forward_authority, "router.example.com.", RecordType::A)
+                .await
+                .is_empty()
+        );
+
+        std::fs::remove_file(lab).unwrap();
+        std::fs::remove_file(office).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_merge_names() {
+        let hosts = std::env::temp_dir().join(format!("zeronsd-hosts-{}", rand::random::<u32>()));
+        std::fs::write(&hosts, "10.0.0.9 islay\n10.0.0.2 islay\n").unwrap();
+
+        let lookup = |zt: ZTAuthority| async move {
+            let mut ips =
+                lookup_data(&zt.forward_authority, "islay.home.arpa.", RecordType::A).await;
+            ips.sort();
+            ips
+        };
+
+        let islay = || test_member("abcdef0123", Some("islay"), &["10.0.0.2", "10.0.0.3"]);
+
+        for (merge_names, want) in [
+            (false, vec!["10.0.0.2", "10.0.0.3"]),
+            // 10.0.0.2 is in both, but only answered once
+            (true, vec!["10.0.0.2", "10.0.0.3", "10.0.0.9"]),
+        ] {
+            let mut zt = test_authority().await;
+            zt.hosts_file = Some(hosts.clone());
+            zt.merge_names = merge_names;
+            zt.configure_hosts().await.unwrap();
+            zt.configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                vec![islay()],
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                lookup(zt.clone()).await,
+                want,
+                "merge_names: {}",
+                merge_names
+            );
+
+            // reloading the hosts file keeps the member's addresses when merging
+            zt.hosts_loaded = None;
+            zt.configure_hosts().await.unwrap();
+            if merge_names {
+                assert_eq!(lookup(zt.clone()).await, want);
+            }
+
+            // once the member is gone, only the hosts file's addresses are left
+            zt.configure_members(test_network(serde_json::json!({"config": {}})), vec![])
+                .await
+                .unwrap();
+            assert_eq!(lookup(zt.clone()).await, vec!["10.0.0.2", "10.0.0.9"]);
+        }
+
+        std::fs::remove_file(hosts).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_precedence() {
+        let hosts = std::env::temp_dir().join(format!("zeronsd-hosts-{}", rand::random::<u32>()));
+        std::fs::write(&hosts, "10.0.0.9 www\n").unwrap();
+
+        let mut zt = test_authority().await;
+        zt.wildcard = true;
+        zt.hosts_sources = vec![(hosts.clone(), Name::from_str("islay.home.arpa.").unwrap())];
+        zt.configure_hosts().await.unwrap();
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member(
+                "abcdef0123",
+                Some("islay"),
+                &["10.0.0.2", "fd00::2"],
+            )],
+        )
+        .await
+        .unwrap();
+
+        let lookup =
+            |name: &str, rtype: RecordType| lookup_answer(&zt.forward_authority, name, rtype);
+
+        // the hosts entry beats the member's wildcard, for the types it has and the ones it has not
+        assert_eq!(
+            lookup("www.islay.home.arpa.", RecordType::A).await.unwrap(),
+            vec!["10.0.0.9"]
+        );
+        assert!(lookup("www.islay.home.arpa.", RecordType::AAAA)
+            .await
+            .unwrap_err()
+            .is_name_exists());
+
+        // names without one of their own still get the wildcard
+        assert_eq!(
+            lookup("ftp.islay.home.arpa.", RecordType::AAAA)
+                .await
+                .unwrap(),
+            vec!["fd00::2"]
+        );
+        assert_eq!(
+            lookup("islay.home.arpa.", RecordType::A).await.unwrap(),
+            vec!["10.0.0.2"]
+        );
+
+        std::fs::remove_file(hosts).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_apex_wildcard() {
+        let mut zt = test_authority().await;
+        zt.wildcard = true;
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+
+        // a member whose name comes out as the domain itself gets no *.home.arpa.
+        let domain = Name::from_str("home.arpa.").unwrap();
+        let mut record = ZTRecord::new(
+            &test_member("abcdef0124", None, &["10.0.0.3"]),
+            None,
+            None,
+            &zt,
+            domain.clone(),
+        )
+        .unwrap();
+        record.custom_name = Some(domain);
+
+        let mut records = Vec::new();
+        zt.forward_authority
+            .insert_member(&mut records, record, &Default::default())
+            .await
+            .unwrap();
+        assert!(!records.contains(&LowerName::from_str("*.home.arpa.").unwrap()));
+        assert!(records.contains(&LowerName::from_str("*.zt-abcdef0124.home.arpa.").unwrap()));
+
+        let lookup = |name: &str| lookup_answer(&zt.forward_authority, name, RecordType::A);
+
+        // only names under a member are wildcarded
+        assert!(lookup("foo.home.arpa.").await.unwrap_err().is_nx_domain());
+        assert_eq!(
+            lookup("foo.islay.home.arpa.").await.unwrap(),
+            vec!["10.0.0.2"]
+        );
+        assert_eq!(lookup("home.arpa.").await.unwrap(), vec!["10.0.0.3"]);
+    }
+
+    #[tokio::test]
+    async fn test_pending_domain() {
+        let mut zt = test_authority().await;
+        zt.pending_domain = Some(Name::from_str("pending.home.arpa.").unwrap());
+        let reverse = zt.reverse_authority_map.values().next().unwrap().clone();
+
+        let lookup = |authority: RecordAuthority, name: &str, rtype: RecordType| {
+            lookup_data(&authority, name, rtype)
+        };
+        let ptr = IpAddr::from_str("10.0.0.3")
+            .unwrap()
+            .into_name()
+            .unwrap()
+            .to_string();
+
+        let mut jura = test_member("0123abcdef", Some("jura"), &["10.0.0.3"]);
+        jura.config.as_mut().unwrap().authorized = Some(false);
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![
+                test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+                jura.clone(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let forward = zt.forward_authority.clone();
+        assert_eq!(
+            lookup(forward.clone(), "jura.pending.home.arpa.", RecordType::A).await,
+            vec!["10.0.0.3"]
+        );
+        assert_eq!(
+            lookup(
+                forward.clone(),
+                "zt-0123abcdef.pending.home.arpa.",
+                RecordType::TXT
+            )
+            .await,
+            vec!["pending=0123abcdef"]
+        );
+        assert!(lookup(forward.clone(), "jura.home.arpa.", RecordType::A)
+            .await
+            .is_empty());
+        assert!(lookup(reverse.clone(), &ptr, RecordType::PTR)
+            .await
+            .is_empty());
+        assert_eq!(
+            lookup(forward.clone(), "islay.home.arpa.", RecordType::A).await,
+            vec!["10.0.0.2"]
+        );
+
+        // once authorized, the member moves to the domain
+        jura.config.as_mut().unwrap().authorized = Some(true);
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![
+                test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+                jura,
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            lookup(forward.clone(), "jura.home.arpa.", RecordType::A).await,
+            vec!["10.0.0.3"]
+        );
+        assert!(
+            lookup(forward.clone(), "jura.pending.home.arpa.", RecordType::A)
+                .await
+                .is_empty()
+        );
+        assert!(lookup(forward, "jura.home.arpa.", RecordType::TXT)
+            .await
+            .is_empty());
+        assert_eq!(
+            lookup(reverse, &ptr, RecordType::PTR).await,
+            vec!["jura.home.arpa."]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_catch_all() {
+        let mut zt = test_authority().await;
+        zt.wildcard = true;
+        zt.forward_authority.catch_all =
+            Some((IpAddr::from_str("10.0.0.250").unwrap(), zt.ready.clone()));
+
+        let lookup =
+            |name: &str, rtype: RecordType| lookup_answer(&zt.forward_authority, name, rtype);
+
+        // nothing is caught before the members are loaded
+        assert!(lookup("nowhere.home.arpa.", RecordType::A)
+            .await
+            .unwrap_err()
+            .is_nx_domain());
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+
+        let name = format!("{}.home.arpa.", rand::random::<u32>());
+        assert_eq!(
+            lookup(&name, RecordType::A).await.unwrap(),
+            vec!["10.0.0.250"]
+        );
+        assert!(lookup(&name, RecordType::AAAA)
+            .await
+            .unwrap_err()
+            .is_name_exists());
+
+        // members and their wildcards are not shadowed
+        assert_eq!(
+            lookup("islay.home.arpa.", RecordType::A).await.unwrap(),
+            vec!["10.0.0.2"]
+        );
+        assert_eq!(
+            lookup("www.islay.home.arpa.", RecordType::A).await.unwrap(),
+            vec!["10.0.0.2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_families() {
+        let mut zt = test_authority().await;
+        zt.wildcard = true;
+        zt.wildcard_families = IpFamilies::AAAA;
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![
+                test_member("abcdef0123", Some("islay"), &["10.0.0.2", "fd00::2"]),
+                test_member("0123abcdef", None, &["10.0.0.3"]),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let lookup = |name: &str, rtype: RecordType| {
+            let data = lookup_data(&zt.forward_authority, name, rtype);
+            async move { data.await.len() }
+        };
+
+        assert_eq!(lookup("www.islay.home.arpa.", RecordType::AAAA).await, 1);
+        assert_eq!(lookup("www.islay.home.arpa.", RecordType::A).await, 0);
+        assert_eq!(
+            lookup("www.zt-abcdef0123.home.arpa.", RecordType::AAAA).await,
+            1
+        );
+        assert_eq!(
+            lookup("www.zt-abcdef0123.home.arpa.", RecordType::A).await,
+            0
+        );
+        // the names themselves are unaffected
+        assert_eq!(lookup("islay.home.arpa.", RecordType::A).await, 1);
+
+        // an IPv4-only member gets no wildcard at all under an AAAA-only policy
+        assert_eq!(
+            lookup("www.zt-0123abcdef.home.arpa.", RecordType::A).await,
+            0
+        );
+        assert_eq!(lookup("zt-0123abcdef.home.arpa.", RecordType::A).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_member_overrides() {
+        let path =
+            std::env::temp_dir().join(format!("zeronsd-overrides-{}", rand::random::<u32>()));
+        std::fs::write(
+            &path,
+            "abcdef0123:\n  ips: [10.0.0.100]\n0123abcdef:\n  ips: [10.0.0.101]\n  cname: islay\n",
+        )
+        .unwrap();
+
+        let mut zt = test_authority().await;
+        zt.overrides_file = Some(path.clone());
+
+        let members = vec![
+            test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+            test_member("0123abcdef", None, &["10.0.0.3"]),
+        ];
+
+        let lookup = |name: &str, rtype: RecordType| {
+            let zt = zt.clone();
+            let name = LowerName::from_str(name).unwrap();
+            async move {
+                zt.forward_authority
+                    .authority
+                    .lookup(&name, rtype, LookupOptions::default())
+                    .await
+                    .map(|l| {
+                        l.iter()
+                            .filter(|r| r.record_type() == rtype)
+                            .map(|r| r.data().unwrap().to_string())
+                            .collect::<Vec<String>>()
+                    })
+                    .unwrap_or_default()
+            }
+        };
+
+        // the second pass makes sure pruning keeps the override records, and that a typo in the
+        // file keeps the last overrides in place
+        for _ in 0..2 {
+            zt.configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                members.clone(),
+            )
+            .await
+            .unwrap();
+
+            for name in ["islay.home.arpa.", "zt-abcdef0123.home.arpa."] {
+                let mut ips = lookup(name, RecordType::A).await;
+                ips.sort();
+                assert_eq!(ips, vec!["10.0.0.100", "10.0.0.2"], "{}", name);
+            }
+
+            // a CNAME takes precedence over the member's addresses
+            assert_eq!(
+                lookup("zt-0123abcdef.home.arpa.", RecordType::CNAME).await,
+                vec!["islay.home.arpa."]
+            );
+            assert!(lookup("zt-0123abcdef.home.arpa.", RecordType::A)
+                .await
+                .is_empty());
+
+            std::fs::write(&path, "abcdef0123: [unclosed\n").unwrap();
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_configure_members_offline() {
+        let zt = test_authority().await;
+
+        zt.configure_members(
+            test_network(serde_json::json!({"id": "1234567891011121", "config": {}})),
+            vec![
+                test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+                test_member("0123abcdef", None, &["10.0.0.3", "fd00::3"]),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let lookup = |authority: RecordAuthority, name: Name, rtype: RecordType| {
+            lookup_data(&authority, &name.to_string(), rtype)
+        };
+
+        let forward = zt.forward_authority.clone();
+        for (name, rtype, expected) in [
+            ("islay.home.arpa.", RecordType::A, "10.0.0.2"),
+            ("zt-abcdef0123.home.arpa.", RecordType::A, "10.0.0.2"),
+            ("zt-0123abcdef.home.arpa.", RecordType::A, "10.0.0.3"),
+            ("zt-0123abcdef.home.arpa.", RecordType::AAAA, "fd00::3"),
+        ] {
+            assert_eq!(
+                lookup(forward.clone(), Name::from_str(name).unwrap(), rtype).await,
+                vec![expected],
+                "{} {}",
+                name,
+                rtype
+            );
+        }
+
+        let reverse = zt.reverse_authority_map.values().next().unwrap().clone();
+        for (ip, expected) in [
+            ("10.0.0.2", "islay.home.arpa."),
+            ("10.0.0.3", "zt-0123abcdef.home.arpa."),
+        ] {
+            assert_eq!(
+                lookup(
+                    reverse.clone(),
+                    IpAddr::from_str(ip).unwrap().into_name().unwrap(),
+                    RecordType::PTR
+                )
+                .await,
+                vec![expected]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_group_tags() {
+        let mut zt = test_authority().await;
+        zt.group_tags = vec!["role".to_string(), "tier".to_string()];
+
+        let member = |node_id: &str, name: &str, ip: &str, tags: serde_json::Value| -> Member {
+            serde_json::from_value(serde_json::json!({
+                "nodeId": node_id,
+                "networkId": "1234567891011121",
+                "name": name,
+                "config": { "ipAssignments": [ip], "tags": tags },
+            }))
+            .unwrap()
+        };
+
+        zt.configure_members(
+            test_network(serde_json::json!({
+                "id": "1234567891011121",
+                "config": {},
+                "tagsByName": {
+                    "role": {"id": 1000, "enums": {"web": 10, "db": 20}},
+                    "tier": {"id": 2000},
+                },
+            })),
+            vec![
+                member(
+                    "abcdef0123",
+                    "islay",
+                    "10.0.0.2",
+                    serde_json::json!([[1000, 10]]),
+                ),
+                member(
+                    "0123abcdef",
+                    "jura",
+                    "10.0.0.3",
+                    serde_json::json!([[1000, 10]]),
+                ),
+                // in two groups: db, and tier 1, which has no name
+                member(
+                    "4567abcdef",
+                    "arran",
+                    "10.0.0.4",
+                    serde_json::json!([[1000, 20], [2000, 1]]),
+                ),
+                // a group cannot take a member's name
+                member("abcdef4567", "db", "10.0.0.5", serde_json::json!([])),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let lookup = |name: &str| {
+            let data = lookup_data(&zt.forward_authority, name, RecordType::A);
+            async move {
+                let mut ips = data.await;
+                ips.sort();
+                ips
+            }
+        };
+
+        assert_eq!(lookup("web.home.arpa.").await, vec!["10.0.0.2", "10.0.0.3"]);
+        assert_eq!(lookup("1.home.arpa.").await, vec!["10.0.0.4"]);
+        assert_eq!(lookup("db.home.arpa.").await, vec!["10.0.0.5"]);
+    }
+
+    #[tokio::test]
+    async fn test_id_name_collision() {
+        for (policy, id_name, ptr) in [
+            // the second member's name takes over the first one's id name
+            (
+                IdNameCollision::Allow,
+                "10.0.0.3",
+                "zt-abcdef0123.home.arpa.",
+            ),
+            (
+                IdNameCollision::Skip,
+                "10.0.0.2",
+                "zt-0123abcdef.home.arpa.",
+            ),
+            (
+                IdNameCollision::Reserve,
+                "10.0.0.2",
+                "zt-0123abcdef.home.arpa.",
+            ),
+        ] {
+            let mut zt = test_authority().await;
+            zt.id_name_collision = policy;
+            zt.configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                vec![
+                    test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+                    test_member("0123abcdef", Some("zt-abcdef0123"), &["10.0.0.3"]),
+                    // no member has this id name, but reserve keeps it anyway
+                    test_member("4567abcdef", Some("zt-4567"), &["10.0.0.4"]),
+                ],
+            )
+            .await
+            .unwrap();
+
+            let lookup =
+                |name: &str, rtype: RecordType| lookup_data(&zt.forward_authority, name, rtype);
+
+            assert_eq!(
+                lookup("zt-abcdef0123.home.arpa.", RecordType::A).await,
+                vec![id_name],
+                "{}",
+                policy
+            );
+            // the member keeps its own id name either way
+            assert_eq!(
+                lookup("zt-0123abcdef.home.arpa.", RecordType::A).await,
+                vec!["10.0.0.3"],
+                "{}",
+                policy
+            );
+
+            let ptrs = zt
+                .reverse_authority_map
+                .values()
+                .next()
+                .unwrap()
+                .authority
+                .lookup(
+                    &LowerName::from_str("3.0.0.10.in-addr.arpa.").unwrap(),
+                    RecordType::PTR,
+                    LookupOptions::default(),
+                )
+                .await
+                .unwrap()
+                .iter()
+                .map(|r| r.data().unwrap().to_string())
+                .collect::<Vec<String>>();
+            assert_eq!(ptrs, vec![ptr], "{}", policy);
+
+            let reserved = lookup("zt-4567.home.arpa.", RecordType::A).await;
+            if policy == IdNameCollision::Reserve {
+                assert!(reserved.is_empty());
+            } else {
+                assert_eq!(reserved, vec!["10.0.0.4"], "{}", policy);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unmanaged_reverse() {
+        let mut zt = test_authority().await;
+        zt.unmanaged_reverse = UnmanagedReverse::NXDomain;
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+
+        let catalog = init_catalog(zt, None).await.unwrap();
+        let lookup = |name: &str| {
+            let name = LowerName::from_str(name).unwrap();
+            let authority = catalog.find(&name).unwrap();
+            async move {
+                tokio::time::timeout(
+                    Duration::from_secs(1),
+                    authority.lookup(&name, RecordType::PTR, LookupOptions::default()),
+                )
+                .await
+                .expect("private reverse query was not answered promptly")
+                .map(|l| {
+                    l.iter()
+                        .map(|r| r.data().unwrap().to_string())
+                        .collect::<Vec<String>>()
+                })
+            }
+        };
+
+        // outside the managed 10.0.0.0/24, but within 10.0.0.0/8
+        assert!(lookup("4.3.2.10.in-addr.arpa.")
+            .await
+            .unwrap_err()
+            .is_nx_domain());
+        assert!(lookup("1.0.16.172.in-addr.arpa.")
+            .await
+            .unwrap_err()
+            .is_nx_domain());
+        assert!(lookup("1.0.168.192.in-addr.arpa.")
+            .await
+            .unwrap_err()
+            .is_nx_domain());
+
+        // the managed reverse zone is not shadowed
+        assert_eq!(
+            lookup("2.0.0.10.in-addr.arpa.").await.unwrap(),
+            vec!["islay.home.arpa."]
+        );
+
+        // public ranges still go to the forwarder
+        assert!(catalog
+            .find(&LowerName::from_str("8.8.8.8.in-addr.arpa.").unwrap())
+            .unwrap()
+            .origin()
+            .is_root());
+    }
+
+    #[tokio::test]
+    async fn test_member_without_config() {
+        let zt = test_authority().await;
+
+        // added to the network but never configured: no managed ips, but its computed ones
+        let member: Member = serde_json::from_value(serde_json::json!({
+            "nodeId": "abcdef0123",
+            "networkId": "1234567891011121",
+            "name": "islay",
+        }))
+        .unwrap();
+        assert!(member.config.is_none());
+
+        zt.configure_members(
+            test_network(serde_json::json!({
+                "id": "1234567891011121",
+                "config": {"v6AssignMode": {"6plane": true, "rfc4193": true}},
+            })),
+            vec![member],
+        )
+        .await
+        .unwrap();
+
+        for name in ["islay.home.arpa.", "zt-abcdef0123.home.arpa."] {
+            let name = LowerName::from_str(name).unwrap();
+            let mut ips: Vec<String> = zt
+                .forward_authority
+                .authority
+                .lookup(&name, RecordType::AAAA, LookupOptions::default())
+                .await
+                .unwrap()
+                .iter()
+                .map(|r| r.data().unwrap().to_string())
+                .collect();
+            ips.sort();
+            assert_eq!(
+                ips,
+                vec![
+                    "fc83:3547:59ab:cdef:123::1",
+                    "fd12:3456:7891:111:2199:93ab:cdef:123"
+                ],
+                "{}",
+                name
+            );
+
+            assert!(zt
+                .forward_authority
+                .authority
+                .lookup(&name, RecordType::A, LookupOptions::default())
+                .await
+                .is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_member_without_node_id() {
+        let zt = test_authority().await;
+        let invite: Member = serde_json::from_value(serde_json::json!({
+            "id": "1234567891011121-invite",
+            "networkId": "1234567891011121",
+            "name": "invited",
+            "config": { "ipAssignments": ["10.0.0.3"] },
+        }))
+        .unwrap();
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![
+                invite,
+                test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let lookup = |name: &str| lookup_data(&zt.forward_authority, name, RecordType::A);
+
+        assert!(lookup("invited.home.arpa.").await.is_empty());
+        assert_eq!(lookup("islay.home.arpa.").await, vec!["10.0.0.2"]);
+    }
+
+    #[tokio::test]
+    async fn test_online_within() {
+        let mut zt = test_authority().await;
+        zt.online = Some(Liveness::new(Duration::from_secs(1)));
+        zt.forward_authority.online = zt.online.clone();
+
+        let lookup =
+            |name: &str, rtype: RecordType| lookup_answer(&zt.forward_authority, name, rtype);
+
+        let members = |online: i64| {
+            let mut islay = test_member("abcdef0123", Some("islay"), &["10.0.0.2", "fd00::2"]);
+            islay.last_online = Some(online);
+            let mut jura = test_member("0123abcdef", Some("jura"), &["10.0.0.3"]);
+            jura.last_online = Some(0);
+            let mut arran = test_member("4567abcdef", Some("arran"), &["10.0.0.4"]);
+            arran.last_online = Some(online - 3_600_000);
+            vec![islay, jura, arran]
+        };
+        let now = || {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as i64
+        };
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            members(now()),
+        )
+        .await
+        .unwrap();
+
+        for name in ["islay.home.arpa.", "zt-abcdef0123.home.arpa."] {
+            assert_eq!(lookup(name, RecordType::A).await.unwrap(), vec!["10.0.0.2"]);
+        }
+        assert!(lookup("arran.home.arpa.", RecordType::A)
+            .await
+            .unwrap_err()
+            .is_name_exists());
+
+        // islay goes offline without another sync: it stops answering once its time is up
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        for name in ["islay.home.arpa.", "zt-abcdef0123.home.arpa."] {
+            for rtype in [RecordType::A, RecordType::AAAA] {
+                assert!(
+                    lookup(name, rtype).await.unwrap_err().is_name_exists(),
+                    "{} {}",
+                    name,
+                    rtype
+                );
+            }
+        }
+        // a member Central lost track of keeps answering
+        assert_eq!(
+            lookup("jura.home.arpa.", RecordType::A).await.unwrap(),
+            vec!["10.0.0.3"]
+        );
+
+        // and islay answers again once a sync sees it online
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            members(now()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            lookup("islay.home.arpa.", RecordType::AAAA).await.unwrap(),
+            vec!["fd00::2"]
+        );
+
+        // without online_within, nobody is left out
+        let mut forward = zt.forward_authority.clone();
+        forward.online = None;
+        let arran = lookup_answer(&forward, "arran.home.arpa.", RecordType::A).await;
+        assert_eq!(arran.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reverse_interval() {
+        use trust_dns_server::client::rr::RrKey;
+
+        let mut zt = test_authority().await;
+        zt.reverse_interval = Some(std::time::Duration::new(1, 0));
+        let reverse = zt.reverse_authority_map.values().next().unwrap().clone();
+
+        let ptr = |ip: &str| IpAddr::from_str(ip).unwrap().into_name().unwrap();
+        let lookup = |ip: &str| lookup_data(&reverse, &ptr(ip).to_string(), RecordType::PTR);
+        let sync = |members| {
+            let zt = zt.clone();
+            async move {
+                zt.configure_members(test_network(serde_json::json!({"config": {}})), members)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        sync(vec![test_member(
+            "abcdef0123",
+            Some("islay"),
+            &["10.0.0.2"],
+        )])
+        .await;
+        assert_eq!(lookup("10.0.0.2").await, vec!["islay.home.arpa."]);
+
+        // with the members unchanged, the reverse zone is not rebuilt before the interval: a PTR
+        // that went missing stays missing.
+        reverse
+            .authority
+            .records_mut()
+            .await
+            .remove(&RrKey::new(ptr("10.0.0.2").into(), RecordType::PTR));
+        sync(vec![test_member(
+            "abcdef0123",
+            Some("islay"),
+            &["10.0.0.2"],
+        )])
+        .await;
+        assert!(lookup("10.0.0.2").await.is_empty());
+
+        // a change that updates the forward zone updates the reverse zone in the same sync
+        sync(vec![test_member("abcdef0123", Some("jura"), &["10.0.0.3"])]).await;
+        assert_eq!(lookup("10.0.0.3").await, vec!["jura.home.arpa."]);
+        assert!(lookup("10.0.0.2").await.is_empty());
+
+        // once the interval has passed, it is rebuilt regardless
+        reverse
+            .authority
+            .records_mut()
+            .await
+            .remove(&RrKey::new(ptr("10.0.0.3").into(), RecordType::PTR));
+        tokio::time::sleep(std::time::Duration::new(1, 0)).await;
+        sync(vec![test_member("abcdef0123", Some("jura"), &["10.0.0.3"])]).await;
+        assert_eq!(lookup("10.0.0.3").await, vec!["jura.home.arpa."]);
+
+        // PTRs that come from elsewhere than a member's name or ips are no different: a gateway
+        // shows up in the next sync too.
+        let mut gateway = zt.clone();
+        gateway.gateway = Some((
+            Name::from_str("gateway.home.arpa.").unwrap(),
+            vec![IpAddr::from_str("10.0.0.1").unwrap()],
+        ));
+        gateway
+            .configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                vec![test_member("abcdef0123", Some("jura"), &["10.0.0.3"])],
+            )
+            .await
+            .unwrap();
+        assert_eq!(lookup("10.0.0.1").await, vec!["gateway.home.arpa."]);
+    }
+
+    #[tokio::test]
+    async fn test_sync_timeout() {
+        // a central that accepts connections but never answers.
+        let url = fake_central(|_| None).await;
+
+        let mut zt = test_authority().await;
+        zt.client = central_api::Client::new(&url);
+        zt.sync_timeout = Some(std::time::Duration::from_millis(200));
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+
+        // each stuck sync gives up on its own, so the next tick always runs
+        for _ in 0..2 {
+            let err = tokio::time::timeout(std::time::Duration::new(5, 0), zt.sync_members())
+                .await
+                .expect("sync was not abandoned")
+                .unwrap_err();
+            assert!(err.to_string().contains("timed out"), "{}", err);
+        }
+
+        // the records from the last complete sync are still served
+        let lookup = zt
+            .forward_authority
+            .authority
+            .lookup(
+                &LowerName::from_str("islay.home.arpa.").unwrap(),
+                RecordType::A,
+                LookupOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(lookup.iter().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ptr_format() {
+        let mut zt = test_authority().await;
+        zt.ptr_format = PtrFormat {
+            prefix: Some("rev-".to_string()),
+            suffix: None,
+        };
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+
+        let lookup = |authority: RecordAuthority, name: Name, rtype: RecordType| {
+            lookup_data(&authority, &name.to_string(), rtype)
+        };
+
+        let reverse = zt.reverse_authority_map.values().next().unwrap().clone();
+        assert_eq!(
+            lookup(
+                reverse,
+                IpAddr::from_str("10.0.0.2").unwrap().into_name().unwrap(),
+                RecordType::PTR
+            )
+            .await,
+            vec!["rev-islay.home.arpa."]
+        );
+
+        // the forward names are unchanged, and the PTR target resolves too
+        for name in [
+            "islay.home.arpa.",
+            "zt-abcdef0123.home.arpa.",
+            "rev-islay.home.arpa.",
+        ] {
+            assert_eq!(
+                lookup(
+                    zt.forward_authority.clone(),
+                    Name::from_str(name).unwrap(),
+                    RecordType::A
+                )
+                .await,
+                vec!["10.0.0.2"],
+                "{}",
+                name
+            );
+        }
+
+        let suffixed = PtrFormat {
+            prefix: None,
+            suffix: Some("-ptr".to_string()),
+        };
+        assert_eq!(
+            suffixed
+                .apply(Name::from_str("zt-abcdef0123.home.arpa.").unwrap())
+                .unwrap(),
+            Name::from_str("zt-abcdef0123-ptr.home.arpa.").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_controller_name() {
+        let mut zt = test_authority().await;
+        zt.controller_name = Some(Name::from_str("controller.home.arpa.").unwrap());
+
+        let lookup = |zt: ZTAuthority| {
+            lookup_data(
+                &zt.forward_authority,
+                "controller.home.arpa.",
+                RecordType::A,
+            )
+        };
+
+        // the network id 1234567891011121 is run by controller 1234567891
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![
+                test_member("1234567891", None, &["10.0.0.1"]),
+                test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(lookup(zt.clone()).await, vec!["10.0.0.1"]);
+
+        // the controller is not always a member; the name goes away rather than going stale
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+        assert!(lookup(zt.clone()).await.is_empty());
 
-        for rt in [RecordType::A, RecordType::AAAA] {
-            let type_records = self.authority.records().await.clone();
-            let name_records = type_records.get(&RrKey::new(name.clone().into(), rt));
+        // Central's controllerId on the member is used when present
+        let mut member = test_member("abcdef0123", Some("islay"), &["10.0.0.2"]);
+        member.controller_id = Some("abcdef0123".to_string());
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![member],
+        )
+        .await
+        .unwrap();
+        assert_eq!(lookup(zt.clone()).await, vec!["10.0.0.2"]);
+    }
 
-            let type_ips: Vec<IpAddr> = ips
-                .iter()
-                .copied()
-                .filter(|ip| {
-                    matches!(
-                        (ip, rt),
-                        (IpAddr::V4(_), RecordType::A) | (IpAddr::V6(_), RecordType::AAAA)
-                    )
-                })
-                .collect();
+    #[tokio::test]
+    async fn test_address_names() {
+        let lookup = |zt: ZTAuthority, rtype: RecordType| {
+            lookup_data(&zt.forward_authority, "abcdef0123.addr.home.arpa.", rtype)
+        };
 
-            match name_records {
-                Some(name_records) => {
-                    if name_records.is_empty()
-                        || !name_records
-                            .records_without_rrsigs()
-                            .all(|r| rdatas.clone().contains(r.data().unwrap()))
-                            && !type_ips.is_empty()
-                    {
-                        self.replace_ip_record(name.clone(), rdatas.clone()).await;
-                    }
-                }
-                None => {
-                    if !type_ips.is_empty() {
-                        self.replace_ip_record(name.clone(), rdatas.clone()).await;
-                    }
-                }
-            }
-        }
+        let islay = || test_member("abcdef0123", Some("islay"), &["10.0.0.2", "fd00::2"]);
+
+        let mut zt = test_authority().await;
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![islay()],
+        )
+        .await
+        .unwrap();
+        assert!(lookup(zt.clone(), RecordType::A).await.is_empty());
+
+        zt.address_names = true;
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![islay()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(lookup(zt.clone(), RecordType::A).await, vec!["10.0.0.2"]);
+        assert_eq!(lookup(zt.clone(), RecordType::AAAA).await, vec!["fd00::2"]);
+
+        // the name is pruned with the member
+        zt.configure_members(test_network(serde_json::json!({"config": {}})), vec![])
+            .await
+            .unwrap();
+        assert!(lookup(zt.clone(), RecordType::A).await.is_empty());
     }
 
-    async fn insert_member(
-        &self,
-        records: &mut Vec<LowerName>,
-        record: ZTRecord,
-    ) -> Result<(), anyhow::Error> {
-        self.match_or_insert(record.fqdn.clone(), &record.ips).await;
-        records.push(record.fqdn.clone().into());
+    #[tokio::test]
+    async fn test_domain_aliases() {
+        let mut zt = test_authority().await;
+        zt.alias_authorities = vec![RecordAuthority::new(
+            LowerName::from_str("new.tld.").unwrap(),
+            LowerName::from_str("zt-foo.home.arpa.").unwrap(),
+            60,
+        )
+        .await
+        .unwrap()];
 
-        if record.wildcard {
-            self.match_or_insert(record.fqdn.clone().to_wildcard(), &record.ips)
-                .await;
-            records.push(record.fqdn.clone().to_wildcard().into());
-        }
+        let lookup = |authority: RecordAuthority, name: &'static str, rtype: RecordType| {
+            lookup_data(&authority, name, rtype)
+        };
 
-        if let Some(name) = &record.custom_name {
-            self.match_or_insert(name.clone(), &record.ips).await;
-            records.push(name.clone().into());
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![
+                test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+                test_member("0123abcdef", Some("jura"), &["10.0.0.3"]),
+            ],
+        )
+        .await
+        .unwrap();
 
-            if record.wildcard {
-                self.match_or_insert(record.get_custom_wildcard().unwrap(), &record.ips)
-                    .await;
-                records.push(record.get_custom_wildcard().unwrap().into());
-            }
+        let (primary, alias) = (
+            zt.forward_authority.clone(),
+            zt.alias_authorities[0].clone(),
+        );
+        for (name, alias_name) in [
+            ("islay.home.arpa.", "islay.new.tld."),
+            ("zt-abcdef0123.home.arpa.", "zt-abcdef0123.new.tld."),
+            ("jura.home.arpa.", "jura.new.tld."),
+        ] {
+            let answer = lookup(primary.clone(), name, RecordType::A).await;
+            assert!(!answer.is_empty(), "{}", name);
+            assert_eq!(
+                lookup(alias.clone(), alias_name, RecordType::A).await,
+                answer
+            );
         }
 
-        Ok(())
+        // names in record data move to the alias too
+        assert_eq!(
+            lookup(alias.clone(), "new.tld.", RecordType::NS).await,
+            vec!["zt-foo.new.tld."]
+        );
+
+        // a member that leaves is pruned from every alias
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+        assert!(lookup(primary, "jura.home.arpa.", RecordType::A)
+            .await
+            .is_empty());
+        assert!(lookup(alias.clone(), "jura.new.tld.", RecordType::A)
+            .await
+            .is_empty());
+        assert_eq!(
+            lookup(alias, "islay.new.tld.", RecordType::A).await,
+            vec!["10.0.0.2"]
+        );
     }
 
-    // insert_member_ptr is a lot like insert_authority, but for PTRs.
-    async fn insert_member_ptr(
-        &self,
-        records: &mut Vec<LowerName>,
-        record: ZTRecord,
-    ) -> Result<(), anyhow::Error> {
-        for ip in record.ips.clone() {
-            let ip = ip.into_name()?;
-            self.configure_ptr(ip.clone(), record.ptr_name.clone())
-                .await?;
-            records.push(ip.into());
+    #[tokio::test]
+    async fn test_glue() {
+        use trust_dns_resolver::proto::rr::{rdata::MX, RData, RecordSet};
+        use trust_dns_server::{authority::AuthorityObject, client::rr::RrKey};
+
+        let mut zt = test_authority().await;
+        zt.wildcard = true;
+        zt.ns_hosts = vec![
+            (
+                Name::from_str("ns1.home.arpa.").unwrap(),
+                vec![IpAddr::from_str("10.0.0.1").unwrap()],
+            ),
+            (
+                Name::from_str("ns2.home.arpa.").unwrap(),
+                vec![IpAddr::from_str("fd00::2").unwrap()],
+            ),
+        ];
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.3"])],
+        )
+        .await
+        .unwrap();
+
+        // a static MX pointing at a member, and one at a name its wildcard covers
+        let name = Name::from_str("home.arpa.").unwrap();
+        let mut rset = RecordSet::with_ttl(name.clone(), RecordType::MX, 60);
+        for exchange in ["islay.home.arpa.", "mail.islay.home.arpa."] {
+            rset.add_rdata(RData::MX(MX::new(10, Name::from_str(exchange).unwrap())));
         }
+        zt.forward_authority.authority.records_mut().await.insert(
+            RrKey::new(name.into(), RecordType::MX),
+            std::sync::Arc::new(rset),
+        );
 
-        Ok(())
+        let additionals = |glue: bool, rtype: RecordType| {
+            let mut authority = zt.forward_authority.clone();
+            authority.glue = glue;
+            async move {
+                let mut lookup = authority
+                    .lookup(
+                        &LowerName::from_str("home.arpa.").unwrap(),
+                        rtype,
+                        LookupOptions::default(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(lookup.iter().count(), 2);
+
+                let mut records: Vec<String> = lookup
+                    .take_additionals()
+                    .map(|a| {
+                        a.iter()
+                            .map(|r| format!("{} {}", r.name(), r.data().unwrap()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                records.sort();
+                records
+            }
+        };
+
+        assert_eq!(
+            additionals(true, RecordType::MX).await,
+            vec![
+                "islay.home.arpa. 10.0.0.3",
+                "mail.islay.home.arpa. 10.0.0.3"
+            ]
+        );
+        assert_eq!(
+            additionals(true, RecordType::NS).await,
+            vec!["ns1.home.arpa. 10.0.0.1", "ns2.home.arpa. fd00::2"]
+        );
+
+        // without it, only the store's own glue for the first target
+        assert!(additionals(false, RecordType::NS).await.len() < 2);
     }
 
-    async fn configure_ptr(&self, ptr: Name, fqdn: Name) -> Result<(), anyhow::Error> {
-        let records = self.authority.records().await.clone();
+    #[tokio::test]
+    async fn test_ns_hosts() {
+        let mut zt = test_authority().await;
+        zt.ns_hosts = vec![
+            (
+                Name::from_str("ns1.home.arpa.").unwrap(),
+                vec![IpAddr::from_str("10.0.0.1").unwrap()],
+            ),
+            (
+                Name::from_str("ns2.home.arpa.").unwrap(),
+                vec![
+                    IpAddr::from_str("10.0.0.2").unwrap(),
+                    IpAddr::from_str("fd00::2").unwrap(),
+                ],
+            ),
+        ];
 
-        match records.get(&RrKey::new(ptr.clone().into(), RecordType::PTR)) {
-            Some(records) => {
-                if !records
-                    .records_without_rrsigs()
-                    .any(|rec| rec.data().unwrap().eq(&RData::PTR(fqdn.clone())))
-                {
-                    self.set_ptr_record(ptr.clone(), fqdn.clone()).await;
-                }
+        let lookup = |zt: ZTAuthority, name: &str, rtype: RecordType| {
+            let data = lookup_data(&zt.forward_authority, name, rtype);
+            async move {
+                let mut records = data.await;
+                records.sort();
+                records
             }
-            None => self.set_ptr_record(ptr.clone(), fqdn.clone()).await,
+        };
+
+        // two syncs, so the glue survives pruning
+        for _ in 0..2 {
+            zt.configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                vec![test_member("abcdef0123", Some("islay"), &["10.0.0.3"])],
+            )
+            .await
+            .unwrap();
         }
 
-        Ok(())
+        assert_eq!(
+            lookup(zt.clone(), "home.arpa.", RecordType::NS).await,
+            vec!["ns1.home.arpa.", "ns2.home.arpa."]
+        );
+        assert_eq!(
+            lookup(zt.clone(), "ns1.home.arpa.", RecordType::A).await,
+            vec!["10.0.0.1"]
+        );
+        assert_eq!(
+            lookup(zt.clone(), "ns2.home.arpa.", RecordType::A).await,
+            vec!["10.0.0.2"]
+        );
+        assert_eq!(
+            lookup(zt.clone(), "ns2.home.arpa.", RecordType::AAAA).await,
+            vec!["fd00::2"]
+        );
+
+        let (_, reverse) = zt.reverse_authority_map.iter().next().unwrap();
+        let mut reverse_ns: Vec<String> = reverse
+            .authority
+            .lookup(
+                &LowerName::from_str("0.0.10.in-addr.arpa.").unwrap(),
+                RecordType::NS,
+                LookupOptions::default(),
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| r.data().unwrap().to_string())
+            .collect();
+        reverse_ns.sort();
+        assert_eq!(reverse_ns, vec!["ns1.home.arpa.", "ns2.home.arpa."]);
     }
 
-    async fn set_ptr_record(&self, ptr: Name, fqdn: Name) {
-        tracing::info!("Adding/Replacing record {}: ({})", ptr, fqdn);
+    #[tokio::test]
+    async fn test_min_members() {
+        let mut zt = test_authority().await;
+        zt.min_members = 1;
 
-        let mut records = self.authority.records_mut().await;
-        records.remove(&RrKey::new(
-            ptr.clone()
-                .into_name()
-                .expect("Could not coerce IP address into DNS name")
-                .into(),
-            RecordType::PTR,
-        ));
-        drop(records);
+        let lookup = |zt: ZTAuthority| {
+            let data = lookup_data(&zt.forward_authority, "islay.home.arpa.", RecordType::A);
+            async move { data.await.len() }
+        };
 
-        let serial = self.authority.serial().await;
-        let mut address = Record::with(ptr.clone(), RecordType::PTR, 60);
-        address.set_data(Some(RData::PTR(fqdn.clone())));
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+        assert_eq!(lookup(zt.clone()).await, 1);
 
-        self.authority.upsert(address, serial).await;
-    }
-}
+        // an empty list is below the minimum, so nothing is pruned
+        zt.configure_members(test_network(serde_json::json!({"config": {}})), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(lookup(zt.clone()).await, 1);
 
-#[async_trait]
-impl AuthorityObject for RecordAuthority {
-    fn box_clone(&self) -> Box<dyn AuthorityObject> {
-        Box::new(self.authority.clone())
+        // without the gate it is
+        zt.min_members = 0;
+        zt.configure_members(test_network(serde_json::json!({"config": {}})), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(lookup(zt.clone()).await, 0);
     }
 
-    fn zone_type(&self) -> trust_dns_server::authority::ZoneType {
-        trust_dns_server::authority::ZoneType::Primary
+    #[tokio::test]
+    async fn test_authorized_txt() {
+        let mut zt = test_authority().await;
+        zt.authorized_txt = Some(TimeFormat::Epoch);
+
+        let mut member = test_member("abcdef0123", Some("islay"), &["10.0.0.2"]);
+        member.config.as_mut().unwrap().last_authorized_time = Some(1700000000123);
+
+        let txt = |zt: ZTAuthority, name: &str| {
+            let name = LowerName::from_str(name).unwrap();
+            async move {
+                zt.forward_authority
+                    .authority
+                    .lookup(&name, RecordType::TXT, LookupOptions::default())
+                    .await
+                    .map(|l| {
+                        l.iter()
+                            .map(|r| r.data().unwrap().to_string())
+                            .collect::<Vec<String>>()
+                    })
+                    .unwrap_or_default()
+            }
+        };
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![member.clone()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            txt(zt.clone(), "islay.home.arpa.").await,
+            vec!["authorized=1700000000"]
+        );
+        assert_eq!(
+            txt(zt.clone(), "zt-abcdef0123.home.arpa.").await,
+            vec!["authorized=1700000000"]
+        );
+
+        zt.authorized_txt = Some(TimeFormat::Iso);
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![member.clone()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            txt(zt.clone(), "islay.home.arpa.").await,
+            vec!["authorized=2023-11-14T22:13:20Z"]
+        );
+
+        // turning it off removes the record
+        zt.authorized_txt = None;
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![member],
+        )
+        .await
+        .unwrap();
+        assert!(txt(zt.clone(), "islay.home.arpa.").await.is_empty());
     }
 
-    fn is_axfr_allowed(&self) -> bool {
-        false
+    #[tokio::test]
+    async fn test_version_txt() {
+        let mut zt = test_authority().await;
+        zt.version_txt = true;
+
+        let member = |version: serde_json::Value| -> Member {
+            serde_json::from_value(serde_json::json!({
+                "nodeId": "abcdef0123",
+                "networkId": "1234567891011121",
+                "name": "islay",
+                "config": { "ipAssignments": ["10.0.0.2"] },
+                "clientVersion": version,
+                "protocolVersion": 12,
+            }))
+            .unwrap()
+        };
+
+        let txt = |zt: ZTAuthority, name: &str| {
+            let name = LowerName::from_str(name).unwrap();
+            async move {
+                let mut txts = zt
+                    .forward_authority
+                    .authority
+                    .lookup(&name, RecordType::TXT, LookupOptions::default())
+                    .await
+                    .map(|l| {
+                        l.iter()
+                            .map(|r| r.data().unwrap().to_string())
+                            .collect::<Vec<String>>()
+                    })
+                    .unwrap_or_default();
+                txts.sort();
+                txts
+            }
+        };
+
+        for (version, want) in [
+            (
+                serde_json::json!("1.12.2"),
+                vec!["protocol=12", "version=1.12.2"],
+            ),
+            // anything but a version number is dropped
+            (
+                serde_json::json!("1.12.2\" onerror=\"x"),
+                vec!["protocol=12", "version=1.12.2onerrorx"],
+            ),
+            (serde_json::json!(" ;"), vec!["protocol=12"]),
+            (serde_json::Value::Null, vec!["protocol=12"]),
+        ] {
+            zt.configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                vec![member(version)],
+            )
+            .await
+            .unwrap();
+
+            for name in ["islay.home.arpa.", "zt-abcdef0123.home.arpa."] {
+                assert_eq!(txt(zt.clone(), name).await, want, "{}", name);
+            }
+        }
+
+        // turning it off removes the records
+        zt.version_txt = false;
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![member(serde_json::json!("1.12.2"))],
+        )
+        .await
+        .unwrap();
+        assert!(txt(zt.clone(), "islay.home.arpa.").await.is_empty());
     }
 
-    async fn update(
-        &self,
-        update: &trust_dns_server::authority::MessageRequest,
-    ) -> trust_dns_server::authority::UpdateResult<bool> {
-        self.authority.update(update).await
+    #[tokio::test]
+    async fn test_primary_ip() {
+        let mut zt = test_authority().await;
+        zt.primary_ip = true;
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member(
+                "abcdef0123",
+                Some("islay"),
+                &["10.0.0.5", "10.0.0.3", "fd00::9", "fd00::2"],
+            )],
+        )
+        .await
+        .unwrap();
+
+        let lookup = |authority: &RecordAuthority, name: &str, rtype: RecordType| {
+            lookup_data(authority, name, rtype)
+        };
+
+        for name in ["islay.home.arpa.", "zt-abcdef0123.home.arpa."] {
+            assert_eq!(
+                lookup(&zt.forward_authority, name, RecordType::A).await,
+                vec!["10.0.0.3"]
+            );
+            assert_eq!(
+                lookup(&zt.forward_authority, name, RecordType::AAAA).await,
+                vec!["fd00::2"]
+            );
+        }
+
+        let reverse = zt.reverse_authority_map.values().next().unwrap();
+        assert_eq!(
+            lookup(reverse, "3.0.0.10.in-addr.arpa.", RecordType::PTR).await,
+            vec!["islay.home.arpa."]
+        );
+        assert!(lookup(reverse, "5.0.0.10.in-addr.arpa.", RecordType::PTR)
+            .await
+            .is_empty());
     }
 
-    fn origin(&self) -> &trust_dns_server::client::rr::LowerName {
-        &self.domain_name
+    #[tokio::test]
+    async fn test_wildcard_dual_stack() {
+        let mut zt = test_authority().await;
+        zt.wildcard = true;
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member(
+                "abcdef0123",
+                Some("islay"),
+                &["10.0.0.2", "fd00::2"],
+            )],
+        )
+        .await
+        .unwrap();
+
+        let lookup = |zt: ZTAuthority, name: &str, rtype: RecordType| {
+            let name = LowerName::from_str(name).unwrap();
+            async move {
+                zt.forward_authority
+                    .authority
+                    .lookup(&name, rtype, LookupOptions::default())
+                    .await
+                    .map(|l| {
+                        l.iter()
+                            .map(|r| (r.record_type(), r.data().unwrap().to_string()))
+                            .collect::<Vec<(RecordType, String)>>()
+                    })
+                    .unwrap_or_default()
+            }
+        };
+
+        // the wildcard itself and names it covers only answer with the family asked for
+        for name in ["*.islay.home.arpa.", "www.islay.home.arpa."] {
+            assert_eq!(
+                lookup(zt.clone(), name, RecordType::A).await,
+                vec![(RecordType::A, "10.0.0.2".to_string())],
+                "{}",
+                name
+            );
+            assert_eq!(
+                lookup(zt.clone(), name, RecordType::AAAA).await,
+                vec![(RecordType::AAAA, "fd00::2".to_string())],
+                "{}",
+                name
+            );
+        }
     }
 
-    async fn lookup(
-        &self,
-        name: &trust_dns_server::client::rr::LowerName,
-        rtype: RecordType,
-        lookup_options: trust_dns_server::authority::LookupOptions,
-    ) -> Result<
-        Box<dyn trust_dns_server::authority::LookupObject>,
-        trust_dns_server::authority::LookupError,
-    > {
-        self.authority.lookup(name, rtype, lookup_options).await
+    #[tokio::test]
+    async fn test_soa_serial() {
+        let zt = test_authority().await;
+        let (_, reverse) = zt.reverse_authority_map.iter().next().unwrap();
+        let serials = || async {
+            (
+                zt.forward_authority.authority.serial().await,
+                reverse.authority.serial().await,
+            )
+        };
+
+        let sync = |ip: &'static str| {
+            zt.configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                vec![test_member("abcdef0123", Some("islay"), &[ip])],
+            )
+        };
+
+        sync("10.0.0.2").await.unwrap();
+        let first = serials().await;
+        assert_eq!(first, (2, 2));
+
+        // nothing changed, so secondaries have nothing to transfer
+        sync("10.0.0.2").await.unwrap();
+        assert_eq!(serials().await, first);
+
+        sync("10.0.0.3").await.unwrap();
+        assert_eq!(serials().await, (3, 3));
     }
 
-    async fn search(
-        &self,
-        request_info: trust_dns_server::server::RequestInfo<'_>,
-        lookup_options: trust_dns_server::authority::LookupOptions,
-    ) -> Result<
-        Box<dyn trust_dns_server::authority::LookupObject>,
-        trust_dns_server::authority::LookupError,
-    > {
-        self.authority.search(request_info, lookup_options).await
+    #[tokio::test]
+    async fn test_soa_timers() {
+        let zt = test_authority().await;
+        let soa = || async {
+            let lookup = zt.forward_authority.authority.soa().await.unwrap();
+            match lookup.iter().next().unwrap().data() {
+                Some(RData::SOA(soa)) => (
+                    soa.serial(),
+                    soa.refresh(),
+                    soa.retry(),
+                    soa.expire(),
+                    soa.minimum(),
+                ),
+                data => panic!("unexpected SOA: {:?}", data),
+            }
+        };
+
+        assert_eq!(soa().await, (1, 7200, 3600, 1209600, 300));
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+
+        // the serial is kept
+        let timers = SoaTimers::new(Some(600), Some(60), Some(86400), Some(30)).unwrap();
+        zt.forward_authority.set_soa_timers(timers).await;
+        assert_eq!(soa().await, (2, 600, 60, 86400, 30));
+
+        assert_eq!(
+            SoaTimers::new(None, None, None, None).unwrap(),
+            SoaTimers::default()
+        );
+        for (refresh, retry, expire, minimum) in [
+            (Some(0), None, None, None),
+            (None, Some(0), None, None),
+            // retry longer than refresh
+            (Some(600), Some(1200), None, None),
+            // expire before refresh and retry have run out
+            (Some(600), Some(60), Some(660), None),
+            (None, None, Some(u32::MAX), None),
+            (None, None, None, Some(86401)),
+        ] {
+            assert!(
+                SoaTimers::new(refresh, retry, expire, minimum).is_err(),
+                "{:?}",
+                (refresh, retry, expire, minimum)
+            );
+        }
     }
 
-    async fn get_nsec_records(
-        &self,
-        name: &trust_dns_server::client::rr::LowerName,
-        lookup_options: trust_dns_server::authority::LookupOptions,
-    ) -> Result<
-        Box<dyn trust_dns_server::authority::LookupObject>,
-        trust_dns_server::authority::LookupError,
-    > {
-        self.authority.get_nsec_records(name, lookup_options).await
+    #[tokio::test]
+    async fn test_reverse_soa_minimum() {
+        let mut zt = test_authority().await;
+        zt.soa_timers = SoaTimers::new(None, None, None, Some(0)).unwrap();
+        zt.reverse_soa_timers = SoaTimers {
+            minimum: 3600,
+            ..zt.soa_timers
+        };
+
+        let catalog = init_catalog(zt, None).await.unwrap();
+        let minimum = |zone: &str| {
+            let zone = LowerName::from_str(zone).unwrap();
+            let authority = catalog.find(&zone).unwrap();
+            async move {
+                let lookup = authority
+                    .lookup(&zone, RecordType::SOA, LookupOptions::default())
+                    .await
+                    .unwrap();
+                let minimum = match lookup.iter().next().unwrap().data() {
+                    Some(RData::SOA(soa)) => soa.minimum(),
+                    data => panic!("unexpected SOA: {:?}", data),
+                };
+                minimum
+            }
+        };
+
+        assert_eq!(minimum("home.arpa.").await, 0);
+        assert_eq!(minimum("0.0.10.in-addr.arpa.").await, 3600);
     }
-}
 
-#[derive(Debug, Clone)]
-struct ZTRecord {
-    fqdn: Name,
-    custom_name: Option<Name>,
-    ptr_name: Name,
-    ips: Vec<IpAddr>,
-    wildcard: bool,
-}
+    #[tokio::test]
+    async fn test_ptr_families() {
+        let ptr = |zt: ZTAuthority, ip: &str| {
+            let ip = IpAddr::from_str(ip).unwrap();
+            async move {
+                let (_, authority) = zt
+                    .reverse_authority_map
+                    .iter()
+                    .find(|(network, _)| network.contains(ip))
+                    .unwrap();
+                authority
+                    .authority
+                    .lookup(
+                        &ip.into_name().unwrap().into(),
+                        RecordType::PTR,
+                        LookupOptions::default(),
+                    )
+                    .await
+                    .map(|l| l.iter().count())
+                    .unwrap_or_default()
+            }
+        };
 
-impl ZTRecord {
-    pub fn new(
-        member: &central_api::types::Member,
-        sixplane: Option<IpNetwork>,
-        rfc4193: Option<IpNetwork>,
-        domain_name: Name,
-        wildcard: bool,
-    ) -> Result<Self, anyhow::Error> {
-        let member_name = format!(
-            "zt-{}",
-            member
-                .clone()
-                .node_id
-                .expect("Node ID for member does not exist")
-        );
+        for (families, v4, v6) in [(IpFamilies::Both, 1, 1), (IpFamilies::A, 1, 0)] {
+            let mut zt = test_authority().await;
+            let cidr = IpNetwork::from_str("fd00::/64").unwrap();
+            zt.reverse_authority_map.insert(
+                cidr,
+                RecordAuthority::new(
+                    cidr.to_ptr_soa_name().unwrap(),
+                    LowerName::from_str("zt-foo.home.arpa.").unwrap(),
+                    60,
+                )
+                .await
+                .unwrap(),
+            );
+            zt.ptr_families = families;
 
-        let fqdn = member_name.to_fqdn(domain_name.clone())?;
+            zt.configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                vec![test_member(
+                    "abcdef0123",
+                    Some("islay"),
+                    &["10.0.0.2", "fd00::2"],
+                )],
+            )
+            .await
+            .unwrap();
 
-        // this is default the zt-<member id> but can switch to a named name if
-        // tweaked in central. see below.
-        let mut custom_name = None;
-        let mut ptr_name = fqdn.clone();
+            assert_eq!(ptr(zt.clone(), "10.0.0.2").await, v4, "{}", families);
+            assert_eq!(ptr(zt.clone(), "fd00::2").await, v6, "{}", families);
 
-        if let Some(name) = parse_member_name(member.name.clone(), domain_name) {
-            custom_name = Some(name.clone());
-            ptr_name = name;
+            // forward records still carry both families
+            for rtype in [RecordType::A, RecordType::AAAA] {
+                let count = zt
+                    .forward_authority
+                    .authority
+                    .lookup(
+                        &LowerName::from_str("islay.home.arpa.").unwrap(),
+                        rtype,
+                        LookupOptions::default(),
+                    )
+                    .await
+                    .map(|l| l.iter().count())
+                    .unwrap_or_default();
+                assert_eq!(count, 1, "{} {}", families, rtype);
+            }
         }
+    }
 
-        let mut ips = member
-            .clone()
-            .config
-            .expect("Member config does not exist")
-            .ip_assignments
-            .map_or(Vec::new(), |v| {
-                v.iter()
-                    .map(|s| IpAddr::from_str(s).expect("Could not parse IP address"))
-                    .collect()
-            });
+    #[tokio::test]
+    async fn test_shared_ip() {
+        for (policy, expected) in [
+            (SharedIpPolicy::Warn, vec!["jura.home.arpa."]),
+            (
+                SharedIpPolicy::Merge,
+                vec!["islay.home.arpa.", "jura.home.arpa."],
+            ),
+            (SharedIpPolicy::Skip, vec![]),
+        ] {
+            let mut zt = test_authority().await;
+            zt.shared_ip = policy;
 
-        if sixplane.is_some() {
-            ips.push(member.clone().sixplane()?.ip());
-        }
+            zt.configure_members(
+                test_network(serde_json::json!({"config": {}})),
+                vec![
+                    test_member("abcdef0123", Some("islay"), &["10.0.0.2"]),
+                    test_member("0123abcdef", Some("jura"), &["10.0.0.2"]),
+                    test_member("0a1b2c3d4e", Some("skye"), &["10.0.0.3"]),
+                ],
+            )
+            .await
+            .unwrap();
 
-        if rfc4193.is_some() {
-            ips.push(member.clone().rfc4193()?.ip());
+            let reverse = zt.reverse_authority_map.values().next().unwrap().clone();
+            let ptrs = |ip: &str| {
+                let name: LowerName = IpAddr::from_str(ip).unwrap().into_name().unwrap().into();
+                let reverse = reverse.clone();
+                async move {
+                    let mut names: Vec<String> = reverse
+                        .authority
+                        .lookup(&name, RecordType::PTR, LookupOptions::default())
+                        .await
+                        .map(|l| l.iter().map(|r| r.data().unwrap().to_string()).collect())
+                        .unwrap_or_default();
+                    names.sort();
+                    names
+                }
+            };
+
+            assert_eq!(ptrs("10.0.0.2").await, expected, "{}", policy);
+            assert_eq!(
+                ptrs("10.0.0.3").await,
+                vec!["skye.home.arpa."],
+                "{}",
+                policy
+            );
+
+            // both members keep their forward records
+            for name in ["islay.home.arpa.", "jura.home.arpa."] {
+                assert!(zt
+                    .forward_authority
+                    .authority
+                    .lookup(
+                        &LowerName::from_str(name).unwrap(),
+                        RecordType::A,
+                        LookupOptions::default(),
+                    )
+                    .await
+                    .is_ok());
+            }
         }
+    }
 
-        Ok(Self {
-            wildcard,
-            fqdn,
-            custom_name,
-            ptr_name,
-            ips,
-        })
+    #[tokio::test]
+    async fn test_host_ptr() {
+        let ptr = |zt: ZTAuthority| async move {
+            zt.reverse_authority_map
+                .values()
+                .next()
+                .unwrap()
+                .authority
+                .lookup(
+                    &IpAddr::from_str("10.0.0.1")
+                        .unwrap()
+                        .into_name()
+                        .unwrap()
+                        .into(),
+                    RecordType::PTR,
+                    LookupOptions::default(),
+                )
+                .await
+                .map(|l| {
+                    l.iter()
+                        .map(|r| r.data().unwrap().to_string())
+                        .collect::<Vec<String>>()
+                })
+                .unwrap_or_default()
+        };
+
+        let mut zt = test_authority().await;
+        zt.host_name = Some(Name::from_str("zt-abcdef0123.home.arpa.").unwrap());
+        zt.host_ips = vec![IpAddr::from_str("10.0.0.1").unwrap()];
+
+        // Central does not list the host yet
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("0123abcdef", None, &["10.0.0.3"])],
+        )
+        .await
+        .unwrap();
+        assert_eq!(ptr(zt.clone()).await, vec!["zt-abcdef0123.home.arpa."]);
+
+        // once it is listed, its member name is used
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![
+                test_member("abcdef0123", Some("islay"), &["10.0.0.1"]),
+                test_member("0123abcdef", None, &["10.0.0.3"]),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(ptr(zt.clone()).await, vec!["islay.home.arpa."]);
+    }
+
+    #[tokio::test]
+    async fn test_gateway() {
+        let lookup = |authority: RecordAuthority, name: Name, rtype: RecordType| {
+            lookup_data(&authority, &name.to_string(), rtype)
+        };
+        let gateway = Name::from_str("gateway.home.arpa.").unwrap();
+        let ip = IpAddr::from_str("10.0.0.254").unwrap();
+
+        let mut zt = test_authority().await;
+        zt.gateway = Some((gateway.clone(), vec![ip]));
+        let reverse = zt.reverse_authority_map.values().next().unwrap().clone();
+
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.2"])],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            lookup(zt.forward_authority.clone(), gateway.clone(), RecordType::A).await,
+            vec!["10.0.0.254"]
+        );
+        assert_eq!(
+            lookup(reverse.clone(), ip.into_name().unwrap(), RecordType::PTR).await,
+            vec!["gateway.home.arpa."]
+        );
+
+        // a member assigned the address takes the PTR; the gateway name stays
+        zt.configure_members(
+            test_network(serde_json::json!({"config": {}})),
+            vec![test_member("abcdef0123", Some("islay"), &["10.0.0.254"])],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            lookup(zt.forward_authority.clone(), gateway, RecordType::A).await,
+            vec!["10.0.0.254"]
+        );
+        assert_eq!(
+            lookup(reverse, ip.into_name().unwrap(), RecordType::PTR).await,
+            vec!["islay.home.arpa."]
+        );
     }
 
-    pub fn get_custom_wildcard(&self) -> Option<Name> {
-        self.custom_name.as_ref().map(ToWildcard::to_wildcard)
+    #[tokio::test]
+    async fn test_sync_schedule_fixed_delay() {
+        use std::{
+            sync::{Arc, Mutex},
+            time::{Duration, Instant},
+        };
+
+        // a central that never answers, so every sync runs until sync_timeout. Each sync sends
+        // a new request; its start time is recorded.
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let recorded = starts.clone();
+        let url = fake_central(move |_| {
+            recorded.lock().unwrap().push(Instant::now());
+            None
+        })
+        .await;
+
+        let sync = Duration::from_millis(200);
+        let interval = Duration::from_millis(200);
+
+        let mut zt = test_authority().await;
+        zt.client = central_api::Client::new(&url);
+        zt.sync_timeout = Some(sync);
+        zt.update_interval = interval;
+        zt.sync_schedule = SyncSchedule::FixedDelay;
+
+        let handle = tokio::spawn(find_members(zt));
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        handle.abort();
+
+        let starts = starts.lock().unwrap().clone();
+        assert!(starts.len() >= 2, "{}", starts.len());
+
+        // every sync waits for the previous one to finish, and then for the whole interval
+        for pair in starts.windows(2) {
+            let gap = pair[1] - pair[0];
+            assert!(
+                gap >= sync + interval - Duration::from_millis(50),
+                "{:?}",
+                gap
+            );
+        }
     }
 }
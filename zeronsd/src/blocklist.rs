@@ -0,0 +1,76 @@
+/// blocklists: names the forwarder refuses to resolve, answered with NXDOMAIN or a sinkhole address.
+use std::{collections::HashSet, net::IpAddr, path::PathBuf, str::FromStr, sync::Arc};
+
+use tracing::warn;
+use trust_dns_resolver::{IntoName, Name};
+use trust_dns_server::client::rr::LowerName;
+
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    names: Arc<HashSet<LowerName>>,
+    pub sinkhole: Vec<IpAddr>,
+}
+
+impl Blocklist {
+    pub fn new(names: HashSet<LowerName>, sinkhole: Vec<IpAddr>) -> Self {
+        Self {
+            names: Arc::new(names),
+            sinkhole,
+        }
+    }
+
+    /// Parses a blocklist file. Each line is either a bare name or an /etc/hosts-style line, as
+    /// used by most published ad and malware lists; the address of the latter is ignored. Names
+    /// that are not valid are skipped with a warning.
+    pub fn from_file(path: PathBuf, sinkhole: Vec<IpAddr>) -> Result<Self, anyhow::Error> {
+        let mut names = HashSet::new();
+
+        for line in std::fs::read_to_string(path)?.lines() {
+            // a comment runs from the first # to the end of the line, as in hosts files.
+            let line = line.split('#').next().unwrap_or_default();
+
+            let mut fields = line.split_whitespace().peekable();
+            if let Some(first) = fields.peek() {
+                if IpAddr::from_str(first).is_ok() {
+                    fields.next();
+                }
+            }
+
+            for name in fields {
+                match name.into_name() {
+                    Ok(name) => {
+                        names.insert(name.into());
+                    }
+                    Err(e) => warn!("Invalid blocklist name {}: {:?}", name, e),
+                }
+            }
+        }
+
+        Ok(Self::new(names, sinkhole))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    // blocked reports whether the name, or any domain it is under, is listed.
+    pub fn blocked(&self, name: &LowerName) -> bool {
+        let mut name = Name::from(name);
+
+        loop {
+            if self.names.contains(&LowerName::from(name.clone())) {
+                return true;
+            }
+
+            if name.is_root() {
+                return false;
+            }
+
+            name = name.base_name();
+        }
+    }
+}
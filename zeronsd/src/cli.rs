@@ -1,19 +1,48 @@
 use crate::{
+    addresses::{decode_member, IpFamilies},
+    authority::{
+        AnyResponse, IdNameCollision, MissingHosts, PresyncResponse, SharedIpPolicy, SyncSchedule,
+        TimeFormat, UnmanagedReverse,
+    },
+    errors::{report, ErrorFormat},
     init::{ConfigFormat, Launcher},
+    log::LevelFilter,
+    server::{RootQueries, Truncation},
     supervise::Properties,
-    utils::ZEROTIER_LOCAL_URL,
+    utils::{DnsPush, ZEROTIER_LOCAL_URL},
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
 };
-use std::{path::PathBuf, time::Duration};
 
 use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser)]
-#[clap(version, author = "ZeroTier, Inc. <https://zerotier.com>")]
+#[clap(
+    version,
+    long_version = crate::utils::LONG_VERSION,
+    author = "ZeroTier, Inc. <https://zerotier.com>"
+)]
 pub struct Cli {
     /// Verbose logging (repeat -v for more verbosity)
     #[clap(short, global = true, parse(from_occurrences))]
     pub verbose: usize,
 
+    /// Disable logging, runtime `error!` lines such as failed syncs included; only the error that
+    /// stops zeronsd is still printed
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Format of the error that stops zeronsd on stderr [text, json]
+    #[clap(long, global = true, value_name = "FORMAT", default_value = "text")]
+    pub error_format: ErrorFormat,
+
+    /// Also write the error that stops zeronsd to this file, as JSON
+    #[clap(long, global = true, value_name = "PATH")]
+    pub error_file: Option<PathBuf>,
+
     #[clap(subcommand)]
     pub command: Command,
 }
@@ -24,10 +53,16 @@ pub enum Command {
     Start(StartArgs),
 
     /// Configure supervision of the nameserver for a single network
-    Supervise(StartArgs),
+    Supervise(SuperviseArgs),
 
     /// Remove supervision of the nameserver for a network
     Unsupervise(UnsuperviseArgs),
+
+    /// Print a JSON Schema of the configuration file, for editor completion and validation
+    ConfigSchema,
+
+    /// Print the node id behind a zt-<node id> member name, and its addresses on a network
+    Decode(DecodeArgs),
 }
 
 #[derive(Args, Clone)]
@@ -72,51 +107,534 @@ pub struct StartArgs {
     #[clap(long = "tls-key", value_name = "PATH")]
     pub tls_key: Option<PathBuf>,
 
+    /// File holding the passphrase of an encrypted tls-key
+    #[clap(long = "tls-key-passphrase", value_name = "PATH")]
+    pub tls_key_passphrase: Option<PathBuf>,
+
     /// Provide a different URL for contacting the local zerotier-one service. Default:
     #[clap(long = "local-url", value_name = "LOCAL_URL", default_value = ZEROTIER_LOCAL_URL)]
     pub local_url: String,
 
+    /// Check listener health every SECONDS and only advertise healthy listen IPs to Central
+    #[clap(long = "health-interval", value_name = "SECONDS")]
+    pub health_interval: Option<u64>,
+
+    /// TTL in seconds for A and AAAA records
+    #[clap(long = "forward-ttl", value_name = "SECONDS")]
+    pub forward_ttl: Option<u32>,
+
+    /// TTL in seconds for PTR records
+    #[clap(long = "reverse-ttl", value_name = "SECONDS")]
+    pub reverse_ttl: Option<u32>,
+
+    /// Append member additions and removals to this file as JSON lines
+    #[clap(long = "audit-file", value_name = "PATH")]
+    pub audit_file: Option<PathBuf>,
+
+    /// Also audit the members present at the first sync as additions
+    #[clap(long = "audit-initial")]
+    pub audit_initial: bool,
+
+    /// After pushing DNS settings, wait up to SECONDS for Central to reflect them before serving
+    #[clap(long = "verify-dns", value_name = "SECONDS")]
+    pub verify_dns_timeout: Option<u64>,
+
+    /// Publish the network's managed routes as TXT records under _routes.<domain>
+    #[clap(long = "routes-txt")]
+    pub routes_txt: bool,
+
+    /// TTL in seconds for records from the hosts file (defaults to the forward TTL)
+    #[clap(long = "hosts-ttl", value_name = "SECONDS")]
+    pub hosts_ttl: Option<u32>,
+
+    /// Address families wildcard records are published for [a, aaaa, both]
+    #[clap(
+        long = "wildcard-families",
+        value_name = "FAMILIES",
+        default_value = "both"
+    )]
+    pub wildcard_families: IpFamilies,
+
+    /// Only push DNS settings to Central after every listener has bound and passed a self-check
+    #[clap(long = "advertise-after-bind")]
+    pub advertise_after_bind: bool,
+
+    /// Path to a YAML or JSON file of per-member override records, keyed by node id
+    #[clap(long = "overrides", value_name = "PATH")]
+    pub overrides: Option<PathBuf>,
+
+    /// Serve the domain even if it is a public suffix such as `com`
+    #[clap(long = "allow-public-tld")]
+    pub allow_public_tld: bool,
+
+    /// How often, in seconds, to sync members from Central (default 30)
+    #[clap(long = "members-interval", value_name = "SECONDS")]
+    pub members_interval: Option<u64>,
+
+    /// Only re-read the hosts file this often, in seconds, or when it changes (default: every sync)
+    #[clap(long = "hosts-interval", value_name = "SECONDS")]
+    pub hosts_interval: Option<u64>,
+
+    /// Path to a list of names the forwarder answers with NXDOMAIN (or the sinkhole addresses)
+    #[clap(long = "blocklist", value_name = "PATH")]
+    pub blocklist: Option<PathBuf>,
+
+    /// Answer blocked names with this address instead of NXDOMAIN; may be repeated for IPv4 and IPv6
+    #[clap(long = "sinkhole", value_name = "IP")]
+    pub sinkhole: Vec<IpAddr>,
+
+    /// Truncate UDP responses larger than this many bytes (or the client's EDNS size, if smaller)
+    #[clap(long = "max-udp-size", value_name = "BYTES")]
+    pub max_udp_size: Option<u16>,
+
+    /// What a truncated UDP response carries: `partial` (the answers that fit) or `empty`
+    #[clap(long = "truncation", value_name = "POLICY", default_value = "partial")]
+    pub truncation: Truncation,
+
+    /// Only push these listen IPs to Central as DNS servers; may be repeated (default: all of them)
+    #[clap(long = "advertise-ip", value_name = "IP")]
+    pub advertise_ips: Vec<IpAddr>,
+
+    /// Abandon a member sync that takes longer than this many seconds
+    #[clap(long = "sync-timeout", value_name = "SECONDS")]
+    pub sync_timeout: Option<u64>,
+
+    /// Skip pushing DNS settings to Central when this node has managed DNS (allowDNS) disabled
+    #[clap(long = "require-allow-dns")]
+    pub require_allow_dns: bool,
+
+    /// Add this prefix to the hostname of PTR targets
+    #[clap(long = "ptr-prefix", value_name = "PREFIX")]
+    pub ptr_prefix: Option<String>,
+
+    /// Add this suffix to the hostname of PTR targets
+    #[clap(long = "ptr-suffix", value_name = "SUFFIX")]
+    pub ptr_suffix: Option<String>,
+
+    /// Also publish the network controller's member under this name, e.g. `controller`
+    #[clap(long = "controller-name", value_name = "NAME")]
+    pub controller_name: Option<String>,
+
+    /// Address families PTR records are published for [a, aaaa, both]
+    #[clap(long = "ptr-families", value_name = "FAMILIES", default_value = "both")]
+    pub ptr_families: IpFamilies,
+
+    /// Publish only the lowest assigned address of each family per member
+    #[clap(long = "primary-ip")]
+    pub primary_ip: bool,
+
+    /// Read the ZeroTier Central token from this service in the OS keyring (account `central-token`)
+    #[clap(long = "token-keyring", value_name = "SERVICE")]
+    pub token_keyring: Option<String>,
+
+    /// Skip member names with a label shorter than this many characters after sanitizing
+    #[clap(long = "min-name-length", value_name = "CHARS")]
+    pub min_name_length: Option<usize>,
+
+    /// Try this many times to get this node's IPs on the network before giving up (default 1)
+    #[clap(long = "listen-attempts", value_name = "COUNT")]
+    pub listen_attempts: Option<u32>,
+
+    /// Seconds to wait before the second attempt; doubled after each one (default 1)
+    #[clap(long = "listen-backoff", value_name = "SECONDS")]
+    pub listen_backoff: Option<u64>,
+
+    /// How ANY queries for managed names are answered: `records` (all of them) or `minimal` (RFC 8482)
+    #[clap(
+        long = "any-response",
+        value_name = "POLICY",
+        default_value = "records"
+    )]
+    pub any_response: AnyResponse,
+
+    /// How member syncs are spaced: `interval` (fixed cadence) or `fixed-delay` (wait after each sync)
+    #[clap(
+        long = "sync-schedule",
+        value_name = "SCHEDULE",
+        default_value = "interval"
+    )]
+    pub sync_schedule: SyncSchedule,
+
+    /// Only answer for the domain on this listen IP; may be repeated (default: all of them)
+    #[clap(long = "forward-listen-ip", value_name = "IP")]
+    pub forward_listen_ips: Vec<IpAddr>,
+
+    /// Only answer for the reverse zones on this listen IP; may be repeated (default: all of them)
+    #[clap(long = "reverse-listen-ip", value_name = "IP")]
+    pub reverse_listen_ips: Vec<IpAddr>,
+
+    /// Skip syncs and the Central DNS push while Central lists fewer than COUNT members
+    #[clap(long = "min-members", value_name = "COUNT")]
+    pub min_members: Option<usize>,
+
+    /// Publish each member's last authorization time as a TXT record, as `epoch` seconds or `iso` 8601
+    #[clap(long = "authorized-txt", value_name = "FORMAT")]
+    pub authorized_txt: Option<TimeFormat>,
+
+    /// Publish the ZeroTier version and protocol each member runs as TXT records
+    #[clap(long = "version-txt")]
+    pub version_txt: bool,
+
+    /// Also answer queries sent as datagrams to a Unix socket at PATH, for local stub resolvers
+    #[clap(long = "unix-socket", value_name = "PATH")]
+    pub unix_socket: Option<PathBuf>,
+
+    /// Advertise the TCP idle timeout to clients with the edns-tcp-keepalive option (RFC 7828)
+    #[clap(long = "tcp-keepalive")]
+    pub tcp_keepalive: bool,
+
+    /// How missing names are answered before the first sync: `nxdomain` or `servfail` (retry later)
+    #[clap(
+        long = "presync-response",
+        value_name = "POLICY",
+        default_value = "nxdomain"
+    )]
+    pub presync_response: PresyncResponse,
+
+    /// PTR records for an IP assigned to several members: `warn`, `merge` (every name) or `skip`
+    #[clap(long = "shared-ip", value_name = "POLICY", default_value = "warn")]
+    pub shared_ip: SharedIpPolicy,
+
+    /// Push metrics to a StatsD or DogStatsD server at this address after every sync
+    #[clap(long = "statsd-addr", value_name = "ADDR")]
+    pub statsd_addr: Option<SocketAddr>,
+
+    /// Shorten member name labels longer than DNS allows instead of skipping the name
+    #[clap(long = "truncate-names")]
+    pub truncate_names: bool,
+
+    /// Answer CHAOS TXT queries for version.bind and version.server with the zeronsd version
+    #[clap(long = "chaos-version")]
+    pub chaos_version: bool,
+
+    /// Answer CHAOS TXT queries for info.zeronsd with the version, build, uptime and record count
+    #[clap(long = "chaos-info")]
+    pub chaos_info: bool,
+
+    /// Run member syncs on a dedicated pool of this many threads, apart from the DNS listeners
+    #[clap(long = "sync-threads", value_name = "N")]
+    pub sync_threads: Option<usize>,
+
+    /// Keep serving forwarded answers for this many seconds after they expire, while refreshing them
+    #[clap(long = "forward-max-stale", value_name = "SECONDS")]
+    pub forward_max_stale: Option<u64>,
+
+    /// POST the members added and removed by each sync to this URL as JSON
+    #[clap(long = "webhook-url", value_name = "URL")]
+    pub webhook_url: Option<String>,
+
+    /// Close new TCP and DoT connections while this many are already open
+    #[clap(long = "max-tcp-connections", value_name = "COUNT")]
+    pub max_tcp_connections: Option<usize>,
+
+    /// Let listeners on IPv6 addresses accept IPv4-mapped connections too
+    #[clap(long = "dual-stack")]
+    pub dual_stack: bool,
+
+    /// Only let the primary of the instances in the configuration's ns_hosts write DNS settings to Central
+    #[clap(long = "primary-election")]
+    pub primary_election: bool,
+
+    /// Seconds the primary instance may go without answering the others before another takes over [default: 300]
+    #[clap(long = "primary-failover", value_name = "SECONDS")]
+    pub primary_failover: Option<u64>,
+
+    /// Receive buffer size (SO_RCVBUF) of every listening socket, in bytes
+    #[clap(long = "recv-buffer", value_name = "BYTES")]
+    pub recv_buffer: Option<usize>,
+
+    /// Send buffer size (SO_SNDBUF) of every listening socket, in bytes
+    #[clap(long = "send-buffer", value_name = "BYTES")]
+    pub send_buffer: Option<usize>,
+
+    /// How many TCP connections may wait to be accepted [default: 1024]
+    #[clap(long = "tcp-backlog", value_name = "CONNECTIONS")]
+    pub tcp_backlog: Option<u32>,
+
+    /// Only advertise a listen IP that recovers once it has passed its health checks for SECONDS
+    #[clap(long = "advertise-grace", value_name = "SECONDS")]
+    pub advertise_grace: Option<u64>,
+
+    /// Only rebuild the reverse zones every SECONDS, unless member names or IPs change
+    #[clap(long = "reverse-interval", value_name = "SECONDS")]
+    pub reverse_interval: Option<u64>,
+
+    /// Add the addresses of every in-zone NS, MX and SRV target to the additional section
+    #[clap(long = "glue")]
+    pub glue: bool,
+
+    /// Refuse queries for forwarded names that carry an EDNS client-subnet option
+    #[clap(long = "refuse-client-subnet")]
+    pub refuse_client_subnet: bool,
+
+    /// Turn on managed DNS (allowDNS) and managed addresses (allowManaged) for the network on this node at startup
+    #[clap(long = "enable-local-dns")]
+    pub enable_local_dns: bool,
+
+    /// Also serve the domain's records under this domain; may be repeated
+    #[clap(long = "domain-alias", value_name = "DOMAIN")]
+    pub domain_aliases: Vec<String>,
+
+    /// Publish the gateway-ip addresses under this name in the domain, with PTRs pointing at it
+    #[clap(long = "gateway-name", value_name = "NAME")]
+    pub gateway_name: Option<String>,
+
+    /// An address of the network's gateway, published under gateway-name; may be repeated
+    #[clap(long = "gateway-ip", value_name = "IP")]
+    pub gateway_ips: Vec<IpAddr>,
+
+    /// When a hosts file goes missing: `keep` its last records or `clear` them
+    #[clap(long = "missing-hosts", value_name = "POLICY", default_value = "keep")]
+    pub missing_hosts: MissingHosts,
+
+    /// Also serve DNS-over-QUIC with the TLS certificate and key; needs the `dns-over-quic` feature
+    #[clap(long = "doq")]
+    pub doq: bool,
+
+    /// UDP port to serve DNS-over-QUIC on (default: 853)
+    #[clap(long = "doq-port", value_name = "PORT")]
+    pub doq_port: Option<u16>,
+
+    /// Answer A or AAAA queries for names in the domain that do not exist with this IP
+    #[clap(long = "catch-all-ip", value_name = "IP")]
+    pub catch_all_ip: Option<IpAddr>,
+
+    /// Publish members that are not authorized yet under this subdomain instead of the domain
+    #[clap(long = "pending-subdomain", value_name = "NAME")]
+    pub pending_subdomain: Option<String>,
+
+    /// When a hosts file name is also a member's name, answer with the addresses of both
+    #[clap(long = "merge-names")]
+    pub merge_names: bool,
+
+    /// Always answer this name in the domain with 127.0.0.1 and ::1, for DNS health checks
+    #[clap(long = "health-name", value_name = "NAME")]
+    pub health_name: Option<String>,
+
+    /// Retry pushing the DNS settings this many times when the network changes in Central meanwhile [default: 3]
+    #[clap(long = "dns-update-retries", value_name = "N")]
+    pub dns_update_retries: Option<u32>,
+
+    /// Also publish each member as <ZeroTier address>.addr.<domain>
+    #[clap(long = "address-names")]
+    pub address_names: bool,
+
+    /// How often, in seconds, secondaries check the zones' SOA serial [default: 7200]
+    #[clap(long = "soa-refresh", value_name = "SECONDS")]
+    pub soa_refresh: Option<u32>,
+
+    /// Seconds a secondary waits to retry a failed SOA check; at most soa-refresh [default: 3600]
+    #[clap(long = "soa-retry", value_name = "SECONDS")]
+    pub soa_retry: Option<u32>,
+
+    /// Seconds a secondary keeps answering without reaching this server; longer than soa-refresh and soa-retry together [default: 1209600]
+    #[clap(long = "soa-expire", value_name = "SECONDS")]
+    pub soa_expire: Option<u32>,
+
+    /// Negative caching TTL in the SOA, in seconds; at most 86400 [default: 300]
+    #[clap(long = "soa-minimum", value_name = "SECONDS")]
+    pub soa_minimum: Option<u32>,
+
+    /// Negative caching TTL in the SOA of the reverse zones, in seconds; at most 86400 [default: soa-minimum]
+    #[clap(long = "reverse-soa-minimum", value_name = "SECONDS")]
+    pub reverse_soa_minimum: Option<u32>,
+
+    /// What to do with queries for the root zone and NS queries outside the managed zones: `forward` or `refuse`
+    #[clap(
+        long = "root-queries",
+        value_name = "POLICY",
+        default_value = "forward"
+    )]
+    pub root_queries: RootQueries,
+
+    /// Raise the TTLs of forwarded answers to at least this many seconds
+    #[clap(long = "forward-min-ttl", value_name = "SECONDS")]
+    pub forward_min_ttl: Option<u32>,
+
+    /// Lower the TTLs of forwarded answers to at most this many seconds
+    #[clap(long = "forward-max-ttl", value_name = "SECONDS")]
+    pub forward_max_ttl: Option<u32>,
+
+    /// Which DNS settings to push to Central: `both`, `servers` or `domain`
+    #[clap(long = "push-dns", value_name = "SETTINGS", default_value = "both")]
+    pub push_dns: DnsPush,
+
+    /// Only answer A and AAAA queries for members that have been online within this many seconds
+    #[clap(long = "online-within", value_name = "SECONDS")]
+    pub online_within: Option<u64>,
+
+    /// Publish <value>.<domain> with the addresses of all members with that value of this tag; may be repeated
+    #[clap(long = "group-tag", value_name = "TAG")]
+    pub group_tags: Vec<String>,
+
+    /// What to do with reverse queries for private ranges outside the reverse zones: `forward` or `nxdomain`
+    #[clap(
+        long = "unmanaged-reverse",
+        value_name = "POLICY",
+        default_value = "forward"
+    )]
+    pub unmanaged_reverse: UnmanagedReverse,
+
+    /// What to do with custom member names that look like another member's zt-<member id> name: `allow`, `skip` or `reserve`
+    #[clap(
+        long = "id-name-collision",
+        value_name = "POLICY",
+        default_value = "allow"
+    )]
+    pub id_name_collision: IdNameCollision,
+
     /// Log Level to print [off, trace, debug, error, warn, info]
     #[clap(short = 'l', long = "log-level", value_name = "LEVEL")]
-    pub log_level: Option<crate::log::LevelFilter>,
+    pub log_level: Option<LevelFilter>,
 }
 
-impl Into<Launcher> for StartArgs {
-    fn into(self) -> Launcher {
-        if let Some(config) = self.config {
-            let res = Launcher::new_from_config(config.to_str().unwrap(), self.config_type);
-            match res {
-                Ok(mut res) => {
-                    res.network_id = Some(self.network_id.clone());
-                    res
-                }
-                Err(e) => {
-                    eprintln!("{}", e);
-                    std::process::exit(1);
-                }
-            }
+impl TryFrom<StartArgs> for Launcher {
+    type Error = anyhow::Error;
+
+    fn try_from(args: StartArgs) -> Result<Self, Self::Error> {
+        if let Some(config) = args.config {
+            let mut res = Launcher::new_from_config(config.to_str().unwrap(), args.config_type)?;
+            res.network_id = Some(args.network_id.clone());
+            Ok(res)
         } else {
-            Launcher {
-                domain: self.domain,
-                hosts: self.hosts,
-                secret: self.secret,
-                token: self.token,
-                wildcard: self.wildcard,
-                chain_cert: self.chain_cert,
-                tls_cert: self.tls_cert,
-                tls_key: self.tls_key,
-                log_level: self.log_level,
-                network_id: Some(self.network_id),
-                local_url: Some(self.local_url),
-            }
+            Ok(Launcher {
+                domain: args.domain,
+                hosts: args.hosts,
+                secret: args.secret,
+                token: args.token,
+                tokens: Default::default(),
+                wildcard: args.wildcard,
+                chain_cert: args.chain_cert,
+                tls_cert: args.tls_cert,
+                tls_key: args.tls_key,
+                tls_key_passphrase: args.tls_key_passphrase,
+                log_level: args.log_level,
+                network_id: Some(args.network_id),
+                local_url: Some(args.local_url),
+                health_interval: args.health_interval,
+                forward_ttl: args.forward_ttl,
+                reverse_ttl: args.reverse_ttl,
+                audit_file: args.audit_file,
+                audit_initial: args.audit_initial,
+                verify_dns_timeout: args.verify_dns_timeout,
+                routes_txt: args.routes_txt,
+                hosts_ttl: args.hosts_ttl,
+                wildcard_families: args.wildcard_families,
+                advertise_after_bind: args.advertise_after_bind,
+                overrides: args.overrides,
+                allow_public_tld: args.allow_public_tld,
+                members_interval: args.members_interval,
+                hosts_interval: args.hosts_interval,
+                blocklist: args.blocklist,
+                sinkhole: args.sinkhole,
+                max_udp_size: args.max_udp_size,
+                truncation: args.truncation,
+                advertise_ips: args.advertise_ips,
+                sync_timeout: args.sync_timeout,
+                require_allow_dns: args.require_allow_dns,
+                ptr_prefix: args.ptr_prefix,
+                ptr_suffix: args.ptr_suffix,
+                controller_name: args.controller_name,
+                ptr_families: args.ptr_families,
+                primary_ip: args.primary_ip,
+                token_keyring: args.token_keyring,
+                min_name_length: args.min_name_length,
+                listen_attempts: args.listen_attempts,
+                listen_backoff: args.listen_backoff,
+                any_response: args.any_response,
+                sync_schedule: args.sync_schedule,
+                forward_listen_ips: args.forward_listen_ips,
+                reverse_listen_ips: args.reverse_listen_ips,
+                ns_hosts: Default::default(),
+                hosts_files: Default::default(),
+                min_members: args.min_members,
+                authorized_txt: args.authorized_txt,
+                version_txt: args.version_txt,
+                unix_socket: args.unix_socket,
+                tcp_keepalive: args.tcp_keepalive,
+                presync_response: args.presync_response,
+                shared_ip: args.shared_ip,
+                statsd_addr: args.statsd_addr,
+                truncate_names: args.truncate_names,
+                chaos_version: args.chaos_version,
+                chaos_info: args.chaos_info,
+                sync_threads: args.sync_threads,
+                forward_max_stale: args.forward_max_stale,
+                webhook_url: args.webhook_url,
+                max_tcp_connections: args.max_tcp_connections,
+                dual_stack: args.dual_stack,
+                primary_election: args.primary_election,
+                primary_failover: args.primary_failover,
+                recv_buffer: args.recv_buffer,
+                send_buffer: args.send_buffer,
+                tcp_backlog: args.tcp_backlog,
+                advertise_grace: args.advertise_grace,
+                reverse_interval: args.reverse_interval,
+                glue: args.glue,
+                refuse_client_subnet: args.refuse_client_subnet,
+                enable_local_dns: args.enable_local_dns,
+                domain_aliases: args.domain_aliases,
+                gateway_name: args.gateway_name,
+                gateway_ips: args.gateway_ips,
+                missing_hosts: args.missing_hosts,
+                doq: args.doq,
+                doq_port: args.doq_port,
+                catch_all_ip: args.catch_all_ip,
+                pending_subdomain: args.pending_subdomain,
+                merge_names: args.merge_names,
+                health_name: args.health_name,
+                dns_update_retries: args.dns_update_retries,
+                address_names: args.address_names,
+                soa_refresh: args.soa_refresh,
+                soa_retry: args.soa_retry,
+                soa_expire: args.soa_expire,
+                soa_minimum: args.soa_minimum,
+                reverse_soa_minimum: args.reverse_soa_minimum,
+                root_queries: args.root_queries,
+                forward_min_ttl: args.forward_min_ttl,
+                forward_max_ttl: args.forward_max_ttl,
+                push_dns: args.push_dns,
+                online_within: args.online_within,
+                group_tags: args.group_tags,
+                unmanaged_reverse: args.unmanaged_reverse,
+                id_name_collision: args.id_name_collision,
+            })
         }
     }
 }
 
+#[derive(Args)]
+pub struct SuperviseArgs {
+    #[clap(flatten)]
+    pub start: StartArgs,
+
+    /// Print the service definition instead of installing it
+    #[clap(long)]
+    pub print: bool,
+
+    /// Install the service definition to this directory instead of the system's
+    #[clap(long = "unit-dir", value_name = "DIR")]
+    pub unit_dir: Option<PathBuf>,
+}
+
 #[derive(Args)]
 pub struct UnsuperviseArgs {
     /// Network ID to remove
     pub network_id: String,
+
+    /// Remove the service definition from this directory instead of the system's
+    #[clap(long = "unit-dir", value_name = "DIR")]
+    pub unit_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct DecodeArgs {
+    /// Member name to decode, e.g. zt-abcdef0123.home.arpa
+    pub name: String,
+
+    /// Network ID to compute the member's 6plane and RFC 4193 addresses on
+    #[clap(long = "network-id", value_name = "NETWORK_ID")]
+    pub network_id: Option<String>,
 }
 
 pub async fn init() -> Result<(), anyhow::Error> {
@@ -124,36 +642,62 @@ pub async fn init() -> Result<(), anyhow::Error> {
 
     let result = match cli.command {
         Command::Start(args) => {
-            start(args).await?;
+            start(args, cli.verbose, cli.quiet).await?;
 
             loop {
                 tokio::time::sleep(Duration::MAX).await
             }
         }
-        Command::Supervise(args) => supervise(args),
-        Command::Unsupervise(args) => unsupervise(args),
+        Command::Supervise(args) => supervise(args, cli.verbose, cli.quiet),
+        Command::Unsupervise(args) => unsupervise(args, cli.verbose, cli.quiet),
+        Command::ConfigSchema => Launcher::schema().map(|schema| println!("{}", schema)),
+        Command::Decode(args) => {
+            decode_member(&args.name, args.network_id.as_deref()).map(|out| print!("{}", out))
+        }
     };
 
-    if result.is_err() {
-        eprintln!("{}", result.unwrap_err())
+    // the error is reported here, in the chosen format; returning it would print it again.
+    if let Err(e) = result {
+        report(
+            &e,
+            cli.error_format,
+            cli.error_file.as_deref(),
+            &mut std::io::stderr(),
+        );
+        std::process::exit(1);
     }
 
     Ok(())
 }
 
-async fn start(args: StartArgs) -> Result<(), anyhow::Error> {
-    let launcher: Launcher = args.into();
+// init_logger sets up logging for every subcommand; `ZERONSD_LOG` and `RUST_LOG` still take
+// precedence over the flags.
+fn init_logger(log_level: Option<LevelFilter>, verbose: usize, quiet: bool) {
+    crate::utils::init_logger(LevelFilter::resolve(log_level, verbose, quiet).to_log());
+}
+
+async fn start(args: StartArgs, verbose: usize, quiet: bool) -> Result<(), anyhow::Error> {
+    let launcher = Launcher::try_from(args)?;
+    init_logger(launcher.log_level.clone(), verbose, quiet);
 
     launcher.start().await?;
     Ok(())
 }
 
-fn unsupervise(args: UnsuperviseArgs) -> Result<(), anyhow::Error> {
-    crate::utils::init_logger(Some(tracing::Level::INFO));
+fn unsupervise(args: UnsuperviseArgs, verbose: usize, quiet: bool) -> Result<(), anyhow::Error> {
+    init_logger(None, verbose, quiet);
     Properties::from(args).uninstall_supervisor()
 }
 
-fn supervise(args: StartArgs) -> Result<(), anyhow::Error> {
-    crate::utils::init_logger(Some(tracing::Level::INFO));
-    Properties::from(args).install_supervisor()
+fn supervise(args: SuperviseArgs, verbose: usize, quiet: bool) -> Result<(), anyhow::Error> {
+    init_logger(args.start.log_level.clone(), verbose, quiet);
+    let mut props = Properties::try_from(args.start)?;
+    props.unit_dir = args.unit_dir;
+
+    if args.print {
+        print!("{}", props.print_supervisor()?);
+        return Ok(());
+    }
+
+    props.install_supervisor()
 }
@@ -0,0 +1,179 @@
+/// choosing the one of several instances serving a network that writes its DNS settings to Central.
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+use trust_dns_resolver::{
+    proto::{
+        op::{Message, Query},
+        rr::{RData, RecordType},
+    },
+    Name,
+};
+
+// INSTANCE_LABEL is the name, below the domain, every instance taking part in the election answers
+// with its node id in a TXT record.
+pub const INSTANCE_LABEL: &str = "_zeronsd-instance";
+
+// how long an instance has to answer whether it is running.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Election tells whether this instance is the primary one of those serving the network. The
+// instances are the listeners at the candidate addresses, the ns_hosts every instance lists alike,
+// and each advertises its node id at INSTANCE_LABEL. The primary is the one with the lowest node id
+// that has answered within failover: an instance whose zeronsd stops answering is passed over, even
+// if its node stays online. Every instance comes to the same answer without talking to Central;
+// the rest keep serving DNS, but leave Central alone.
+#[derive(Debug, Clone)]
+pub struct Election {
+    node_id: String,
+    name: Name,
+    // the other instances' listeners; this instance's own are left out.
+    peers: Vec<SocketAddr>,
+    // whether this instance is one of the candidates. One that is not is never the primary.
+    candidate: bool,
+    failover: Duration,
+    seen: Arc<Mutex<HashMap<String, Instant>>>,
+    primary: Arc<AtomicBool>,
+}
+
+impl Election {
+    pub fn new(
+        node_id: String,
+        domain_name: &Name,
+        peers: Vec<SocketAddr>,
+        candidate: bool,
+        failover: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            node_id,
+            name: Name::from_str(INSTANCE_LABEL)?.append_domain(domain_name)?,
+            peers,
+            candidate,
+            failover,
+            seen: Default::default(),
+            primary: Default::default(),
+        })
+    }
+
+    // advertised is the name this instance answers at, and the node id it answers with.
+    pub fn advertised(&self) -> (Name, String) {
+        (self.name.clone(), self.node_id.clone())
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.primary.load(Ordering::Relaxed)
+    }
+
+    // elect picks the primary's node id among the instances that have answered within failover.
+    // This instance is running, so it always stands if it is a candidate.
+    pub fn elect(&self, now: Instant) -> Option<String> {
+        let seen = self.seen.lock().unwrap();
+
+        seen.iter()
+            .filter(|(_, at)| now.saturating_duration_since(**at) <= self.failover)
+            .map(|(node_id, _)| node_id.clone())
+            .chain(self.candidate.then(|| self.node_id.clone()))
+            .min()
+    }
+
+    // record notes the node ids of the instances that answered.
+    pub fn record(&self, answered: Vec<String>, now: Instant) {
+        let mut seen = self.seen.lock().unwrap();
+        for node_id in answered {
+            seen.insert(node_id, now);
+        }
+    }
+
+    // update asks the other instances whether they are running, holds the election, and tells
+    // whether this instance has just become the primary.
+    pub async fn update(&self) -> bool {
+        let answered = self.probe().await;
+        self.record(answered, Instant::now());
+        self.decide(Instant::now())
+    }
+
+    // decide holds the election over what has been recorded so far.
+    pub fn decide(&self, now: Instant) -> bool {
+        let primary = self.elect(now);
+        let won = primary.as_ref() == Some(&self.node_id);
+
+        let was = self.primary.swap(won, Ordering::Relaxed);
+        if was != won {
+            match primary {
+                _ if won => {
+                    info!("This instance is now the primary; it writes the DNS settings to Central")
+                }
+                Some(primary) => info!(
+                    "Member {} is the primary instance; leaving the DNS settings in Central to it",
+                    primary
+                ),
+                None => info!("No instance is the primary; leaving the DNS settings in Central"),
+            }
+        }
+
+        won && !was
+    }
+
+    // probe queries every peer for its node id; those that do not answer are left out.
+    async fn probe(&self) -> Vec<String> {
+        let mut answered = Vec::new();
+        for peer in &self.peers {
+            match tokio::time::timeout(PROBE_TIMEOUT, instance_at(*peer, &self.name)).await {
+                Ok(Ok(Some(node_id))) => answered.push(node_id),
+                Ok(Ok(None)) => debug!(
+                    "The instance at {} does not take part in the election",
+                    peer
+                ),
+                Ok(Err(e)) => debug!(
+                    "Could not ask the instance at {} for its node id: {}",
+                    peer, e
+                ),
+                Err(_) => debug!("The instance at {} did not answer in time", peer),
+            }
+        }
+
+        answered
+    }
+}
+
+// instance_at asks the listener at addr for the node id it advertises at name.
+async fn instance_at(addr: SocketAddr, name: &Name) -> Result<Option<String>, anyhow::Error> {
+    let bind: IpAddr = match addr {
+        SocketAddr::V4(_) => [0, 0, 0, 0].into(),
+        SocketAddr::V6(_) => [0u16; 8].into(),
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(bind, 0)).await?;
+
+    let mut query = Message::new();
+    query.set_id(rand::random());
+    query.set_recursion_desired(false);
+    query.add_query(Query::query(name.clone(), RecordType::TXT));
+    socket.send_to(&query.to_vec()?, addr).await?;
+
+    let mut buf = vec![0; 4096];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await?;
+        let response = match Message::from_vec(&buf[..len]) {
+            Ok(response) if from == addr && response.id() == query.id() => response,
+            _ => continue,
+        };
+
+        return Ok(response
+            .answers()
+            .iter()
+            .find_map(|record| match record.data() {
+                Some(RData::TXT(txt)) => Some(txt.to_string()),
+                _ => None,
+            }));
+    }
+}
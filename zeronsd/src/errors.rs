@@ -0,0 +1,116 @@
+/// reporting the error that stops zeronsd in a form orchestration can act on.
+use std::{io::Write, path::Path, str::FromStr};
+
+use anyhow::anyhow;
+use serde::Serialize;
+
+// ConfigError marks an error as coming from the configuration: a file that cannot be read or
+// parsed, or a setting that is out of range. It reads the same as the error it wraps.
+#[derive(Debug)]
+pub struct ConfigError(pub anyhow::Error);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+// config_error tags an error as a configuration error.
+pub fn config_error(err: impl Into<anyhow::Error>) -> anyhow::Error {
+    ConfigError(err.into()).into()
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCategory {
+    Config,
+    Tls,
+    Network,
+    Io,
+    Other,
+}
+
+impl ErrorCategory {
+    // of picks the category from the first error in the chain it knows.
+    pub fn of(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if cause.is::<ConfigError>() {
+                return Self::Config;
+            } else if cause.is::<openssl::error::ErrorStack>() {
+                return Self::Tls;
+            } else if cause.is::<reqwest::Error>() {
+                return Self::Network;
+            } else if cause.is::<std::io::Error>() {
+                return Self::Io;
+            }
+        }
+
+        Self::Other
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("invalid format: allowed values: [text, json]")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub category: ErrorCategory,
+    pub message: String,
+    // the errors that led to this one, outermost first.
+    pub causes: Vec<String>,
+}
+
+impl From<&anyhow::Error> for ErrorReport {
+    fn from(err: &anyhow::Error) -> Self {
+        Self {
+            category: ErrorCategory::of(err),
+            message: err.to_string(),
+            causes: err.chain().skip(1).map(ToString::to_string).collect(),
+        }
+    }
+}
+
+// report prints the error to out (stderr, outside of tests) in the chosen format; with a file,
+// the JSON report is also written there, whatever the format. It does not go through the logger,
+// so --quiet does not hide it.
+pub fn report(err: &anyhow::Error, format: ErrorFormat, file: Option<&Path>, out: &mut impl Write) {
+    let json = serde_json::to_string(&ErrorReport::from(err)).unwrap_or_default();
+
+    let _ = match format {
+        ErrorFormat::Text => writeln!(out, "{}", err),
+        ErrorFormat::Json => writeln!(out, "{}", json),
+    };
+
+    if let Some(file) = file {
+        if let Err(e) = std::fs::write(file, json + "\n") {
+            let _ = writeln!(
+                out,
+                "Could not write the error to {}: {}",
+                file.display(),
+                e
+            );
+        }
+    }
+}
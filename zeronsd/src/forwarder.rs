@@ -0,0 +1,386 @@
+/// the forwarder used for everything outside of the managed zones.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use trust_dns_resolver::{
+    error::ResolveErrorKind,
+    lookup::Lookup,
+    proto::{
+        op::{Query, ResponseCode},
+        rr::{RData, Record, RecordType},
+    },
+};
+use trust_dns_server::{
+    authority::{Authority, LookupError, LookupOptions, MessageRequest, UpdateResult, ZoneType},
+    client::rr::LowerName,
+    server::RequestInfo,
+    store::forwarder::{ForwardAuthority, ForwardLookup},
+};
+
+use crate::{blocklist::Blocklist, metrics::ForwardMetrics};
+
+// TTL of stale answers, as RFC 8767 suggests.
+const STALE_TTL: u32 = 30;
+
+// expired entries are swept out of the stale cache after this many new answers.
+const STALE_SWEEP_INTERVAL: usize = 1024;
+
+type StaleKey = (LowerName, RecordType);
+
+#[derive(Default)]
+struct StaleEntries {
+    answers: HashMap<StaleKey, Lookup>,
+    refreshing: HashSet<StaleKey>,
+    stored: usize,
+}
+
+// StaleCache keeps the last answer the upstream resolvers gave for each name and type. For up to
+// max_stale after an answer expires, it is served while it is refreshed in the background, and
+// when the upstream resolvers fail.
+#[derive(Clone)]
+pub struct StaleCache {
+    max_stale: Duration,
+    entries: Arc<Mutex<StaleEntries>>,
+}
+
+impl StaleCache {
+    pub fn new(max_stale: Duration) -> Self {
+        Self {
+            max_stale,
+            entries: Default::default(),
+        }
+    }
+
+    fn store(&self, key: StaleKey, lookup: &Lookup) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.refreshing.remove(&key);
+        entries.answers.insert(key, lookup.clone());
+
+        entries.stored += 1;
+        if entries.stored.is_multiple_of(STALE_SWEEP_INTERVAL) {
+            let now = Instant::now();
+            let max_stale = self.max_stale;
+            entries
+                .answers
+                .retain(|_, lookup| lookup.valid_until() + max_stale > now);
+        }
+    }
+
+    // get returns the answer for key if it has not been expired for longer than max_stale, and
+    // whether it has expired.
+    fn get(&self, key: &StaleKey) -> Option<(Lookup, bool)> {
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+        let lookup = entries.answers.get(key)?;
+
+        (lookup.valid_until() + self.max_stale > now)
+            .then(|| (lookup.clone(), lookup.valid_until() <= now))
+    }
+
+    // start_refresh reports whether the caller should refresh key, so only one refresh at a time
+    // runs for it.
+    fn start_refresh(&self, key: &StaleKey) -> bool {
+        self.entries.lock().unwrap().refreshing.insert(key.clone())
+    }
+
+    fn end_refresh(&self, key: &StaleKey) {
+        self.entries.lock().unwrap().refreshing.remove(key);
+    }
+}
+
+// stale_lookup turns a cached answer into one served stale, with the short TTL.
+fn stale_lookup(lookup: Lookup) -> ForwardLookup {
+    let records: Vec<Record> = lookup
+        .records()
+        .iter()
+        .cloned()
+        .map(|mut record| {
+            record.set_ttl(record.ttl().min(STALE_TTL));
+            record
+        })
+        .collect();
+
+    ForwardLookup(Lookup::new_with_max_ttl(
+        lookup.query().clone(),
+        Arc::from(records),
+    ))
+}
+
+// TtlClamp bounds the TTLs of forwarded answers, so clients and the stale cache keep them for at
+// least min and at most max seconds. The managed zones have TTLs of their own and are not affected.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TtlClamp {
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+}
+
+impl TtlClamp {
+    fn apply(&self, ttl: u32) -> u32 {
+        let ttl = self.min.map_or(ttl, |min| ttl.max(min));
+        self.max.map_or(ttl, |max| ttl.min(max))
+    }
+
+    // clamp rewrites the TTLs of an upstream answer. It expires with its shortest TTL.
+    fn clamp(&self, lookup: ForwardLookup) -> ForwardLookup {
+        if *self == Self::default() {
+            return lookup;
+        }
+
+        let records: Vec<Record> = lookup
+            .0
+            .records()
+            .iter()
+            .cloned()
+            .map(|mut record| {
+                record.set_ttl(self.apply(record.ttl()));
+                record
+            })
+            .collect();
+
+        let valid_until = match records.iter().map(Record::ttl).min() {
+            Some(ttl) => Instant::now() + Duration::from_secs(ttl.into()),
+            None => lookup.0.valid_until(),
+        };
+
+        ForwardLookup(Lookup::new_with_deadline(
+            lookup.0.query().clone(),
+            Arc::from(records),
+            valid_until,
+        ))
+    }
+}
+
+// upstream_failed tells failures to reach an answer apart from answers that the name or records
+// do not exist, which are passed on as they are.
+fn upstream_failed(err: &LookupError) -> bool {
+    match err {
+        LookupError::ResolveError(e) => !matches!(
+            e.kind(),
+            ResolveErrorKind::NoRecordsFound {
+                response_code: ResponseCode::NXDomain | ResponseCode::NoError,
+                ..
+            }
+        ),
+        LookupError::ResponseCode(code) => *code == ResponseCode::ServFail,
+        LookupError::NameExists => false,
+        _ => true,
+    }
+}
+
+// ZTForwarder wraps the forwarder so every query it answers is counted and timed, and blocked names
+// never leave the host. With a stale cache, expired answers keep being served for a while, and a TTL
+// clamp bounds the TTLs of answers. Everything else is passed through untouched.
+pub struct ZTForwarder {
+    forwarder: Arc<ForwardAuthority>,
+    metrics: ForwardMetrics,
+    blocklist: Option<Blocklist>,
+    stale: Option<StaleCache>,
+    ttl_clamp: TtlClamp,
+}
+
+impl ZTForwarder {
+    pub fn new(
+        forwarder: ForwardAuthority,
+        metrics: ForwardMetrics,
+        blocklist: Option<Blocklist>,
+        stale: Option<StaleCache>,
+        ttl_clamp: TtlClamp,
+    ) -> Self {
+        Self {
+            forwarder: Arc::new(forwarder),
+            metrics,
+            blocklist,
+            stale,
+            ttl_clamp,
+        }
+    }
+
+    async fn forward(
+        forwarder: &ForwardAuthority,
+        metrics: &ForwardMetrics,
+        ttl_clamp: TtlClamp,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> Result<ForwardLookup, LookupError> {
+        let start = Instant::now();
+        let res = forwarder.lookup(name, rtype, lookup_options).await;
+        metrics.observe(start, res.is_ok());
+        res.map(|lookup| ttl_clamp.clamp(lookup))
+    }
+
+    // forward_stale forwards the query, answering from the stale cache when its answer has expired
+    // or the upstream resolvers fail.
+    async fn forward_stale(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> Result<ForwardLookup, LookupError> {
+        let stale = match &self.stale {
+            Some(stale) => stale,
+            None => {
+                return Self::forward(
+                    &self.forwarder,
+                    &self.metrics,
+                    self.ttl_clamp,
+                    name,
+                    rtype,
+                    lookup_options,
+                )
+                .await
+            }
+        };
+
+        let key = (name.clone(), rtype);
+        if let Some((lookup, true)) = stale.get(&key) {
+            if stale.start_refresh(&key) {
+                let (forwarder, metrics, stale, ttl_clamp) = (
+                    self.forwarder.clone(),
+                    self.metrics.clone(),
+                    stale.clone(),
+                    self.ttl_clamp,
+                );
+                tokio::spawn(async move {
+                    match Self::forward(
+                        &forwarder,
+                        &metrics,
+                        ttl_clamp,
+                        &key.0,
+                        key.1,
+                        lookup_options,
+                    )
+                    .await
+                    {
+                        Ok(lookup) => stale.store(key, &lookup.0),
+                        Err(_) => stale.end_refresh(&key),
+                    }
+                });
+            }
+
+            return Ok(stale_lookup(lookup));
+        }
+
+        match Self::forward(
+            &self.forwarder,
+            &self.metrics,
+            self.ttl_clamp,
+            name,
+            rtype,
+            lookup_options,
+        )
+        .await
+        {
+            Ok(lookup) => {
+                stale.store(key, &lookup.0);
+                Ok(lookup)
+            }
+            Err(e) if upstream_failed(&e) => match stale.get(&key) {
+                Some((lookup, _)) => {
+                    tracing::debug!("upstream failed, answering stale: {} {}", name, rtype);
+                    Ok(stale_lookup(lookup))
+                }
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    // blocked answers a blocked name from the sinkhole addresses of the matching family, or with
+    // NXDOMAIN if there are none configured at all.
+    fn blocked(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+    ) -> Option<Result<ForwardLookup, LookupError>> {
+        let blocklist = self.blocklist.as_ref()?;
+        if !blocklist.blocked(name) {
+            return None;
+        }
+
+        tracing::debug!("blocked lookup: {} {}", name, rtype);
+
+        if blocklist.sinkhole.is_empty() {
+            return Some(Err(LookupError::from(ResponseCode::NXDomain)));
+        }
+
+        let records: Vec<Record> = blocklist
+            .sinkhole
+            .iter()
+            .filter_map(|ip| match (ip, rtype) {
+                (std::net::IpAddr::V4(ip), RecordType::A) => Some(RData::A(*ip)),
+                (std::net::IpAddr::V6(ip), RecordType::AAAA) => Some(RData::AAAA(*ip)),
+                _ => None,
+            })
+            .map(|rdata| Record::from_rdata(name.into(), 60, rdata))
+            .collect();
+
+        Some(Ok(ForwardLookup(Lookup::new_with_max_ttl(
+            Query::query(name.into(), rtype),
+            Arc::from(records),
+        ))))
+    }
+}
+
+#[async_trait]
+impl Authority for ZTForwarder {
+    type Lookup = ForwardLookup;
+
+    fn zone_type(&self) -> ZoneType {
+        self.forwarder.zone_type()
+    }
+
+    fn is_axfr_allowed(&self) -> bool {
+        self.forwarder.is_axfr_allowed()
+    }
+
+    async fn update(&self, update: &MessageRequest) -> UpdateResult<bool> {
+        self.forwarder.update(update).await
+    }
+
+    fn origin(&self) -> &LowerName {
+        self.forwarder.origin()
+    }
+
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> Result<Self::Lookup, LookupError> {
+        if let Some(res) = self.blocked(name, rtype) {
+            return res;
+        }
+
+        self.forward_stale(name, rtype, lookup_options).await
+    }
+
+    async fn search(
+        &self,
+        request: RequestInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> Result<Self::Lookup, LookupError> {
+        if let Some(res) = self.blocked(request.query.name(), request.query.query_type()) {
+            return res;
+        }
+
+        self.forward_stale(
+            request.query.name(),
+            request.query.query_type(),
+            lookup_options,
+        )
+        .await
+    }
+
+    async fn get_nsec_records(
+        &self,
+        name: &LowerName,
+        lookup_options: LookupOptions,
+    ) -> Result<Self::Lookup, LookupError> {
+        self.forwarder.get_nsec_records(name, lookup_options).await
+    }
+}
@@ -5,6 +5,9 @@ use std::{
     path::PathBuf,
     str::FromStr,
 };
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use tracing::warn;
 use trust_dns_server::client::rr::Name;
 
@@ -12,11 +15,19 @@ use crate::traits::ToHostname;
 
 pub type HostsFile = HashMap<IpAddr, Vec<Name>>;
 
+/// An additional hosts file whose names are placed under `suffix` within the domain, e.g. `lab`
+/// for `<name>.lab.home.arpa`. Without a suffix they go directly in the domain.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct HostsSource {
+    pub path: PathBuf,
+    pub suffix: Option<String>,
+}
+
 const WHITESPACE_SPLIT: &str = r"\s+";
-const COMMENT_MATCH: &str = r"^\s*#";
 
 /// Parses an /etc/hosts-formatted file into a mapping of ip -> [name]. Used to populate the
-/// authority.
+/// authority. Names are placed in `domain_name`, except those ending in a dot, which are already
+/// fully qualified.
 pub fn parse_hosts(
     hosts_file: Option<PathBuf>,
     domain_name: Name,
@@ -28,36 +39,37 @@ pub fn parse_hosts(
     }
 
     let whitespace = regex::Regex::new(WHITESPACE_SPLIT).unwrap();
-    let comment = regex::Regex::new(COMMENT_MATCH).unwrap();
     let content = std::fs::read_to_string(hosts_file.unwrap())?;
 
     for line in content.lines() {
-        if line.trim().is_empty() {
+        // a comment runs from the first # to the end of the line, wherever it starts; names
+        // cannot contain one, so `foo#bar` is the name foo.
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
             continue;
         }
 
-        // after whitespace is ruled out as the only thing on the line, the line is split by ..
-        // whitespace and the parts iterated.
+        // after comments and whitespace are ruled out as the only thing on the line, the line is
+        // split by whitespace and the parts iterated.
         let mut ary = whitespace.split(line);
 
         // the first item will be the ip
         if let Some(ip) = ary.next() {
-            // technically we're still matching the head of the line at this point. if it's a
-            // comment, bail.
-            if comment.is_match(ip) {
-                continue;
-            }
-
             // ensure we have an IP, again, this is still the first field.
             match IpAddr::from_str(ip) {
                 Ok(parsed_ip) => {
                     // now that we have the ip, it's all names now.
                     let mut v: Vec<Name> = Vec::new();
 
-                    // continue to iterate over the hosts. If we encounter a comment, stop
-                    // processing.
-                    for host in ary.take_while(|h| !comment.is_match(h)) {
-                        let fqdn = match host.to_fqdn(domain_name.clone()) {
+                    // continue to iterate over the hosts.
+                    for host in ary {
+                        let fqdn = if host.ends_with('.') {
+                            Name::from_str(host).map_err(anyhow::Error::from)
+                        } else {
+                            host.to_fqdn(domain_name.clone())
+                        };
+
+                        let fqdn = match fqdn {
                             Ok(fqdn) => Some(fqdn),
                             Err(e) => {
                                 warn!("Invalid host {}: {:?}", host, e);
@@ -1,38 +1,191 @@
 use std::{
     collections::{hash_map::Entry, HashMap},
-    path::PathBuf,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
     str::FromStr,
     time::Duration,
 };
 
 use anyhow::anyhow;
 use ipnetwork::IpNetwork;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
-use openssl::{pkey::PKey, stack::Stack, x509::X509};
+use openssl::{
+    pkey::{PKey, Private},
+    ssl::SslAcceptor,
+    stack::Stack,
+    x509::X509,
+};
+use trust_dns_resolver::Name;
+use zerotier_api::central_api;
 
 use crate::{
     addresses::*,
-    authority::{find_members, RecordAuthority, ZTAuthority},
+    audit::{AuditLog, Webhook},
+    authority::{
+        spawn_sync, AnyResponse, IdNameCollision, Liveness, MissingHosts, PresyncResponse,
+        PtrFormat, RecordAuthority, SharedIpPolicy, SoaTimers, SyncSchedule, TimeFormat,
+        UnmanagedReverse, ZTAuthority,
+    },
+    blocklist::Blocklist,
+    election::Election,
+    errors::config_error,
+    forwarder::{StaleCache, TtlClamp},
+    hosts::HostsSource,
+    metrics::StatsdSink,
+    overrides::parse_overrides,
     server::*,
+    tls::{check_tls, new_acceptor, DoqConfig, ReloadableAcceptor, DOQ_PORT},
     traits::ToPointerSOA,
     utils::*,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Launcher {
     pub domain: Option<String>,
     pub hosts: Option<PathBuf>,
     pub secret: Option<PathBuf>,
     pub token: Option<PathBuf>,
+    #[serde(default)]
+    pub tokens: HashMap<String, PathBuf>,
     pub chain_cert: Option<PathBuf>,
     pub tls_cert: Option<PathBuf>,
     pub tls_key: Option<PathBuf>,
+    pub tls_key_passphrase: Option<PathBuf>,
     pub wildcard: bool,
     pub log_level: Option<crate::log::LevelFilter>,
     pub local_url: Option<String>,
+    pub health_interval: Option<u64>,
+    pub forward_ttl: Option<u32>,
+    pub reverse_ttl: Option<u32>,
+    pub audit_file: Option<PathBuf>,
+    #[serde(default)]
+    pub audit_initial: bool,
+    pub verify_dns_timeout: Option<u64>,
+    #[serde(default)]
+    pub routes_txt: bool,
+    pub hosts_ttl: Option<u32>,
+    #[serde(default)]
+    pub wildcard_families: IpFamilies,
+    #[serde(default)]
+    pub advertise_after_bind: bool,
+    pub overrides: Option<PathBuf>,
+    #[serde(default)]
+    pub allow_public_tld: bool,
+    pub members_interval: Option<u64>,
+    pub hosts_interval: Option<u64>,
+    pub blocklist: Option<PathBuf>,
+    #[serde(default)]
+    pub sinkhole: Vec<IpAddr>,
+    pub max_udp_size: Option<u16>,
+    #[serde(default)]
+    pub truncation: Truncation,
+    #[serde(default)]
+    pub advertise_ips: Vec<IpAddr>,
+    pub sync_timeout: Option<u64>,
+    #[serde(default)]
+    pub require_allow_dns: bool,
+    pub ptr_prefix: Option<String>,
+    pub ptr_suffix: Option<String>,
+    pub controller_name: Option<String>,
+    #[serde(default)]
+    pub ptr_families: IpFamilies,
+    #[serde(default)]
+    pub primary_ip: bool,
+    pub token_keyring: Option<String>,
+    pub min_name_length: Option<usize>,
+    pub listen_attempts: Option<u32>,
+    pub listen_backoff: Option<u64>,
+    #[serde(default)]
+    pub any_response: AnyResponse,
+    #[serde(default)]
+    pub sync_schedule: SyncSchedule,
+    #[serde(default)]
+    pub forward_listen_ips: Vec<IpAddr>,
+    #[serde(default)]
+    pub reverse_listen_ips: Vec<IpAddr>,
+    #[serde(default)]
+    pub ns_hosts: HashMap<String, Vec<IpAddr>>,
+    pub min_members: Option<usize>,
+    pub authorized_txt: Option<TimeFormat>,
+    #[serde(default)]
+    pub version_txt: bool,
+    pub unix_socket: Option<PathBuf>,
+    #[serde(default)]
+    pub tcp_keepalive: bool,
+    #[serde(default)]
+    pub presync_response: PresyncResponse,
+    #[serde(default)]
+    pub hosts_files: Vec<HostsSource>,
+    #[serde(default)]
+    pub shared_ip: SharedIpPolicy,
+    pub statsd_addr: Option<SocketAddr>,
+    #[serde(default)]
+    pub truncate_names: bool,
+    #[serde(default)]
+    pub chaos_version: bool,
+    #[serde(default)]
+    pub chaos_info: bool,
+    pub sync_threads: Option<usize>,
+    pub forward_max_stale: Option<u64>,
+    pub webhook_url: Option<String>,
+    pub max_tcp_connections: Option<usize>,
+    #[serde(default)]
+    pub dual_stack: bool,
+    #[serde(default)]
+    pub primary_election: bool,
+    pub primary_failover: Option<u64>,
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+    pub tcp_backlog: Option<u32>,
+    pub advertise_grace: Option<u64>,
+    pub reverse_interval: Option<u64>,
+    #[serde(default)]
+    pub glue: bool,
+    #[serde(default)]
+    pub refuse_client_subnet: bool,
+    #[serde(default)]
+    pub enable_local_dns: bool,
+    #[serde(default)]
+    pub domain_aliases: Vec<String>,
+    pub gateway_name: Option<String>,
+    #[serde(default)]
+    pub gateway_ips: Vec<IpAddr>,
+    #[serde(default)]
+    pub missing_hosts: MissingHosts,
+    #[serde(default)]
+    pub doq: bool,
+    pub doq_port: Option<u16>,
+    pub catch_all_ip: Option<IpAddr>,
+    pub pending_subdomain: Option<String>,
+    #[serde(default)]
+    pub merge_names: bool,
+    pub health_name: Option<String>,
+    pub dns_update_retries: Option<u32>,
+    #[serde(default)]
+    pub address_names: bool,
+    pub soa_refresh: Option<u32>,
+    pub soa_retry: Option<u32>,
+    pub soa_expire: Option<u32>,
+    pub soa_minimum: Option<u32>,
+    pub reverse_soa_minimum: Option<u32>,
+    #[serde(default)]
+    pub root_queries: RootQueries,
+    pub forward_min_ttl: Option<u32>,
+    pub forward_max_ttl: Option<u32>,
+    #[serde(default)]
+    pub push_dns: DnsPush,
+    pub online_within: Option<u64>,
+    #[serde(default)]
+    pub group_tags: Vec<String>,
+    #[serde(default)]
+    pub unmanaged_reverse: UnmanagedReverse,
+    #[serde(default)]
+    pub id_name_collision: IdNameCollision,
     #[serde(skip_deserializing)]
+    #[schemars(skip)]
     pub network_id: Option<String>,
 }
 
@@ -65,21 +218,118 @@ impl Default for Launcher {
             hosts: None,
             secret: None,
             token: None,
+            tokens: HashMap::new(),
             chain_cert: None,
             tls_cert: None,
             tls_key: None,
+            tls_key_passphrase: None,
             wildcard: false,
             network_id: None,
             log_level: None,
             local_url: Some(ZEROTIER_LOCAL_URL.to_string()),
+            health_interval: None,
+            forward_ttl: None,
+            reverse_ttl: None,
+            audit_file: None,
+            audit_initial: false,
+            verify_dns_timeout: None,
+            routes_txt: false,
+            hosts_ttl: None,
+            wildcard_families: IpFamilies::Both,
+            advertise_after_bind: false,
+            overrides: None,
+            allow_public_tld: false,
+            members_interval: None,
+            hosts_interval: None,
+            blocklist: None,
+            sinkhole: Vec::new(),
+            max_udp_size: None,
+            truncation: Truncation::Partial,
+            advertise_ips: Vec::new(),
+            sync_timeout: None,
+            require_allow_dns: false,
+            ptr_prefix: None,
+            ptr_suffix: None,
+            controller_name: None,
+            ptr_families: IpFamilies::Both,
+            primary_ip: false,
+            token_keyring: None,
+            min_name_length: None,
+            listen_attempts: None,
+            listen_backoff: None,
+            any_response: AnyResponse::Records,
+            sync_schedule: SyncSchedule::Interval,
+            forward_listen_ips: Vec::new(),
+            reverse_listen_ips: Vec::new(),
+            ns_hosts: HashMap::new(),
+            min_members: None,
+            authorized_txt: None,
+            version_txt: false,
+            unix_socket: None,
+            tcp_keepalive: false,
+            presync_response: PresyncResponse::NXDomain,
+            hosts_files: Vec::new(),
+            shared_ip: SharedIpPolicy::Warn,
+            statsd_addr: None,
+            truncate_names: false,
+            chaos_version: false,
+            chaos_info: false,
+            sync_threads: None,
+            forward_max_stale: None,
+            webhook_url: None,
+            max_tcp_connections: None,
+            dual_stack: false,
+            primary_election: false,
+            primary_failover: None,
+            recv_buffer: None,
+            send_buffer: None,
+            tcp_backlog: None,
+            advertise_grace: None,
+            reverse_interval: None,
+            glue: false,
+            refuse_client_subnet: false,
+            enable_local_dns: false,
+            domain_aliases: Vec::new(),
+            gateway_name: None,
+            gateway_ips: Vec::new(),
+            missing_hosts: MissingHosts::Keep,
+            doq: false,
+            doq_port: None,
+            catch_all_ip: None,
+            pending_subdomain: None,
+            merge_names: false,
+            health_name: None,
+            dns_update_retries: None,
+            address_names: false,
+            soa_refresh: None,
+            soa_retry: None,
+            soa_expire: None,
+            soa_minimum: None,
+            reverse_soa_minimum: None,
+            root_queries: RootQueries::Forward,
+            forward_min_ttl: None,
+            forward_max_ttl: None,
+            push_dns: DnsPush::Both,
+            online_within: None,
+            group_tags: Vec::new(),
+            unmanaged_reverse: UnmanagedReverse::Forward,
+            id_name_collision: IdNameCollision::Allow,
         }
     }
 }
 
 impl Launcher {
     pub fn new_from_config(filename: &str, format: ConfigFormat) -> Result<Self, anyhow::Error> {
-        let res = std::fs::read_to_string(filename)?;
-        Self::parse_format(&res, format)
+        let res = std::fs::read_to_string(filename).map_err(config_error)?;
+        Self::parse_format(&res, format).map_err(config_error)
+    }
+
+    // schema renders a JSON Schema of the configuration file, for editors to complete and
+    // validate it with.
+    pub fn schema() -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(&schemars::schema_for!(
+            Launcher
+        ))?)
     }
 
     pub fn parse_format(s: &str, format: ConfigFormat) -> Result<Self, anyhow::Error> {
@@ -96,6 +346,196 @@ impl Launcher {
         Ok(l)
     }
 
+    // load_tls reads the DoT certificate, chain and key, if configured.
+    pub(crate) fn load_tls(&self) -> Result<Option<SslAcceptor>, anyhow::Error> {
+        match self.read_tls()? {
+            Some((cert, chain, key)) => Ok(Some(new_acceptor(cert, chain, key)?)),
+            None => Ok(None),
+        }
+    }
+
+    // load_doq reads the TLS material for the DNS-over-QUIC listener, if one is wanted.
+    fn load_doq(&self) -> Result<Option<DoqConfig>, anyhow::Error> {
+        if !self.doq {
+            return Ok(None);
+        }
+
+        let (cert, chain, key) = self.read_tls()?.ok_or_else(|| {
+            anyhow!("DNS-over-QUIC needs tls_cert and tls_key to be set; cannot continue")
+        })?;
+
+        Ok(Some(DoqConfig::new(
+            self.doq_port.unwrap_or(DOQ_PORT),
+            &cert,
+            chain.as_ref(),
+            &key,
+        )?))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn read_tls(
+        &self,
+    ) -> Result<Option<(X509, Option<Stack<X509>>, PKey<Private>)>, anyhow::Error> {
+        let (tls_cert, key) = match (self.tls_cert.clone(), self.tls_key.clone()) {
+            (Some(tls_cert), Some(key_path)) => (tls_cert, key_path),
+            _ => return Ok(None),
+        };
+
+        let tls_cert = X509::from_pem(&std::fs::read(tls_cert)?)?;
+
+        let chain = if let Some(chain_cert) = self.chain_cert.clone() {
+            let pem = std::fs::read(chain_cert)?;
+            let chain = X509::stack_from_pem(&pem)?;
+
+            let mut stack = Stack::new()?;
+            for cert in chain {
+                stack.push(cert)?;
+            }
+            Some(stack)
+        } else {
+            None
+        };
+
+        let key = std::fs::read(key)?;
+        let key = match &self.tls_key_passphrase {
+            Some(passphrase) => PKey::private_key_from_pem_passphrase(
+                &key,
+                std::fs::read_to_string(passphrase)?.trim_end().as_bytes(),
+            )?,
+            None => PKey::private_key_from_pem(&key)?,
+        };
+
+        check_tls(&tls_cert, chain.as_ref(), &key)?;
+
+        Ok(Some((tls_cert, chain, key)))
+    }
+
+    // push_central_dns points the network's DNS settings in central at this instance.
+    pub(crate) async fn push_central_dns(
+        &self,
+        domain_name: Name,
+        servers: Vec<String>,
+        client: central_api::Client,
+        election: Option<&Election>,
+    ) -> Result<(), anyhow::Error> {
+        if election.is_some_and(|election| !election.is_primary()) {
+            return Ok(());
+        }
+
+        if let Some(min) = self.min_members.filter(|min| *min > 0) {
+            let members = client
+                .get_network_member_list(self.network_id.as_deref().unwrap())
+                .await?;
+            if members.len() < min {
+                warn!(
+                    "Central returned {} members, fewer than the minimum of {}; not pushing DNS settings to Central",
+                    members.len(),
+                    min
+                );
+                return Ok(());
+            }
+        }
+
+        let (domain_name, servers) = self.push_dns.select(domain_name, servers);
+
+        update_central_dns(
+            domain_name.clone(),
+            servers.clone(),
+            client.clone(),
+            self.network_id.clone().unwrap(),
+            self.dns_update_retries
+                .unwrap_or(DEFAULT_DNS_UPDATE_RETRIES),
+        )
+        .await?;
+
+        if let Some(timeout) = self.verify_dns_timeout {
+            verify_central_dns(
+                domain_name,
+                servers,
+                client,
+                self.network_id.clone().unwrap(),
+                Duration::new(timeout, 0),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    // election sets up the primary election among the instances listed in ns_hosts, and holds the
+    // first one. Without ns_hosts addresses there is no one to elect, and this instance writes to
+    // Central as if the election were off.
+    async fn election(
+        &self,
+        authtoken: &Path,
+        domain_name: &Name,
+        listen_ips: &[IpAddr],
+    ) -> Result<Option<Election>, anyhow::Error> {
+        let candidates: Vec<IpAddr> = self.ns_hosts.values().flatten().copied().collect();
+        if candidates.is_empty() {
+            warn!("primary_election needs the addresses of every instance in ns_hosts; without them, this instance is always the primary");
+            return Ok(None);
+        }
+
+        let candidate = candidates.iter().any(|ip| listen_ips.contains(ip));
+        if !candidate {
+            warn!("None of this instance's addresses are in ns_hosts; it never writes the DNS settings to Central");
+        }
+
+        let election = Election::new(
+            get_node_id(
+                authtoken,
+                self.local_url
+                    .clone()
+                    .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+            )
+            .await?,
+            domain_name,
+            candidates
+                .into_iter()
+                .filter(|ip| !listen_ips.contains(ip))
+                .map(|ip| SocketAddr::new(ip, 53))
+                .collect(),
+            candidate,
+            Duration::new(self.primary_failover.unwrap_or(DEFAULT_PRIMARY_FAILOVER), 0),
+        )?;
+        election.update().await;
+
+        Ok(Some(election))
+    }
+
+    // watch_election holds the election again every interval, and pushes the DNS settings to
+    // Central when this instance becomes the primary.
+    async fn watch_election(
+        self,
+        election: Election,
+        domain_name: Name,
+        servers: Vec<String>,
+        client: central_api::Client,
+        interval: Duration,
+    ) {
+        let mut timer = tokio::time::interval(interval);
+        timer.tick().await;
+
+        loop {
+            timer.tick().await;
+
+            if election.update().await {
+                if let Err(e) = self
+                    .push_central_dns(
+                        domain_name.clone(),
+                        servers.clone(),
+                        client.clone(),
+                        Some(&election),
+                    )
+                    .await
+                {
+                    tracing::error!("error updating central DNS: {}", e);
+                }
+            }
+        }
+    }
+
     pub async fn start(&self) -> Result<ZTAuthority, anyhow::Error> {
         crate::utils::init_logger(
             self.log_level
@@ -105,32 +545,158 @@ impl Launcher {
         );
 
         if self.network_id.is_none() {
-            return Err(anyhow!("network ID is invalid; cannot continue"));
+            return Err(config_error(anyhow!(
+                "network ID is invalid; cannot continue"
+            )));
         }
 
-        let domain_name = domain_or_default(self.domain.as_deref())?;
+        let domain_name = domain_or_default(self.domain.as_deref()).map_err(config_error)?;
+        check_public_domain(&domain_name, self.allow_public_tld).map_err(config_error)?;
+        let update_interval = members_interval(self.members_interval).map_err(config_error)?;
+        let forward_ttl = match (self.forward_min_ttl, self.forward_max_ttl) {
+            (Some(min), Some(max)) if min > max => {
+                return Err(config_error(anyhow!(
+                "forward_min_ttl ({}) must not be more than forward_max_ttl ({}); cannot continue",
+                min,
+                max
+            )))
+            }
+            (min, max) => TtlClamp { min, max },
+        };
+        let soa_timers = SoaTimers::new(
+            self.soa_refresh,
+            self.soa_retry,
+            self.soa_expire,
+            self.soa_minimum,
+        )
+        .map_err(config_error)?;
+        let reverse_soa_timers = SoaTimers::new(
+            self.soa_refresh,
+            self.soa_retry,
+            self.soa_expire,
+            self.reverse_soa_minimum.or(self.soa_minimum),
+        )
+        .map_err(config_error)?;
+        // a certificate that does not go with its key or chain stops startup here, instead of
+        // failing every connection later.
+        self.read_tls()?;
+        // the overrides are only re-read on each sync after this, where a bad edit keeps the last
+        // good ones; a bad file at startup has nothing to fall back to.
+        parse_overrides(self.overrides.clone()).map_err(config_error)?;
+        let controller_name = self
+            .controller_name
+            .as_deref()
+            .map(|name| -> Result<Name, anyhow::Error> {
+                Ok(Name::from_str(name)?.append_domain(&domain_name)?)
+            })
+            .transpose()?;
+        let pending_domain = self
+            .pending_subdomain
+            .as_deref()
+            .map(|name| -> Result<Name, anyhow::Error> {
+                Ok(Name::from_str(name)?.append_domain(&domain_name)?)
+            })
+            .transpose()?;
+        let health_name = self
+            .health_name
+            .as_deref()
+            .map(|name| -> Result<Name, anyhow::Error> {
+                Ok(Name::from_str(name)?.append_domain(&domain_name)?)
+            })
+            .transpose()?;
+        let gateway = match (&self.gateway_name, self.gateway_ips.is_empty()) {
+            (Some(name), false) => Some((
+                Name::from_str(name)?.append_domain(&domain_name)?,
+                self.gateway_ips.clone(),
+            )),
+            (None, true) => None,
+            _ => {
+                return Err(config_error(anyhow!(
+                    "gateway_name and gateway_ips must be set together; cannot continue"
+                )))
+            }
+        };
         let authtoken = authtoken_path(self.secret.as_deref());
-        let client = central_client(central_token(self.token.as_deref())?)?;
+        let client = central_client(central_token_for(
+            self.network_id.as_deref().unwrap(),
+            &self.tokens,
+            self.token.as_deref(),
+            self.token_keyring.as_deref(),
+        )?)?;
 
         info!("Welcome to ZeroNS!");
-        let ips = get_listen_ips(
-            &authtoken,
+        if self.enable_local_dns {
+            enable_local_dns(
+                authtoken,
+                &self.network_id.clone().unwrap(),
+                self.local_url
+                    .clone()
+                    .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+                self.listen_attempts.unwrap_or(1),
+                Duration::new(self.listen_backoff.unwrap_or(1), 0),
+            )
+            .await?;
+        }
+
+        let ips = get_listen_ips_retry(
+            authtoken,
             &self.network_id.clone().unwrap(),
             self.local_url
                 .clone()
                 .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+            self.listen_attempts.unwrap_or(1),
+            Duration::new(self.listen_backoff.unwrap_or(1), 0),
         )
         .await?;
 
         // more or less the setup for the "main loop"
         if !ips.is_empty() {
-            update_central_dns(
+            let bound: Vec<IpAddr> = ips.iter().map(|i| parse_ip_from_cidr(i.clone())).collect();
+            let advertised = advertised_ips(&bound, &self.advertise_ips).map_err(config_error)?;
+            check_zone_ips(&bound, &self.forward_listen_ips).map_err(config_error)?;
+            check_zone_ips(&bound, &self.reverse_listen_ips).map_err(config_error)?;
+            let servers: Vec<String> = advertised.iter().map(ToString::to_string).collect();
+
+            // allowDNS only decides the push, so a node that cannot be asked is not fatal.
+            let allow_dns = get_allow_dns(
+                authtoken,
+                &self.network_id.clone().unwrap(),
+                self.local_url
+                    .clone()
+                    .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+            )
+            .await
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Could not read managed DNS (allowDNS) on this node: {}; treating it as unknown",
+                    e
+                );
+                None
+            });
+            let push_wanted = dns_push_wanted(allow_dns, self.require_allow_dns);
+
+            let election = match self.primary_election {
+                true => self.election(authtoken, &domain_name, &bound).await?,
+                false => None,
+            };
+
+            if push_wanted && !self.advertise_after_bind {
+                self.push_central_dns(
+                    domain_name.clone(),
+                    servers.clone(),
+                    client.clone(),
+                    election.as_ref(),
+                )
+                .await?;
+            }
+
+            // the reverse zones name this instance as their nameserver, like the forward zone.
+            let member_name = get_member_name(
+                authtoken,
                 domain_name.clone(),
-                ips.iter()
-                    .map(|i| parse_ip_from_cidr(i.clone()).to_string())
-                    .collect(),
-                client.clone(),
-                self.network_id.clone().unwrap(),
+                self.local_url
+                    .clone()
+                    .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
             )
             .await?;
 
@@ -146,22 +712,16 @@ impl Launcher {
 
                 if let Entry::Vacant(e) = authority_map.entry(cidr) {
                     tracing::debug!("{}", cidr.to_ptr_soa_name()?);
-                    let ptr_authority =
-                        RecordAuthority::new(cidr.to_ptr_soa_name()?, cidr.to_ptr_soa_name()?)
-                            .await?;
+                    let ptr_authority = RecordAuthority::new(
+                        cidr.to_ptr_soa_name()?,
+                        member_name.clone(),
+                        self.reverse_ttl.unwrap_or(DEFAULT_RECORD_TTL),
+                    )
+                    .await?;
                     e.insert(ptr_authority);
                 }
             }
 
-            let member_name = get_member_name(
-                authtoken,
-                domain_name.clone(),
-                self.local_url
-                    .clone()
-                    .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
-            )
-            .await?;
-
             let network = client
                 .get_network_by_id(&self.network_id.clone().unwrap())
                 .await?;
@@ -175,73 +735,301 @@ impl Launcher {
                     let cidr = network.clone().rfc4193().unwrap();
                     if let Entry::Vacant(e) = authority_map.entry(cidr) {
                         tracing::debug!("{}", cidr.to_ptr_soa_name()?);
-                        let ptr_authority =
-                            RecordAuthority::new(cidr.to_ptr_soa_name()?, cidr.to_ptr_soa_name()?)
-                                .await?;
+                        let ptr_authority = RecordAuthority::new(
+                            cidr.to_ptr_soa_name()?,
+                            member_name.clone(),
+                            self.reverse_ttl.unwrap_or(DEFAULT_RECORD_TTL),
+                        )
+                        .await?;
                         e.insert(ptr_authority);
                     }
                 }
             }
 
-            let authority =
-                RecordAuthority::new(domain_name.clone().into(), member_name.clone()).await?;
+            // NS hosts are labels in the domain; without addresses of its own, a host is glued to
+            // this instance's listen IPs.
+            let mut ns_hosts = Vec::new();
+            for (label, ips) in &self.ns_hosts {
+                let name = Name::from_str(label)
+                    .and_then(|name| name.append_domain(&domain_name))
+                    .map_err(|e| anyhow!("Invalid NS host {:?}: {}", label, e))?;
+                let ips = if ips.is_empty() {
+                    listen_ips.clone()
+                } else {
+                    ips.clone()
+                };
+                ns_hosts.push((name, ips));
+            }
+            ns_hosts.sort();
+
+            let mut hosts_sources = Vec::new();
+            for source in &self.hosts_files {
+                let domain = match &source.suffix {
+                    Some(suffix) => Name::from_str(suffix)
+                        .and_then(|name| name.append_domain(&domain_name))
+                        .map_err(|e| {
+                            config_error(anyhow!("Invalid hosts file suffix {:?}: {}", suffix, e))
+                        })?,
+                    None => domain_name.clone(),
+                };
+                hosts_sources.push((source.path.clone(), domain));
+            }
+
+            let authority = RecordAuthority::new(
+                domain_name.clone().into(),
+                member_name.clone(),
+                self.forward_ttl.unwrap_or(DEFAULT_RECORD_TTL),
+            )
+            .await?;
+
+            // an alias overlapping the domain would have one zone shadow part of the other.
+            let mut alias_authorities = Vec::new();
+            for alias in &self.domain_aliases {
+                let alias = domain_or_default(Some(alias)).map_err(config_error)?;
+                check_public_domain(&alias, self.allow_public_tld).map_err(config_error)?;
+                if alias.zone_of(&domain_name) || domain_name.zone_of(&alias) {
+                    return Err(config_error(anyhow!(
+                        "Domain alias {} overlaps the domain {}",
+                        alias,
+                        domain_name
+                    )));
+                }
+
+                alias_authorities.push(
+                    RecordAuthority::new(
+                        alias.into(),
+                        member_name.clone(),
+                        self.forward_ttl.unwrap_or(DEFAULT_RECORD_TTL),
+                    )
+                    .await?,
+                );
+            }
+
+            let blocklist = match self.blocklist.clone() {
+                Some(path) => {
+                    let blocklist =
+                        Blocklist::from_file(path, self.sinkhole.clone()).map_err(config_error)?;
+                    info!("Blocking {} names at the forwarder", blocklist.len());
+                    Some(blocklist)
+                }
+                None => None,
+            };
 
             let ztauthority = ZTAuthority {
-                client,
-                network_id: self.network_id.clone().unwrap(),
-                hosts: None, // this will be parsed later.
                 hosts_file: self.hosts.clone(),
-                reverse_authority_map: authority_map,
-                forward_authority: authority,
                 wildcard: self.wildcard,
-                update_interval: Duration::new(30, 0),
+                update_interval,
+                audit: self
+                    .audit_file
+                    .clone()
+                    .map(|path| AuditLog::new(path, self.audit_initial)),
+                routes_txt: self.routes_txt,
+                hosts_ttl: self.hosts_ttl,
+                wildcard_families: self.wildcard_families,
+                overrides_file: self.overrides.clone(),
+                hosts_interval: self.hosts_interval.map(|i| Duration::new(i, 0)),
+                blocklist,
+                max_udp_size: self.max_udp_size,
+                truncation: self.truncation,
+                sync_timeout: self.sync_timeout.map(|t| Duration::new(t, 0)),
+                ptr_format: PtrFormat {
+                    prefix: self.ptr_prefix.clone(),
+                    suffix: self.ptr_suffix.clone(),
+                },
+                controller_name,
+                ptr_families: self.ptr_families,
+                primary_ip: self.primary_ip,
+                min_name_length: self.min_name_length.unwrap_or_default(),
+                host_name: Some(member_name.clone().into()),
+                host_ips: listen_ips.clone(),
+                any_response: self.any_response,
+                sync_schedule: self.sync_schedule,
+                forward_ips: self.forward_listen_ips.clone(),
+                reverse_ips: self.reverse_listen_ips.clone(),
+                ns_hosts,
+                min_members: self.min_members.unwrap_or_default(),
+                authorized_txt: self.authorized_txt,
+                version_txt: self.version_txt,
+                tcp_keepalive: self.tcp_keepalive,
+                presync_response: self.presync_response,
+                hosts_sources,
+                shared_ip: self.shared_ip,
+                statsd: self.statsd_addr.map(StatsdSink::new).transpose()?,
+                truncate_names: self.truncate_names,
+                chaos_version: self.chaos_version,
+                chaos_info: self.chaos_info,
+                stale_cache: self
+                    .forward_max_stale
+                    .map(|s| StaleCache::new(Duration::new(s, 0))),
+                webhook: self
+                    .webhook_url
+                    .clone()
+                    .map(|url| {
+                        Webhook::new(
+                            url,
+                            self.network_id.clone().unwrap(),
+                            WEBHOOK_ATTEMPTS,
+                            Duration::new(1, 0),
+                        )
+                    })
+                    .transpose()?,
+                max_tcp_connections: self.max_tcp_connections,
+                socket_options: SocketOptions {
+                    dual_stack: self.dual_stack,
+                    recv_buffer: self.recv_buffer,
+                    send_buffer: self.send_buffer,
+                    tcp_backlog: self
+                        .tcp_backlog
+                        .map(|backlog| i32::try_from(backlog).unwrap_or(i32::MAX)),
+                },
+                reverse_interval: self.reverse_interval.map(|secs| Duration::new(secs, 0)),
+                glue: self.glue,
+                refuse_client_subnet: self.refuse_client_subnet,
+                alias_authorities,
+                gateway,
+                doq: self.load_doq()?,
+                pending_domain,
+                merge_names: self.merge_names,
+                health_name,
+                address_names: self.address_names,
+                root_queries: self.root_queries,
+                forward_ttl,
+                soa_timers,
+                reverse_soa_timers,
+                missing_hosts: self.missing_hosts,
+                catch_all_ip: self.catch_all_ip,
+                online: self
+                    .online_within
+                    .map(|secs| Liveness::new(Duration::from_secs(secs))),
+                group_tags: self.group_tags.clone(),
+                unmanaged_reverse: self.unmanaged_reverse,
+                id_name_collision: self.id_name_collision,
+                instance_txt: election.as_ref().map(Election::advertised),
+                ..ZTAuthority::new(
+                    self.network_id.clone().unwrap(),
+                    client,
+                    authority,
+                    authority_map,
+                )
             };
 
-            tokio::spawn(find_members(ztauthority.clone()));
+            spawn_sync(ztauthority.clone(), self.sync_threads)?;
 
             let server = Server::new(ztauthority.to_owned());
-            for ip in listen_ips {
-                info!("Your IP for this network: {}", ip);
 
-                let tls_cert = if let Some(tls_cert) = self.tls_cert.clone() {
-                    let pem = std::fs::read(tls_cert)?;
-                    Some(X509::from_pem(&pem)?)
-                } else {
-                    None
-                };
+            let tls = self.load_tls()?.map(ReloadableAcceptor::new);
+            #[cfg(unix)]
+            if let Some(tls) = tls.clone() {
+                let launcher = self.clone();
+                tokio::spawn(crate::tls::reload_on_sighup(tls, move || {
+                    launcher
+                        .load_tls()?
+                        .ok_or_else(|| anyhow!("TLS is no longer configured"))
+                }));
+            }
 
-                let chain = if let Some(chain_cert) = self.chain_cert.clone() {
-                    let pem = std::fs::read(chain_cert)?;
-                    let chain = X509::stack_from_pem(&pem)?;
+            if self.advertise_after_bind {
+                let binds = listen_ips
+                    .iter()
+                    .map(|ip| {
+                        let ip = *ip;
+                        let server = server.clone();
+                        let tls = tls.clone();
+                        async move {
+                            info!("Your IP for this network: {}", ip);
+                            let sf = server
+                                .bind(SocketAddr::new(ip, 53), Duration::new(1, 0), tls.clone())
+                                .await?;
+                            tokio::spawn(Server::serve(sf));
+                            Ok(())
+                        }
+                    })
+                    .collect();
 
-                    let mut stack = Stack::new()?;
-                    for cert in chain {
-                        stack.push(cert)?;
-                    }
-                    Some(stack)
-                } else {
-                    None
-                };
+                let check = listen_ips.clone();
+                advertise_when_bound(
+                    binds,
+                    async move {
+                        for ip in check {
+                            if !listener_healthy(SocketAddr::new(ip, 53), Duration::new(1, 0)).await
+                            {
+                                warn!("Listener on {} failed its self-check", ip);
+                                return false;
+                            }
+                        }
+                        true
+                    },
+                    async {
+                        if push_wanted {
+                            self.push_central_dns(
+                                domain_name.clone(),
+                                servers.clone(),
+                                ztauthority.client.clone(),
+                                election.as_ref(),
+                            )
+                            .await
+                        } else {
+                            Ok(())
+                        }
+                    },
+                )
+                .await?;
+            } else {
+                for ip in listen_ips.clone() {
+                    info!("Your IP for this network: {}", ip);
 
-                let key = if let Some(key_path) = self.tls_key.clone() {
-                    let pem = std::fs::read(key_path)?;
-                    Some(PKey::private_key_from_pem(&pem)?)
-                } else {
-                    None
-                };
+                    tokio::spawn(server.clone().listen(ip, Duration::new(1, 0), tls.clone()));
+                }
+            }
 
-                tokio::spawn(
-                    server
-                        .clone()
-                        .listen(ip, Duration::new(1, 0), tls_cert, chain, key),
-                );
+            if let Some(path) = &self.unix_socket {
+                #[cfg(unix)]
+                {
+                    info!("Listening on unix socket {}", path.display());
+                    tokio::spawn(server.clone().bind_unix(path).await?.serve());
+                }
+                #[cfg(not(unix))]
+                return Err(anyhow!(
+                    "unix_socket {} is only supported on Unix",
+                    path.display()
+                ));
+            }
+
+            // with only the search domain pushed, there are no listeners to advertise.
+            if let (Some(interval), true) = (
+                self.health_interval,
+                push_wanted && self.push_dns != DnsPush::Domain,
+            ) {
+                tokio::spawn(advertise_healthy_ips(
+                    self.push_dns.select(domain_name.clone(), Vec::new()).0,
+                    advertised,
+                    ztauthority.client.clone(),
+                    self.network_id.clone().unwrap(),
+                    Duration::new(interval, 0),
+                    Duration::new(self.advertise_grace.unwrap_or_default(), 0),
+                    self.dns_update_retries
+                        .unwrap_or(DEFAULT_DNS_UPDATE_RETRIES),
+                    election.clone(),
+                ));
+            }
+
+            // another instance takes over writing to Central once the primary has been offline
+            // for too long.
+            if let Some(election) = election.filter(|_| push_wanted) {
+                tokio::spawn(self.clone().watch_election(
+                    election,
+                    domain_name.clone(),
+                    servers,
+                    ztauthority.client.clone(),
+                    update_interval,
+                ));
             }
 
             return Ok(ztauthority);
         }
 
-        return Err(anyhow!(
+        Err(anyhow!(
             "No listening IPs for your interface; assign one in ZeroTier Central."
-        ));
+        ))
     }
 }
@@ -1,10 +1,18 @@
 pub mod addresses;
+pub mod audit;
 pub mod authority;
+pub mod blocklist;
 pub mod cli;
+pub mod election;
+pub mod errors;
+pub mod forwarder;
 pub mod hosts;
 pub mod log;
+pub mod metrics;
+pub mod overrides;
 pub mod server;
 pub mod supervise;
+pub mod tls;
 pub mod traits;
 pub mod utils;
 
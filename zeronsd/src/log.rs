@@ -1,9 +1,10 @@
 use std::str::FromStr;
 
 use anyhow::anyhow;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub enum LevelFilter {
     #[serde(rename(deserialize = "off"))]
     Off,
@@ -32,17 +33,36 @@ impl LevelFilter {
     }
 }
 
-impl ToString for LevelFilter {
-    fn to_string(&self) -> String {
-        match self {
+impl LevelFilter {
+    // resolve picks the level to log at: an explicit level always wins, then `--quiet`, then each
+    // `-v` raises the default of `info` by one step.
+    pub fn resolve(explicit: Option<LevelFilter>, verbose: usize, quiet: bool) -> LevelFilter {
+        if let Some(level) = explicit {
+            return level;
+        }
+
+        if quiet {
+            return LevelFilter::Off;
+        }
+
+        match verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+}
+
+impl std::fmt::Display for LevelFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
             LevelFilter::Off => "off",
             LevelFilter::Error => "error",
             LevelFilter::Warn => "warn",
             LevelFilter::Info => "info",
             LevelFilter::Trace => "trace",
             LevelFilter::Debug => "debug",
-        }
-        .to_string()
+        })
     }
 }
 
@@ -71,7 +91,7 @@ mod tests {
 
     #[test]
     fn test_level_from_str() {
-        for item in vec![
+        for item in [
             ("off", LevelFilter::Off),
             ("error", LevelFilter::Error),
             ("warn", LevelFilter::Warn),
@@ -85,9 +105,25 @@ mod tests {
         assert!(LevelFilter::from_str("foo").is_err());
     }
 
+    #[test]
+    fn test_level_resolve() {
+        assert_eq!(LevelFilter::resolve(None, 0, false), LevelFilter::Info);
+        assert_eq!(LevelFilter::resolve(None, 1, false), LevelFilter::Debug);
+        assert_eq!(LevelFilter::resolve(None, 3, false), LevelFilter::Trace);
+        assert_eq!(LevelFilter::resolve(None, 0, true), LevelFilter::Off);
+        assert_eq!(
+            LevelFilter::resolve(Some(LevelFilter::Warn), 2, false),
+            LevelFilter::Warn
+        );
+        assert_eq!(
+            LevelFilter::resolve(Some(LevelFilter::Error), 0, true),
+            LevelFilter::Error
+        );
+    }
+
     #[test]
     fn test_level_to_string() {
-        for item in vec![
+        for item in [
             (LevelFilter::Off, "off"),
             (LevelFilter::Error, "error"),
             (LevelFilter::Warn, "warn"),
@@ -0,0 +1,121 @@
+/// counters for queries handed to the upstream forwarder, and a StatsD sink to push them to.
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct ForwardMetrics {
+    queries: Arc<AtomicU64>,
+    errors: Arc<AtomicU64>,
+    latency_micros: Arc<AtomicU64>,
+    max_latency_micros: Arc<AtomicU64>,
+}
+
+impl ForwardMetrics {
+    pub(crate) fn observe(&self, start: Instant, ok: bool) {
+        let micros = start.elapsed().as_micros() as u64;
+
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_latency_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub fn queries(&self) -> u64 {
+        self.queries.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    // average upstream latency in microseconds, including answers from the resolver's cache.
+    pub fn average_latency_micros(&self) -> u64 {
+        self.latency_micros
+            .load(Ordering::Relaxed)
+            .checked_div(self.queries())
+            .unwrap_or_default()
+    }
+
+    pub fn max_latency_micros(&self) -> u64 {
+        self.max_latency_micros.load(Ordering::Relaxed)
+    }
+
+    pub fn log(&self) {
+        tracing::debug!(
+            "forwarder: {} queries, {} errors, {}us average / {}us max latency",
+            self.queries(),
+            self.errors(),
+            self.average_latency_micros(),
+            self.max_latency_micros()
+        );
+    }
+}
+
+// StatsdSink pushes the metrics to a StatsD (or DogStatsD) server after every sync. Sends are
+// fire-and-forget over UDP; a server that is down or unreachable only loses the metrics.
+#[derive(Debug, Clone)]
+pub struct StatsdSink {
+    socket: Arc<UdpSocket>,
+    // forwarder totals at the last report, so counters are sent as deltas.
+    queries: Arc<AtomicU64>,
+    errors: Arc<AtomicU64>,
+}
+
+impl StatsdSink {
+    pub fn new(addr: SocketAddr) -> Result<Self, anyhow::Error> {
+        let bind: SocketAddr = if addr.is_ipv4() {
+            "0.0.0.0:0".parse()?
+        } else {
+            "[::]:0".parse()?
+        };
+
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_nonblocking(true)?;
+        socket.connect(addr)?;
+
+        Ok(Self {
+            socket: Arc::new(socket),
+            queries: Default::default(),
+            errors: Default::default(),
+        })
+    }
+
+    // report sends the forwarder counters, whether the sync failed and the number of record sets
+    // served, in a single datagram.
+    pub fn report(&self, forward: &ForwardMetrics, sync_failed: bool, records: usize) {
+        let queries = forward.queries();
+        let errors = forward.errors();
+        let lines = [
+            format!(
+                "zeronsd.forward.queries:{}|c",
+                queries - self.queries.swap(queries, Ordering::Relaxed)
+            ),
+            format!(
+                "zeronsd.forward.errors:{}|c",
+                errors - self.errors.swap(errors, Ordering::Relaxed)
+            ),
+            format!(
+                "zeronsd.forward.latency_avg_us:{}|g",
+                forward.average_latency_micros()
+            ),
+            format!(
+                "zeronsd.forward.latency_max_us:{}|g",
+                forward.max_latency_micros()
+            ),
+            format!("zeronsd.sync.errors:{}|c", sync_failed as u8),
+            format!("zeronsd.records:{}|g", records),
+        ];
+
+        if let Err(e) = self.socket.send(lines.join("\n").as_bytes()) {
+            tracing::debug!("error sending metrics to statsd: {}", e);
+        }
+    }
+}
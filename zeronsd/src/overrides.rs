@@ -0,0 +1,25 @@
+/// per-member overrides: extra records for specific members, keyed by node id, kept in a file
+/// next to the config instead of in Central.
+use std::{collections::HashMap, net::IpAddr, path::PathBuf};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct MemberOverride {
+    // served alongside the member's own addresses.
+    #[serde(default)]
+    pub ips: Vec<IpAddr>,
+    // when set, the member's names are served as a CNAME to this name instead of addresses.
+    // Relative names are placed under the domain.
+    pub cname: Option<String>,
+}
+
+pub type Overrides = HashMap<String, MemberOverride>;
+
+/// Parses the overrides file. YAML is a superset of JSON, so either format is accepted.
+pub fn parse_overrides(overrides_file: Option<PathBuf>) -> Result<Overrides, anyhow::Error> {
+    match overrides_file {
+        Some(path) => Ok(serde_yml::from_str(&std::fs::read_to_string(path)?)?),
+        None => Ok(Overrides::new()),
+    }
+}
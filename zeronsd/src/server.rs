@@ -1,19 +1,59 @@
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::{
-    net::{IpAddr, SocketAddr},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
-use tracing::info;
+use tracing::{debug, info, warn};
 
-use openssl::{
-    pkey::{PKey, Private},
-    stack::Stack,
-    x509::X509,
+use anyhow::anyhow;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Socket, Type};
+
+use futures_util::StreamExt;
+#[cfg(unix)]
+use tokio::net::UnixDatagram;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, UdpSocket},
+    sync::Semaphore,
 };
-use tokio::net::{TcpListener, UdpSocket};
 
-use trust_dns_server::server::ServerFuture;
+use trust_dns_resolver::proto::{
+    iocompat::AsyncIoTokioAsStd,
+    op::{Header, Message, ResponseCode},
+    rr::{
+        rdata::{
+            opt::{EdnsCode, EdnsOption},
+            TXT,
+        },
+        DNSClass, Name, RData, Record, RecordType,
+    },
+    serialize::binary::{BinDecodable, BinEncodable, BinEncoder},
+    tcp::TcpStream as DnsTcpStream,
+};
+use trust_dns_server::{
+    authority::{Catalog, MessageRequest, MessageResponse, MessageResponseBuilder},
+    client::rr::LowerName,
+    server::{
+        Protocol, Request, RequestHandler, ResponseHandle, ResponseHandler, ResponseInfo,
+        ServerFuture, TimeoutStream,
+    },
+};
 
-use crate::authority::{init_catalog, ZTAuthority};
+use crate::{
+    authority::{init_catalog, PresyncResponse, ZTAuthority},
+    tls::{register_doq, serve_tls, ReloadableAcceptor},
+    utils::{GIT_SHA, VERSION_STRING},
+};
 
 #[derive(Clone)]
 pub struct Server(ZTAuthority);
@@ -28,32 +68,800 @@ impl Server {
         self,
         ip: IpAddr,
         tcp_timeout: Duration,
-        certs: Option<X509>,
-        cert_chain: Option<Stack<X509>>,
-        key: Option<PKey<Private>>,
+        tls: Option<ReloadableAcceptor>,
     ) -> Result<(), anyhow::Error> {
-        let sa = SocketAddr::new(ip, 53);
-        let tcp = TcpListener::bind(sa).await?;
-        let udp = UdpSocket::bind(sa).await?;
+        let sf = self.bind(SocketAddr::new(ip, 53), tcp_timeout, tls).await?;
+
+        Self::serve(sf).await
+    }
 
-        let mut sf = ServerFuture::new(init_catalog(self.0).await?);
+    // bind opens the sockets for the listener without serving them yet, so callers can be sure
+    // the address is usable before advertising it.
+    pub async fn bind(
+        self,
+        sa: SocketAddr,
+        tcp_timeout: Duration,
+        tls: Option<ReloadableAcceptor>,
+    ) -> Result<ServerFuture<LimitedCatalog>, anyhow::Error> {
+        let options = self.0.socket_options;
+        let tcp = bind_tcp(sa, options)?;
+        let udp = bind_udp(sa, options)?;
+        let max_tcp_connections = self.0.max_tcp_connections;
+        let doq = self.0.doq.clone();
+
+        let catalog = self.catalog(Some(sa.ip()), Some(tcp_timeout)).await?;
+        let mut sf = ServerFuture::new(catalog.clone());
+        let catalog = Arc::new(catalog);
+        let permits = max_tcp_connections.map(|max| Arc::new(Semaphore::new(max)));
 
-        if let (Some(certs), Some(key)) = (certs.clone(), key.clone()) {
+        if let Some(tls) = tls {
             info!("Configuring DoT Listener");
-            let tls = TcpListener::bind(SocketAddr::new(ip, 853)).await?;
+            let listener = bind_tcp(SocketAddr::new(sa.ip(), 853), options)?;
+            tokio::spawn(serve_tls(
+                listener,
+                tls,
+                catalog.clone(),
+                tcp_timeout,
+                permits.clone(),
+            ));
+        }
 
-            match sf.register_tls_listener(tls, tcp_timeout, ((certs, cert_chain), key)) {
-                Ok(_) => {}
-                Err(e) => tracing::error!("Cannot start DoT listener: {}", e),
-            }
+        if let Some(doq) = &doq {
+            info!("Configuring DoQ Listener");
+            let socket = bind_udp(SocketAddr::new(sa.ip(), doq.port), options)?;
+            register_doq(&mut sf, socket, doq, tcp_timeout)?;
         }
 
         sf.register_socket(udp);
-        sf.register_listener(tcp, tcp_timeout);
+        match permits {
+            Some(permits) => {
+                tokio::spawn(serve_limited_tcp(tcp, catalog, tcp_timeout, permits));
+            }
+            None => sf.register_listener(tcp, tcp_timeout),
+        }
+
+        Ok(sf)
+    }
 
+    pub async fn serve(sf: ServerFuture<LimitedCatalog>) -> Result<(), anyhow::Error> {
         match sf.block_until_done().await {
             Ok(_) => Ok(()),
             Err(e) => Err(anyhow::anyhow!("{}", e)),
         }
     }
+
+    // bind_unix opens a Unix datagram socket at path for stub resolvers on this host. Any stale
+    // socket left at the path is replaced; anything else there is left alone, and is an error.
+    #[cfg(unix)]
+    pub async fn bind_unix(self, path: &Path) -> Result<UnixServer, anyhow::Error> {
+        use std::os::unix::fs::FileTypeExt;
+
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(anyhow!(
+                    "unix_socket {} exists and is not a socket; refusing to replace it",
+                    path.display()
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(UnixServer {
+            socket: Arc::new(UnixDatagram::bind(path)?),
+            catalog: Arc::new(self.catalog(None, None).await?),
+        })
+    }
+
+    // catalog builds the catalog for a listener on ip, or for local clients without one. The TCP
+    // timeout is advertised as the keepalive, if that is enabled and the listener has one.
+    pub(crate) async fn catalog(
+        self,
+        ip: Option<IpAddr>,
+        tcp_timeout: Option<Duration>,
+    ) -> Result<LimitedCatalog, anyhow::Error> {
+        Ok(LimitedCatalog {
+            max_udp_size: self.0.max_udp_size,
+            truncation: self.0.truncation,
+            tcp_keepalive: tcp_timeout.filter(|_| self.0.tcp_keepalive),
+            presync: Presync::new(&self.0),
+            chaos_version: self.0.chaos_version,
+            info: self.0.chaos_info.then(|| self.0.clone()),
+            health_name: self.0.health_name.clone().map(Into::into),
+            instance: self
+                .0
+                .instance_txt
+                .clone()
+                .map(|(name, node_id)| (name.into(), node_id)),
+            refuse_client_subnet: self.0.refuse_client_subnet.then(|| self.0.zones()),
+            refuse_root: (self.0.root_queries == RootQueries::Refuse).then(|| self.0.zones()),
+            catalog: Arc::new(init_catalog(self.0, ip).await?),
+        })
+    }
+}
+
+// SocketOptions are set on every listening socket. Buffer sizes left unset keep the operating
+// system's defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SocketOptions {
+    // let IPv6 listeners accept IPv4-mapped clients too.
+    pub dual_stack: bool,
+    // SO_RCVBUF and SO_SNDBUF, in bytes.
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+    // how many connections the kernel queues for a TCP listener before they are accepted.
+    pub tcp_backlog: Option<i32>,
+}
+
+// socket_for creates a socket for sa. IPV6_V6ONLY is always set on IPv6 sockets, as the default
+// differs between platforms: without dual_stack an IPv6 listener never captures IPv4 clients.
+fn socket_for(sa: SocketAddr, ty: Type, options: SocketOptions) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(sa), ty, None)?;
+    if sa.is_ipv6() {
+        socket.set_only_v6(!options.dual_stack)?;
+    }
+
+    // the kernel caps buffers at its own limits (net.core.rmem_max and wmem_max on Linux) without
+    // an error, so what it settled on is read back.
+    if let Some(size) = options.recv_buffer {
+        socket.set_recv_buffer_size(size)?;
+        warn_clamped("receive", sa, size, socket.recv_buffer_size()?);
+    }
+    if let Some(size) = options.send_buffer {
+        socket.set_send_buffer_size(size)?;
+        warn_clamped("send", sa, size, socket.send_buffer_size()?);
+    }
+
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+fn warn_clamped(buffer: &str, sa: SocketAddr, wanted: usize, got: usize) {
+    if got < wanted {
+        warn!(
+            "The {} buffer of the listener on {} is {} bytes instead of {}; the system limits it",
+            buffer, sa, got, wanted
+        );
+    }
+}
+
+pub fn bind_tcp(sa: SocketAddr, options: SocketOptions) -> io::Result<TcpListener> {
+    let socket = socket_for(sa, Type::STREAM, options)?;
+    // as tokio does, so a restart does not wait out connections in TIME_WAIT.
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&sa.into())?;
+    socket.listen(options.tcp_backlog.unwrap_or(1024))?;
+    TcpListener::from_std(socket.into())
+}
+
+pub fn bind_udp(sa: SocketAddr, options: SocketOptions) -> io::Result<UdpSocket> {
+    let socket = socket_for(sa, Type::DGRAM, options)?;
+    socket.bind(&sa.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+// serve_limited_tcp answers the connections accepted on listener from the catalog, as long as
+// fewer than permits allows are open. Connections beyond that are closed straight away. A
+// connection gives its permit back when the client closes it, or when it has sent nothing for
+// timeout.
+pub async fn serve_limited_tcp(
+    listener: TcpListener,
+    catalog: Arc<LimitedCatalog>,
+    timeout: Duration,
+    permits: Arc<Semaphore>,
+) {
+    loop {
+        let (mut tcp, src) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                debug!("error accepting TCP connection: {}", e);
+                continue;
+            }
+        };
+
+        let permit = match permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                debug!(
+                    "closing TCP connection from {}: the connection limit is reached",
+                    src
+                );
+                let _ = tcp.shutdown().await;
+                continue;
+            }
+        };
+
+        let catalog = catalog.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            serve_stream(tcp, src, Protocol::Tcp, catalog, timeout).await
+        });
+    }
+}
+
+// serve_stream answers the DNS messages on a connection, in the length-prefixed framing TCP and
+// DoT share, until the client closes it or sends nothing for timeout. As with trust-dns' own
+// listeners, one message is answered at a time.
+pub(crate) async fn serve_stream<S>(
+    stream: S,
+    src: SocketAddr,
+    protocol: Protocol,
+    catalog: Arc<LimitedCatalog>,
+    timeout: Duration,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static,
+{
+    let (stream, stream_handle) = DnsTcpStream::from_stream(AsyncIoTokioAsStd(stream), src);
+    let mut messages = TimeoutStream::new(stream, timeout);
+
+    while let Some(message) = messages.next().await {
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                debug!("closing {} connection from {}: {}", protocol, src, e);
+                return;
+            }
+        };
+
+        let request = match MessageRequest::from_bytes(message.bytes()) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("closing {} connection from {}: {}", protocol, src, e);
+                return;
+            }
+        };
+
+        catalog
+            .handle_request(
+                &Request::new(request, src, protocol),
+                ResponseHandle::new(src, stream_handle.clone()),
+            )
+            .await;
+    }
+}
+
+// UnixServer answers DNS messages sent as datagrams to a Unix socket, one message per datagram as
+// over UDP. Clients have to bind their own socket to a path to receive the answer.
+#[cfg(unix)]
+pub struct UnixServer {
+    socket: Arc<UnixDatagram>,
+    catalog: Arc<LimitedCatalog>,
+}
+
+#[cfg(unix)]
+impl UnixServer {
+    pub async fn serve(self) -> Result<(), anyhow::Error> {
+        // the catalog wants a source address; these clients are all local.
+        let src = SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 0);
+        let mut buf = vec![0; 65535];
+
+        loop {
+            // a failed receive only loses that datagram; the socket keeps serving the others.
+            let (len, peer) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Could not receive a query on the unix socket: {}", e);
+                    continue;
+                }
+            };
+            let peer = match peer.as_pathname() {
+                Some(peer) => peer.to_path_buf(),
+                None => {
+                    debug!("Dropping query from an unbound unix socket; it cannot be answered");
+                    continue;
+                }
+            };
+
+            let message = match MessageRequest::from_bytes(&buf[..len]) {
+                Ok(message) => message,
+                Err(e) => {
+                    debug!("Dropping malformed query from {}: {}", peer.display(), e);
+                    continue;
+                }
+            };
+
+            let socket = self.socket.clone();
+            let catalog = self.catalog.clone();
+            tokio::spawn(async move {
+                catalog
+                    .handle_request(
+                        &Request::new(message, src, Protocol::Udp),
+                        UnixResponseHandle { socket, peer },
+                    )
+                    .await
+            });
+        }
+    }
+}
+
+#[cfg(unix)]
+#[derive(Clone)]
+struct UnixResponseHandle {
+    socket: Arc<UnixDatagram>,
+    peer: PathBuf,
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl ResponseHandler for UnixResponseHandle {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        let mut buffer = Vec::with_capacity(512);
+        let info = response
+            .destructive_emit(&mut BinEncoder::new(&mut buffer))
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        self.socket.send_to(&buffer, &self.peer).await?;
+        Ok(info)
+    }
+}
+
+// Truncation selects what a UDP response that does not fit carries, besides the TC bit.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum Truncation {
+    // as many answers as fit.
+    #[default]
+    #[serde(rename = "partial")]
+    Partial,
+    // no records at all, so the client has to retry over TCP.
+    #[serde(rename = "empty")]
+    Empty,
+}
+
+impl std::fmt::Display for Truncation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Truncation::Partial => "partial",
+            Truncation::Empty => "empty",
+        })
+    }
+}
+
+impl FromStr for Truncation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "partial" => Ok(Truncation::Partial),
+            "empty" => Ok(Truncation::Empty),
+            _ => Err(anyhow!("invalid format: allowed values: [partial, empty]")),
+        }
+    }
+}
+
+// RootQueries selects what happens to queries for the root zone and NS queries for names outside
+// the managed zones, which a resolver would otherwise answer from upstream.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum RootQueries {
+    // pass them to the forwarder, like any other query.
+    #[default]
+    #[serde(rename = "forward")]
+    Forward,
+    // answer them with REFUSED. NS queries for the managed zones are still answered.
+    #[serde(rename = "refuse")]
+    Refuse,
+}
+
+impl std::fmt::Display for RootQueries {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RootQueries::Forward => "forward",
+            RootQueries::Refuse => "refuse",
+        })
+    }
+}
+
+impl FromStr for RootQueries {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forward" => Ok(RootQueries::Forward),
+            "refuse" => Ok(RootQueries::Refuse),
+            _ => Err(anyhow!("invalid format: allowed values: [forward, refuse]")),
+        }
+    }
+}
+
+// limit_message fits a response into limit bytes. If it is too large, the TC bit is set and the
+// answers are either cut down to what fits or dropped, depending on the policy. The authority and
+// additional sections are always dropped; the question and EDNS record are always kept.
+pub fn limit_message(
+    message: Message,
+    limit: usize,
+    truncation: Truncation,
+) -> Result<Message, anyhow::Error> {
+    if message.to_vec()?.len() <= limit {
+        return Ok(message);
+    }
+
+    let mut truncated = message.clone();
+    truncated.take_answers();
+    truncated.take_name_servers();
+    truncated.take_additionals();
+    truncated.set_truncated(true);
+
+    if truncation == Truncation::Partial {
+        // record sizes are measured without name compression, so this errs on the small side.
+        let mut size = truncated.to_vec()?.len();
+        for answer in message.answers() {
+            size += answer.to_bytes()?.len();
+            if size > limit {
+                break;
+            }
+            truncated.add_answer(answer.clone());
+        }
+    }
+
+    Ok(truncated)
+}
+
+// keepalive_option builds the edns-tcp-keepalive option (RFC 7828) for an idle timeout, which is
+// sent in units of 100 milliseconds.
+pub fn keepalive_option(timeout: Duration) -> EdnsOption {
+    let units = (timeout.as_millis() / 100).min(u16::MAX as u128) as u16;
+    EdnsOption::Unknown(u16::from(EdnsCode::Keepalive), units.to_be_bytes().to_vec())
+}
+
+// Presync holds what is needed to answer SERVFAIL instead of NXDOMAIN for names in the managed
+// zones until the first sync has completed. The catalog itself cannot answer SERVFAIL, so this is
+// applied to its responses.
+#[derive(Clone)]
+struct Presync {
+    ready: Arc<AtomicBool>,
+    zones: Vec<LowerName>,
+}
+
+impl Presync {
+    fn new(zt: &ZTAuthority) -> Option<Self> {
+        (zt.presync_response == PresyncResponse::ServFail).then(|| Self {
+            ready: zt.ready.clone(),
+            zones: zt.zones(),
+        })
+    }
+
+    fn pending(&self, name: &LowerName) -> bool {
+        !self.ready.load(Ordering::Relaxed) && self.zones.iter().any(|zone| zone.zone_of(name))
+    }
+}
+
+// names that answer CHAOS TXT queries with the version, as BIND and RFC 4892 use them.
+const CHAOS_VERSION_NAMES: [&str; 2] = ["version.bind.", "version.server."];
+// the name that answers CHAOS TXT queries with the diagnostics support asks for.
+const CHAOS_INFO_NAME: &str = "info.zeronsd.";
+
+// LimitedCatalog applies the configured UDP response size limit, TCP keepalive, pre-sync response
+// and client-subnet policy in front of the catalog. Without any of them, responses are passed
+// through exactly as before. CHAOS-class queries never reach the catalog, whose records are all IN.
+#[derive(Clone)]
+pub struct LimitedCatalog {
+    catalog: Arc<Catalog>,
+    max_udp_size: Option<u16>,
+    truncation: Truncation,
+    // the idle timeout advertised to TCP clients that sent EDNS.
+    tcp_keepalive: Option<Duration>,
+    presync: Option<Presync>,
+    // answer CHAOS TXT queries for the version names.
+    chaos_version: bool,
+    // answer CHAOS TXT queries for the info name with the uptime and records of this authority.
+    info: Option<ZTAuthority>,
+    // answered with the loopback addresses before the catalog is consulted, so health checks do
+    // not depend on the members sync.
+    health_name: Option<LowerName>,
+    // answered with this instance's node id, for the other instances holding the primary election.
+    instance: Option<(LowerName, String)>,
+    // refuse queries with an EDNS client-subnet option, except for names in these zones, which
+    // are answered locally.
+    refuse_client_subnet: Option<Vec<LowerName>>,
+    // refuse queries for the root and NS queries outside these zones.
+    refuse_root: Option<Vec<LowerName>>,
+}
+
+impl LimitedCatalog {
+    // answer_chaos answers a CHAOS-class query: the version names and the info name with a TXT
+    // record if they are enabled, and everything else with REFUSED.
+    async fn answer_chaos<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.request_info().query;
+        let is = |name: &str| LowerName::from_str(name).is_ok_and(|name| &name == query.name());
+
+        let txt = match &self.info {
+            _ if !matches!(query.query_type(), RecordType::TXT | RecordType::ANY) => None,
+            _ if self.chaos_version && CHAOS_VERSION_NAMES.iter().any(|name| is(name)) => {
+                Some(vec![format!("zeronsd {}", VERSION_STRING)])
+            }
+            Some(zt) if is(CHAOS_INFO_NAME) => Some(vec![
+                format!("version={}", VERSION_STRING),
+                format!("git={}", GIT_SHA),
+                format!("uptime={}", zt.started.elapsed().as_secs()),
+                format!("records={}", zt.record_count().await),
+            ]),
+            _ => None,
+        };
+
+        let txt = match txt {
+            Some(txt) => txt,
+            None => return refuse(request, response_handle).await,
+        };
+
+        let mut record = Record::from_rdata(query.name().into(), 0, RData::TXT(TXT::new(txt)));
+        record.set_dns_class(DNSClass::CH);
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_authoritative(true);
+
+        let answers = [record];
+        let builder = MessageResponseBuilder::from_message_request(request);
+        sent(
+            response_handle
+                .send_response(builder.build(header, answers.iter(), [], [], []))
+                .await,
+        )
+    }
+
+    // answer_health answers the health name: A with 127.0.0.1, AAAA with ::1, both for ANY and
+    // nothing for other types. The TTL is 0 so every probe reaches the server.
+    async fn answer_health<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.request_info().query;
+        let name: Name = query.name().into();
+
+        let answers: Vec<Record> = [
+            (RecordType::A, RData::A(Ipv4Addr::LOCALHOST)),
+            (RecordType::AAAA, RData::AAAA(Ipv6Addr::LOCALHOST)),
+        ]
+        .into_iter()
+        .filter(|(rtype, _)| query.query_type() == *rtype || query.query_type() == RecordType::ANY)
+        .map(|(_, rdata)| Record::from_rdata(name.clone(), 0, rdata))
+        .collect();
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_authoritative(true);
+
+        let builder = MessageResponseBuilder::from_message_request(request);
+        sent(
+            response_handle
+                .send_response(builder.build(header, answers.iter(), [], [], []))
+                .await,
+        )
+    }
+
+    // answer_instance answers the instance name with the node id in a TXT record, and nothing
+    // for other types. The TTL is 0, as the answer is only of use while this instance runs.
+    async fn answer_instance<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        node_id: &str,
+        mut response_handle: R,
+    ) -> ResponseInfo {
+        let query = request.request_info().query;
+
+        let answers: Vec<Record> = matches!(query.query_type(), RecordType::TXT | RecordType::ANY)
+            .then(|| {
+                Record::from_rdata(
+                    query.name().into(),
+                    0,
+                    RData::TXT(TXT::new(vec![node_id.to_string()])),
+                )
+            })
+            .into_iter()
+            .collect();
+
+        let mut header = Header::response_from_request(request.header());
+        header.set_authoritative(true);
+
+        let builder = MessageResponseBuilder::from_message_request(request);
+        sent(
+            response_handle
+                .send_response(builder.build(header, answers.iter(), [], [], []))
+                .await,
+        )
+    }
+
+    // rejects_root reports whether a query is for the root, or for the NS records of a name outside
+    // the managed zones, and such queries are refused.
+    fn rejects_root(&self, request: &Request) -> bool {
+        let query = request.request_info().query;
+
+        match &self.refuse_root {
+            Some(zones) => {
+                query.name().is_root()
+                    || (query.query_type() == RecordType::NS
+                        && !zones.iter().any(|zone| zone.zone_of(query.name())))
+            }
+            None => false,
+        }
+    }
+
+    // rejects_client_subnet reports whether a query carries an EDNS client-subnet option that is
+    // not allowed: only queries that would be forwarded are refused.
+    fn rejects_client_subnet(&self, request: &Request) -> bool {
+        match &self.refuse_client_subnet {
+            Some(zones) => {
+                request
+                    .edns()
+                    .is_some_and(|edns| edns.option(EdnsCode::Subnet).is_some())
+                    && !zones
+                        .iter()
+                        .any(|zone| zone.zone_of(request.request_info().query.name()))
+            }
+            None => false,
+        }
+    }
+}
+
+async fn refuse<R: ResponseHandler>(request: &Request, mut response_handle: R) -> ResponseInfo {
+    let builder = MessageResponseBuilder::from_message_request(request);
+    sent(
+        response_handle
+            .send_response(builder.error_msg(request.header(), ResponseCode::Refused))
+            .await,
+    )
+}
+
+fn sent(result: io::Result<ResponseInfo>) -> ResponseInfo {
+    match result {
+        Ok(info) => info,
+        Err(e) => {
+            tracing::error!("request error: {}", e);
+            let mut header = Header::new();
+            header.set_response_code(ResponseCode::ServFail);
+            header.into()
+        }
+    }
+}
+
+#[async_trait]
+impl RequestHandler for LimitedCatalog {
+    async fn handle_request<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        if request.request_info().query.query_class() == DNSClass::CH {
+            return self.answer_chaos(request, response_handle).await;
+        }
+
+        if self
+            .health_name
+            .as_ref()
+            .is_some_and(|name| name == request.request_info().query.name())
+        {
+            return self.answer_health(request, response_handle).await;
+        }
+
+        if let Some((_, node_id)) = self
+            .instance
+            .as_ref()
+            .filter(|(name, _)| name == request.request_info().query.name())
+        {
+            return self
+                .answer_instance(request, node_id, response_handle)
+                .await;
+        }
+
+        if self.rejects_root(request) || self.rejects_client_subnet(request) {
+            return refuse(request, response_handle).await;
+        }
+
+        let limit = match self.max_udp_size {
+            // never exceed what the client advertised, either.
+            Some(max) if matches!(request.protocol(), Protocol::Udp) => {
+                Some(max.min(request.max_payload()) as usize)
+            }
+            _ => None,
+        };
+
+        // RFC 7828 only allows the option over TCP, in answer to a query with an OPT record.
+        let keepalive = self
+            .tcp_keepalive
+            .filter(|_| matches!(request.protocol(), Protocol::Tcp) && request.edns().is_some())
+            .map(keepalive_option);
+
+        let presync = self
+            .presync
+            .as_ref()
+            .is_some_and(|presync| presync.pending(request.request_info().query.name()));
+
+        if limit.is_none() && keepalive.is_none() && !presync {
+            return self.catalog.handle_request(request, response_handle).await;
+        }
+
+        self.catalog
+            .handle_request(
+                request,
+                LimitedResponseHandle {
+                    inner: response_handle,
+                    limit,
+                    truncation: self.truncation,
+                    keepalive,
+                    presync,
+                },
+            )
+            .await
+    }
+}
+
+#[derive(Clone)]
+struct LimitedResponseHandle<R: ResponseHandler> {
+    inner: R,
+    limit: Option<usize>,
+    truncation: Truncation,
+    keepalive: Option<EdnsOption>,
+    // answer SERVFAIL rather than NXDOMAIN.
+    presync: bool,
+}
+
+#[async_trait]
+impl<R: ResponseHandler> ResponseHandler for LimitedResponseHandle<R> {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        fn to_io(e: impl std::fmt::Display) -> io::Error {
+            io::Error::other(e.to_string())
+        }
+
+        // the response can only be inspected once encoded; decode it, fit it to the limit and
+        // hand the result to the real handle.
+        let mut buffer = Vec::with_capacity(512);
+        response
+            .destructive_emit(&mut BinEncoder::new(&mut buffer))
+            .map_err(to_io)?;
+
+        let mut message = Message::from_vec(&buffer).map_err(to_io)?;
+        if self.presync && message.response_code() == ResponseCode::NXDomain {
+            message.set_response_code(ResponseCode::ServFail);
+            message.take_name_servers();
+        }
+
+        if let (Some(option), Some(edns)) = (&self.keepalive, message.extensions_mut()) {
+            edns.options_mut().insert(option.clone());
+        }
+
+        if let Some(limit) = self.limit {
+            message = limit_message(message, limit, self.truncation).map_err(to_io)?;
+        }
+        let limited =
+            MessageRequest::from_bytes(&message.to_vec().map_err(to_io)?).map_err(to_io)?;
+
+        let mut builder = MessageResponseBuilder::from_message_request(&limited);
+        if let Some(edns) = limited.edns() {
+            builder.edns(edns.clone());
+        }
+
+        self.inner
+            .send_response(builder.build(
+                *limited.header(),
+                limited.answers(),
+                limited.name_servers(),
+                &[],
+                limited.additionals(),
+            ))
+            .await
+    }
 }
@@ -4,6 +4,7 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::anyhow;
+use clap::Parser;
 use regex::Regex;
 use serde::Serialize;
 use tinytemplate::TinyTemplate;
@@ -13,7 +14,7 @@ use trust_dns_resolver::Name;
 use std::os::unix::fs::PermissionsExt;
 
 use crate::{
-    cli::{StartArgs, UnsuperviseArgs},
+    cli::{Cli, Command, StartArgs, UnsuperviseArgs},
     init::{ConfigFormat, Launcher},
 };
 
@@ -40,7 +41,7 @@ After=zerotier-one.service
 
 [Service]
 Type=simple
-ExecStart={binpath} start -t {launcher.token} {{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ if launcher.hosts }}-f {launcher.hosts} {{ endif }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{launcher.network_id}
+ExecStart={binpath} start {{ if launcher.token }}-t {launcher.token} {{ endif }}{{ if launcher.token_keyring }}--token-keyring {launcher.token_keyring} {{ endif }}{{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ if launcher.hosts }}-f {launcher.hosts} {{ endif }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{launcher.network_id}
 TimeoutStopSec=30
 Restart=always
 
@@ -61,7 +62,7 @@ depend() \{
 
 description="zeronsd for network {launcher.network_id}"
 command="{binpath}"
-command_args="start -t {launcher.token} {{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ if launcher.hosts }}-f {launcher.hosts} {{ endif }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{launcher.network_id}"
+command_args="start {{ if launcher.token }}-t {launcher.token} {{ endif }}{{ if launcher.token_keyring }}--token-keyring {launcher.token_keyring} {{ endif }}{{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ if launcher.hosts }}-f {launcher.hosts} {{ endif }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{launcher.network_id}"
 command_background="yes"
 pidfile="/run/$RC_SVCNAME.pid"
 "#;
@@ -84,8 +85,14 @@ const SERVICE_TEMPLATE: &str = r#"
     <array>
       <string>{binpath}</string>
       <string>start</string>
+      {{ if launcher.token }}
       <string>-t</string>
       <string>{launcher.token}</string>
+      {{ endif }}
+      {{ if launcher.token_keyring }}
+      <string>--token-keyring</string>
+      <string>{launcher.token_keyring}</string>
+      {{ endif }}
       {{ if launcher.wildcard }}
       <string>-w</string>
       {{endif}}
@@ -125,6 +132,63 @@ const SERVICE_TEMPLATE: &str = r#"
     </plist>
 "#;
 
+// check_file ensures path is a regular file and returns its canonical form. When lenient, a path
+// that cannot be checked is returned unchanged.
+fn check_file(path: PathBuf, kind: &str, lenient: bool) -> Result<PathBuf, anyhow::Error> {
+    let stat = match std::fs::metadata(&path) {
+        Ok(stat) => stat,
+        Err(_) if lenient => return Ok(path),
+        Err(e) => {
+            return Err(anyhow!(
+                "Could not stat {} file {}: {}",
+                kind,
+                path.display(),
+                e
+            ))
+        }
+    };
+
+    if !stat.is_file() {
+        if lenient {
+            return Ok(path);
+        }
+
+        return Err(anyhow!("{} file {} is not a file", kind, path.display()));
+    }
+
+    Ok(path.canonicalize()?)
+}
+
+// check_carried refuses start flags the service definition has no place for, instead of dropping
+// them from the unit; those settings belong in the config file given with -c.
+fn check_carried(args: &StartArgs) -> Result<(), anyhow::Error> {
+    let mut carried = match Cli::try_parse_from(["zeronsd", "start", &args.network_id])?.command {
+        Command::Start(start) => start,
+        _ => unreachable!("parsed as start"),
+    };
+    carried.domain = args.domain.clone();
+    carried.hosts = args.hosts.clone();
+    carried.secret = args.secret.clone();
+    carried.token = args.token.clone();
+    carried.token_keyring = args.token_keyring.clone();
+    carried.wildcard = args.wildcard;
+
+    // the config file is read by start itself, so only the flags are compared.
+    let settings = |args: &StartArgs| -> Result<serde_json::Value, anyhow::Error> {
+        let args = StartArgs {
+            config: None,
+            ..args.clone()
+        };
+        Ok(serde_json::to_value(Launcher::try_from(args)?)?)
+    };
+
+    if settings(&carried)? != settings(args)? {
+        return Err(anyhow!("zeronsd supervise only carries -t, --token-keyring, -c, --config-type, -w, -s, -f and -d over to the service; put the other settings in a config file and pass it with -c"));
+    }
+
+    Ok(())
+}
+
 #[derive(Serialize)]
 pub struct Properties {
     pub launcher: Launcher,
@@ -133,14 +197,19 @@ pub struct Properties {
     pub config_type: ConfigFormat,
     pub config_type_supplied: bool,
     pub distro: Option<String>,
+    // when set, the unit is installed to and removed from this directory instead of the system's.
+    pub unit_dir: Option<PathBuf>,
 }
 
-impl From<StartArgs> for Properties {
-    fn from(args: StartArgs) -> Self {
-        let launcher: crate::init::Launcher = args.clone().into();
+impl TryFrom<StartArgs> for Properties {
+    type Error = anyhow::Error;
+
+    fn try_from(args: StartArgs) -> Result<Self, Self::Error> {
+        check_carried(&args)?;
+        let launcher = Launcher::try_from(args.clone())?;
 
         // FIXME rewrite this to use a struct init later
-        Self::new(launcher, args.config.as_deref(), args.config_type).unwrap()
+        Self::new(launcher, args.config.as_deref(), args.config_type)
     }
 }
 
@@ -151,7 +220,10 @@ impl From<UnsuperviseArgs> for Properties {
             ..Default::default()
         };
 
-        Self::new(l, None, ConfigFormat::YAML).unwrap()
+        Self {
+            unit_dir: args.unit_dir,
+            ..Self::new(l, None, ConfigFormat::YAML).unwrap()
+        }
     }
 }
 
@@ -164,6 +236,7 @@ impl Default for Properties {
             config_type: ConfigFormat::YAML,
             config_type_supplied: false,
             distro: None,
+            unit_dir: None,
         }
     }
 }
@@ -197,68 +270,57 @@ impl Properties {
             config_type_supplied: config_type != ConfigFormat::YAML,
             config: config.map(|config| config.to_owned()),
             launcher,
+            unit_dir: None,
         })
     }
 
     pub fn validate(&mut self) -> Result<(), anyhow::Error> {
+        self.validate_with(false)
+    }
+
+    // validate_with checks the properties before a template is rendered. When lenient, files that
+    // are missing are not an error and their paths are used as given, so a template can be
+    // printed on a machine other than the one it is meant for.
+    pub fn validate_with(&mut self, lenient: bool) -> Result<(), anyhow::Error> {
         self.config = match self.config.clone() {
             Some(config) => match config.canonicalize() {
                 Ok(res) => Some(res),
+                Err(_) if lenient => Some(config),
                 Err(e) => return Err(anyhow!("Could not find token file: {}", e)),
             },
             None => None,
         };
 
-        let token = self
+        let network_id = self
             .launcher
-            .token
+            .network_id
             .clone()
-            .expect("Could not find token file: {}")
-            .canonicalize()?;
-
-        let tstat = match std::fs::metadata(token.clone()) {
-            Ok(ts) => ts,
-            Err(e) => {
-                return Err(anyhow!(
-                    "Could not stat token file {}: {}",
-                    token.display(),
-                    e
-                ))
-            }
-        };
+            .expect("network_id is not provided");
 
-        if !tstat.is_file() {
-            return Err(anyhow!("Token file {} is not a file", token.display()));
+        // the same file start reads: a per-network token wins over the global one.
+        let token = self
+            .launcher
+            .tokens
+            .get(&network_id)
+            .cloned()
+            .or_else(|| self.launcher.token.clone());
+
+        match token {
+            Some(token) => {
+                check_file(token, "token", lenient)?;
+            }
+            // start reads the token from the keyring service instead.
+            None if self.launcher.token_keyring.is_some() => {}
+            None if lenient => {}
+            None => return Err(anyhow!("Could not find token file")),
         }
 
-        if self
-            .launcher
-            .network_id
-            .clone()
-            .expect("network_id is not provided")
-            .len()
-            != 16
-        {
+        if network_id.len() != 16 {
             return Err(anyhow!("Network ID must be 16 characters"));
         }
 
         if let Some(hosts_file) = self.launcher.hosts.clone() {
-            let hstat = match std::fs::metadata(hosts_file.clone()) {
-                Ok(hs) => hs,
-                Err(e) => {
-                    return Err(anyhow!(
-                        "Could not stat hosts file {}: {}",
-                        hosts_file.display(),
-                        e
-                    ))
-                }
-            };
-
-            if !hstat.is_file() {
-                return Err(anyhow!("Hosts file {} is not a file", hosts_file.display()));
-            }
-
-            self.launcher.hosts = Some(hosts_file.canonicalize()?);
+            self.launcher.hosts = Some(check_file(hosts_file, "hosts", lenient)?);
         }
 
         if let Some(domain) = self.launcher.domain.clone() {
@@ -272,30 +334,18 @@ impl Properties {
         }
 
         if let Some(authtoken) = self.launcher.secret.clone() {
-            let hstat = match std::fs::metadata(authtoken.clone()) {
-                Ok(hs) => hs,
-                Err(e) => {
-                    return Err(anyhow!(
-                        "Could not stat authtoken file {}: {}",
-                        authtoken.display(),
-                        e
-                    ))
-                }
-            };
-
-            if !hstat.is_file() {
-                return Err(anyhow!(
-                    "launcher.secret file {} is not a file",
-                    authtoken.display()
-                ));
-            }
-
-            self.launcher.secret = Some(authtoken.canonicalize()?);
+            self.launcher.secret = Some(check_file(authtoken, "authtoken", lenient)?);
         }
 
         Ok(())
     }
 
+    // print_supervisor renders the template for this platform without installing it.
+    pub fn print_supervisor(&mut self) -> Result<String, anyhow::Error> {
+        self.validate_with(true)?;
+        self.supervise_template()
+    }
+
     pub fn supervise_template(&self) -> Result<String, anyhow::Error> {
         let template = self.get_service_template();
 
@@ -355,6 +405,10 @@ impl Properties {
     }
 
     fn service_path(&self) -> PathBuf {
+        if let Some(dir) = &self.unit_dir {
+            return dir.join(self.service_name());
+        }
+
         let dir = match self.distro.as_deref() {
             Some("alpine") => ALPINE_INIT_DIR,
             _ => SUPERVISE_SYSTEM_DIR,
@@ -362,6 +416,49 @@ impl Properties {
         PathBuf::from(dir).join(self.service_name())
     }
 
+    // install_help is what to run after the unit is written to service_path. A unit outside the
+    // system's directory may not be found by name, so how to link it in is mentioned too.
+    pub fn install_help(&self, service_path: &Path) -> String {
+        let network = self
+            .launcher
+            .network_id
+            .clone()
+            .expect("network_id missing");
+
+        match self.distro.as_deref() {
+            Some("alpine") => {
+                let help = format!(
+                    "Don't forget to `rc-update add zeronsd-{}` and `rc-service zeronsd-{} start`",
+                    network, network
+                );
+                match &self.unit_dir {
+                    Some(_) => format!(
+                        "{}.\nIf {} is not in {}, link it there first.",
+                        help,
+                        service_path.display(),
+                        ALPINE_INIT_DIR
+                    ),
+                    None => help,
+                }
+            }
+            _ => {
+                let help = format!("Don't forget to `systemctl daemon-reload`, `systemctl enable zeronsd-{}` and `systemctl start zeronsd-{}`.", network, network);
+                match &self.unit_dir {
+                    Some(_) => format!(
+                        "{}\nIf systemd does not load units from {}, run `systemctl link {}` first.",
+                        help,
+                        service_path
+                            .parent()
+                            .map(Path::display)
+                            .expect("service path has no directory"),
+                        service_path.display()
+                    ),
+                    None => help,
+                }
+            }
+        }
+    }
+
     pub fn install_supervisor(&mut self) -> Result<(), anyhow::Error> {
         self.validate()?;
 
@@ -392,21 +489,7 @@ impl Properties {
                 std::fs::set_permissions(service_path.clone(), perms)?;
             }
 
-            let network = self
-                .launcher
-                .network_id
-                .clone()
-                .expect("network_id missing");
-            let systemd_help = format!("Don't forget to `systemctl daemon-reload`, `systemctl enable zeronsd-{}` and `systemctl start zeronsd-{}`.", network, network);
-            let alpine_help = format!(
-                "Don't forget to `rc-update add zeronsd-{}` and `rc-service zeronsd-{} start`",
-                network, network
-            );
-
-            let help = match self.distro.as_deref() {
-                Some("alpine") => alpine_help,
-                _ => systemd_help,
-            };
+            let help = self.install_help(&service_path);
 
             eprintln!(
                 "Service definition written to {}.\n{}",
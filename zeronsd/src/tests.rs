@@ -1,18 +1,161 @@
 use std::{
-    net::IpAddr,
+    io::Write,
+    net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
     str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
+use trust_dns_resolver::{
+    proto::{
+        op::{Message, Query},
+        rr::RecordType,
+    },
+    Name,
+};
+use trust_dns_server::client::rr::LowerName;
+
+use crate::authority::{RecordAuthority, ZTAuthority};
+use crate::server::Server;
 use crate::traits::ToHostname;
 use crate::utils::domain_or_default;
 
+// test_zt is an authority for home.arpa., without reverse zones, on a Central nobody answers at.
+async fn test_zt() -> ZTAuthority {
+    test_zt_reverse(&[]).await
+}
+
+// test_zt_reverse is test_zt with a reverse zone for each of cidrs.
+async fn test_zt_reverse(cidrs: &[&str]) -> ZTAuthority {
+    use crate::traits::ToPointerSOA;
+    use ipnetwork::IpNetwork;
+
+    let member = LowerName::from_str("zt-foo.home.arpa.").unwrap();
+    let forward = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        member.clone(),
+        60,
+    )
+    .await
+    .unwrap();
+
+    let mut reverse_authority_map = std::collections::HashMap::new();
+    for cidr in cidrs {
+        let cidr = IpNetwork::from_str(cidr).unwrap();
+        reverse_authority_map.insert(
+            cidr,
+            RecordAuthority::new(cidr.to_ptr_soa_name().unwrap(), member.clone(), 60)
+                .await
+                .unwrap(),
+        );
+    }
+
+    ZTAuthority::new(
+        "1234567891011121".to_string(),
+        zerotier_api::central_api::Client::new("http://127.0.0.1:9"),
+        forward,
+        reverse_authority_map,
+    )
+}
+
+// free_port is an address on 127.0.0.1 that nothing listens on right now.
+async fn free_port() -> SocketAddr {
+    let probe = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    probe.local_addr().unwrap()
+}
+
+// serve serves zt on a free port with the given TCP timeout, and returns its address.
+async fn serve(zt: ZTAuthority, timeout: Duration) -> SocketAddr {
+    let sa = free_port().await;
+    let sf = Server::new(zt).bind(sa, timeout, None).await.unwrap();
+    tokio::spawn(Server::serve(sf));
+    sa
+}
+
+// fake_central stands in for Central or zerotier-one on a free port, and returns its URL. handler
+// gets each request as it was sent, and answers with a status, such as "200 OK", and a JSON body;
+// without an answer, the connection is held open.
+pub(crate) async fn fake_central<F>(mut handler: F) -> String
+where
+    F: FnMut(&str) -> Option<(&'static str, String)> + Send + 'static,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((mut conn, _)) = listener.accept().await {
+            let mut buf = vec![0; 65535];
+            let len = conn.read(&mut buf).await.unwrap_or_default();
+            let request = String::from_utf8_lossy(&buf[..len]).to_string();
+
+            match handler(&request) {
+                Some((status, body)) => {
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = conn.write_all(response.as_bytes()).await;
+                }
+                None => held.push(conn),
+            }
+        }
+    });
+
+    format!("http://{}", addr)
+}
+
+// udp_query sends query to sa over UDP and returns the answer.
+async fn udp_query(sa: SocketAddr, query: &Message) -> Message {
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.send_to(&query.to_vec().unwrap(), sa).await.unwrap();
+
+    let mut buf = vec![0; 65535];
+    let len = tokio::time::timeout(Duration::new(5, 0), socket.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    Message::from_vec(&buf[..len]).unwrap()
+}
+
+// query asks sa for name over UDP.
+async fn query(sa: SocketAddr, name: &str, rtype: RecordType) -> Message {
+    let mut query = Message::new();
+    query.add_query(Query::query(Name::from_str(name).unwrap(), rtype));
+    udp_query(sa, &query).await
+}
+
+// Capture collects what a log subscriber writes to it.
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    fn output(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 #[test]
 fn test_parse_member_name() {
     use crate::utils::parse_member_name;
 
     let actual_domains: &mut Vec<Option<&str>> =
-        &mut vec!["tld", "domain", "zerotier", "test.subdomain"]
+        &mut ["tld", "domain", "zerotier", "test.subdomain"]
             .iter()
             .map(|s| Some(*s))
             .collect::<Vec<Option<&str>>>();
@@ -22,11 +165,11 @@ fn test_parse_member_name() {
     for domain in actual_domains {
         let domain_name = domain_or_default(*domain).unwrap().clone();
 
-        assert_eq!(parse_member_name(None, domain_name.clone()), None);
+        assert_eq!(parse_member_name(None, domain_name.clone(), 0, false), None);
 
         for name in ["islay", "ALL-CAPS", "Capitalized", "with.dots"] {
             assert_eq!(
-                parse_member_name(Some(name.to_string()), domain_name.clone()),
+                parse_member_name(Some(name.to_string()), domain_name.clone(), 0, false),
                 Some(name.to_fqdn(domain_name.clone()).unwrap()),
                 "{}",
                 name,
@@ -35,7 +178,7 @@ fn test_parse_member_name() {
 
         for bad_name in [".", "!", "arghle."] {
             assert_eq!(
-                parse_member_name(Some(bad_name.to_string()), domain_name.clone()),
+                parse_member_name(Some(bad_name.to_string()), domain_name.clone(), 0, false),
                 None,
                 "{}",
                 bad_name,
@@ -44,7 +187,7 @@ fn test_parse_member_name() {
 
         for (orig, translated) in [("Erik's laptop", "eriks-laptop"), ("!foo", "foo")] {
             assert_eq!(
-                parse_member_name(Some(orig.to_string()), domain_name.clone()),
+                parse_member_name(Some(orig.to_string()), domain_name.clone(), 0, false),
                 Some(translated.to_fqdn(domain_name.clone()).unwrap()),
                 "{}",
                 orig,
@@ -53,6 +196,79 @@ fn test_parse_member_name() {
     }
 }
 
+#[test]
+fn test_parse_member_name_min_len() {
+    use crate::utils::parse_member_name;
+
+    let domain_name = domain_or_default(None).unwrap();
+
+    // "!a" sanitizes to a single character
+    assert_eq!(
+        parse_member_name(Some("!a".to_string()), domain_name.clone(), 0, false),
+        Some("a".to_fqdn(domain_name.clone()).unwrap())
+    );
+    assert_eq!(
+        parse_member_name(Some("!a".to_string()), domain_name.clone(), 2, false),
+        None
+    );
+    // every label counts, not only the first
+    assert_eq!(
+        parse_member_name(Some("islay.b".to_string()), domain_name.clone(), 2, false),
+        None
+    );
+    assert_eq!(
+        parse_member_name(Some("islay".to_string()), domain_name.clone(), 2, false),
+        Some("islay".to_fqdn(domain_name).unwrap())
+    );
+}
+
+#[test]
+fn test_parse_member_name_truncate() {
+    use crate::utils::parse_member_name;
+    use trust_dns_server::client::rr::Name;
+
+    let domain_name = Name::from_str("home.arpa.").unwrap();
+    let long = "a".repeat(70);
+    let other = "a".repeat(60) + "bbbbbbbbbb";
+
+    // skipped unless truncation is on
+    assert_eq!(
+        parse_member_name(Some(long.clone()), domain_name.clone(), 0, false),
+        None
+    );
+
+    let name = parse_member_name(Some(long.clone()), domain_name.clone(), 0, true).unwrap();
+    let label = String::from_utf8(name.iter().next().unwrap().to_vec()).unwrap();
+    assert_eq!(label.len(), 63);
+    assert!(label.starts_with(&"a".repeat(54)), "{}", label);
+    assert!(domain_name.zone_of(&name));
+
+    // the same name truncates the same way, a name differing after the cut does not
+    assert_eq!(
+        parse_member_name(Some(long), domain_name.clone(), 0, true),
+        Some(name.clone())
+    );
+    assert_ne!(
+        parse_member_name(Some(other), domain_name.clone(), 0, true),
+        Some(name)
+    );
+
+    // only the long label is touched
+    let name = parse_member_name(
+        Some(format!("islay.{}", "b".repeat(64))),
+        domain_name.clone(),
+        0,
+        true,
+    )
+    .unwrap();
+    assert_eq!(name.iter().next().unwrap(), b"islay");
+    assert_eq!(name.num_labels(), 4);
+
+    // a name that is too long as a whole is skipped rather than entered
+    let labels = vec!["c".repeat(63); 4].join(".");
+    assert_eq!(parse_member_name(Some(labels), domain_name, 0, true), None);
+}
+
 #[test]
 fn test_parse_ip_from_cidr() {
     use crate::utils::parse_ip_from_cidr;
@@ -71,45 +287,2622 @@ fn test_parse_ip_from_cidr() {
             cidr
         );
     }
-}
+}
+
+#[test]
+fn test_domain_or_default() {
+    use crate::utils::{domain_or_default, DEFAULT_DOMAIN_NAME};
+    use trust_dns_server::client::rr::Name;
+
+    assert_eq!(
+        domain_or_default(None).unwrap(),
+        Name::from_str(DEFAULT_DOMAIN_NAME).unwrap()
+    );
+
+    assert_eq!(
+        domain_or_default(Some("zerotier")).unwrap(),
+        Name::from_str("zerotier").unwrap()
+    );
+
+    assert_eq!(
+        domain_or_default(Some("zerotier.tld")).unwrap(),
+        Name::from_str("zerotier.tld").unwrap()
+    );
+
+    assert_eq!(
+        domain_or_default(Some("corp.example.internal")).unwrap(),
+        Name::from_str("corp.example.internal.").unwrap()
+    );
+
+    // a trailing dot is optional
+    for (dotted, plain) in [
+        ("home.arpa.", "home.arpa"),
+        ("zerotier.", "zerotier"),
+        ("corp.example.internal.", "corp.example.internal"),
+    ] {
+        let name = domain_or_default(Some(dotted)).unwrap();
+        assert_eq!(name, domain_or_default(Some(plain)).unwrap());
+        assert!(name.is_fqdn());
+        assert_eq!(name.to_string(), format!("{}.", plain));
+    }
+
+    for bad in ["~", "!", ".", "", "..", "bad.."] {
+        assert!(domain_or_default(Some(bad)).is_err(), "{}", bad);
+    }
+
+    for (bad, msg) in [
+        (".", "root"),
+        ("corp..internal", "empty label"),
+        (".internal", "empty label"),
+        ("corp!.internal", "contains '!'"),
+        ("-corp.internal", "hyphen"),
+        (&"a".repeat(64), "longer than 63"),
+    ] {
+        let err = domain_or_default(Some(bad)).unwrap_err().to_string();
+        assert!(err.contains(msg), "{}: {}", bad, err);
+    }
+}
+
+#[test]
+fn test_long_version() {
+    use crate::cli::Cli;
+    use crate::utils::{GIT_SHA, LONG_VERSION, VERSION_STRING};
+    use clap::{ErrorKind, Parser};
+
+    assert!(!GIT_SHA.is_empty());
+    assert_eq!(
+        LONG_VERSION,
+        format!("{} (git {})", VERSION_STRING, GIT_SHA)
+    );
+
+    let err = Cli::try_parse_from(["zeronsd", "--version"]).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::DisplayVersion);
+    assert_eq!(err.to_string(), format!("zeronsd {}\n", LONG_VERSION));
+
+    // -V stays the short form
+    let err = Cli::try_parse_from(["zeronsd", "-V"]).err().unwrap();
+    assert_eq!(err.to_string(), format!("zeronsd {}\n", VERSION_STRING));
+}
+
+#[test]
+fn test_decode_member() {
+    use crate::addresses::decode_member;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    assert_eq!(
+        decode_member("zt-abcdef0123.home.arpa.", Some("1234567891011121")).unwrap(),
+        "node id: abcdef0123\n6plane:  fc83:3547:59ab:cdef:123::1\nrfc4193: fd12:3456:7891:111:2199:93ab:cdef:123\n"
+    );
+    // without a network only the node id is known
+    assert_eq!(
+        decode_member("ZT-ABCDEF0123", None).unwrap(),
+        "node id: abcdef0123\n"
+    );
+
+    for (name, network_id, msg) in [
+        (
+            "islay.home.arpa.",
+            None,
+            "members named in Central cannot be decoded",
+        ),
+        ("zt-abcdef.home.arpa.", None, "10 hex digits"),
+        ("zt-abcdefghij.home.arpa.", None, "10 hex digits"),
+        ("zt-abcdef0123", Some("12345"), "16 hex digits"),
+    ] {
+        let err = decode_member(name, network_id).unwrap_err().to_string();
+        assert!(err.contains(msg), "{}: {}", name, err);
+    }
+
+    let cli = Cli::try_parse_from([
+        "zeronsd",
+        "decode",
+        "zt-abcdef0123",
+        "--network-id",
+        "1234567891011121",
+    ])
+    .unwrap();
+    match cli.command {
+        Command::Decode(args) => {
+            assert_eq!(args.name, "zt-abcdef0123");
+            assert_eq!(args.network_id.as_deref(), Some("1234567891011121"));
+        }
+        _ => panic!("decode was not parsed"),
+    }
+}
+
+#[test]
+fn test_config_schema() {
+    use crate::init::Launcher;
+
+    let schema: serde_json::Value = serde_json::from_str(&Launcher::schema().unwrap()).unwrap();
+
+    assert_eq!(
+        schema["properties"]["wildcard"]["type"],
+        serde_json::json!("boolean")
+    );
+    assert_eq!(
+        schema["definitions"]["LevelFilter"]["enum"],
+        serde_json::json!(["off", "error", "warn", "info", "trace", "debug"])
+    );
+    assert!(schema["properties"].get("network_id").is_none());
+}
+
+#[test]
+fn test_check_public_domain() {
+    use crate::utils::{check_public_domain, domain_or_default};
+
+    for public in ["com", "CO.UK", "arpa"] {
+        let domain = domain_or_default(Some(public)).unwrap();
+        assert!(check_public_domain(&domain, false).is_err(), "{}", public);
+        assert!(check_public_domain(&domain, true).is_ok(), "{}", public);
+    }
+
+    for private in [
+        None,
+        Some("internal"),
+        Some("example.com"),
+        Some("zerotier"),
+    ] {
+        let domain = domain_or_default(private).unwrap();
+        assert!(check_public_domain(&domain, false).is_ok(), "{:?}", private);
+    }
+}
+
+#[test]
+fn test_central_token() {
+    use crate::utils::central_token;
+
+    assert!(central_token(None).is_err());
+    std::env::set_var("ZEROTIER_CENTRAL_TOKEN", "abcdef");
+    assert_eq!(central_token(None).unwrap(), "abcdef");
+
+    let hosts = std::fs::read_to_string("/etc/hosts").unwrap();
+    let token = central_token(Some(Path::new("/etc/hosts")));
+    assert!(token.is_ok());
+    assert_eq!(token.unwrap(), hosts.trim());
+}
+
+#[test]
+fn test_central_token_for() {
+    use crate::utils::central_token_for;
+    use std::collections::HashMap;
+
+    let dir = std::env::temp_dir().join(format!("zeronsd-tokens-{}", rand::random::<u32>()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut tokens = HashMap::new();
+    for (network, token) in [
+        ("1234567891011121", "first"),
+        ("2234567891011121", "second"),
+    ] {
+        let path = dir.join(network);
+        std::fs::write(&path, format!("{}\n", token)).unwrap();
+        tokens.insert(network.to_string(), path);
+    }
+
+    assert_eq!(
+        central_token_for("1234567891011121", &tokens, None, None).unwrap(),
+        "first"
+    );
+    assert_eq!(
+        central_token_for("2234567891011121", &tokens, None, None).unwrap(),
+        "second"
+    );
+
+    tokens.insert("3234567891011121".to_string(), dir.join("nonexistent"));
+    let err = central_token_for("3234567891011121", &tokens, None, None).unwrap_err();
+    assert!(err.to_string().contains("3234567891011121"), "{}", err);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_central_tokens_sync() {
+    use crate::{
+        authority::{RecordAuthority, ZTAuthority},
+        init::Launcher,
+        supervise::Properties,
+        utils::{central_client_at, central_token_for},
+    };
+    use std::collections::HashMap;
+
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+
+    // a Central holding two networks, each only readable with its own token.
+    async fn central() -> String {
+        fake_central(|request| {
+            let request = request.to_lowercase();
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+            let networks = [
+                ("1234567891011121", "first", "islay", "10.0.0.2"),
+                ("2234567891011121", "second", "jura", "10.0.1.2"),
+            ];
+            let body = networks.iter().find_map(|(id, token, name, ip)| {
+                if !request.contains(&format!("authorization: bearer {}\r\n", token)) {
+                    return None;
+                }
+
+                if path.ends_with(&format!("/network/{}/member", id)) {
+                    Some(serde_json::json!([{
+                        "nodeId": "abcdef0123",
+                        "networkId": id,
+                        "name": name,
+                        "config": { "ipAssignments": [ip], "authorized": true },
+                    }]))
+                } else if path.ends_with(&format!("/network/{}", id)) {
+                    Some(serde_json::json!({"id": id, "config": {}}))
+                } else {
+                    None
+                }
+            });
+
+            Some(match body {
+                Some(body) => ("200 OK", body.to_string()),
+                None => ("401 Unauthorized", String::new()),
+            })
+        })
+        .await
+    }
+
+    let dir = std::env::temp_dir().join(format!("zeronsd-tokens-{}", rand::random::<u32>()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut tokens = HashMap::new();
+    for (network, token) in [
+        ("1234567891011121", "first"),
+        ("2234567891011121", "second"),
+    ] {
+        let path = dir.join(network);
+        std::fs::write(&path, format!("{}\n", token)).unwrap();
+        tokens.insert(network.to_string(), path);
+    }
+    // the global token is good for neither network
+    let global = dir.join("global");
+    std::fs::write(&global, "global\n").unwrap();
+
+    let url = central().await;
+    for (network, name) in [
+        ("1234567891011121", "islay.home.arpa."),
+        ("2234567891011121", "jura.home.arpa."),
+    ] {
+        let token = central_token_for(network, &tokens, Some(&global), None).unwrap();
+        let forward = RecordAuthority::new(
+            LowerName::from_str("home.arpa.").unwrap(),
+            LowerName::from_str("zt-foo.home.arpa.").unwrap(),
+            60,
+        )
+        .await
+        .unwrap();
+        let zt = ZTAuthority::new(
+            network.to_string(),
+            central_client_at(&url, token, false).unwrap(),
+            forward,
+            HashMap::new(),
+        );
+
+        zt.sync_members().await.unwrap();
+        assert!(
+            zt.forward_authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok(),
+            "{}",
+            network
+        );
+    }
+
+    // supervise checks the file start reads: the per-network one, even with a global one set
+    let mut props = Properties {
+        launcher: Launcher {
+            network_id: Some("1234567891011121".to_string()),
+            token: Some(dir.join("nonexistent")),
+            tokens: tokens.clone(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    props.validate_with(false).unwrap();
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_central_token_keyring() {
+    use crate::utils::central_token_from;
+
+    let keyring = |service: &str| -> Result<Option<String>, anyhow::Error> {
+        match service {
+            "zeronsd" => Ok(Some("from-keyring\n".to_string())),
+            "broken" => Err(anyhow::anyhow!("secret store is locked")),
+            _ => Ok(None),
+        }
+    };
+
+    assert_eq!(
+        central_token_from(Some("zeronsd"), Some(Path::new("/etc/hosts")), keyring).unwrap(),
+        "from-keyring"
+    );
+
+    // a missing entry falls back to the token file
+    let hosts = std::fs::read_to_string("/etc/hosts").unwrap();
+    assert_eq!(
+        central_token_from(Some("other"), Some(Path::new("/etc/hosts")), keyring).unwrap(),
+        hosts.trim()
+    );
+
+    // other keyring failures are not papered over
+    let err = central_token_from(Some("broken"), None, keyring).unwrap_err();
+    assert!(err.to_string().contains("locked"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_healthy_ips() {
+    use crate::utils::{healthy_ips, listener_healthy};
+    use std::time::Duration;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let healthy = listener.local_addr().unwrap();
+    let unhealthy = {
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        closed.local_addr().unwrap()
+    };
+
+    let timeout = Duration::new(1, 0);
+    assert!(listener_healthy(healthy, timeout).await);
+    assert!(!listener_healthy(unhealthy, timeout).await);
+
+    let ips: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2", "fd00::1"]
+        .iter()
+        .map(|ip| IpAddr::from_str(ip).unwrap())
+        .collect();
+
+    let health = vec![
+        listener_healthy(healthy, timeout).await,
+        listener_healthy(unhealthy, timeout).await,
+        true,
+    ];
+    assert_eq!(healthy_ips(&ips, &health), vec![ips[0], ips[2]]);
+
+    // never advertise an empty list
+    assert_eq!(healthy_ips(&ips, &[false, false, false]), ips);
+    assert_eq!(healthy_ips(&ips[..1], &[false]), vec![ips[0]]);
+}
+
+#[test]
+fn test_graced_health() {
+    use crate::utils::{graced_health, healthy_ips};
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    let ips: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2"]
+        .iter()
+        .map(|ip| IpAddr::from_str(ip).unwrap())
+        .collect();
+    let grace = Duration::new(30, 0);
+    let start = Instant::now();
+    let mut since = HashMap::new();
+
+    // 10.0.0.2 comes up after the initial push; it is held back until it has passed for the grace.
+    let mut advertised = vec![ips[0]];
+    for (secs, expected) in [(0, vec![ips[0]]), (20, vec![ips[0]]), (30, ips.clone())] {
+        let health = graced_health(
+            &ips,
+            &[true, true],
+            &advertised,
+            &mut since,
+            grace,
+            start + Duration::new(secs, 0),
+        );
+        assert_eq!(healthy_ips(&ips, &health), expected, "after {}s", secs);
+    }
+    advertised = ips.clone();
+
+    // a failure withdraws it at once, and it has to earn its way back
+    let health = graced_health(
+        &ips,
+        &[true, false],
+        &advertised,
+        &mut since,
+        grace,
+        start + Duration::new(40, 0),
+    );
+    advertised = healthy_ips(&ips, &health);
+    assert_eq!(advertised, vec![ips[0]]);
+
+    let health = graced_health(
+        &ips,
+        &[true, true],
+        &advertised,
+        &mut since,
+        grace,
+        start + Duration::new(50, 0),
+    );
+    assert_eq!(healthy_ips(&ips, &health), vec![ips[0]]);
+
+    // without a grace, the first passing check is enough
+    let health = graced_health(
+        &ips,
+        &[true, true],
+        &[ips[0]],
+        &mut HashMap::new(),
+        Duration::ZERO,
+        start,
+    );
+    assert_eq!(healthy_ips(&ips, &health), ips);
+}
+
+#[tokio::test]
+async fn test_advertise_when_bound() {
+    use crate::utils::advertise_when_bound;
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let bind = |addr: &'static str| {
+        let events = events.clone();
+        async move {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            events.lock().unwrap().push(format!("bind {}", addr));
+            drop(listener);
+            Ok(())
+        }
+    };
+    let advertise = || {
+        let events = events.clone();
+        async move {
+            events.lock().unwrap().push("advertise".to_string());
+            Ok(())
+        }
+    };
+
+    advertise_when_bound(
+        vec![bind("127.0.0.1:0"), bind("[::1]:0")],
+        async { true },
+        advertise(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["bind 127.0.0.1:0", "bind [::1]:0", "advertise"]
+    );
+
+    // a failed bind never reaches central
+    events.lock().unwrap().clear();
+    assert!(advertise_when_bound(
+        vec![bind("127.0.0.1:0"), bind("192.0.2.1:0")],
+        async { true },
+        advertise(),
+    )
+    .await
+    .is_err());
+    assert_eq!(*events.lock().unwrap(), vec!["bind 127.0.0.1:0"]);
+
+    // neither does a failed self-check
+    events.lock().unwrap().clear();
+    assert!(
+        advertise_when_bound(vec![bind("127.0.0.1:0")], async { false }, advertise())
+            .await
+            .is_err()
+    );
+    assert_eq!(*events.lock().unwrap(), vec!["bind 127.0.0.1:0"]);
+}
+
+#[tokio::test]
+async fn test_forward_metrics() {
+    use crate::{
+        forwarder::{TtlClamp, ZTForwarder},
+        metrics::ForwardMetrics,
+    };
+    use std::time::Duration;
+    use trust_dns_resolver::{
+        config::{NameServerConfigGroup, ResolverOpts},
+        proto::rr::RecordType,
+        Name,
+    };
+    use trust_dns_server::{
+        authority::{Authority, LookupOptions, ZoneType},
+        client::rr::LowerName,
+        store::forwarder::{ForwardAuthority, ForwardConfig},
+    };
+
+    // nothing answers on the discard port, so the query fails quickly without leaving the host.
+    let mut options = ResolverOpts::default();
+    options.timeout = Duration::from_millis(100);
+    options.attempts = 1;
+
+    let forwarder = ForwardAuthority::try_from_config(
+        Name::root(),
+        ZoneType::Primary,
+        &ForwardConfig {
+            name_servers: NameServerConfigGroup::from_ips_clear(
+                &[IpAddr::from_str("127.0.0.1").unwrap()],
+                9,
+                true,
+            ),
+            options: Some(options),
+        },
+    )
+    .unwrap();
+
+    let metrics = ForwardMetrics::default();
+    let metered = ZTForwarder::new(forwarder, metrics.clone(), None, None, TtlClamp::default());
+
+    assert_eq!(metered.origin(), &LowerName::from(Name::root()));
+    assert_eq!(metrics.queries(), 0);
+
+    let res = metered
+        .lookup(
+            &LowerName::from_str("example.com.").unwrap(),
+            RecordType::A,
+            LookupOptions::default(),
+        )
+        .await;
+
+    assert!(res.is_err());
+    assert_eq!(metrics.queries(), 1);
+    assert_eq!(metrics.errors(), 1);
+    assert!(metrics.max_latency_micros() >= metrics.average_latency_micros());
+}
+
+#[tokio::test]
+async fn test_forward_stale() {
+    use crate::{
+        forwarder::{StaleCache, TtlClamp, ZTForwarder},
+        metrics::ForwardMetrics,
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicU8, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use trust_dns_resolver::{
+        config::{NameServerConfigGroup, ResolverOpts},
+        proto::{
+            op::{Message, MessageType},
+            rr::{RData, Record, RecordType},
+        },
+        Name,
+    };
+    use trust_dns_server::{
+        authority::{Authority, LookupObject, LookupOptions, ZoneType},
+        client::rr::LowerName,
+        store::forwarder::{ForwardAuthority, ForwardConfig},
+    };
+
+    // an upstream that answers every A query with 192.0.2.<up> and a one second TTL, and drops
+    // queries while up is 0.
+    let up = Arc::new(AtomicU8::new(10));
+    let udp = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let upstream = udp.local_addr().unwrap();
+    let u = up.clone();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 65535];
+        while let Ok((len, src)) = udp.recv_from(&mut buf).await {
+            let octet = u.load(Ordering::SeqCst);
+            if octet == 0 {
+                continue;
+            }
+
+            let query = Message::from_vec(&buf[..len]).unwrap();
+            let mut response = query.clone();
+            response.set_message_type(MessageType::Response);
+            response.set_recursion_available(true);
+            response.add_answer(Record::from_rdata(
+                query.queries()[0].name().clone(),
+                1,
+                RData::A([192, 0, 2, octet].into()),
+            ));
+            udp.send_to(&response.to_vec().unwrap(), src).await.unwrap();
+        }
+    });
+
+    let mut options = ResolverOpts::default();
+    options.timeout = Duration::from_millis(200);
+    options.attempts = 1;
+    options.cache_size = 0;
+
+    let forwarder = |max_stale: Duration| {
+        Arc::new(ZTForwarder::new(
+            ForwardAuthority::try_from_config(
+                Name::root(),
+                ZoneType::Primary,
+                &ForwardConfig {
+                    name_servers: NameServerConfigGroup::from_ips_clear(
+                        &[upstream.ip()],
+                        upstream.port(),
+                        false,
+                    ),
+                    options: Some(options),
+                },
+            )
+            .unwrap(),
+            ForwardMetrics::default(),
+            None,
+            Some(StaleCache::new(max_stale)),
+            TtlClamp::default(),
+        ))
+    };
+
+    let lookup = |zt: Arc<ZTForwarder>, name: &str| {
+        let name = LowerName::from_str(name).unwrap();
+        async move {
+            zt.lookup(&name, RecordType::A, LookupOptions::default())
+                .await
+                .map(|l| {
+                    l.iter()
+                        .map(|r| (r.data().unwrap().to_string(), r.ttl()))
+                        .collect::<Vec<(String, u32)>>()
+                })
+        }
+    };
+
+    let long = forwarder(Duration::new(60, 0));
+    let short = forwarder(Duration::from_millis(500));
+    for zt in [long.clone(), short.clone()] {
+        assert_eq!(
+            lookup(zt, "www.example.com.").await.unwrap(),
+            vec![("192.0.2.10".to_string(), 1)]
+        );
+    }
+
+    up.store(0, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(1600)).await;
+
+    // expired, but still within the stale window of one of them
+    assert_eq!(
+        lookup(long.clone(), "www.example.com.").await.unwrap(),
+        vec![("192.0.2.10".to_string(), 1)]
+    );
+    assert!(lookup(short, "www.example.com.").await.is_err());
+
+    // names that were never answered are not made up
+    assert!(lookup(long.clone(), "other.example.com.").await.is_err());
+
+    // once upstream is back, the stale answer is served while the refresh picks up the new one
+    up.store(11, Ordering::SeqCst);
+    assert_eq!(
+        lookup(long.clone(), "www.example.com.").await.unwrap(),
+        vec![("192.0.2.10".to_string(), 1)]
+    );
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(
+        lookup(long, "www.example.com.").await.unwrap(),
+        vec![("192.0.2.11".to_string(), 1)]
+    );
+}
+
+#[tokio::test]
+async fn test_forward_ttl_clamp() {
+    use crate::{
+        forwarder::{StaleCache, TtlClamp, ZTForwarder},
+        metrics::ForwardMetrics,
+    };
+    use std::time::Duration;
+    use trust_dns_resolver::{
+        config::{NameServerConfigGroup, ResolverOpts},
+        proto::{
+            op::{Message, MessageType},
+            rr::{RData, Record, RecordType},
+        },
+        Name,
+    };
+    use trust_dns_server::{
+        authority::{Authority, LookupObject, LookupOptions, ZoneType},
+        client::rr::LowerName,
+        store::forwarder::{ForwardAuthority, ForwardConfig},
+    };
+
+    // an upstream that answers low.example.com. with a one second TTL and anything else with a day.
+    let udp = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let upstream = udp.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 65535];
+        while let Ok((len, src)) = udp.recv_from(&mut buf).await {
+            let query = Message::from_vec(&buf[..len]).unwrap();
+            let name = query.queries()[0].name().clone();
+            let ttl = if name.to_string() == "low.example.com." {
+                1
+            } else {
+                86400
+            };
+
+            let mut response = query.clone();
+            response.set_message_type(MessageType::Response);
+            response.set_recursion_available(true);
+            response.add_answer(Record::from_rdata(
+                name,
+                ttl,
+                RData::A([192, 0, 2, 1].into()),
+            ));
+            udp.send_to(&response.to_vec().unwrap(), src).await.unwrap();
+        }
+    });
+
+    let mut options = ResolverOpts::default();
+    options.timeout = Duration::from_millis(200);
+    options.attempts = 1;
+    options.cache_size = 0;
+
+    let forwarder = |ttl_clamp: TtlClamp, stale: Option<StaleCache>| {
+        ZTForwarder::new(
+            ForwardAuthority::try_from_config(
+                Name::root(),
+                ZoneType::Primary,
+                &ForwardConfig {
+                    name_servers: NameServerConfigGroup::from_ips_clear(
+                        &[upstream.ip()],
+                        upstream.port(),
+                        false,
+                    ),
+                    options: Some(options),
+                },
+            )
+            .unwrap(),
+            ForwardMetrics::default(),
+            None,
+            stale,
+            ttl_clamp,
+        )
+    };
+
+    async fn ttls(zt: &ZTForwarder, name: &str) -> Vec<u32> {
+        zt.lookup(
+            &LowerName::from_str(name).unwrap(),
+            RecordType::A,
+            LookupOptions::default(),
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| r.ttl())
+        .collect()
+    }
+
+    let unclamped = forwarder(TtlClamp::default(), None);
+    assert_eq!(ttls(&unclamped, "low.example.com.").await, vec![1]);
+    assert_eq!(ttls(&unclamped, "high.example.com.").await, vec![86400]);
+
+    let clamped = forwarder(
+        TtlClamp {
+            min: Some(300),
+            max: Some(3600),
+        },
+        Some(StaleCache::new(Duration::new(60, 0))),
+    );
+    assert_eq!(ttls(&clamped, "low.example.com.").await, vec![300]);
+    assert_eq!(ttls(&clamped, "high.example.com.").await, vec![3600]);
+
+    // the stale cache keeps the raised TTL: after the upstream TTL has passed, the answer is not
+    // served as expired
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    assert_eq!(ttls(&clamped, "low.example.com.").await, vec![300]);
+}
+
+#[test]
+fn test_statsd_sink() {
+    use crate::metrics::{ForwardMetrics, StatsdSink};
+    use std::{net::UdpSocket, time::Instant};
+
+    let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+    listener
+        .set_read_timeout(Some(std::time::Duration::new(5, 0)))
+        .unwrap();
+
+    let metrics = ForwardMetrics::default();
+    metrics.observe(Instant::now(), true);
+    metrics.observe(Instant::now(), false);
+
+    let sink = StatsdSink::new(listener.local_addr().unwrap()).unwrap();
+    let mut buf = [0; 1500];
+
+    let mut recv = || {
+        let len = listener.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    };
+
+    sink.report(&metrics, true, 5);
+    let packet = recv();
+    let lines: Vec<&str> = packet.lines().collect();
+    assert!(lines.contains(&"zeronsd.forward.queries:2|c"), "{}", packet);
+    assert!(lines.contains(&"zeronsd.forward.errors:1|c"), "{}", packet);
+    assert!(lines.contains(&"zeronsd.sync.errors:1|c"), "{}", packet);
+    assert!(lines.contains(&"zeronsd.records:5|g"), "{}", packet);
+    assert!(lines
+        .iter()
+        .any(|l| l.starts_with("zeronsd.forward.latency_max_us:") && l.ends_with("|g")));
+
+    // counters are sent as the change since the last report
+    metrics.observe(Instant::now(), true);
+    sink.report(&metrics, false, 5);
+    let packet = recv();
+    let lines: Vec<&str> = packet.lines().collect();
+    assert!(lines.contains(&"zeronsd.forward.queries:1|c"), "{}", packet);
+    assert!(lines.contains(&"zeronsd.forward.errors:0|c"), "{}", packet);
+    assert!(lines.contains(&"zeronsd.sync.errors:0|c"), "{}", packet);
+
+    // nothing listening is not an error
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    let sink = StatsdSink::new(addr).unwrap();
+    sink.report(&metrics, false, 5);
+    sink.report(&metrics, false, 5);
+}
+
+#[tokio::test]
+async fn test_blocklist() {
+    use crate::{
+        blocklist::Blocklist,
+        forwarder::{TtlClamp, ZTForwarder},
+        metrics::ForwardMetrics,
+    };
+    use std::{collections::BTreeMap, sync::Arc, time::Duration};
+    use trust_dns_resolver::{
+        config::{NameServerConfigGroup, ResolverOpts},
+        proto::rr::{rdata::SOA, RData, Record, RecordSet, RecordType},
+        Name,
+    };
+    use trust_dns_server::{
+        authority::{Authority, Catalog, LookupObject, LookupOptions, ZoneType},
+        client::rr::{LowerName, RrKey},
+        server::ServerFuture,
+        store::{
+            forwarder::{ForwardAuthority, ForwardConfig},
+            in_memory::InMemoryAuthority,
+        },
+    };
+
+    // a local upstream serving example.com., so nothing leaves the host.
+    let origin = Name::from_str("example.com.").unwrap();
+    let mut records = BTreeMap::new();
+    let mut soa = RecordSet::new(&origin, RecordType::SOA, 1);
+    soa.add_rdata(RData::SOA(SOA::new(
+        origin.clone(),
+        origin.clone(),
+        1,
+        60,
+        60,
+        60,
+        60,
+    )));
+    records.insert(RrKey::new(origin.clone().into(), RecordType::SOA), soa);
+    for name in ["www.example.com.", "ads.example.com."] {
+        let name = Name::from_str(name).unwrap();
+        let mut rs = RecordSet::new(&name, RecordType::A, 1);
+        rs.insert(
+            Record::from_rdata(name.clone(), 60, RData::A("192.0.2.10".parse().unwrap())),
+            1,
+        );
+        records.insert(RrKey::new(name.into(), RecordType::A), rs);
+    }
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(
+        origin.clone().into(),
+        Box::new(Arc::new(
+            InMemoryAuthority::new(origin, records, ZoneType::Primary, false).unwrap(),
+        )),
+    );
+
+    let udp = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let upstream = udp.local_addr().unwrap();
+    let mut sf = ServerFuture::new(catalog);
+    sf.register_socket(udp);
+    tokio::spawn(sf.block_until_done());
+
+    let mut options = ResolverOpts::default();
+    options.timeout = Duration::from_millis(500);
+    options.cache_size = 0;
+
+    let forwarder = || {
+        ForwardAuthority::try_from_config(
+            Name::root(),
+            ZoneType::Primary,
+            &ForwardConfig {
+                name_servers: NameServerConfigGroup::from_ips_clear(
+                    &[upstream.ip()],
+                    upstream.port(),
+                    false,
+                ),
+                options: Some(options),
+            },
+        )
+        .unwrap()
+    };
+
+    let dir = std::env::temp_dir().join(format!("zeronsd-blocklist-{}", rand::random::<u32>()));
+    // comments and invalid names are skipped, not fatal
+    std::fs::write(
+        &dir,
+        format!(
+            "# ads\n0.0.0.0 ads.example.com # tracking\nmalware.test\n{}.test\n",
+            "a".repeat(64)
+        ),
+    )
+    .unwrap();
+
+    let lookup = |zt: Arc<ZTForwarder>, name: &str| {
+        let name = LowerName::from_str(name).unwrap();
+        async move {
+            zt.lookup(&name, RecordType::A, LookupOptions::default())
+                .await
+                .map(|l| {
+                    l.iter()
+                        .map(|r| r.data().unwrap().to_string())
+                        .collect::<Vec<String>>()
+                })
+        }
+    };
+
+    let blocklist = Blocklist::from_file(dir.clone(), vec![]).unwrap();
+    assert_eq!(blocklist.len(), 2);
+
+    let zt = Arc::new(ZTForwarder::new(
+        forwarder(),
+        ForwardMetrics::default(),
+        Some(blocklist),
+        None,
+        TtlClamp::default(),
+    ));
+
+    assert_eq!(
+        lookup(zt.clone(), "www.example.com.").await.unwrap(),
+        vec!["192.0.2.10"]
+    );
+    for blocked in ["ads.example.com.", "tracker.ads.example.com."] {
+        assert!(lookup(zt.clone(), blocked)
+            .await
+            .unwrap_err()
+            .is_nx_domain());
+    }
+
+    let zt = Arc::new(ZTForwarder::new(
+        forwarder(),
+        ForwardMetrics::default(),
+        Some(
+            Blocklist::from_file(dir.clone(), vec![IpAddr::from_str("0.0.0.0").unwrap()]).unwrap(),
+        ),
+        None,
+        TtlClamp::default(),
+    ));
+    assert_eq!(
+        lookup(zt.clone(), "ads.example.com.").await.unwrap(),
+        vec!["0.0.0.0"]
+    );
+
+    std::fs::remove_file(dir).unwrap();
+}
+
+#[test]
+fn test_limit_message() {
+    use crate::server::{limit_message, Truncation};
+    use std::net::Ipv4Addr;
+    use trust_dns_resolver::{
+        proto::{
+            op::{Edns, Message, Query},
+            rr::{RData, Record, RecordType},
+        },
+        Name,
+    };
+
+    let name = Name::from_str("many.home.arpa.").unwrap();
+    let mut message = Message::new();
+    message.add_query(Query::query(name.clone(), RecordType::A));
+    message.set_edns(Edns::new());
+    for i in 0..100 {
+        message.add_answer(Record::from_rdata(
+            name.clone(),
+            60,
+            RData::A(Ipv4Addr::new(10, 0, 0, i)),
+        ));
+    }
+
+    let full = message.to_vec().unwrap().len();
+    assert!(full > 512);
+
+    // under the limit, nothing changes
+    let limited = limit_message(message.clone(), full, Truncation::Partial).unwrap();
+    assert_eq!(limited.answers().len(), 100);
+    assert!(!limited.truncated());
+
+    for limit in [512, 1232] {
+        let limited = limit_message(message.clone(), limit, Truncation::Partial).unwrap();
+        assert!(limited.to_vec().unwrap().len() <= limit, "{}", limit);
+        assert!(limited.truncated());
+        assert!(!limited.answers().is_empty());
+        assert!(limited.answers().len() < 100);
+        assert!(limited.extensions().is_some());
+        assert_eq!(limited.queries().len(), 1);
+    }
+
+    let small = limit_message(message.clone(), 512, Truncation::Partial).unwrap();
+    let large = limit_message(message.clone(), 1232, Truncation::Partial).unwrap();
+    assert!(small.answers().len() < large.answers().len());
+
+    let empty = limit_message(message, 1232, Truncation::Empty).unwrap();
+    assert!(empty.truncated());
+    assert!(empty.answers().is_empty());
+    assert!(empty.extensions().is_some());
+}
+
+#[tokio::test]
+async fn test_max_udp_size() {
+    use std::time::Duration;
+    use trust_dns_resolver::{
+        proto::{
+            op::{Edns, Message, Query},
+            rr::RecordType,
+        },
+        Name,
+    };
+
+    let mut zt = test_zt().await;
+
+    let name = Name::from_str("many.home.arpa.").unwrap();
+    let ips: Vec<IpAddr> = (0..100)
+        .map(|i| IpAddr::from_str(&format!("10.0.0.{}", i)).unwrap())
+        .collect();
+    zt.forward_authority
+        .match_or_insert(name.clone(), &ips)
+        .await;
+
+    zt.max_udp_size = Some(512);
+
+    let sa = serve(zt, Duration::new(1, 0)).await;
+
+    // the client advertises a large buffer; the configured limit still applies.
+    let mut query = Message::new();
+    query.add_query(Query::query(name, RecordType::A));
+    let mut edns = Edns::new();
+    edns.set_max_payload(4096);
+    query.set_edns(edns);
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.send_to(&query.to_vec().unwrap(), sa).await.unwrap();
+
+    let mut buf = vec![0; 65535];
+    let len = tokio::time::timeout(Duration::new(5, 0), socket.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(len <= 512, "{}", len);
+
+    let response = Message::from_vec(&buf[..len]).unwrap();
+    assert!(response.truncated());
+    assert!(!response.answers().is_empty());
+    assert!(response.answers().len() < 100);
+}
+
+#[tokio::test]
+async fn test_tcp_keepalive() {
+    use crate::server::{keepalive_option, Server};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use trust_dns_resolver::{
+        proto::{
+            op::{Edns, Message, Query},
+            rr::{rdata::opt::EdnsCode, RecordType},
+        },
+        Name,
+    };
+
+    let mut zt = test_zt().await;
+
+    zt.tcp_keepalive = true;
+
+    let sa = free_port().await;
+    let timeout = Duration::new(3, 0);
+    let sf = Server::new(zt).bind(sa, timeout, None).await.unwrap();
+    tokio::spawn(Server::serve(sf));
+
+    let mut query = Message::new();
+    query.add_query(Query::query(
+        Name::from_str("home.arpa.").unwrap(),
+        RecordType::SOA,
+    ));
+    query.set_edns(Edns::new());
+    let query = query.to_vec().unwrap();
+
+    // over TCP the option carries the idle timeout, in units of 100ms
+    let mut stream = tokio::net::TcpStream::connect(sa).await.unwrap();
+    stream
+        .write_all(&(query.len() as u16).to_be_bytes())
+        .await
+        .unwrap();
+    stream.write_all(&query).await.unwrap();
+
+    let len = tokio::time::timeout(Duration::new(5, 0), stream.read_u16())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut buf = vec![0; len as usize];
+    stream.read_exact(&mut buf).await.unwrap();
+
+    let response = Message::from_vec(&buf).unwrap();
+    assert_eq!(
+        response
+            .extensions()
+            .as_ref()
+            .unwrap()
+            .option(EdnsCode::Keepalive),
+        Some(&keepalive_option(timeout))
+    );
+    assert_eq!(
+        keepalive_option(timeout),
+        trust_dns_resolver::proto::rr::rdata::opt::EdnsOption::Unknown(11, vec![0, 30])
+    );
+
+    // but never over UDP
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.send_to(&query, sa).await.unwrap();
+
+    let mut buf = vec![0; 65535];
+    let len = tokio::time::timeout(Duration::new(5, 0), socket.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    let response = Message::from_vec(&buf[..len]).unwrap();
+    assert!(response
+        .extensions()
+        .as_ref()
+        .unwrap()
+        .option(EdnsCode::Keepalive)
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_presync_response() {
+    use crate::authority::PresyncResponse;
+    use std::time::Duration;
+    use trust_dns_resolver::proto::{op::ResponseCode, rr::RecordType};
+
+    let mut zt = test_zt().await;
+
+    zt.presync_response = PresyncResponse::ServFail;
+
+    let sa = serve(zt.clone(), Duration::new(1, 0)).await;
+
+    // before the first sync, a member name is not known to be missing yet
+    let response = query(sa, "islay.home.arpa.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::ServFail);
+
+    zt.configure_members(
+        serde_json::from_value(serde_json::json!({"config": {}})).unwrap(),
+        vec![serde_json::from_value(serde_json::json!({
+            "nodeId": "abcdef0123",
+            "name": "islay",
+            "config": { "ipAssignments": ["10.0.0.2"] },
+        }))
+        .unwrap()],
+    )
+    .await
+    .unwrap();
+
+    let response = query(sa, "islay.home.arpa.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+
+    let response = query(sa, "missing.home.arpa.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+}
+
+#[tokio::test]
+async fn test_chaos_version() {
+    use crate::utils::{GIT_SHA, VERSION_STRING};
+    use std::{net::SocketAddr, time::Duration};
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, Query, ResponseCode},
+            rr::{DNSClass, RData, RecordType},
+        },
+        Name,
+    };
+
+    async fn query(sa: SocketAddr, name: &str, rtype: RecordType) -> Message {
+        let mut q = Query::query(Name::from_str(name).unwrap(), rtype);
+        q.set_query_class(DNSClass::CH);
+        let mut query = Message::new();
+        query.add_query(q);
+
+        udp_query(sa, &query).await
+    }
+
+    let mut zt = test_zt().await;
+
+    zt.chaos_version = true;
+    zt.configure_members(
+        serde_json::from_value(serde_json::json!({"config": {}})).unwrap(),
+        vec![serde_json::from_value(serde_json::json!({
+            "nodeId": "abcdef0123",
+            "name": "islay",
+            "config": { "ipAssignments": ["10.0.0.2"] },
+        }))
+        .unwrap()],
+    )
+    .await
+    .unwrap();
+
+    let sa = serve(zt.clone(), Duration::new(1, 0)).await;
+
+    let response = query(sa, "version.bind.", RecordType::TXT).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+    assert_eq!(response.answers()[0].dns_class(), DNSClass::CH);
+    match response.answers()[0].data() {
+        Some(RData::TXT(txt)) => {
+            assert_eq!(txt.to_string(), format!("zeronsd {}", VERSION_STRING))
+        }
+        data => panic!("unexpected answer: {:?}", data),
+    }
+
+    // member names are IN only
+    let response = query(sa, "islay.home.arpa.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+    assert!(response.answers().is_empty());
+
+    let response = query(sa, "islay.home.arpa.", RecordType::TXT).await;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+
+    // the info name is only answered when it is enabled on its own
+    let response = query(sa, "info.zeronsd.", RecordType::TXT).await;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+
+    zt.chaos_info = true;
+    let records = zt.record_count().await;
+    assert!(records > 0);
+    let sa = serve(zt, Duration::new(1, 0)).await;
+
+    let response = query(sa, "info.zeronsd.", RecordType::TXT).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+    let info: Vec<String> = match response.answers()[0].data() {
+        Some(RData::TXT(txt)) => txt
+            .iter()
+            .map(|s| String::from_utf8(s.to_vec()).unwrap())
+            .collect(),
+        data => panic!("unexpected answer: {:?}", data),
+    };
+    assert_eq!(info[0], format!("version={}", env!("CARGO_PKG_VERSION")));
+    assert_eq!(info[1], format!("git={}", GIT_SHA));
+    assert!(info[2].starts_with("uptime="), "{:?}", info);
+    assert_eq!(info[3], format!("records={}", records), "{:?}", info);
+}
+
+#[tokio::test]
+async fn test_health_name() {
+    use crate::authority::PresyncResponse;
+    use std::{net::SocketAddr, time::Duration};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, Query, ResponseCode},
+            rr::RecordType,
+        },
+        Name,
+    };
+
+    fn request(name: &str, rtype: RecordType) -> Vec<u8> {
+        let mut query = Message::new();
+        query.add_query(Query::query(Name::from_str(name).unwrap(), rtype));
+        query.to_vec().unwrap()
+    }
+
+    async fn tcp(sa: SocketAddr, name: &str, rtype: RecordType) -> Message {
+        let query = request(name, rtype);
+        let mut stream = tokio::net::TcpStream::connect(sa).await.unwrap();
+        stream
+            .write_all(&(query.len() as u16).to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(&query).await.unwrap();
+
+        let len = tokio::time::timeout(Duration::new(5, 0), stream.read_u16())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await.unwrap();
+        Message::from_vec(&buf).unwrap()
+    }
+
+    fn answers(response: &Message) -> Vec<String> {
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+        assert!(response.answers().iter().all(|r| r.ttl() == 0));
+        response
+            .answers()
+            .iter()
+            .map(|r| r.data().unwrap().to_string())
+            .collect()
+    }
+
+    let mut zt = test_zt().await;
+
+    zt.presync_response = PresyncResponse::ServFail;
+    zt.health_name = Some(Name::from_str("health.home.arpa.").unwrap());
+
+    let sa = serve(zt.clone(), Duration::new(1, 0)).await;
+
+    for synced in [false, true] {
+        if synced {
+            zt.configure_members(
+                serde_json::from_value(serde_json::json!({"config": {}})).unwrap(),
+                vec![],
+            )
+            .await
+            .unwrap();
+        } else {
+            // the rest of the zone is not answered yet
+            let response = query(sa, "islay.home.arpa.", RecordType::A).await;
+            assert_eq!(response.response_code(), ResponseCode::ServFail);
+        }
+
+        assert_eq!(
+            answers(&query(sa, "health.home.arpa.", RecordType::A).await),
+            vec!["127.0.0.1"]
+        );
+        assert_eq!(
+            answers(&tcp(sa, "health.home.arpa.", RecordType::A).await),
+            vec!["127.0.0.1"]
+        );
+        assert_eq!(
+            answers(&query(sa, "health.home.arpa.", RecordType::AAAA).await),
+            vec!["::1"]
+        );
+        assert!(answers(&query(sa, "health.home.arpa.", RecordType::TXT).await).is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_root_queries() {
+    use crate::server::RootQueries;
+    use std::time::Duration;
+    use trust_dns_resolver::proto::{op::ResponseCode, rr::RecordType};
+
+    let mut zt = test_zt().await;
+
+    zt.root_queries = RootQueries::Refuse;
+    zt.configure_members(
+        serde_json::from_value(serde_json::json!({"config": {}})).unwrap(),
+        vec![],
+    )
+    .await
+    .unwrap();
+
+    let sa = serve(zt, Duration::new(1, 0)).await;
+
+    for (name, rtype) in [
+        (".", RecordType::NS),
+        (".", RecordType::SOA),
+        ("example.com.", RecordType::NS),
+    ] {
+        let response = query(sa, name, rtype).await;
+        assert_eq!(response.response_code(), ResponseCode::Refused, "{}", name);
+        assert!(response.answers().is_empty(), "{}", name);
+    }
+
+    // the managed zone still answers for itself
+    let response = query(sa, "home.arpa.", RecordType::NS).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert_eq!(
+        response.answers()[0].data().unwrap().to_string(),
+        "zt-foo.home.arpa."
+    );
+}
+
+#[tokio::test]
+async fn test_reverse_apex() {
+    use std::time::Duration;
+    use trust_dns_resolver::{
+        proto::{
+            op::ResponseCode,
+            rr::{RData, RecordType},
+        },
+        Name,
+    };
+
+    // an IPv4 /24 and the network's RFC 4193 /88, which ends on a nibble boundary
+    let zt = test_zt_reverse(&["10.0.0.0/24", "fd12:3456:7891:111:2199:9300::/88"]).await;
+    zt.configure_members(
+        serde_json::from_value(serde_json::json!({"config": {}})).unwrap(),
+        Vec::new(),
+    )
+    .await
+    .unwrap();
+
+    let sa = serve(zt, Duration::new(1, 0)).await;
+
+    for (apex, labels) in [
+        ("0.0.10.in-addr.arpa.", 5),
+        ("3.9.9.9.1.2.1.1.1.0.1.9.8.7.6.5.4.3.2.1.d.f.ip6.arpa.", 24),
+    ] {
+        let apex = Name::from_str(apex).unwrap();
+        assert_eq!(apex.num_labels(), labels);
+
+        let response = query(sa, &apex.to_string(), RecordType::SOA).await;
+        assert_eq!(response.response_code(), ResponseCode::NoError, "{}", apex);
+        assert!(response.authoritative(), "{}", apex);
+        assert_eq!(response.answers().len(), 1, "{}", apex);
+        match response.answers()[0].data() {
+            Some(RData::SOA(soa)) => assert_eq!(soa.mname(), &apex),
+            data => panic!("unexpected answer for {}: {:?}", apex, data),
+        }
+
+        // the nameserver is this instance's name in the forward zone, not the reverse zone itself
+        let response = query(sa, &apex.to_string(), RecordType::NS).await;
+        assert_eq!(response.response_code(), ResponseCode::NoError, "{}", apex);
+        assert!(response.authoritative(), "{}", apex);
+        assert_eq!(response.answers().len(), 1, "{}", apex);
+        assert_eq!(
+            response.answers()[0].data().unwrap().to_string(),
+            "zt-foo.home.arpa."
+        );
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_sync_threads() {
+    use crate::authority::spawn_dedicated;
+    use std::time::{Duration, Instant};
+    use trust_dns_resolver::proto::{op::ResponseCode, rr::RecordType};
+
+    let zt = test_zt().await;
+
+    let sa = serve(zt.clone(), Duration::new(1, 0)).await;
+
+    let members = (0..2000)
+        .map(|i| {
+            serde_json::from_value(serde_json::json!({
+                "nodeId": format!("{:010x}", i),
+                "name": format!("member-{}", i),
+                "config": { "ipAssignments": [format!("10.0.{}.{}", i / 250, i % 250 + 1)] },
+            }))
+            .unwrap()
+        })
+        .collect();
+
+    let (tx, mut done) = tokio::sync::oneshot::channel();
+    let sync = zt.clone();
+    spawn_dedicated(2, async move {
+        sync.configure_members(
+            serde_json::from_value(serde_json::json!({"config": {}})).unwrap(),
+            members,
+        )
+        .await
+        .unwrap();
+        let _ = tx.send(());
+    })
+    .unwrap();
+
+    // the single-threaded test runtime serves queries while the sync runs on its own threads.
+    let mut slowest = Duration::default();
+    let mut queries = 0;
+
+    loop {
+        let start = Instant::now();
+        let response = query(sa, "zt-foo.home.arpa.", RecordType::SOA).await;
+        slowest = slowest.max(start.elapsed());
+        queries += 1;
+
+        assert_ne!(response.response_code(), ResponseCode::ServFail);
+
+        if done.try_recv().is_ok() {
+            break;
+        }
+    }
+
+    assert!(queries > 1);
+    assert!(slowest < Duration::new(1, 0), "{:?}", slowest);
+
+    // and every member is in once it is done
+    let response = query(sa, "member-1999.home.arpa.", RecordType::A).await;
+    assert_eq!(response.answers().len(), 1);
+}
+
+#[tokio::test]
+async fn test_any_response() {
+    use crate::authority::AnyResponse;
+    use std::time::Duration;
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, Query},
+            rr::{RData, RecordType},
+        },
+        Name,
+    };
+
+    async fn query_any(any_response: AnyResponse) -> Message {
+        let mut zt = test_zt().await;
+
+        let name = Name::from_str("islay.home.arpa.").unwrap();
+        zt.forward_authority
+            .match_or_insert(
+                name.clone(),
+                &[
+                    IpAddr::from_str("10.0.0.2").unwrap(),
+                    IpAddr::from_str("fd00::2").unwrap(),
+                ],
+            )
+            .await;
+
+        zt.any_response = any_response;
+
+        let sa = serve(zt, Duration::new(1, 0)).await;
+
+        let mut query = Message::new();
+        query.add_query(Query::query(name, RecordType::ANY));
+
+        udp_query(sa, &query).await
+    }
+
+    let response = query_any(AnyResponse::Records).await;
+    let mut types: Vec<RecordType> = response.answers().iter().map(|r| r.record_type()).collect();
+    types.sort();
+    assert_eq!(types, vec![RecordType::A, RecordType::AAAA]);
+
+    let response = query_any(AnyResponse::Minimal).await;
+    assert_eq!(response.answers().len(), 1);
+    match response.answers()[0].data() {
+        Some(RData::HINFO(hinfo)) => assert_eq!(hinfo.cpu(), b"RFC8482"),
+        other => panic!("unexpected answer: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_reverse_listen_ips() {
+    use crate::server::Server;
+    use std::{net::SocketAddr, time::Duration};
+    use trust_dns_resolver::{
+        proto::{op::ResponseCode, rr::RecordType},
+        IntoName, Name,
+    };
+
+    let mut zt = test_zt_reverse(&["10.0.0.0/24"]).await;
+    let ip = IpAddr::from_str("10.0.0.2").unwrap();
+    zt.forward_authority
+        .match_or_insert(Name::from_str("islay.home.arpa.").unwrap(), &[ip])
+        .await;
+
+    zt.host_name = Some(Name::from_str("islay.home.arpa.").unwrap());
+    zt.host_ips = vec![ip];
+    zt.configure_members(
+        serde_json::from_value(serde_json::json!({"config": {}})).unwrap(),
+        Vec::new(),
+    )
+    .await
+    .unwrap();
+
+    let port = free_port().await.port();
+    let reverse_sa = SocketAddr::new(IpAddr::from_str("127.0.0.1").unwrap(), port);
+    let forward_sa = SocketAddr::new(IpAddr::from_str("127.0.0.2").unwrap(), port);
+    zt.reverse_ips = vec![reverse_sa.ip()];
+
+    for sa in [reverse_sa, forward_sa] {
+        let sf = Server::new(zt.clone())
+            .bind(sa, Duration::new(1, 0), None)
+            .await
+            .unwrap();
+        tokio::spawn(Server::serve(sf));
+    }
+
+    let ptr = ip.into_name().unwrap();
+    let response = query(reverse_sa, &ptr.to_string(), RecordType::PTR).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(
+        response.answers()[0].data().unwrap().to_string(),
+        "islay.home.arpa."
+    );
+
+    // the other listener refuses rather than forwarding the query
+    let response = query(forward_sa, &ptr.to_string(), RecordType::PTR).await;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+    assert!(response.answers().is_empty());
+
+    // the forward zone is still served on both
+    for sa in [reverse_sa, forward_sa] {
+        let response = query(sa, "home.arpa.", RecordType::SOA).await;
+        assert_eq!(response.answers().len(), 1, "{}", sa);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket() {
+    use crate::server::Server;
+    use std::time::Duration;
+    use tokio::net::UnixDatagram;
+    use trust_dns_resolver::proto::{
+        op::{Message, Query, ResponseCode},
+        rr::RecordType,
+    };
+    use trust_dns_server::client::rr::Name;
+
+    let mut zt = test_zt().await;
+    zt.forward_authority
+        .match_or_insert(
+            Name::from_str("islay.home.arpa.").unwrap(),
+            &[IpAddr::from_str("10.0.0.2").unwrap()],
+        )
+        .await;
+
+    // limiting the zone to a listen IP does not apply to local clients
+    zt.forward_ips = vec![IpAddr::from_str("10.0.0.1").unwrap()];
+
+    let dir = std::env::temp_dir().join(format!("zeronsd-unix-{}", rand::random::<u32>()));
+    std::fs::create_dir(&dir).unwrap();
+    let path = dir.join("zeronsd.sock");
+
+    // a file that is not a socket is never replaced
+    std::fs::write(&path, "precious").unwrap();
+    let err = Server::new(zt.clone())
+        .bind_unix(&path)
+        .await
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("is not a socket"), "{}", err);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "precious");
+    std::fs::remove_file(&path).unwrap();
+
+    // a stale socket is
+    drop(std::os::unix::net::UnixDatagram::bind(&path).unwrap());
+
+    let server = Server::new(zt).bind_unix(&path).await.unwrap();
+    tokio::spawn(server.serve());
+
+    let client = UnixDatagram::bind(dir.join("client.sock")).unwrap();
+    let mut query = Message::new();
+    query.add_query(Query::query(
+        Name::from_str("islay.home.arpa.").unwrap(),
+        RecordType::A,
+    ));
+    client
+        .send_to(&query.to_vec().unwrap(), &path)
+        .await
+        .unwrap();
+
+    let mut buf = vec![0; 65535];
+    let len = tokio::time::timeout(Duration::new(5, 0), client.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    let response = Message::from_vec(&buf[..len]).unwrap();
+
+    assert_eq!(response.id(), query.id());
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(
+        response.answers()[0].data().unwrap().to_string(),
+        "10.0.0.2"
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_advertised_ips() {
+    use crate::utils::advertised_ips;
+
+    let bound: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2", "fd00::1"]
+        .iter()
+        .map(|ip| IpAddr::from_str(ip).unwrap())
+        .collect();
+
+    assert_eq!(advertised_ips(&bound, &[]).unwrap(), bound);
+    assert_eq!(
+        advertised_ips(&bound, &bound[1..2]).unwrap(),
+        vec![bound[1]]
+    );
+    assert!(advertised_ips(&bound, &[IpAddr::from_str("10.0.0.3").unwrap()]).is_err());
+}
+
+#[test]
+fn test_dns_push_wanted() {
+    use crate::utils::{dns_push_wanted, log_subscriber};
+
+    let logged = |allow_dns: Option<bool>, require: bool| {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber =
+            log_subscriber(Some(tracing::Level::WARN), move || writer.clone()).unwrap();
+        let wanted =
+            tracing::subscriber::with_default(subscriber, || dns_push_wanted(allow_dns, require));
+        (wanted, capture.output())
+    };
+
+    for require in [false, true] {
+        let (wanted, output) = logged(Some(true), require);
+        assert!(wanted);
+        assert!(output.is_empty(), "{}", output);
+    }
+
+    // allowDNS is per member, so only an explicit requirement skips the push
+    let (wanted, output) = logged(Some(false), false);
+    assert!(wanted);
+    assert!(output.contains("members need it enabled"), "{}", output);
+
+    // an allowDNS that could not be read counts as off
+    for allow_dns in [Some(false), None] {
+        let (wanted, output) = logged(allow_dns, true);
+        assert!(!wanted);
+        assert!(output.contains("not pushing DNS settings"), "{}", output);
+    }
+}
+
+#[tokio::test]
+async fn test_tls_reload() {
+    use crate::{
+        server::Server,
+        tls::{new_acceptor, serve_tls, ReloadableAcceptor},
+    };
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        pkey::PKey,
+        rsa::Rsa,
+        ssl::{SslConnector, SslMethod, SslVerifyMode},
+        x509::{X509NameBuilder, X509},
+    };
+    use std::{pin::Pin, sync::Arc, time::Duration};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_openssl::SslStream;
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, Query, ResponseCode},
+            rr::RecordType,
+        },
+        Name,
+    };
+
+    fn self_signed(cn: &str) -> openssl::ssl::SslAcceptor {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", cn).unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+        new_acceptor(cert.build(), None, key).unwrap()
+    }
+
+    async fn connect(addr: std::net::SocketAddr) -> (SslStream<tokio::net::TcpStream>, String) {
+        let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+        connector.set_verify(SslVerifyMode::NONE);
+        let ssl = connector
+            .build()
+            .configure()
+            .unwrap()
+            .into_ssl("localhost")
+            .unwrap();
+
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut tls = SslStream::new(ssl, tcp).unwrap();
+        Pin::new(&mut tls).connect().await.unwrap();
+
+        let cn = tls
+            .ssl()
+            .peer_certificate()
+            .unwrap()
+            .subject_name()
+            .entries()
+            .next()
+            .unwrap()
+            .data()
+            .as_utf8()
+            .unwrap()
+            .to_string();
+        (tls, cn)
+    }
+
+    async fn query(stream: &mut SslStream<tokio::net::TcpStream>) -> Message {
+        let mut query = Message::new();
+        query.add_query(Query::query(
+            Name::from_str("home.arpa.").unwrap(),
+            RecordType::SOA,
+        ));
+        let query = query.to_vec().unwrap();
+
+        stream
+            .write_all(&(query.len() as u16).to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(&query).await.unwrap();
+
+        let len = stream.read_u16().await.unwrap();
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await.unwrap();
+        Message::from_vec(&buf).unwrap()
+    }
+
+    let zt = test_zt().await;
+    let catalog = Arc::new(Server::new(zt).catalog(None, None).await.unwrap());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let acceptor = ReloadableAcceptor::new(self_signed("old"));
+    tokio::spawn(serve_tls(
+        listener,
+        acceptor.clone(),
+        catalog,
+        Duration::new(1, 0),
+        None,
+    ));
+
+    let (mut old, cn) = connect(addr).await;
+    assert_eq!(cn, "old");
+
+    acceptor.reload(self_signed("new"));
+
+    let (_, cn) = connect(addr).await;
+    assert_eq!(cn, "new");
+
+    // the connection made before the reload keeps working
+    assert_eq!(query(&mut old).await.response_code(), ResponseCode::NoError);
+
+    // a client that never finishes its handshake is closed after the timeout
+    let mut slow = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0; 1];
+    let read = tokio::time::timeout(Duration::new(5, 0), slow.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+}
+
+#[test]
+fn test_tls_check() {
+    use crate::init::Launcher;
+    use openssl::symm::Cipher;
+
+    let dir = std::env::temp_dir().join(format!("zeronsd-tls-{}", rand::random::<u32>()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let (cert, key) = doq_material();
+    let (other_cert, other_key) = doq_material();
+    std::fs::write(dir.join("cert.pem"), cert.to_pem().unwrap()).unwrap();
+    std::fs::write(dir.join("other.pem"), other_cert.to_pem().unwrap()).unwrap();
+    std::fs::write(
+        dir.join("key.pem"),
+        key.private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), b"hunter2")
+            .unwrap(),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("other-key.pem"),
+        other_key.private_key_to_pem_pkcs8().unwrap(),
+    )
+    .unwrap();
+    std::fs::write(dir.join("passphrase"), "hunter2\n").unwrap();
+
+    let launcher = |cert: &str, key: &str, chain: Option<&str>| Launcher {
+        tls_cert: Some(dir.join(cert)),
+        tls_key: Some(dir.join(key)),
+        chain_cert: chain.map(|chain| dir.join(chain)),
+        tls_key_passphrase: Some(dir.join("passphrase")),
+        ..Default::default()
+    };
+
+    // the encrypted key is read with the passphrase, and goes with its certificate
+    assert!(launcher("cert.pem", "key.pem", None)
+        .load_tls()
+        .unwrap()
+        .is_some());
+
+    let err = launcher("cert.pem", "other-key.pem", None)
+        .load_tls()
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(
+        err.contains("tls_key is not the private key of tls_cert (CN=localhost)"),
+        "{}",
+        err
+    );
+
+    let err = launcher("cert.pem", "key.pem", Some("other.pem"))
+        .load_tls()
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("chain_cert does not validate"), "{}", err);
+
+    // a wrong passphrase is an error of its own
+    std::fs::write(dir.join("passphrase"), "hunter3").unwrap();
+    assert!(launcher("cert.pem", "key.pem", None).load_tls().is_err());
+}
+
+// doq_material makes a self-signed certificate for localhost and its key.
+fn doq_material() -> (
+    openssl::x509::X509,
+    openssl::pkey::PKey<openssl::pkey::Private>,
+) {
+    use openssl::{
+        asn1::{Asn1Integer, Asn1Time},
+        bn::BigNum,
+        hash::MessageDigest,
+        pkey::PKey,
+        rsa::Rsa,
+        x509::{extension::SubjectAlternativeName, X509NameBuilder, X509},
+    };
+
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_serial_number(&Asn1Integer::from_bn(&BigNum::from_u32(1).unwrap()).unwrap())
+        .unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    let san = SubjectAlternativeName::new()
+        .dns("localhost")
+        .build(&cert.x509v3_context(None, None))
+        .unwrap();
+    cert.append_extension(san).unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+    (cert.build(), key)
+}
+
+#[cfg(feature = "dns-over-quic")]
+#[tokio::test]
+async fn test_doq() {
+    use crate::{
+        authority::{RecordAuthority, ZTAuthority},
+        server::Server,
+        tls::DoqConfig,
+    };
+    use std::{collections::HashMap, net::SocketAddr, time::Duration};
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, Query},
+            quic::QuicClientStream,
+            rr::RecordType,
+            xfer::{DnsRequestSender, FirstAnswer},
+        },
+        Name,
+    };
+    use trust_dns_server::client::rr::LowerName;
+    use zerotier_api::central_api;
+
+    let mut zt = test_zt().await;
+    let name = Name::from_str("islay.home.arpa.").unwrap();
+    zt.forward_authority
+        .match_or_insert(name.clone(), &[IpAddr::from_str("10.0.0.2").unwrap()])
+        .await;
+
+    let (cert, key) = doq_material();
+    let doq_port = {
+        let probe = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        probe.local_addr().unwrap().port()
+    };
+
+    zt.doq = Some(DoqConfig::new(doq_port, &cert, None, &key).unwrap());
+
+    let sa = serve(zt, Duration::new(1, 0)).await;
+
+    // the client only trusts the server's own certificate
+    let mut roots = rustls::RootCertStore::empty();
+    roots
+        .add(&rustls::Certificate(cert.to_der().unwrap()))
+        .unwrap();
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let mut builder = QuicClientStream::builder();
+    builder.crypto_config(config);
+    let mut client = tokio::time::timeout(
+        Duration::new(5, 0),
+        builder.build(SocketAddr::new(sa.ip(), doq_port), "localhost".to_string()),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    let mut query = Message::new();
+    query.set_recursion_desired(true);
+    query.add_query(Query::query(name, RecordType::A));
+    let query = Message::from_vec(&query.to_vec().unwrap()).unwrap();
+
+    let response = tokio::time::timeout(
+        Duration::new(5, 0),
+        client.send_message(query.into()).first_answer(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    let answers: Vec<String> = response
+        .answers()
+        .iter()
+        .map(|r| r.data().unwrap().to_string())
+        .collect();
+    assert_eq!(answers, vec!["10.0.0.2"]);
+}
+
+#[cfg(not(feature = "dns-over-quic"))]
+#[test]
+fn test_doq_unavailable() {
+    use crate::tls::DoqConfig;
+
+    let (cert, key) = doq_material();
+    let err = DoqConfig::new(853, &cert, None, &key)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("dns-over-quic"), "{}", err);
+}
+
+#[test]
+fn test_members_interval() {
+    use crate::utils::{log_subscriber, members_interval};
+
+    let logged = |secs: Option<u64>| {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber =
+            log_subscriber(Some(tracing::Level::WARN), move || writer.clone()).unwrap();
+        let interval = tracing::subscriber::with_default(subscriber, || members_interval(secs));
+        (interval, capture.output())
+    };
+
+    let (interval, output) = logged(Some(0));
+    assert!(interval.unwrap_err().to_string().contains("at least 1"));
+    assert!(output.is_empty(), "{}", output);
+
+    let (interval, output) = logged(Some(2));
+    assert_eq!(interval.unwrap(), Duration::new(2, 0));
+    assert!(output.contains("rate-limited"), "{}", output);
+
+    for (secs, expected) in [(Some(5), 5), (None, 30)] {
+        let (interval, output) = logged(secs);
+        assert_eq!(interval.unwrap(), Duration::new(expected, 0));
+        assert!(output.is_empty(), "{}", output);
+    }
+}
+
+#[test]
+fn test_quiet_logging() {
+    use crate::{cli::Cli, errors::report, log::LevelFilter, utils::log_subscriber};
+    use clap::Parser;
+
+    fn logged(level: LevelFilter) -> String {
+        let capture = Capture::default();
+        let writer = capture.clone();
+
+        match log_subscriber(level.to_log(), move || writer.clone()) {
+            Some(subscriber) => tracing::subscriber::with_default(subscriber, || {
+                tracing::info!("an info line");
+                tracing::error!("an error line");
+            }),
+            None => {
+                tracing::info!("an info line");
+                tracing::error!("an error line");
+            }
+        }
+
+        capture.output()
+    }
+
+    let out = logged(LevelFilter::resolve(None, 0, false));
+    assert!(out.contains("an info line"));
+    assert!(out.contains("an error line"));
+
+    // --quiet turns logging off entirely
+    assert!(logged(LevelFilter::resolve(None, 2, true)).is_empty());
+
+    // an explicit level wins over --quiet, so errors can still be kept
+    let out = logged(LevelFilter::resolve(Some(LevelFilter::Error), 0, true));
+    assert!(!out.contains("an info line"));
+    assert!(out.contains("an error line"));
+
+    // the error that stops zeronsd bypasses the logger, so --quiet still prints it
+    let cli = Cli::try_parse_from(["zeronsd", "--quiet", "config-schema"]).unwrap();
+    assert!(logged(LevelFilter::resolve(None, cli.verbose, cli.quiet)).is_empty());
+
+    let stderr = Capture::default();
+    report(
+        &anyhow::anyhow!("a fatal error"),
+        cli.error_format,
+        cli.error_file.as_deref(),
+        &mut stderr.clone(),
+    );
+    assert_eq!(stderr.output(), "a fatal error\n");
+}
+
+#[tokio::test]
+async fn test_get_listen_ips_retry() {
+    use crate::utils::get_listen_ips_retry;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    // a zerotier-one that only assigns an address from the `assign_after`th request on.
+    async fn local_service(assign_after: usize) -> String {
+        let requests = AtomicUsize::new(0);
+        fake_central(move |_| {
+            let n = requests.fetch_add(1, Ordering::SeqCst) + 1;
+            let body = if n >= assign_after {
+                r#"{"id":"1234567891011121","assignedAddresses":["10.0.0.1/24"]}"#
+            } else {
+                r#"{"id":"1234567891011121"}"#
+            };
+            Some(("200 OK", body.to_string()))
+        })
+        .await
+    }
+
+    let authtoken =
+        std::env::temp_dir().join(format!("zeronsd-authtoken-{}", rand::random::<u32>()));
+    std::fs::write(&authtoken, "secret").unwrap();
+    let backoff = Duration::from_millis(10);
+
+    // the address shows up on the third try, within a budget of five
+    let url = local_service(3).await;
+    let ips = get_listen_ips_retry(&authtoken, "1234567891011121", url, 5, backoff)
+        .await
+        .unwrap();
+    assert_eq!(ips, vec!["10.0.0.1/24"]);
+
+    // but not within a budget of two
+    let url = local_service(3).await;
+    let err = get_listen_ips_retry(&authtoken, "1234567891011121", url, 2, backoff)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("No listen IPs"), "{}", err);
+    assert!(err.contains("2 attempts"), "{}", err);
+
+    // an unreachable zerotier-one is reported as such
+    let err = get_listen_ips_retry(
+        &authtoken,
+        "1234567891011121",
+        "http://127.0.0.1:9".to_string(),
+        2,
+        backoff,
+    )
+    .await
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("Could not reach zerotier-one"), "{}", err);
+
+    std::fs::remove_file(authtoken).unwrap();
+}
+
+#[tokio::test]
+async fn test_authtoken_rotation() {
+    use crate::utils::get_listen_ips_retry;
+    use std::{path::PathBuf, time::Duration};
+
+    // a zerotier-one that only takes the token `new`, and writes it to authtoken when refusing
+    // another one, if rotate is set.
+    async fn local_service(authtoken: PathBuf, rotate: bool) -> String {
+        fake_central(move |request| {
+            if request.to_lowercase().contains("x-zt1-auth: new\r\n") {
+                let body = r#"{"id":"1234567891011121","assignedAddresses":["10.0.0.1/24"]}"#;
+                return Some(("200 OK", body.to_string()));
+            }
+
+            if rotate {
+                std::fs::write(&authtoken, "new").unwrap();
+            }
+            Some(("401 Unauthorized", String::new()))
+        })
+        .await
+    }
+
+    let authtoken =
+        std::env::temp_dir().join(format!("zeronsd-authtoken-{}", rand::random::<u32>()));
+    let backoff = Duration::from_millis(10);
+
+    // the rotated token is picked up without using up an attempt
+    std::fs::write(&authtoken, "old").unwrap();
+    let url = local_service(authtoken.clone(), true).await;
+    let ips = get_listen_ips_retry(&authtoken, "1234567891011121", url, 1, backoff)
+        .await
+        .unwrap();
+    assert_eq!(ips, vec!["10.0.0.1/24"]);
+
+    // a token that is refused but did not change is an error, like any other
+    std::fs::write(&authtoken, "old").unwrap();
+    let url = local_service(authtoken.clone(), false).await;
+    let err = get_listen_ips_retry(&authtoken, "1234567891011121", url, 1, backoff)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("401"), "{}", err);
+
+    std::fs::remove_file(authtoken).unwrap();
+}
+
+#[tokio::test]
+async fn test_update_member_retry() {
+    use crate::utils::update_member_retry;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use zerotier_api::central_api;
+
+    // a Central that fails the first `failures` requests with `status` and then saves the member.
+    async fn central(
+        failures: usize,
+        status: &'static str,
+    ) -> (String, Arc<AtomicUsize>, Arc<Mutex<Option<String>>>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let saved = Arc::new(Mutex::new(None));
+
+        let (r, sv) = (requests.clone(), saved.clone());
+        let url = fake_central(move |request| {
+            let n = r.fetch_add(1, Ordering::SeqCst) + 1;
+            if n <= failures {
+                return Some((status, String::new()));
+            }
+
+            let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+            *sv.lock().unwrap() = Some(body.to_string());
+            Some(("200 OK", body.to_string()))
+        })
+        .await;
+
+        (url, requests, saved)
+    }
+
+    let member: central_api::types::Member =
+        serde_json::from_value(serde_json::json!({"name": "islay"})).unwrap();
+    let backoff = Duration::from_millis(10);
+
+    // a transient 503 is retried until the update applies
+    let (url, requests, saved) = central(1, "503 Service Unavailable").await;
+    let client = central_api::Client::new(&url);
+    let updated = update_member_retry(
+        &client,
+        "1234567891011121",
+        "abcdef0123",
+        &member,
+        3,
+        backoff,
+    )
+    .await
+    .unwrap();
+    assert_eq!(updated.name, Some("islay".to_string()));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    assert!(saved.lock().unwrap().as_ref().unwrap().contains("islay"));
+
+    // but a 404 is not
+    let (url, requests, saved) = central(1, "404 Not Found").await;
+    let client = central_api::Client::new(&url);
+    let err = update_member_retry(
+        &client,
+        "1234567891011121",
+        "abcdef0123",
+        &member,
+        3,
+        backoff,
+    )
+    .await
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("abcdef0123"), "{}", err);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert!(saved.lock().unwrap().is_none());
+
+    // and the attempts are bounded
+    let (url, requests, _) = central(5, "503 Service Unavailable").await;
+    let client = central_api::Client::new(&url);
+    assert!(update_member_retry(
+        &client,
+        "1234567891011121",
+        "abcdef0123",
+        &member,
+        3,
+        backoff
+    )
+    .await
+    .is_err());
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_enable_local_dns() {
+    use crate::utils::enable_local_dns;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    // a zerotier-one that keeps the network it is given; every POST body is kept.
+    async fn local_service(
+        network: serde_json::Value,
+    ) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let posts = Arc::new(Mutex::new(Vec::new()));
+        let mut network = network;
+
+        let p = posts.clone();
+        let url = fake_central(move |request| {
+            if request.starts_with("POST") {
+                let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+                let body: serde_json::Value = serde_json::from_str(body).unwrap();
+                p.lock().unwrap().push(body.clone());
+                network = body;
+            }
+
+            Some(("200 OK", network.to_string()))
+        })
+        .await;
+
+        (url, posts)
+    }
+
+    let authtoken =
+        std::env::temp_dir().join(format!("zeronsd-authtoken-{}", rand::random::<u32>()));
+    std::fs::write(&authtoken, "secret").unwrap();
+    let backoff = Duration::from_millis(10);
+
+    // flags that are off are turned on, and the rest of the network is left alone
+    let (url, posts) = local_service(serde_json::json!({
+        "id": "1234567891011121",
+        "allowDNS": false,
+        "allowManaged": false,
+        "allowGlobal": true,
+    }))
+    .await;
+    enable_local_dns(&authtoken, "1234567891011121", url, 1, backoff)
+        .await
+        .unwrap();
+    let posts = posts.lock().unwrap().clone();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0]["allowDNS"], true);
+    assert_eq!(posts[0]["allowManaged"], true);
+    assert_eq!(posts[0]["allowGlobal"], true);
+
+    // nothing is written when both are already on
+    let (url, posts) = local_service(serde_json::json!({
+        "id": "1234567891011121",
+        "allowDNS": true,
+        "allowManaged": true,
+    }))
+    .await;
+    enable_local_dns(&authtoken, "1234567891011121", url, 1, backoff)
+        .await
+        .unwrap();
+    assert!(posts.lock().unwrap().is_empty());
+
+    // an unreachable zerotier-one is retried, then reported
+    let err = enable_local_dns(
+        &authtoken,
+        "1234567891011121",
+        "http://127.0.0.1:9".to_string(),
+        2,
+        backoff,
+    )
+    .await
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("Could not enable managed DNS"), "{}", err);
+
+    std::fs::remove_file(authtoken).unwrap();
+}
+
+#[tokio::test]
+async fn test_primary_election() {
+    use crate::{election::Election, init::Launcher, server::Server};
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use trust_dns_resolver::Name;
+
+    use zerotier_api::central_api;
+
+    // a Central that serves the network and counts the writes to it.
+    async fn central() -> (String, Arc<AtomicUsize>) {
+        let posts = Arc::new(AtomicUsize::new(0));
+
+        let p = posts.clone();
+        let url = fake_central(move |request| {
+            if request.starts_with("POST") {
+                p.fetch_add(1, Ordering::SeqCst);
+            }
+
+            let body = serde_json::json!({"id": "1234567891011121", "config": {}});
+            Some(("200 OK", body.to_string()))
+        })
+        .await;
+
+        (url, posts)
+    }
+
+    // an instance's listener, which advertises its node id.
+    async fn instance(election: &Election) -> (SocketAddr, tokio::task::JoinHandle<()>) {
+        let mut zt = test_zt().await;
+        zt.instance_txt = Some(election.advertised());
+
+        let sa = free_port().await;
+        let sf = Server::new(zt)
+            .bind(sa, Duration::new(1, 0), None)
+            .await
+            .unwrap();
+        (
+            sa,
+            tokio::spawn(async move {
+                let _ = Server::serve(sf).await;
+            }),
+        )
+    }
+
+    let domain = Name::from_str("home.arpa.").unwrap();
+    // longer than an unanswered probe takes
+    let failover = Duration::from_secs(3);
+    let (url, posts) = central().await;
+    let client = central_api::Client::new(&url);
+    let launcher = Launcher {
+        network_id: Some("1234567891011121".to_string()),
+        ..Default::default()
+    };
+
+    // each instance only knows the other one's address; the listeners are bound before the
+    // elections that ask them are set up, so they advertise under throwaway elections.
+    let advertise = |node_id: &str| {
+        Election::new(node_id.to_string(), &domain, Vec::new(), true, failover).unwrap()
+    };
+    let (first_sa, first_task) = instance(&advertise("0123abcdef")).await;
+    let (second_sa, _second_task) = instance(&advertise("abcdef0123")).await;
+    let first = Election::new(
+        "0123abcdef".to_string(),
+        &domain,
+        vec![second_sa],
+        true,
+        failover,
+    )
+    .unwrap();
+    let second = Election::new(
+        "abcdef0123".to_string(),
+        &domain,
+        vec![first_sa],
+        true,
+        failover,
+    )
+    .unwrap();
+
+    assert!(first.update().await);
+    assert!(!second.update().await);
+    assert!(first.is_primary());
+    assert!(!second.is_primary());
+
+    // both go to push their settings; only the primary writes them
+    for election in [&first, &second] {
+        launcher
+            .push_central_dns(
+                domain.clone(),
+                vec!["10.0.0.2".to_string()],
+                client.clone(),
+                Some(election),
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(posts.load(Ordering::SeqCst), 1);
 
-#[test]
-fn test_domain_or_default() {
-    use crate::utils::{domain_or_default, DEFAULT_DOMAIN_NAME};
-    use trust_dns_server::client::rr::Name;
+    // nothing changes on the next election
+    assert!(!first.update().await);
+    assert!(!second.update().await);
 
-    assert_eq!(
-        domain_or_default(None).unwrap(),
-        Name::from_str(DEFAULT_DOMAIN_NAME).unwrap()
-    );
+    // once the primary's zeronsd has stopped answering for the failover, the second takes over
+    let stopped = tokio::time::Instant::now();
+    first_task.abort();
+    let _ = first_task.await;
+    assert!(!second.update().await);
+    tokio::time::sleep_until(stopped + failover + Duration::from_millis(100)).await;
+    assert!(second.update().await);
+    launcher
+        .push_central_dns(
+            domain.clone(),
+            vec!["10.0.0.3".to_string()],
+            client.clone(),
+            Some(&second),
+        )
+        .await
+        .unwrap();
+    assert_eq!(posts.load(Ordering::SeqCst), 2);
 
+    // an instance that is not one of the candidates never elects itself, however low its node id
+    let outsider = Election::new(
+        "0000000001".to_string(),
+        &domain,
+        vec![second_sa],
+        false,
+        failover,
+    )
+    .unwrap();
+    assert!(!outsider.update().await);
     assert_eq!(
-        domain_or_default(Some("zerotier")).unwrap(),
-        Name::from_str("zerotier").unwrap()
+        outsider.elect(std::time::Instant::now()).as_deref(),
+        Some("abcdef0123")
     );
-
-    assert_eq!(
-        domain_or_default(Some("zerotier.tld")).unwrap(),
-        Name::from_str("zerotier.tld").unwrap()
+    assert!(
+        !Election::new(
+            "0000000001".to_string(),
+            &domain,
+            Vec::new(),
+            false,
+            failover
+        )
+        .unwrap()
+        .update()
+        .await
     );
-
-    for bad in ["bad.", "~", "!", ".", ""] {
-        assert!(domain_or_default(Some(bad)).is_err(), "{}", bad);
-    }
 }
 
 #[test]
-fn test_central_token() {
-    use crate::utils::central_token;
+fn test_dns_applied() {
+    use crate::utils::{dns_applied, domain_or_default};
+    use zerotier_api::central_api::types::Network;
 
-    assert!(central_token(None).is_err());
-    std::env::set_var("ZEROTIER_CENTRAL_TOKEN", "abcdef");
-    assert_eq!(central_token(None).unwrap(), "abcdef");
+    let network: Network = serde_json::from_value(serde_json::json!({
+        "id": "1234567891011121",
+        "config": {
+            "dns": {
+                "domain": "home.arpa",
+                "servers": ["10.0.0.2", "10.0.0.1"],
+            }
+        }
+    }))
+    .unwrap();
 
-    let hosts = std::fs::read_to_string("/etc/hosts").unwrap();
-    let token = central_token(Some(Path::new("/etc/hosts")));
-    assert!(token.is_ok());
-    assert_eq!(token.unwrap(), hosts.trim());
+    let domain = domain_or_default(None).unwrap();
+    let ips = vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()];
+
+    assert!(dns_applied(&network, Some(&domain), &ips));
+    assert!(!dns_applied(&network, Some(&domain), &ips[..1]));
+    assert!(!dns_applied(
+        &network,
+        Some(&domain_or_default(Some("zerotier")).unwrap()),
+        &ips
+    ));
+    assert!(!dns_applied(&network, None, &ips));
+
+    let unapplied: Network =
+        serde_json::from_value(serde_json::json!({"id": "1234567891011121", "config": {}}))
+            .unwrap();
+    assert!(!dns_applied(&unapplied, Some(&domain), &ips));
 }
 
 #[test]
@@ -159,7 +2952,7 @@ fn test_supervise_systemd_green() {
     ];
 
     let write = match std::env::var("WRITE_FIXTURES") {
-        Ok(var) => var != "",
+        Ok(var) => !var.is_empty(),
         Err(_) => false,
     };
 
@@ -191,6 +2984,217 @@ fn test_supervise_systemd_green() {
     }
 }
 
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_print() {
+    use std::path::PathBuf;
+
+    use crate::init::Launcher;
+
+    let props = |token: &str| crate::supervise::Properties {
+        binpath: String::from("zeronsd"),
+        launcher: Launcher {
+            network_id: Some(String::from("1234567891011121")),
+            token: Some(PathBuf::from(token)),
+            domain: Some(String::from("zerotier")),
+            secret: Some(PathBuf::from("/var/lib/zerotier-one/authtoken.secret")),
+            hosts: Some(PathBuf::from("/etc/hosts")),
+            wildcard: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let expected =
+        std::fs::read_to_string("../testdata/supervise/systemd/with-filled-in-properties.unit")
+            .unwrap();
+    assert_eq!(props("/proc/cpuinfo").print_supervisor().unwrap(), expected);
+
+    // files that do not exist here are printed as given instead of failing
+    let mut missing = props("/nonexistent/token");
+    assert!(missing.validate().is_err());
+    assert_eq!(
+        missing.print_supervisor().unwrap(),
+        expected.replace("/proc/cpuinfo", "/nonexistent/token")
+    );
+
+    // the network id is still checked
+    let mut bad = props("/proc/cpuinfo");
+    bad.launcher.network_id = Some(String::from("123456789101112"));
+    assert!(bad.print_supervisor().is_err());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_tokens_only() {
+    use crate::{
+        init::{ConfigFormat, Launcher},
+        supervise::Properties,
+    };
+
+    let dir = std::env::temp_dir().join(format!("zeronsd-supervise-{}", rand::random::<u32>()));
+    std::fs::create_dir(&dir).unwrap();
+    let config = dir.join("zeronsd.yaml");
+    std::fs::write(
+        &config,
+        "wildcard: false\ntokens:\n  \"1234567891011121\": /proc/cpuinfo\n",
+    )
+    .unwrap();
+
+    let mut launcher =
+        Launcher::new_from_config(config.to_str().unwrap(), ConfigFormat::YAML).unwrap();
+    launcher.network_id = Some(String::from("1234567891011121"));
+    assert!(launcher.token.is_none());
+
+    let mut props = Properties {
+        binpath: String::from("zeronsd"),
+        launcher,
+        config: Some(config.clone()),
+        ..Default::default()
+    };
+    props.validate().unwrap();
+
+    // start reads the token from the config, so there is no -t to render
+    let unit = props.supervise_template().unwrap();
+    assert!(
+        unit.contains(&format!(
+            "ExecStart=zeronsd start -c {} 1234567891011121\n",
+            config.canonicalize().unwrap().display()
+        )),
+        "{}",
+        unit
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_token_keyring() {
+    use clap::Parser;
+
+    use crate::{
+        cli::{Cli, Command},
+        init::Launcher,
+        supervise::Properties,
+    };
+
+    let mut props = Properties {
+        binpath: String::from("zeronsd"),
+        launcher: Launcher {
+            network_id: Some(String::from("1234567891011121")),
+            token_keyring: Some(String::from("zeronsd")),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // the keyring service stands in for the token file
+    props.validate().unwrap();
+    let unit = props.supervise_template().unwrap();
+    assert!(
+        unit.contains("ExecStart=zeronsd start --token-keyring zeronsd 1234567891011121\n"),
+        "{}",
+        unit
+    );
+
+    props.launcher.token = Some(PathBuf::from("/proc/cpuinfo"));
+    let unit = props.supervise_template().unwrap();
+    assert!(
+        unit.contains("start -t /proc/cpuinfo --token-keyring zeronsd 1234567891011121\n"),
+        "{}",
+        unit
+    );
+
+    // start flags the unit cannot carry over are refused rather than dropped
+    for flags in [
+        &["--tls-cert", "/etc/zeronsd/cert.pem"][..],
+        &["--wildcard-families", "a"],
+        &["--chaos-version"],
+    ] {
+        let args = [
+            &["zeronsd", "supervise", "-t", "/proc/cpuinfo"][..],
+            flags,
+            &["1234567891011121"],
+        ]
+        .concat();
+        let start = match Cli::try_parse_from(args).unwrap().command {
+            Command::Supervise(args) => args.start,
+            _ => unreachable!(),
+        };
+        let err = Properties::try_from(start).err().unwrap().to_string();
+        assert!(err.contains("pass it with -c"), "{:?}: {}", flags, err);
+    }
+
+    // the ones it does carry over are fine
+    let args = [
+        "zeronsd",
+        "supervise",
+        "-t",
+        "/proc/cpuinfo",
+        "--token-keyring",
+        "zeronsd",
+        "-w",
+        "-d",
+        "zerotier",
+        "-f",
+        "/etc/hosts",
+        "-s",
+        "/proc/cpuinfo",
+        "1234567891011121",
+    ];
+    let start = match Cli::try_parse_from(args).unwrap().command {
+        Command::Supervise(args) => args.start,
+        _ => unreachable!(),
+    };
+    if let Err(e) = Properties::try_from(start) {
+        assert!(!e.to_string().contains("pass it with -c"), "{}", e);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_unit_dir() {
+    use std::path::PathBuf;
+
+    use crate::{cli::UnsuperviseArgs, init::Launcher, supervise::Properties};
+
+    let dir = std::env::temp_dir().join(format!("zeronsd-units-{}", rand::random::<u32>()));
+    std::fs::create_dir(&dir).unwrap();
+
+    let mut props = Properties {
+        binpath: String::from("zeronsd"),
+        launcher: Launcher {
+            network_id: Some(String::from("1234567891011121")),
+            token: Some(PathBuf::from("/proc/cpuinfo")),
+            ..Default::default()
+        },
+        distro: Some(String::from("debian")),
+        unit_dir: Some(dir.clone()),
+        ..Default::default()
+    };
+    props.install_supervisor().unwrap();
+
+    let unit = dir.join("zeronsd-1234567891011121.service");
+    assert_eq!(
+        std::fs::read_to_string(&unit).unwrap(),
+        std::fs::read_to_string("../testdata/supervise/systemd/basic.unit").unwrap()
+    );
+    assert!(props
+        .install_help(&unit)
+        .contains(&format!("systemctl link {}", unit.display())));
+
+    let mut uninstall = Properties::from(UnsuperviseArgs {
+        network_id: String::from("1234567891011121"),
+        unit_dir: Some(dir.clone()),
+    });
+    uninstall.distro = Some(String::from("debian"));
+    uninstall.uninstall_supervisor().unwrap();
+    assert!(!unit.exists());
+
+    std::fs::remove_dir(dir).unwrap();
+}
+
 #[test]
 #[cfg(target_os = "linux")]
 fn test_supervise_systemd_red() {
@@ -331,7 +3335,6 @@ fn test_parse_hosts() {
 
     for path in std::fs::read_dir(crate::utils::TEST_HOSTS_DIR)
         .unwrap()
-        .into_iter()
         .map(|p| p.unwrap())
     {
         if path.metadata().unwrap().is_file() {
@@ -415,3 +3418,695 @@ fn test_parse_hosts_duplicate() {
             .unwrap()
     ));
 }
+
+#[tokio::test]
+async fn test_update_central_dns() {
+    use crate::utils::update_central_dns;
+    use std::sync::{Arc, Mutex};
+
+    use trust_dns_server::client::rr::Name;
+    use zerotier_api::central_api;
+
+    // a Central that serves a network and saves whatever is posted back.
+    let saved: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+
+    let sv = saved.clone();
+    let url = fake_central(move |request| {
+        let body = if request.starts_with("GET") {
+            serde_json::json!({
+                "id": "1234567891011121",
+                "config": {"name": "islay", "dns": {"domain": "old.arpa", "servers": []}},
+            })
+            .to_string()
+        } else {
+            let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+            *sv.lock().unwrap() = Some(body.to_string());
+            body.to_string()
+        };
+
+        Some(("200 OK", body))
+    })
+    .await;
+
+    update_central_dns(
+        Some(Name::from_str("home.arpa.").unwrap()),
+        vec!["10.0.0.1".to_string(), "fd00::1".to_string()],
+        central_api::Client::new(&url),
+        "1234567891011121".to_string(),
+        3,
+    )
+    .await
+    .unwrap();
+
+    let pushed: serde_json::Value =
+        serde_json::from_str(saved.lock().unwrap().as_ref().unwrap()).unwrap();
+    assert_eq!(
+        pushed["config"]["dns"],
+        serde_json::json!({"domain": "home.arpa", "servers": ["10.0.0.1", "fd00::1"]})
+    );
+    // the rest of the network config is sent back as it was
+    assert_eq!(pushed["config"]["name"], serde_json::json!("islay"));
+}
+
+#[tokio::test]
+async fn test_update_central_dns_concurrent() {
+    use crate::utils::update_central_dns;
+    use std::sync::{Arc, Mutex};
+
+    use trust_dns_server::client::rr::Name;
+    use zerotier_api::central_api;
+
+    // a Central whose network is renamed by someone else on each of the first `edits` reads, and
+    // that saves whatever is posted back.
+    async fn central(edits: usize) -> (String, Arc<Mutex<Option<String>>>) {
+        let saved: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+
+        let sv = saved.clone();
+        let mut reads = 0;
+        let url = fake_central(move |request| {
+            let body = if request.starts_with("GET") {
+                reads += 1;
+                serde_json::json!({
+                    "id": "1234567891011121",
+                    "config": {"name": format!("islay-{}", reads.min(edits + 1))},
+                })
+                .to_string()
+            } else {
+                let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+                *sv.lock().unwrap() = Some(body.to_string());
+                body.to_string()
+            };
+
+            Some(("200 OK", body))
+        })
+        .await;
+
+        (url, saved)
+    }
+
+    // renamed between the first read and the write: the update is redone on top of the rename
+    let (url, saved) = central(1).await;
+    update_central_dns(
+        Some(Name::from_str("home.arpa.").unwrap()),
+        vec!["10.0.0.1".to_string()],
+        central_api::Client::new(&url),
+        "1234567891011121".to_string(),
+        3,
+    )
+    .await
+    .unwrap();
+
+    let pushed: serde_json::Value =
+        serde_json::from_str(saved.lock().unwrap().as_ref().unwrap()).unwrap();
+    assert_eq!(pushed["config"]["name"], serde_json::json!("islay-2"));
+    assert_eq!(
+        pushed["config"]["dns"],
+        serde_json::json!({"domain": "home.arpa", "servers": ["10.0.0.1"]})
+    );
+
+    // renamed on every read: nothing is written and the update fails once the retries run out
+    let (url, saved) = central(10).await;
+    let err = update_central_dns(
+        Some(Name::from_str("home.arpa.").unwrap()),
+        vec!["10.0.0.1".to_string()],
+        central_api::Client::new(&url),
+        "1234567891011121".to_string(),
+        2,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("after 3 attempts"), "{}", err);
+    assert!(saved.lock().unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_update_central_dns_modes() {
+    use crate::utils::{dns_applied, update_central_dns, DnsPush};
+    use std::sync::{Arc, Mutex};
+
+    use trust_dns_server::client::rr::Name;
+    use zerotier_api::central_api;
+
+    // a Central that serves a network and saves whatever is posted back.
+    let saved: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+
+    let sv = saved.clone();
+    let url = fake_central(move |request| {
+        let body = if request.starts_with("GET") {
+            serde_json::json!({"id": "1234567891011121", "config": {"name": "islay"}}).to_string()
+        } else {
+            let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+            *sv.lock().unwrap() = Some(body.to_string());
+            body.to_string()
+        };
+
+        Some(("200 OK", body))
+    })
+    .await;
+
+    // Central takes the domain and the servers together, so the one not pushed is sent empty.
+    for (mode, dns) in [
+        (
+            DnsPush::Both,
+            serde_json::json!({"domain": "home.arpa", "servers": ["10.0.0.1"]}),
+        ),
+        (
+            DnsPush::Servers,
+            serde_json::json!({"domain": "", "servers": ["10.0.0.1"]}),
+        ),
+        (
+            DnsPush::Domain,
+            serde_json::json!({"domain": "home.arpa", "servers": []}),
+        ),
+    ] {
+        assert_eq!(DnsPush::from_str(&mode.to_string()).unwrap(), mode);
+
+        let (domain_name, servers) = mode.select(
+            Name::from_str("home.arpa.").unwrap(),
+            vec!["10.0.0.1".to_string()],
+        );
+        update_central_dns(
+            domain_name.clone(),
+            servers.clone(),
+            central_api::Client::new(&url),
+            "1234567891011121".to_string(),
+            3,
+        )
+        .await
+        .unwrap();
+
+        let pushed: serde_json::Value =
+            serde_json::from_str(saved.lock().unwrap().as_ref().unwrap()).unwrap();
+        assert_eq!(pushed["config"]["dns"], dns, "{}", mode);
+
+        let network: central_api::types::Network = serde_json::from_value(pushed).unwrap();
+        assert!(
+            dns_applied(&network, domain_name.as_ref(), &servers),
+            "{}",
+            mode
+        );
+    }
+
+    assert!(DnsPush::from_str("search").is_err());
+}
+
+#[tokio::test]
+async fn test_dnssec_denial() {
+    use std::{net::SocketAddr, time::Duration};
+    use trust_dns_resolver::{
+        proto::{
+            op::{Edns, Message, Query, ResponseCode},
+            rr::RecordType,
+        },
+        Name,
+    };
+
+    async fn query(sa: SocketAddr, name: &str, rtype: RecordType) -> Message {
+        let mut query = Message::new();
+        query.add_query(Query::query(Name::from_str(name).unwrap(), rtype));
+        let mut edns = Edns::new();
+        edns.set_dnssec_ok(true);
+        query.set_edns(edns);
+
+        udp_query(sa, &query).await
+    }
+
+    let mut zt = test_zt().await;
+
+    zt.wildcard = true;
+    zt.configure_members(
+        serde_json::from_value(serde_json::json!({"config": {}})).unwrap(),
+        vec![serde_json::from_value(serde_json::json!({
+            "nodeId": "abcdef0123",
+            "name": "islay",
+            "config": { "ipAssignments": ["10.0.0.2"] },
+        }))
+        .unwrap()],
+    )
+    .await
+    .unwrap();
+
+    let sa = serve(zt, Duration::new(1, 0)).await;
+
+    // the zone is unsigned: denials carry the SOA for negative caching and no NSEC records, which
+    // a validator could only take as bogus.
+    let assert_denial = |response: &Message, code: ResponseCode| {
+        assert_eq!(response.response_code(), code);
+        assert!(response.authoritative());
+        assert!(response.answers().is_empty());
+        assert_eq!(response.name_servers().len(), 1);
+        assert_eq!(response.name_servers()[0].record_type(), RecordType::SOA);
+        assert!(response
+            .name_servers()
+            .iter()
+            .chain(response.additionals())
+            .all(|r| r.record_type() != RecordType::NSEC));
+    };
+
+    for name in ["nonexistent.home.arpa.", "foo.nonexistent.home.arpa."] {
+        assert_denial(
+            &query(sa, name, RecordType::A).await,
+            ResponseCode::NXDomain,
+        );
+    }
+
+    // NODATA for a member without the type, and for a name its wildcard covers.
+    for name in ["islay.home.arpa.", "foo.islay.home.arpa."] {
+        assert_denial(
+            &query(sa, name, RecordType::TXT).await,
+            ResponseCode::NoError,
+        );
+    }
+
+    let response = query(sa, "foo.islay.home.arpa.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+}
+
+#[tokio::test]
+async fn test_max_tcp_connections() {
+    use std::time::Duration;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, Query, ResponseCode},
+            rr::RecordType,
+        },
+        Name,
+    };
+
+    async fn query(stream: &mut TcpStream) -> Option<Message> {
+        let mut query = Message::new();
+        query.add_query(Query::query(
+            Name::from_str("home.arpa.").unwrap(),
+            RecordType::SOA,
+        ));
+        let query = query.to_vec().unwrap();
+
+        stream
+            .write_all(&(query.len() as u16).to_be_bytes())
+            .await
+            .ok()?;
+        stream.write_all(&query).await.ok()?;
+
+        let len = tokio::time::timeout(Duration::new(5, 0), stream.read_u16())
+            .await
+            .unwrap()
+            .ok()?;
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await.ok()?;
+        Some(Message::from_vec(&buf).unwrap())
+    }
+
+    let mut zt = test_zt().await;
+
+    zt.max_tcp_connections = Some(2);
+
+    let sa = serve(zt, Duration::new(10, 0)).await;
+
+    let mut first = TcpStream::connect(sa).await.unwrap();
+    let mut second = TcpStream::connect(sa).await.unwrap();
+    for stream in [&mut first, &mut second] {
+        let response = query(stream).await.unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+    }
+
+    // the third connection is accepted, then closed without an answer
+    let mut third = TcpStream::connect(sa).await.unwrap();
+    assert!(query(&mut third).await.is_none());
+
+    // the open connections are unaffected
+    for stream in [&mut first, &mut second] {
+        assert!(query(stream).await.is_some());
+    }
+
+    // closing one makes room for another
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut fourth = TcpStream::connect(sa).await.unwrap();
+    assert!(query(&mut fourth).await.is_some());
+
+    // UDP is not limited
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut message = Message::new();
+    message.add_query(Query::query(
+        Name::from_str("home.arpa.").unwrap(),
+        RecordType::SOA,
+    ));
+    socket
+        .send_to(&message.to_vec().unwrap(), sa)
+        .await
+        .unwrap();
+    let mut buf = vec![0; 65535];
+    tokio::time::timeout(Duration::new(5, 0), socket.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_max_tcp_connections_idle() {
+    use std::time::Duration;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+    use trust_dns_resolver::{
+        proto::{
+            op::{Message, Query},
+            rr::RecordType,
+        },
+        Name,
+    };
+
+    async fn query(stream: &mut TcpStream) -> Option<Message> {
+        let mut query = Message::new();
+        query.add_query(Query::query(
+            Name::from_str("home.arpa.").unwrap(),
+            RecordType::SOA,
+        ));
+        let query = query.to_vec().unwrap();
+
+        stream
+            .write_all(&(query.len() as u16).to_be_bytes())
+            .await
+            .ok()?;
+        stream.write_all(&query).await.ok()?;
+
+        let len = tokio::time::timeout(Duration::new(5, 0), stream.read_u16())
+            .await
+            .unwrap()
+            .ok()?;
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await.ok()?;
+        Some(Message::from_vec(&buf).unwrap())
+    }
+
+    let mut zt = test_zt().await;
+
+    zt.max_tcp_connections = Some(2);
+
+    let sa = serve(zt, Duration::new(1, 0)).await;
+
+    // two clients that connect and never send anything use up the limit
+    let mut idle = vec![
+        TcpStream::connect(sa).await.unwrap(),
+        TcpStream::connect(sa).await.unwrap(),
+    ];
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut refused = TcpStream::connect(sa).await.unwrap();
+    assert!(query(&mut refused).await.is_none());
+
+    // once the timeout has passed they are closed, without the clients doing anything
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    for stream in &mut idle {
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(Duration::new(1, 0), stream.read(&mut buf))
+            .await
+            .unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+    }
+
+    let mut client = TcpStream::connect(sa).await.unwrap();
+    assert!(query(&mut client).await.is_some());
+}
+
+#[tokio::test]
+async fn test_dual_stack() {
+    use crate::server::{bind_tcp, bind_udp, SocketOptions};
+    use socket2::SockRef;
+    use std::net::SocketAddr;
+
+    // the unspecified address is where it shows; Linux marks sockets bound to a specific IPv6
+    // address V6ONLY regardless.
+    let sa = SocketAddr::from_str("[::]:0").unwrap();
+    for dual_stack in [false, true] {
+        let options = SocketOptions {
+            dual_stack,
+            ..Default::default()
+        };
+        let udp = bind_udp(sa, options).unwrap();
+        assert_eq!(SockRef::from(&udp).only_v6().unwrap(), !dual_stack);
+
+        let tcp = bind_tcp(sa, options).unwrap();
+        assert_eq!(SockRef::from(&tcp).only_v6().unwrap(), !dual_stack);
+
+        // IPv4 clients only reach the IPv6 listener in dual stack.
+        let v4 = SocketAddr::new(
+            IpAddr::from_str("127.0.0.1").unwrap(),
+            tcp.local_addr().unwrap().port(),
+        );
+        assert_eq!(tokio::net::TcpStream::connect(v4).await.is_ok(), dual_stack);
+    }
+
+    // IPv4 sockets are left alone
+    let tcp = bind_tcp(
+        SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        SocketOptions::default(),
+    )
+    .unwrap();
+    assert!(SockRef::from(&tcp).only_v6().is_err());
+}
+
+#[tokio::test]
+async fn test_socket_buffers() {
+    use crate::server::{bind_tcp, bind_udp, SocketOptions};
+    use socket2::SockRef;
+    use std::net::SocketAddr;
+
+    let sa = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let options = SocketOptions {
+        recv_buffer: Some(16384),
+        send_buffer: Some(32768),
+        tcp_backlog: Some(16),
+        ..Default::default()
+    };
+
+    // Linux doubles what it is given, to leave room for its own bookkeeping.
+    let applied = |got: usize, wanted: usize| got >= wanted && got <= 2 * wanted;
+
+    let udp = bind_udp(sa, options).unwrap();
+    let udp = SockRef::from(&udp);
+    assert!(applied(udp.recv_buffer_size().unwrap(), 16384));
+    assert!(applied(udp.send_buffer_size().unwrap(), 32768));
+
+    let tcp = bind_tcp(sa, options).unwrap();
+    assert!(applied(
+        SockRef::from(&tcp).recv_buffer_size().unwrap(),
+        16384
+    ));
+
+    // the backlog is not observable, but the listener takes connections
+    assert!(tokio::net::TcpStream::connect(tcp.local_addr().unwrap())
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn test_client_subnet() {
+    use crate::{
+        forwarder::{TtlClamp, ZTForwarder},
+        metrics::ForwardMetrics,
+    };
+    use std::{net::SocketAddr, sync::Arc, time::Duration};
+    use trust_dns_resolver::{
+        config::NameServerConfigGroup,
+        proto::{
+            op::{Edns, Message, MessageType, Query, ResponseCode},
+            rr::{
+                rdata::opt::{EdnsCode, EdnsOption},
+                RData, Record, RecordType,
+            },
+        },
+        Name,
+    };
+    use trust_dns_server::{
+        authority::{Catalog, ZoneType},
+        store::forwarder::{ForwardAuthority, ForwardConfig},
+        ServerFuture,
+    };
+
+    async fn query(sa: SocketAddr, name: &str, rtype: RecordType) -> Message {
+        let mut query = Message::new();
+        query.add_query(Query::query(Name::from_str(name).unwrap(), rtype));
+        query.set_recursion_desired(true);
+        let mut edns = Edns::new();
+        // 192.0.2.0/24
+        edns.options_mut().insert(EdnsOption::Unknown(
+            u16::from(EdnsCode::Subnet),
+            vec![0, 1, 24, 0, 192, 0, 2],
+        ));
+        query.set_edns(edns);
+
+        udp_query(sa, &query).await
+    }
+
+    // an upstream that passes on every query it gets and answers it.
+    let udp = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let upstream = udp.local_addr().unwrap();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 65535];
+        while let Ok((len, src)) = udp.recv_from(&mut buf).await {
+            let query = Message::from_vec(&buf[..len]).unwrap();
+            let mut response = query.clone();
+            response.set_message_type(MessageType::Response);
+            response.set_recursion_available(true);
+            response.add_answer(Record::from_rdata(
+                query.queries()[0].name().clone(),
+                60,
+                RData::A([192, 0, 2, 1].into()),
+            ));
+            udp.send_to(&response.to_vec().unwrap(), src).await.unwrap();
+            tx.send(query).unwrap();
+        }
+    });
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(
+        Name::root().into(),
+        Box::new(Arc::new(ZTForwarder::new(
+            ForwardAuthority::try_from_config(
+                Name::root(),
+                ZoneType::Primary,
+                &ForwardConfig {
+                    name_servers: NameServerConfigGroup::from_ips_clear(
+                        &[upstream.ip()],
+                        upstream.port(),
+                        false,
+                    ),
+                    options: None,
+                },
+            )
+            .unwrap(),
+            ForwardMetrics::default(),
+            None,
+            None,
+            TtlClamp::default(),
+        ))),
+    );
+    let sa = free_port().await;
+    let mut sf = ServerFuture::new(catalog);
+    sf.register_socket(tokio::net::UdpSocket::bind(sa).await.unwrap());
+    tokio::spawn(sf.block_until_done());
+
+    // the forwarder asks upstream with a query of its own, which never carries the client's subnet
+    let response = query(sa, "example.com.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+
+    let forwarded = rx.recv().await.unwrap();
+    assert_eq!(forwarded.queries()[0].name().to_string(), "example.com.");
+    assert!(forwarded
+        .extensions()
+        .as_ref()
+        .is_none_or(|edns| edns.option(EdnsCode::Subnet).is_none()));
+
+    // refusing turns such queries away before they reach the forwarder
+    let mut zt = test_zt().await;
+    zt.refuse_client_subnet = true;
+
+    let sa = serve(zt, Duration::new(1, 0)).await;
+
+    let response = query(sa, "example.com.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+    assert!(response.answers().is_empty());
+
+    // the managed zone is answered locally, subnet or not
+    let response = query(sa, "home.arpa.", RecordType::SOA).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+}
+
+#[tokio::test]
+async fn test_error_report() {
+    use crate::cli::{Cli, Command};
+    use crate::errors::{report, ErrorCategory, ErrorFormat, ErrorReport};
+    use crate::init::Launcher;
+    use clap::Parser;
+
+    let dir = std::env::temp_dir().join(format!("zeronsd-errors-{}", rand::random::<u32>()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.yaml");
+    std::fs::write(&config, "domain: [unclosed\n").unwrap();
+
+    let cli = Cli::try_parse_from([
+        "zeronsd",
+        "start",
+        "--error-format",
+        "json",
+        "-c",
+        config.to_str().unwrap(),
+        "1234567891011121",
+    ])
+    .unwrap();
+    assert_eq!(cli.error_format, ErrorFormat::Json);
+    let args = match cli.command {
+        Command::Start(args) => args,
+        _ => unreachable!(),
+    };
+
+    // a config file that does not parse is a config error, not an exit from inside the parser
+    let err = Launcher::try_from(args).err().unwrap();
+    let json = serde_json::to_value(ErrorReport::from(&err)).unwrap();
+    assert_eq!(json["category"], "config");
+    assert_eq!(json["message"], err.to_string());
+
+    // so is a missing one; the io error underneath does not change the category
+    let err = Launcher::new_from_config(
+        dir.join("missing.yaml").to_str().unwrap(),
+        crate::init::ConfigFormat::YAML,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(ErrorCategory::of(&err), ErrorCategory::Config);
+
+    // as are settings out of range, caught before anything is contacted
+    let err = Launcher {
+        network_id: Some("1234567891011121".to_string()),
+        forward_min_ttl: Some(600),
+        forward_max_ttl: Some(60),
+        ..Default::default()
+    }
+    .start()
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(ErrorCategory::of(&err), ErrorCategory::Config);
+
+    // and an overrides file that does not parse
+    let overrides = dir.join("overrides.yaml");
+    std::fs::write(&overrides, "islay: [unclosed\n").unwrap();
+    let err = Launcher {
+        network_id: Some("1234567891011121".to_string()),
+        overrides: Some(overrides),
+        ..Default::default()
+    }
+    .start()
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(ErrorCategory::of(&err), ErrorCategory::Config);
+
+    let err = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::AddrInUse));
+    assert_eq!(ErrorCategory::of(&err), ErrorCategory::Io);
+    assert_eq!(
+        ErrorCategory::of(&anyhow::anyhow!("something else")),
+        ErrorCategory::Other
+    );
+
+    // the file gets the JSON report whatever the stderr format
+    let file = dir.join("error.json");
+    report(&err, ErrorFormat::Text, Some(&file), &mut std::io::sink());
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(json["category"], "io");
+    assert!(json["causes"].as_array().unwrap().is_empty());
+
+    assert!(Cli::try_parse_from(["zeronsd", "--error-format", "xml", "config-schema"]).is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
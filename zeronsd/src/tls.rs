@@ -0,0 +1,256 @@
+/// DNS-over-TLS with certificates that can be swapped while serving, and DNS-over-QUIC.
+use std::{
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use openssl::{
+    pkey::{PKey, Private},
+    ssl::{Ssl, SslAcceptor},
+    stack::Stack,
+    x509::{X509Ref, X509},
+};
+use tokio::{
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::Semaphore,
+};
+use tokio_openssl::SslStream;
+use tracing::{debug, info};
+use trust_dns_server::server::Protocol;
+
+use crate::server::{serve_stream, LimitedCatalog};
+
+// ReloadableAcceptor holds the TLS acceptor shared by every DoT listener. Replacing it only affects
+// connections accepted afterwards; established ones keep the certificate they started with.
+#[derive(Clone)]
+pub struct ReloadableAcceptor(Arc<RwLock<Arc<SslAcceptor>>>);
+
+impl ReloadableAcceptor {
+    pub fn new(acceptor: SslAcceptor) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(acceptor))))
+    }
+
+    pub fn current(&self) -> Arc<SslAcceptor> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn reload(&self, acceptor: SslAcceptor) {
+        *self.0.write().unwrap() = Arc::new(acceptor);
+    }
+}
+
+// check_tls makes sure key goes with cert, and that each certificate in chain signed the one before
+// it, starting with cert. openssl only finds out about a mismatch when a client connects.
+pub fn check_tls(
+    cert: &X509,
+    chain: Option<&Stack<X509>>,
+    key: &PKey<Private>,
+) -> Result<(), anyhow::Error> {
+    if !cert.public_key()?.public_eq(key) {
+        return Err(anyhow!(
+            "tls_key is not the private key of tls_cert ({}); cannot continue",
+            subject(cert)
+        ));
+    }
+
+    let mut signed = cert.as_ref();
+    for issuer in chain.into_iter().flatten() {
+        if !signed.verify(issuer.public_key()?.as_ref())? {
+            return Err(anyhow!(
+                "chain_cert does not validate: {} is not signed by {}; cannot continue",
+                subject(signed),
+                subject(issuer)
+            ));
+        }
+        signed = issuer;
+    }
+
+    Ok(())
+}
+
+fn subject(cert: &X509Ref) -> String {
+    cert.subject_name()
+        .entries()
+        .map(|entry| {
+            format!(
+                "{}={}",
+                entry.object().nid().short_name().unwrap_or_default(),
+                entry
+                    .data()
+                    .as_utf8()
+                    .map(|data| data.to_string())
+                    .unwrap_or_default()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub fn new_acceptor(
+    cert: X509,
+    chain: Option<Stack<X509>>,
+    key: PKey<Private>,
+) -> Result<SslAcceptor, anyhow::Error> {
+    Ok(trust_dns_proto::openssl::tls_server::new_acceptor(
+        cert, chain, key,
+    )?)
+}
+
+// serve_tls answers DNS over each connection once its TLS handshake is done, which has to be
+// within timeout like any wait for the client. With permits, DoT connections count against the
+// same limit as TCP ones.
+pub async fn serve_tls(
+    listener: TcpListener,
+    acceptor: ReloadableAcceptor,
+    catalog: Arc<LimitedCatalog>,
+    timeout: Duration,
+    permits: Option<Arc<Semaphore>>,
+) {
+    loop {
+        let (tcp, src) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                debug!("error accepting TLS connection: {}", e);
+                continue;
+            }
+        };
+
+        let permit = match permits
+            .clone()
+            .map(Semaphore::try_acquire_owned)
+            .transpose()
+        {
+            Ok(permit) => permit,
+            Err(_) => {
+                debug!(
+                    "closing TLS connection from {}: the connection limit is reached",
+                    src
+                );
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.current();
+        let catalog = catalog.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            match tokio::time::timeout(timeout, accept_tls(tcp, acceptor)).await {
+                Ok(Ok(tls)) => serve_stream(tls, src, Protocol::Tls, catalog, timeout).await,
+                Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", src, e),
+                Err(_) => debug!("TLS handshake with {} timed out", src),
+            }
+        });
+    }
+}
+
+async fn accept_tls(
+    tcp: TcpStream,
+    acceptor: Arc<SslAcceptor>,
+) -> Result<SslStream<TcpStream>, anyhow::Error> {
+    let mut tls = SslStream::new(Ssl::new(acceptor.context())?, tcp)?;
+    Pin::new(&mut tls).accept().await?;
+    Ok(tls)
+}
+
+// reload_on_sighup re-reads the certificates with load whenever the process gets SIGHUP. If they
+// cannot be loaded, the current ones stay in use.
+#[cfg(unix)]
+pub async fn reload_on_sighup<F>(acceptor: ReloadableAcceptor, load: F) -> Result<(), anyhow::Error>
+where
+    F: Fn() -> Result<SslAcceptor, anyhow::Error> + Send + 'static,
+{
+    let mut hup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+    while hup.recv().await.is_some() {
+        match load() {
+            Ok(new) => {
+                acceptor.reload(new);
+                info!("Reloaded TLS certificates");
+            }
+            Err(e) => tracing::error!(
+                "error reloading TLS certificates, keeping the old ones: {}",
+                e
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+// DOQ_PORT is the port DNS-over-QUIC is served on unless another is given (RFC 9250).
+pub const DOQ_PORT: u16 = 853;
+
+// DoqConfig is the DNS-over-QUIC listener: its port, and the certificates and key shared with DoT,
+// DER-encoded for the QUIC stack. They are read once; SIGHUP only reloads the DoT certificates.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "dns-over-quic"), allow(dead_code))]
+pub struct DoqConfig {
+    pub port: u16,
+    certs: Vec<Vec<u8>>,
+    key: Vec<u8>,
+}
+
+impl DoqConfig {
+    pub fn new(
+        port: u16,
+        cert: &X509,
+        chain: Option<&Stack<X509>>,
+        key: &PKey<Private>,
+    ) -> Result<Self, anyhow::Error> {
+        if !cfg!(feature = "dns-over-quic") {
+            return Err(anyhow::anyhow!(
+                "cannot serve DNS-over-QUIC: zeronsd was built without the `dns-over-quic` feature"
+            ));
+        }
+
+        let mut certs = vec![cert.to_der()?];
+        for cert in chain.into_iter().flatten() {
+            certs.push(cert.to_der()?);
+        }
+
+        Ok(Self {
+            port,
+            certs,
+            key: key.private_key_to_pkcs8()?,
+        })
+    }
+}
+
+// register_doq has the server answer DNS-over-QUIC on the socket.
+#[cfg(feature = "dns-over-quic")]
+pub fn register_doq<T>(
+    sf: &mut trust_dns_server::ServerFuture<T>,
+    socket: UdpSocket,
+    doq: &DoqConfig,
+    timeout: Duration,
+) -> Result<(), anyhow::Error>
+where
+    T: trust_dns_server::server::RequestHandler,
+{
+    let certs = doq.certs.iter().cloned().map(rustls::Certificate).collect();
+    sf.register_quic_listener(
+        socket,
+        timeout,
+        (certs, rustls::PrivateKey(doq.key.clone())),
+        String::new(),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "dns-over-quic"))]
+pub fn register_doq<T>(
+    _sf: &mut trust_dns_server::ServerFuture<T>,
+    _socket: UdpSocket,
+    _doq: &DoqConfig,
+    _timeout: Duration,
+) -> Result<(), anyhow::Error>
+where
+    T: trust_dns_server::server::RequestHandler,
+{
+    Err(anyhow::anyhow!(
+        "cannot serve DNS-over-QUIC: zeronsd was built without the `dns-over-quic` feature"
+    ))
+}
@@ -72,15 +72,48 @@ impl ToHostname for Member {
     }
 }
 
+// node_id_from_hostname is the inverse of a member's to_hostname: it takes the node id back out of
+// a zt-<node id> name, with or without the domain. Names set in Central have nothing to do with
+// the node id, so they cannot be decoded.
+pub fn node_id_from_hostname(name: &str) -> Result<String, anyhow::Error> {
+    let label = name.trim().split('.').next().unwrap_or_default();
+
+    let node_id = match label.to_ascii_lowercase().strip_prefix("zt-") {
+        Some(node_id) => node_id.to_string(),
+        None => {
+            return Err(anyhow!(
+                "{} is not a zt-<node id> name; members named in Central cannot be decoded",
+                name
+            ))
+        }
+    };
+
+    if node_id.len() != 10 || !node_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            "{} is not a ZeroTier node id; node ids are 10 hex digits",
+            node_id
+        ));
+    }
+
+    Ok(node_id)
+}
+
+// sanitize_hostname applies the translation table to a member name, without checking that the
+// result is a valid name.
+pub fn sanitize_hostname(name: &str) -> String {
+    let mut s = name.trim().to_string();
+    for (regex, replacement) in TRANSLATION_TABLE.iter() {
+        s = regex.replace_all(&s, *replacement).to_string();
+    }
+
+    s.trim().to_string()
+}
+
 impl ToHostname for String {
     // to_hostname turns member names into trust-dns compatible dns names.
     fn to_hostname(&self) -> Result<Name, anyhow::Error> {
-        let mut s = self.trim().to_string();
-        for (regex, replacement) in TRANSLATION_TABLE.iter() {
-            s = regex.replace_all(&s, *replacement).to_string();
-        }
-
-        let s = s.trim();
+        let s = sanitize_hostname(self);
+        let s = s.as_str();
 
         if s == "." || s.ends_with('.') {
             return Err(anyhow!("Record {} not entered into catalog: '.' and records that ends in '.' are disallowed", s));
@@ -110,7 +143,7 @@ mod tests {
 
     #[test]
     fn test_to_ptr_soa_name() {
-        for item in vec![
+        for item in [
             (
                 IpNetwork::from_str("1.2.3.4/24").unwrap(),
                 LowerName::from_str("3.2.1.in-addr.arpa").unwrap(),
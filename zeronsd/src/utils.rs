@@ -1,13 +1,29 @@
-use std::{net::IpAddr, path::Path, str::FromStr, sync::Once};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, Once},
+    time::{Duration, Instant},
+};
 
 use ipnetwork::IpNetwork;
-use reqwest::header::{HeaderMap, HeaderValue};
-use tracing::warn;
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    StatusCode,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
 use trust_dns_server::client::rr::{LowerName, Name};
 
 use anyhow::anyhow;
 
-use crate::traits::ToHostname;
+use crate::{
+    election::Election,
+    traits::{sanitize_hostname, ToHostname},
+};
 
 use zerotier_api::{central_api, service_api};
 
@@ -16,10 +32,29 @@ pub const TEST_HOSTS_DIR: &str = "../testdata/hosts-files";
 pub const DEFAULT_DOMAIN_NAME: &str = "home.arpa.";
 // zeronsd version calculated from Cargo.toml
 pub const VERSION_STRING: &str = env!("CARGO_PKG_VERSION");
+// commit zeronsd was built from, embedded by build.rs; "unknown" outside of a git checkout
+pub const GIT_SHA: &str = env!("ZERONSD_GIT_SHA");
+// version and commit, as printed by `zeronsd --version`
+pub const LONG_VERSION: &str = env!("ZERONSD_LONG_VERSION");
 // address of Central
 pub const CENTRAL_BASEURL: &str = "https://my.zerotier.com/api/v1";
 // address of local zerotier instance
 pub const ZEROTIER_LOCAL_URL: &str = "http://127.0.0.1:9993";
+// TTL of member, hosts and PTR records unless configured otherwise
+pub const DEFAULT_RECORD_TTL: u32 = 60;
+// deliveries of each webhook payload before it is dropped
+pub const WEBHOOK_ATTEMPTS: usize = 5;
+// seconds between member syncs unless configured otherwise, the shortest interval accepted, and
+// the shortest one that does not get a warning; every sync makes several requests to Central.
+pub const DEFAULT_MEMBERS_INTERVAL: u64 = 30;
+pub const MIN_MEMBERS_INTERVAL: u64 = 1;
+pub const RECOMMENDED_MEMBERS_INTERVAL: u64 = 5;
+// how many more times pushing the DNS settings is tried when the network changes in Central while
+// it is being updated.
+pub const DEFAULT_DNS_UPDATE_RETRIES: u32 = 3;
+// seconds the primary instance may go without being online in Central before another one takes
+// over, long enough that a late update of the time does not hand it over.
+pub const DEFAULT_PRIMARY_FAILOVER: u64 = 300;
 
 // this really needs to be replaced with lazy_static! magic
 fn version() -> String {
@@ -43,22 +78,48 @@ pub fn init_logger(level: Option<tracing::Level>) {
 
         tracing_log::log_tracer::LogTracer::init().expect("initializing logger failed");
 
-        if let Some(level) = level {
-            let subscriber = tracing_subscriber::FmtSubscriber::builder()
-                // all spans/events with a level higher than TRACE (e.g, debug, info, warn, etc.)
-                // will be written to stdout.
-                .with_max_level(level)
-                // completes the builder.
-                .finish();
-
+        if let Some(subscriber) = log_subscriber(level, std::io::stdout) {
             tracing::subscriber::set_global_default(subscriber)
                 .expect("setting default subscriber failed");
         }
     })
 }
 
+// builds the subscriber init_logger installs, writing to `writer`; no level means no logging.
+pub fn log_subscriber<W>(
+    level: Option<tracing::Level>,
+    writer: W,
+) -> Option<impl tracing::Subscriber + Send + Sync + 'static>
+where
+    W: for<'a> tracing_subscriber::fmt::MakeWriter<'a> + Send + Sync + 'static,
+{
+    level.map(|level| {
+        tracing_subscriber::FmtSubscriber::builder()
+            // all spans/events with a level higher than TRACE (e.g, debug, info, warn, etc.)
+            // will be written to the writer.
+            .with_max_level(level)
+            .with_writer(writer)
+            // completes the builder.
+            .finish()
+    })
+}
+
 // this provides the production configuration for talking to central through the openapi libraries.
 pub fn central_client(token: String) -> Result<central_api::Client, anyhow::Error> {
+    central_client_at(
+        &std::env::var("ZEROTIER_CENTRAL_INSTANCE").unwrap_or(CENTRAL_BASEURL.to_string()),
+        token,
+        true,
+    )
+}
+
+// central_client_at builds a client for the Central at url; tests turn https_only off to talk to a
+// local one.
+pub(crate) fn central_client_at(
+    url: &str,
+    token: String,
+    https_only: bool,
+) -> Result<central_api::Client, anyhow::Error> {
     let mut headers = HeaderMap::new();
     headers.insert(
         "Authorization",
@@ -66,10 +127,10 @@ pub fn central_client(token: String) -> Result<central_api::Client, anyhow::Erro
     );
 
     Ok(central_api::Client::new_with_client(
-        &std::env::var("ZEROTIER_CENTRAL_INSTANCE").unwrap_or(CENTRAL_BASEURL.to_string()),
+        url,
         reqwest::Client::builder()
             .user_agent(version())
-            .https_only(true)
+            .https_only(https_only)
             .default_headers(headers)
             .build()?,
     ))
@@ -97,7 +158,98 @@ pub fn central_token(arg: Option<&Path>) -> Result<String, anyhow::Error> {
         }
     }
 
-    return Err(anyhow!("missing zerotier central token: set ZEROTIER_CENTRAL_TOKEN in environment, or pass a file containing it with -t"));
+    Err(anyhow!("missing zerotier central token: set ZEROTIER_CENTRAL_TOKEN in environment, or pass a file containing it with -t"))
+}
+
+// the account the central token is stored under in the keyring service given by --token-keyring.
+pub const KEYRING_USER: &str = "central-token";
+
+// fetch the central token from the platform secret store. A missing entry is not an error, so the
+// token file and environment can still be used.
+#[cfg(feature = "keyring")]
+pub fn keyring_token(service: &str) -> Result<Option<String>, anyhow::Error> {
+    match keyring::Entry::new(service, KEYRING_USER)?.get_password() {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(anyhow!("could not read keyring service {}: {}", service, e)),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn keyring_token(service: &str) -> Result<Option<String>, anyhow::Error> {
+    Err(anyhow!(
+        "cannot read keyring service {}: zeronsd was built without the `keyring` feature",
+        service
+    ))
+}
+
+// load the central API token, trying the keyring service first if one is given. lookup is
+// keyring_token outside of tests.
+pub fn central_token_from<F>(
+    keyring: Option<&str>,
+    arg: Option<&Path>,
+    lookup: F,
+) -> Result<String, anyhow::Error>
+where
+    F: Fn(&str) -> Result<Option<String>, anyhow::Error>,
+{
+    let service = match keyring {
+        Some(service) => service,
+        None => return central_token(arg),
+    };
+
+    if let Some(token) = lookup(service)? {
+        let token = token.trim();
+        if !token.is_empty() {
+            return Ok(token.to_string());
+        }
+    }
+
+    central_token(arg).map_err(|e| {
+        anyhow!(
+            "no token for account {} in keyring service {}, and {}",
+            KEYRING_USER,
+            service,
+            e
+        )
+    })
+}
+
+// load the central API token for a specific network. tokens configured per-network win over the
+// keyring, global token file and environment, so a single config can span several Central
+// organizations.
+pub fn central_token_for(
+    network_id: &str,
+    tokens: &HashMap<String, PathBuf>,
+    arg: Option<&Path>,
+    keyring: Option<&str>,
+) -> Result<String, anyhow::Error> {
+    if let Some(path) = tokens.get(network_id) {
+        let token = match std::fs::read_to_string(path) {
+            Ok(token) => token.trim().to_string(),
+            Err(e) => {
+                return Err(anyhow!(
+                    "Could not load token file {} for network {}: {}",
+                    path.display(),
+                    network_id,
+                    e
+                ))
+            }
+        };
+
+        if token.is_empty() {
+            return Err(anyhow!(
+                "Token file {} for network {} is empty",
+                path.display(),
+                network_id
+            ));
+        }
+
+        return Ok(token);
+    }
+
+    central_token_from(keyring, arg, keyring_token)
+        .map_err(|e| anyhow!("network {}: {}", network_id, e))
 }
 
 // determine the path of the authtoken.secret
@@ -117,26 +269,196 @@ pub fn authtoken_path(arg: Option<&Path>) -> &Path {
     }
 }
 
-// use the default tld if none is supplied.
+// use the default tld if none is supplied. `home.arpa` and `home.arpa.` are the same domain.
 pub fn domain_or_default(tld: Option<&str>) -> Result<Name, anyhow::Error> {
     if let Some(tld) = tld {
-        if !tld.is_empty() {
-            return Ok(Name::from_str(&format!("{}.", tld))?);
-        } else {
-            return Err(anyhow!("Domain name must not be empty if provided."));
+        if tld == "." {
+            return Err(anyhow!(
+                "Invalid domain name \".\": the root cannot be served, every name would be claimed"
+            ));
         }
+
+        let domain = tld.strip_suffix('.').unwrap_or(tld);
+        validate_domain(domain)?;
+        return Name::from_str(&format!("{}.", domain))
+            .map_err(|e| anyhow!("Invalid domain name {:?}: {}", tld, e));
     };
 
     Ok(Name::from_str(DEFAULT_DOMAIN_NAME)?)
 }
 
-// parse_member_name ensures member names are DNS compliant
-pub fn parse_member_name(name: Option<String>, domain_name: Name) -> Option<Name> {
+// validate_domain checks a user-supplied domain label by label, so a mistake is reported with a
+// message that says what to fix instead of a parser error. Any number of labels is accepted, e.g.
+// `corp.example.internal`.
+fn validate_domain(domain: &str) -> Result<(), anyhow::Error> {
+    if domain.is_empty() {
+        return Err(anyhow!("Domain name must not be empty if provided."));
+    }
+
+    if domain.len() > 253 {
+        return Err(anyhow!(
+            "Invalid domain name {:?}: must be at most 253 characters long",
+            domain
+        ));
+    }
+
+    for label in domain.split('.') {
+        if label.is_empty() {
+            return Err(anyhow!(
+                "Invalid domain name {:?}: contains an empty label (leading or repeated dot)",
+                domain
+            ));
+        }
+
+        if label.len() > 63 {
+            return Err(anyhow!(
+                "Invalid domain name {:?}: label {:?} is longer than 63 characters",
+                domain,
+                label
+            ));
+        }
+
+        if let Some(c) = label
+            .chars()
+            .find(|c| c.is_ascii() && !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+        {
+            return Err(anyhow!(
+                "Invalid domain name {:?}: label {:?} contains {:?}; only letters, digits, hyphens and underscores are allowed",
+                domain,
+                label,
+                c
+            ));
+        }
+
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(anyhow!(
+                "Invalid domain name {:?}: label {:?} must not start or end with a hyphen",
+                domain,
+                label
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// a small list of public suffixes that zeronsd should never claim by accident. It is not meant to
+// be exhaustive; it catches the common mistakes.
+const PUBLIC_SUFFIXES: &[&str] = &[
+    "com", "net", "org", "edu", "gov", "mil", "int", "info", "biz", "io", "co", "ai", "app", "dev",
+    "me", "tv", "xyz", "online", "site", "us", "uk", "co.uk", "org.uk", "ca", "de", "fr", "nl",
+    "eu", "au", "com.au", "jp", "co.jp", "cn", "com.cn", "in", "co.in", "br", "com.br", "ru", "ch",
+    "se", "no", "es", "it", "pl", "nz", "co.nz", "za", "co.za", "arpa",
+];
+
+// check_public_domain refuses to serve a domain that is itself a public suffix, as zeronsd would
+// then be authoritative for every name under it. `allow` turns the refusal into a warning.
+pub fn check_public_domain(domain: &Name, allow: bool) -> Result<(), anyhow::Error> {
+    let name = domain.to_ascii().trim_end_matches('.').to_lowercase();
+
+    if PUBLIC_SUFFIXES.contains(&name.as_str()) {
+        if !allow {
+            return Err(anyhow!(
+                "Domain {} is a public suffix; pass --allow-public-tld to serve it anyway",
+                domain
+            ));
+        }
+
+        warn!(
+            "Domain {} is a public suffix; lookups for it will be answered locally",
+            domain
+        );
+    }
+
+    Ok(())
+}
+
+// label_too_long reports whether a label is over the 63 octets DNS allows for one.
+fn label_too_long(label: &str) -> bool {
+    label.len() > 63 && Name::from_utf8(label).is_err()
+}
+
+// truncate_label shortens a label that is too long for DNS, keeping it unique by replacing its end
+// with a hash of the whole label: `<prefix>-<8 hex digits>`. Other labels are returned as they are.
+pub fn truncate_label(label: &str) -> String {
+    if !label_too_long(label) {
+        return label.to_string();
+    }
+
+    let hash = hex::encode(&openssl::sha::sha256(label.as_bytes())[..4]);
+    let mut prefix: Vec<char> = label.chars().collect();
+
+    // non-ASCII labels are longer once punycoded, so shorten until the result fits.
+    while !prefix.is_empty() {
+        let candidate = format!(
+            "{}-{}",
+            prefix.iter().collect::<String>().trim_end_matches('-'),
+            hash
+        );
+        if Name::from_utf8(&candidate).is_ok() {
+            return candidate;
+        }
+        prefix.pop();
+    }
+
+    label.to_string()
+}
+
+// parse_member_name ensures member names are DNS compliant, and that none of their labels are
+// shorter than min_len once sanitized. Labels longer than DNS allows are shortened with
+// truncate_label if truncate is set; otherwise the name is skipped.
+pub fn parse_member_name(
+    name: Option<String>,
+    domain_name: Name,
+    min_len: usize,
+    truncate: bool,
+) -> Option<Name> {
     if let Some(name) = name {
         let name = name.trim();
         if !name.is_empty() {
-            match name.to_fqdn(domain_name) {
-                Ok(record) => return Some(record),
+            let sanitized = sanitize_hostname(name);
+            let long_label = sanitized.split('.').find(|label| label_too_long(label));
+
+            let host = match long_label {
+                Some(_) if truncate => sanitized
+                    .split('.')
+                    .map(truncate_label)
+                    .collect::<Vec<String>>()
+                    .join(".")
+                    .to_hostname(),
+                Some(label) => {
+                    warn!(
+                        "Record {} not entered into catalog: label {:?} is longer than the 63 octets DNS allows; set truncate_names to shorten it",
+                        name, label
+                    );
+                    return None;
+                }
+                None => name.to_hostname(),
+            };
+
+            match host {
+                Ok(host) => {
+                    if let Some(label) = host.iter().find(|label| label.len() < min_len) {
+                        warn!(
+                            "Record {} not entered into catalog: label {:?} is shorter than {} characters",
+                            name,
+                            String::from_utf8_lossy(label),
+                            min_len
+                        );
+                        return None;
+                    }
+
+                    match host.clone().append_domain(&domain_name) {
+                        Ok(fqdn) => return Some(fqdn),
+                        Err(_) => {
+                            warn!(
+                                "Record {} not entered into catalog: {}.{} is longer than the 255 octets DNS allows",
+                                name, host, domain_name
+                            );
+                            return None;
+                        }
+                    }
+                }
                 Err(e) => {
                     warn!("Record {} not entered into catalog: {:?}", name, e);
                     return None;
@@ -153,16 +475,25 @@ pub async fn get_member_name(
     domain_name: Name,
     local_url: String,
 ) -> Result<LowerName, anyhow::Error> {
-    let client = local_client_from_file(authtoken_path, local_url)?;
+    let address = get_node_id(authtoken_path, local_url).await?;
+    Ok(("zt-".to_string() + &address).to_fqdn(domain_name)?.into())
+}
 
-    let status = client.get_status().await?.into_inner();
-    if let Some(address) = &status.address {
-        return Ok(("zt-".to_string() + address).to_fqdn(domain_name)?.into());
-    }
+// get_node_id returns the ZeroTier address of the node zeronsd runs on.
+pub async fn get_node_id(
+    authtoken_path: &Path,
+    local_url: String,
+) -> Result<String, anyhow::Error> {
+    let client = local_client_from_file(authtoken_path, local_url)?;
 
-    Err(anyhow!(
-        "No member found for this instance; is zerotier connected to this network?"
-    ))
+    client
+        .get_status()
+        .await?
+        .into_inner()
+        .address
+        .ok_or_else(|| {
+            anyhow!("No member found for this instance; is zerotier connected to this network?")
+        })
 }
 
 fn local_client_from_file(
@@ -173,6 +504,113 @@ fn local_client_from_file(
     local_client(authtoken, local_url)
 }
 
+// RebindingClient is a zerotier-one client for calls that are retried. zerotier-one may write a new
+// authtoken, e.g. when it is reinstalled or first started, and then refuses the one the client was
+// built with; the file is read again only then, not on every failed request.
+struct RebindingClient {
+    authtoken_path: PathBuf,
+    local_url: String,
+    authtoken: String,
+    client: service_api::Client,
+}
+
+impl RebindingClient {
+    fn new(authtoken_path: &Path, local_url: String) -> Result<Self, anyhow::Error> {
+        let authtoken = std::fs::read_to_string(authtoken_path)?;
+        Ok(Self {
+            authtoken_path: authtoken_path.to_path_buf(),
+            client: local_client(authtoken.clone(), local_url.clone())?,
+            local_url,
+            authtoken,
+        })
+    }
+
+    // rebind rebuilds the client if err is zerotier-one refusing the authtoken, and the file now
+    // holds a different one. It tells whether the request is worth repeating right away.
+    fn rebind<T>(&mut self, err: &service_api::Error<T>) -> Result<bool, anyhow::Error> {
+        if !err.status().is_some_and(|status| {
+            status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+        }) {
+            return Ok(false);
+        }
+
+        let authtoken = std::fs::read_to_string(&self.authtoken_path)?;
+        if authtoken == self.authtoken {
+            return Ok(false);
+        }
+
+        info!(
+            "The authtoken in {} changed; reconnecting to zerotier-one",
+            self.authtoken_path.display()
+        );
+        self.client = local_client(authtoken.clone(), self.local_url.clone())?;
+        self.authtoken = authtoken;
+        Ok(true)
+    }
+
+    // retry is how a request that failed with err is retried: right away if the client was rebuilt,
+    // and otherwise after the backoff, failing with `described` once the attempts run out.
+    fn retry<T>(&mut self, err: &service_api::Error<T>, described: anyhow::Error) -> Retry {
+        match self.rebind(err) {
+            Ok(true) => Retry::Now,
+            Ok(false) => Retry::Later(described),
+            Err(e) => Retry::Never(e),
+        }
+    }
+}
+
+// Retry is what retry_with_backoff does after an attempt that failed.
+pub enum Retry {
+    // try again after the backoff, unless that was the last attempt.
+    Later(anyhow::Error),
+    // try again right away, without using up an attempt.
+    Now,
+    // give up; the error will not go away by itself.
+    Never(anyhow::Error),
+}
+
+// retry_with_backoff calls f up to `attempts` times, doubling `backoff` between tries, until it
+// succeeds or fails for good. Each retry is logged; once the attempts run out, the last error is
+// returned.
+pub async fn retry_with_backoff<T, F, Fut>(
+    attempts: u32,
+    backoff: Duration,
+    mut f: F,
+) -> Result<T, anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Retry>>,
+{
+    let attempts = attempts.max(1);
+    let mut wait = backoff;
+    let mut attempt = 1;
+
+    loop {
+        let err = match f().await {
+            Ok(res) => return Ok(res),
+            Err(Retry::Now) => continue,
+            Err(Retry::Never(err)) => return Err(err),
+            Err(Retry::Later(err)) => err,
+        };
+
+        if attempt == attempts {
+            if attempts > 1 {
+                return Err(anyhow!("{} (gave up after {} attempts)", err, attempts));
+            }
+
+            return Err(err);
+        }
+
+        warn!(
+            "{}; retrying in {:?} (attempt {}/{})",
+            err, wait, attempt, attempts
+        );
+        tokio::time::sleep(wait).await;
+        wait *= 2;
+        attempt += 1;
+    }
+}
+
 pub fn local_client(
     authtoken: String,
     local_url: String,
@@ -196,47 +634,526 @@ pub async fn get_listen_ips(
     network_id: &str,
     local_url: String,
 ) -> Result<Vec<String>, anyhow::Error> {
+    get_listen_ips_retry(authtoken_path, network_id, local_url, 1, Duration::ZERO).await
+}
+
+// get_listen_ips_retry is get_listen_ips for a node that may still be joining: it tries up to
+// `attempts` times, doubling `backoff` between tries, so a just-booted system can converge. The
+// error after the last attempt says whether zerotier-one could not be reached or the network has
+// not assigned this node any IPs.
+pub async fn get_listen_ips_retry(
+    authtoken_path: &Path,
+    network_id: &str,
+    local_url: String,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<Vec<String>, anyhow::Error> {
+    let client = Mutex::new(RebindingClient::new(authtoken_path, local_url.clone())?);
+    let (client, local_url) = (&client, local_url.as_str());
+
+    retry_with_backoff(attempts, backoff, || async move {
+        let local = client.lock().unwrap().client.clone();
+        match local.get_network(network_id).await {
+            Ok(network) => {
+                let assigned = network.into_inner().assigned_addresses;
+                if !assigned.is_empty() {
+                    return Ok(assigned);
+                }
+
+                Err(Retry::Later(anyhow!(
+                    "No listen IPs available on this network"
+                )))
+            }
+            Err(service_api::Error::CommunicationError(error)) => Err(Retry::Later(anyhow!(
+                "Could not reach zerotier-one at {}: {}. Is it running?",
+                local_url,
+                error
+            ))),
+            Err(error) => Err(client.lock().unwrap().retry(
+                &error,
+                anyhow!("Error: {}. Are you joined to {}?", error, network_id),
+            )),
+        }
+    })
+    .await
+}
+
+// update_member_retry saves `member` in Central, retrying up to `attempts` times, doubling
+// `backoff` between tries, while Central cannot be reached or answers with a server error. Client
+// errors such as 403 or 404 will not go away by themselves and are returned right away. The whole
+// member is sent every time, so repeating an update that was applied but not acknowledged leaves
+// the member as intended.
+pub async fn update_member_retry(
+    client: &central_api::Client,
+    network_id: &str,
+    member_id: &str,
+    member: &central_api::types::Member,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<central_api::types::Member, anyhow::Error> {
+    retry_with_backoff(attempts, backoff, || async move {
+        let err = match client
+            .update_network_member(network_id, member_id, member)
+            .await
+        {
+            Ok(member) => return Ok(member.into_inner()),
+            Err(err) => err,
+        };
+
+        let transient = match &err {
+            central_api::Error::CommunicationError(_) => true,
+            err => err.status().is_some_and(|s| s.is_server_error()),
+        };
+
+        let err = anyhow!(
+            "Could not update member {} of network {}: {}",
+            member_id,
+            network_id,
+            err
+        );
+        Err(if transient {
+            Retry::Later(err)
+        } else {
+            Retry::Never(err)
+        })
+    })
+    .await
+}
+
+// members_interval validates the configured time between member syncs. Intervals under
+// MIN_MEMBERS_INTERVAL are refused, and those under RECOMMENDED_MEMBERS_INTERVAL are only warned
+// about, as Central may rate-limit the token.
+pub fn members_interval(secs: Option<u64>) -> Result<Duration, anyhow::Error> {
+    let secs = secs.unwrap_or(DEFAULT_MEMBERS_INTERVAL);
+
+    if secs < MIN_MEMBERS_INTERVAL {
+        return Err(anyhow!(
+            "members_interval must be at least {} second; got {}",
+            MIN_MEMBERS_INTERVAL,
+            secs
+        ));
+    }
+
+    if secs < RECOMMENDED_MEMBERS_INTERVAL {
+        warn!(
+            "members_interval of {}s polls Central very often and may be rate-limited; at least {}s is recommended",
+            secs, RECOMMENDED_MEMBERS_INTERVAL
+        );
+    }
+
+    Ok(Duration::new(secs, 0))
+}
+
+// get_allow_dns reads whether this node accepts the DNS settings managed for the network.
+pub async fn get_allow_dns(
+    authtoken_path: &Path,
+    network_id: &str,
+    local_url: String,
+) -> Result<Option<bool>, anyhow::Error> {
     let client = local_client_from_file(authtoken_path, local_url)?;
+    Ok(client.get_network(network_id).await?.into_inner().allow_dns)
+}
 
-    match client.get_network(network_id).await {
-        Err(error) => Err(anyhow!(
-            "Error: {}. Are you joined to {}?",
-            error,
-            network_id
-        )),
-        Ok(listen) => {
-            let assigned = listen.into_inner().assigned_addresses.to_owned();
-            if !assigned.is_empty() {
-                Ok(assigned)
-            } else {
-                Err(anyhow!("No listen IPs available on this network"))
+// enable_local_dns turns on allowDNS and allowManaged for the network on this node, so it accepts
+// the DNS settings pushed to Central and the addresses zeronsd listens on. Nothing is written if
+// both are already on. zerotier-one is tried up to `attempts` times, doubling `backoff` between
+// tries, as it may still be starting.
+pub async fn enable_local_dns(
+    authtoken_path: &Path,
+    network_id: &str,
+    local_url: String,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<(), anyhow::Error> {
+    let client = Mutex::new(RebindingClient::new(authtoken_path, local_url)?);
+    let client = &client;
+
+    retry_with_backoff(attempts, backoff, || async move {
+        let local = client.lock().unwrap().client.clone();
+        let res = async {
+            let mut network = local.get_network(network_id).await?.into_inner();
+            if network.allow_dns == Some(true) && network.allow_managed == Some(true) {
+                return Ok(());
             }
+
+            network.allow_dns = Some(true);
+            network.allow_managed = Some(true);
+            local.update_network(network_id, &network).await?;
+            info!("Enabled managed DNS (allowDNS) and addresses (allowManaged) on this node");
+            Ok::<_, service_api::Error>(())
+        }
+        .await;
+
+        res.map_err(|err| {
+            client.lock().unwrap().retry(
+                &err,
+                anyhow!(
+                    "Could not enable managed DNS for {} on this node: {}",
+                    network_id,
+                    err
+                ),
+            )
+        })
+    })
+    .await
+}
+
+// dns_push_wanted decides whether the DNS settings are pushed to central, given this node's
+// allowDNS setting. allowDNS is decided by every member on its own, so by default a node without it
+// only gets a warning; `require` skips the push instead.
+pub fn dns_push_wanted(allow_dns: Option<bool>, require: bool) -> bool {
+    if allow_dns.unwrap_or(false) {
+        return true;
+    }
+
+    if require {
+        warn!("Managed DNS (allowDNS) is disabled for this network on this node; not pushing DNS settings to Central");
+        false
+    } else {
+        warn!("Managed DNS (allowDNS) is disabled for this network on this node; members need it enabled to use the DNS settings pushed to Central");
+        true
+    }
+}
+
+// DnsPush selects which of the DNS settings are pushed to Central. Central takes them as a whole,
+// so the one that is not pushed is sent empty rather than left out.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum DnsPush {
+    // the search domain and the servers.
+    #[default]
+    #[serde(rename = "both")]
+    Both,
+    // only the servers, so clients resolve the domain here without searching it for short names.
+    #[serde(rename = "servers")]
+    Servers,
+    // only the search domain, for networks whose clients reach this instance some other way.
+    #[serde(rename = "domain")]
+    Domain,
+}
+
+impl DnsPush {
+    // select drops the settings this mode does not push.
+    pub fn select(&self, domain_name: Name, ips: Vec<String>) -> (Option<Name>, Vec<String>) {
+        match self {
+            DnsPush::Both => (Some(domain_name), ips),
+            DnsPush::Servers => (None, ips),
+            DnsPush::Domain => (Some(domain_name), Vec::new()),
+        }
+    }
+}
+
+impl std::fmt::Display for DnsPush {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DnsPush::Both => "both",
+            DnsPush::Servers => "servers",
+            DnsPush::Domain => "domain",
+        })
+    }
+}
+
+impl FromStr for DnsPush {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "both" => Ok(DnsPush::Both),
+            "servers" => Ok(DnsPush::Servers),
+            "domain" => Ok(DnsPush::Domain),
+            _ => Err(anyhow!(
+                "invalid format: allowed values: [both, servers, domain]"
+            )),
         }
     }
 }
 
-// update_central_dns pushes the search records
+// central_domain is the search domain as Central stores it; no domain is stored as an empty one.
+fn central_domain(domain_name: Option<&Name>) -> String {
+    domain_name.map_or_else(String::new, |domain_name| {
+        let mut domain_name = domain_name.clone();
+        domain_name.set_fqdn(false);
+        domain_name.to_string()
+    })
+}
+
+// central_dns builds the DNS settings pushed to Central. They are only the search domain and the
+// servers: Central has no TTL or other hint for how long clients keep them, so how quickly a change
+// is picked up is up to the ZeroTier client. Central wants both, so a missing domain is sent empty.
+pub fn central_dns(domain_name: Option<Name>, ips: Vec<String>) -> central_api::types::Dns {
+    central_api::types::Dns {
+        domain: Some(central_domain(domain_name.as_ref())),
+        servers: Some(ips),
+    }
+}
+
+// editable_network is the part of a network that is sent back to Central when updating it, so a
+// change to it between reading and writing the network would be lost.
+fn editable_network(network: &central_api::types::Network) -> serde_json::Value {
+    serde_json::json!([network.config, network.description, network.rules_source])
+}
+
+// update_central_dns pushes the search records. Central has no conditional updates, so the network
+// is read again right before it is written back; if someone else changed it in the meantime, the
+// update starts over from their version, up to `retries` more times.
 pub async fn update_central_dns(
-    domain_name: Name,
+    domain_name: Option<Name>,
     ips: Vec<String>,
     client: central_api::Client,
     network: String,
+    retries: u32,
 ) -> Result<(), anyhow::Error> {
-    let mut zt_network = client.get_network_by_id(&network).await?;
+    let mut zt_network = client.get_network_by_id(&network).await?.into_inner();
 
-    let mut domain_name = domain_name;
-    domain_name.set_fqdn(false);
+    for _ in 0..=retries {
+        let mut zt_network_config = match zt_network.config.to_owned() {
+            Some(config) => config,
+            None => return Ok(()),
+        };
 
-    let dns = Some(central_api::types::Dns {
-        domain: Some(domain_name.to_string()),
-        servers: Some(ips),
-    });
+        let current = client.get_network_by_id(&network).await?.into_inner();
+        if editable_network(&current) != editable_network(&zt_network) {
+            warn!(
+                "network {} changed in Central while updating its DNS settings; retrying",
+                network
+            );
+            zt_network = current;
+            continue;
+        }
 
-    if let Some(mut zt_network_config) = zt_network.config.to_owned() {
-        zt_network_config.dns = dns;
+        zt_network_config.dns = Some(central_dns(domain_name.clone(), ips.clone()));
         zt_network.config = Some(zt_network_config);
         client.update_network(&network, &zt_network).await?;
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "network {} kept changing in Central; gave up updating its DNS settings after {} attempts",
+        network,
+        retries + 1
+    ))
+}
+
+// listener_healthy checks that something is accepting connections on the socket address. The TCP
+// listener is bound alongside the UDP socket, so this is a reasonable proxy for the whole listener.
+pub async fn listener_healthy(sa: SocketAddr, timeout: Duration) -> bool {
+    matches!(
+        tokio::time::timeout(timeout, tokio::net::TcpStream::connect(sa)).await,
+        Ok(Ok(_))
+    )
+}
+
+// healthy_ips filters the listen ips down to the healthy ones. If nothing is healthy, all ips are
+// returned instead; advertising no nameservers at all is worse than advertising a broken one.
+pub fn healthy_ips(ips: &[IpAddr], health: &[bool]) -> Vec<IpAddr> {
+    let healthy: Vec<IpAddr> = ips
+        .iter()
+        .zip(health)
+        .filter_map(|(ip, healthy)| healthy.then_some(*ip))
+        .collect();
+
+    if healthy.is_empty() {
+        ips.to_vec()
+    } else {
+        healthy
+    }
+}
+
+// advertised_ips selects which of the bound ips are pushed to Central. All of them are, unless a
+// subset was asked for; every ip in that subset must actually be bound.
+pub fn advertised_ips(
+    bound: &[IpAddr],
+    advertise: &[IpAddr],
+) -> Result<Vec<IpAddr>, anyhow::Error> {
+    if advertise.is_empty() {
+        return Ok(bound.to_vec());
+    }
+
+    for ip in advertise {
+        if !bound.contains(ip) {
+            return Err(anyhow!(
+                "Advertised IP {} is not one of this member's IPs on the network: {:?}",
+                ip,
+                bound
+            ));
+        }
+    }
+
+    Ok(advertise.to_vec())
+}
+
+// check_zone_ips ensures every ip a zone is limited to is actually bound.
+pub fn check_zone_ips(bound: &[IpAddr], ips: &[IpAddr]) -> Result<(), anyhow::Error> {
+    for ip in ips {
+        if !bound.contains(ip) {
+            return Err(anyhow!(
+                "Listen IP {} is not one of this member's IPs on the network: {:?}",
+                ip,
+                bound
+            ));
+        }
     }
 
     Ok(())
 }
+
+// graced_health holds back listeners that are not advertised yet until they have been passing their
+// health checks for at least grace. Advertised ones only have to pass the current check, so one
+// that fails is withdrawn right away. healthy_since tracks when each listener started passing.
+pub fn graced_health(
+    ips: &[IpAddr],
+    health: &[bool],
+    advertised: &[IpAddr],
+    healthy_since: &mut HashMap<IpAddr, Instant>,
+    grace: Duration,
+    now: Instant,
+) -> Vec<bool> {
+    ips.iter()
+        .zip(health)
+        .map(|(ip, healthy)| {
+            if !healthy {
+                healthy_since.remove(ip);
+                return false;
+            }
+
+            let since = *healthy_since.entry(*ip).or_insert(now);
+            advertised.contains(ip) || now.duration_since(since) >= grace
+        })
+        .collect()
+}
+
+// advertise_healthy_ips periodically checks the listeners and re-pushes the DNS settings to
+// central whenever the set of healthy listeners changes. Listeners only join the set once they
+// have been healthy for grace. With an election, only the primary instance pushes them.
+#[allow(clippy::too_many_arguments)]
+pub async fn advertise_healthy_ips(
+    domain_name: Option<Name>,
+    listen_ips: Vec<IpAddr>,
+    client: central_api::Client,
+    network: String,
+    interval: Duration,
+    grace: Duration,
+    retries: u32,
+    election: Option<Election>,
+) {
+    let mut advertised = listen_ips.clone();
+    let mut healthy_since = HashMap::new();
+    let mut timer = tokio::time::interval(interval);
+    timer.tick().await;
+
+    loop {
+        timer.tick().await;
+
+        let mut health = Vec::new();
+        for ip in &listen_ips {
+            health.push(listener_healthy(SocketAddr::new(*ip, 53), Duration::new(1, 0)).await);
+        }
+
+        let health = graced_health(
+            &listen_ips,
+            &health,
+            &advertised,
+            &mut healthy_since,
+            grace,
+            Instant::now(),
+        );
+        let healthy = healthy_ips(&listen_ips, &health);
+        if healthy == advertised || election.as_ref().is_some_and(|e| !e.is_primary()) {
+            continue;
+        }
+
+        info!("Advertising healthy listeners to Central: {:?}", healthy);
+        match update_central_dns(
+            domain_name.clone(),
+            healthy.iter().map(|ip| ip.to_string()).collect(),
+            client.clone(),
+            network.clone(),
+            retries,
+        )
+        .await
+        {
+            Ok(_) => advertised = healthy,
+            Err(e) => tracing::error!("error updating central DNS: {}", e),
+        }
+    }
+}
+
+// dns_applied reports whether the network's DNS settings in central match what was pushed.
+pub fn dns_applied(
+    network: &central_api::types::Network,
+    domain_name: Option<&Name>,
+    ips: &[String],
+) -> bool {
+    match network.config.as_ref().and_then(|c| c.dns.as_ref()) {
+        Some(dns) => {
+            let mut servers = dns.servers.clone().unwrap_or_default();
+            let mut ips = ips.to_vec();
+            servers.sort();
+            ips.sort();
+
+            dns.domain.clone().unwrap_or_default() == central_domain(domain_name) && servers == ips
+        }
+        None => false,
+    }
+}
+
+// verify_central_dns re-reads the network from central until the pushed DNS settings show up, or
+// the timeout expires.
+pub async fn verify_central_dns(
+    domain_name: Option<Name>,
+    ips: Vec<String>,
+    client: central_api::Client,
+    network: String,
+    timeout: Duration,
+) -> Result<(), anyhow::Error> {
+    let verify = async {
+        loop {
+            match client.get_network_by_id(&network).await {
+                Ok(zt_network) => {
+                    if dns_applied(&zt_network, domain_name.as_ref(), &ips) {
+                        return;
+                    }
+                }
+                Err(e) => warn!("error verifying central DNS settings: {}", e),
+            }
+
+            tokio::time::sleep(Duration::new(1, 0)).await;
+        }
+    };
+
+    match tokio::time::timeout(timeout, verify).await {
+        Ok(_) => {
+            info!("Central DNS settings verified for network {}", network);
+            Ok(())
+        }
+        Err(_) => Err(anyhow!(
+            "DNS settings for network {} were not applied by Central after {}s",
+            network,
+            timeout.as_secs()
+        )),
+    }
+}
+
+// advertise_when_bound runs every bind in order and only advertises once all of them succeeded
+// and the listeners pass their self-check; on any failure, advertise is never polled.
+pub async fn advertise_when_bound<B, C, A>(
+    binds: Vec<B>,
+    check: C,
+    advertise: A,
+) -> Result<(), anyhow::Error>
+where
+    B: Future<Output = Result<(), anyhow::Error>>,
+    C: Future<Output = bool>,
+    A: Future<Output = Result<(), anyhow::Error>>,
+{
+    for bind in binds {
+        bind.await?;
+    }
+
+    if !check.await {
+        return Err(anyhow!(
+            "Listeners failed their self-check; not advertising to Central"
+        ));
+    }
+
+    advertise.await
+}
@@ -12,7 +12,7 @@ fn generate(apiname: &str) -> Result<()> {
     let content = prettyplease::unparse(&ast);
 
     let mut out_file = std::path::Path::new(&std::env::var("OUT_DIR")?).to_path_buf();
-    out_file.push(&format!("{}.rs", apiname));
+    out_file.push(format!("{}.rs", apiname));
 
     std::fs::write(out_file, content).unwrap();
 